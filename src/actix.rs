@@ -0,0 +1,430 @@
+use std::{
+    fmt,
+    future::{Future, Ready, ready},
+    hash::Hash,
+    net::IpAddr,
+    pin::Pin,
+    sync::Arc,
+};
+
+use actix_web::{
+    Error, HttpResponse,
+    body::{EitherBody, MessageBody},
+    dev::{Service, ServiceRequest, ServiceResponse, Transform, forward_ready},
+    http::{
+        StatusCode,
+        header::{HeaderName, HeaderValue},
+    },
+};
+
+#[cfg(feature = "http")]
+use crate::extract::KeyExtractor;
+use crate::{
+    algorithm::Algorithm,
+    clock::Clock,
+    error::AcquireError,
+    handle::RateLimiterHandle,
+    headers::{HeaderStyle, RateLimitHeaders},
+    limiter::RateLimiter,
+    state::State,
+};
+
+type KeyFn<K> = Arc<dyn Fn(&ServiceRequest) -> Option<K> + Send + Sync>;
+type RejectionFn = Arc<dyn Fn(&AcquireError) -> HttpResponse + Send + Sync>;
+
+/// An actix-web middleware that limits requests with a shared
+/// [`RateLimiter`].
+///
+/// Each request is limited under the key derived from it by
+/// [`with_key`](Self::with_key), or on the base state if it has no key or
+/// the limiter doesn't know the key. Denied requests get an empty `429 Too
+/// Many Requests` response, or `503 Service Unavailable` while the limiter
+/// is disabled, unless [`with_rejection`](Self::with_rejection) builds them
+/// another. The [`RateLimitHeaders`] are added in the [chosen
+/// style](Self::with_header_style) either way.
+///
+/// Routes are configured by wrapping each `Resource` or `Scope` in its own
+/// middleware, or by keying one limiter on the route through
+/// [`per_route`](Self::per_route) and giving each route pattern its quota
+/// with [`insert_key`](RateLimiter::insert_key).
+///
+/// ```
+/// use actix_web::{App, HttpResponse, web};
+/// use ratelimit::{ActixRateLimit, MonotonicClock, Quota, RateLimiter, State};
+///
+/// let per_ip = RateLimiter::keyed(State::new(Quota::per_second(1000), MonotonicClock))
+///     .with_default_key_quota(Quota::per_second(10))
+///     .into_handle();
+/// let app = App::new().service(
+///     web::resource("/search")
+///         .wrap(ActixRateLimit::peer_ip(per_ip))
+///         .to(HttpResponse::Ok),
+/// );
+/// # drop(app);
+/// ```
+pub struct ActixRateLimit<C, S = State<C>, K = String>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    limiter: RateLimiterHandle<C, S, K>,
+    key: Option<KeyFn<K>>,
+    header_style: HeaderStyle,
+    rejection: Option<RejectionFn>,
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> ActixRateLimit<C, S, K> {
+    /// Limits every request on the base state of `limiter`.
+    pub fn new(limiter: RateLimiterHandle<C, S, K>) -> Self {
+        Self {
+            limiter,
+            key: None,
+            header_style: HeaderStyle::default(),
+            rejection: None,
+        }
+    }
+
+    /// Builds the responses of denied requests with `rejection`, e.g. to
+    /// match an API's error envelope. Headers it sets take precedence over
+    /// the rate limiting headers.
+    pub fn with_rejection<F>(mut self, rejection: F) -> Self
+    where
+        F: Fn(&AcquireError) -> HttpResponse + Send + Sync + 'static,
+    {
+        self.rejection = Some(Arc::new(rejection));
+        self
+    }
+
+    /// Names the rate limiting headers of denied requests' responses in
+    /// `style`.
+    pub fn with_header_style(mut self, style: HeaderStyle) -> Self {
+        self.header_style = style;
+        self
+    }
+
+    /// Limits each request under the key `key` derives from it.
+    pub fn with_key<F>(mut self, key: F) -> Self
+    where
+        F: Fn(&ServiceRequest) -> Option<K> + Send + Sync + 'static,
+    {
+        self.key = Some(Arc::new(key));
+        self
+    }
+
+    /// Limits each request under the key `extractor` derives from it, so the
+    /// extractors shared by the HTTP integrations, such as
+    /// [`ForwardedIp`](crate::ForwardedIp), work here too.
+    ///
+    /// The extractor sees a copy of the request's method, URI and headers,
+    /// with the peer address as a `SocketAddr` extension.
+    #[cfg(feature = "http")]
+    pub fn with_extractor<X>(self, extractor: X) -> Self
+    where
+        X: KeyExtractor<Key = K> + Send + Sync + 'static,
+    {
+        self.with_key(move |request| extractor.extract(&to_http_request(request)?))
+    }
+}
+
+/// Copies the head of `request` into an [`http::Request`], since actix-web
+/// is built on an older version of the `http` crate.
+#[cfg(feature = "http")]
+fn to_http_request(request: &ServiceRequest) -> Option<http::Request<()>> {
+    let mut builder = http::Request::builder()
+        .method(request.method().as_str())
+        .uri(request.uri().to_string());
+    for (name, value) in request.headers() {
+        builder = builder.header(name.as_str(), value.as_bytes());
+    }
+    let mut converted = builder.body(()).ok()?;
+    if let Some(peer) = request.peer_addr() {
+        converted.extensions_mut().insert(peer);
+    }
+    Some(converted)
+}
+
+impl<C: Clock, S: Algorithm<C>> ActixRateLimit<C, S, IpAddr> {
+    /// Keys requests by the IP address of the connected peer.
+    pub fn peer_ip(limiter: RateLimiterHandle<C, S, IpAddr>) -> Self {
+        Self::new(limiter).with_key(|request| request.peer_addr().map(|addr| addr.ip()))
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>> ActixRateLimit<C, S> {
+    /// Keys requests by the pattern of the matched route, e.g.
+    /// `/users/{id}`, so each route has its own quota.
+    pub fn per_route(limiter: RateLimiterHandle<C, S>) -> Self {
+        Self::new(limiter).with_key(ServiceRequest::match_pattern)
+    }
+}
+
+impl<C, S, K> Clone for ActixRateLimit<C, S, K>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            limiter: self.limiter.clone(),
+            key: self.key.clone(),
+            header_style: self.header_style,
+            rejection: self.rejection.clone(),
+        }
+    }
+}
+
+impl<C, S, K> fmt::Debug for ActixRateLimit<C, S, K>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    RateLimiter<C, S, K>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActixRateLimit")
+            .field("limiter", &self.limiter)
+            .field("keyed", &self.key.is_some())
+            .field("header_style", &self.header_style)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Svc, B, C, S, K> Transform<Svc, ServiceRequest> for ActixRateLimit<C, S, K>
+where
+    Svc: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    Svc::Future: 'static,
+    B: MessageBody + 'static,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = ActixRateLimitMiddleware<Svc, C, S, K>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, ()>>;
+
+    fn new_transform(&self, service: Svc) -> Self::Future {
+        ready(Ok(ActixRateLimitMiddleware {
+            service,
+            config: self.clone(),
+        }))
+    }
+}
+
+/// The service built by [`ActixRateLimit`].
+pub struct ActixRateLimitMiddleware<Svc, C, S = State<C>, K = String>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    service: Svc,
+    config: ActixRateLimit<C, S, K>,
+}
+
+impl<Svc, C, S, K> fmt::Debug for ActixRateLimitMiddleware<Svc, C, S, K>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    RateLimiter<C, S, K>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ActixRateLimitMiddleware")
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Svc, B, C, S, K> Service<ServiceRequest> for ActixRateLimitMiddleware<Svc, C, S, K>
+where
+    Svc: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    Svc::Future: 'static,
+    B: MessageBody + 'static,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, request: ServiceRequest) -> Self::Future {
+        let key = self.config.key.as_ref().and_then(|key| key(&request));
+        match self.config.limiter.acquire_by_key_or_base(key.as_ref()) {
+            Ok(()) => {
+                let response = self.service.call(request);
+                Box::pin(async move { Ok(response.await?.map_into_left_body()) })
+            }
+            Err(err) => {
+                let response = request.into_response(self.config.rejection(&err));
+                Box::pin(ready(Ok(response.map_into_right_body())))
+            }
+        }
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> ActixRateLimit<C, S, K> {
+    /// The response denying a request with `err`.
+    fn rejection(&self, err: &AcquireError) -> HttpResponse {
+        let mut response = match &self.rejection {
+            Some(rejection) => rejection(err),
+            None => HttpResponse::new(match err {
+                AcquireError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::TOO_MANY_REQUESTS,
+            }),
+        };
+        if let Some(headers) = RateLimitHeaders::denied(err) {
+            let map = response.headers_mut();
+            for (name, value) in headers.to_pairs(self.header_style) {
+                let name = HeaderName::from_static(name);
+                if !map.contains_key(&name) {
+                    let value = HeaderValue::from_str(&value).expect("header values are numeric");
+                    map.insert(name, value);
+                }
+            }
+        }
+        response
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        App,
+        http::header::{CONTENT_TYPE, RETRY_AFTER},
+        rt::System,
+        test, web,
+    };
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, quota::Quota};
+
+    #[test]
+    fn test_actix_peer_ip() {
+        System::new().block_on(async {
+            let limiter = RateLimiter::keyed(State::new(
+                Quota::per_second(100),
+                FakeRelativeClock::default(),
+            ))
+            .with_default_key_quota(Quota::per_second(1))
+            .into_handle();
+            let app = test::init_service(
+                App::new()
+                    .wrap(ActixRateLimit::peer_ip(limiter))
+                    .route("/", web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+            let request = |peer: &str| {
+                test::TestRequest::get()
+                    .peer_addr(peer.parse().unwrap())
+                    .to_request()
+            };
+
+            let response = test::call_service(&app, request("10.0.0.1:1000")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let response = test::call_service(&app, request("10.0.0.1:2000")).await;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+            // 不同的 IP 有各自的配额
+            let response = test::call_service(&app, request("10.0.0.2:1000")).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_actix_with_rejection() {
+        System::new().block_on(async {
+            let limiter = RateLimiter::new(Quota::per_second(1), FakeRelativeClock::default());
+            let middleware = ActixRateLimit::new(limiter.into_handle()).with_rejection(|err| {
+                HttpResponse::TooManyRequests()
+                    .content_type("application/json")
+                    .body(format!(r#"{{"error":{{"code":429,"message":"{err}"}}}}"#))
+            });
+            let app = test::init_service(
+                App::new()
+                    .wrap(middleware)
+                    .route("/", web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+
+            test::call_service(&app, test::TestRequest::get().to_request()).await;
+            let response = test::call_service(&app, test::TestRequest::get().to_request()).await;
+            assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(
+                response.headers().get(CONTENT_TYPE).unwrap(),
+                "application/json"
+            );
+            // 自定义响应仍然带有限流头
+            assert_eq!(response.headers().get(RETRY_AFTER).unwrap(), "1");
+            let body = test::read_body(response).await;
+            assert!(body.starts_with(br#"{"error":{"code":429,"message":"rate limited"#));
+        });
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn test_actix_with_extractor() {
+        use crate::extract::ForwardedIp;
+
+        System::new().block_on(async {
+            let limiter = RateLimiter::keyed(State::new(
+                Quota::per_second(100),
+                FakeRelativeClock::default(),
+            ))
+            .with_default_key_quota(Quota::per_second(1))
+            .into_handle();
+            let app = test::init_service(
+                App::new()
+                    .wrap(
+                        ActixRateLimit::new(limiter)
+                            .with_extractor(ForwardedIp::x_forwarded_for(1)),
+                    )
+                    .route("/", web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+            let status = |client: &str| {
+                let request = test::TestRequest::get()
+                    .insert_header(("x-forwarded-for", client))
+                    .to_request();
+                let app = &app;
+                async move { test::call_service(app, request).await.status() }
+            };
+
+            assert_eq!(status("203.0.113.7").await, StatusCode::OK);
+            assert_eq!(status("203.0.113.7").await, StatusCode::TOO_MANY_REQUESTS);
+            assert_eq!(status("203.0.113.8").await, StatusCode::OK);
+        });
+    }
+
+    #[test]
+    fn test_actix_per_route() {
+        System::new().block_on(async {
+            let limiter = RateLimiter::new(Quota::per_second(100), FakeRelativeClock::default());
+            limiter.insert_key("/users/{id}", Quota::per_second(1));
+            let app = test::init_service(
+                App::new()
+                    .wrap(ActixRateLimit::per_route(limiter.into_handle()))
+                    .route("/users/{id}", web::get().to(HttpResponse::Ok))
+                    .route("/health", web::get().to(HttpResponse::Ok)),
+            )
+            .await;
+            let status = |uri: &str| {
+                let request = test::TestRequest::get().uri(uri).to_request();
+                let app = &app;
+                async move { test::call_service(app, request).await.status() }
+            };
+
+            assert_eq!(status("/users/1").await, StatusCode::OK);
+            // 同一路由模式共享配额
+            assert_eq!(status("/users/2").await, StatusCode::TOO_MANY_REQUESTS);
+            // 未配置的路由走基础配额
+            assert_eq!(status("/health").await, StatusCode::OK);
+        });
+    }
+}
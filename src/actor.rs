@@ -0,0 +1,189 @@
+use std::{
+    borrow::Borrow,
+    hash::Hash,
+    sync::mpsc::{self, Receiver, Sender, SyncSender},
+    thread,
+};
+
+use crate::{
+    algorithm::Algorithm,
+    clock::Clock,
+    error::AcquireError,
+    limiter::{RateLimiter, StatsSnapshot},
+    quota::Quota,
+};
+
+/// A clonable handle to a [`RateLimiter`] running on its own thread,
+/// created by [`RateLimiter::spawn_actor`].
+///
+/// Every call sends a command over a channel and, unless it only configures
+/// the limiter, blocks until the actor replies. The actor owns the limiter,
+/// so it is never contended, and stops once every handle has been dropped.
+#[derive(Debug)]
+pub struct ActorHandle<K = String> {
+    commands: Sender<Command<K>>,
+}
+
+#[derive(Debug)]
+enum Command<K> {
+    Acquire {
+        key: K,
+        n: u64,
+        reply: SyncSender<Result<(), AcquireError>>,
+    },
+    InsertKey {
+        key: K,
+        quota: Quota,
+    },
+    Query {
+        key: K,
+        reply: SyncSender<Result<u64, AcquireError>>,
+    },
+    Stats {
+        reply: SyncSender<StatsSnapshot<K>>,
+    },
+}
+
+impl<C, S, K> RateLimiter<C, S, K>
+where
+    C: Clock + Send + 'static,
+    S: Algorithm<C> + Send + 'static,
+    K: Hash + Eq + Clone + Send + 'static,
+    C::Instant: Send,
+{
+    /// Moves the limiter onto a dedicated thread that serves commands sent
+    /// through the returned handle.
+    pub fn spawn_actor(self) -> ActorHandle<K> {
+        let (commands, receiver) = mpsc::channel();
+        thread::spawn(move || self.serve(receiver));
+        ActorHandle { commands }
+    }
+
+    fn serve(self, commands: Receiver<Command<K>>) {
+        // A failed reply means the caller stopped waiting for it.
+        for command in commands {
+            match command {
+                Command::Acquire { key, n, reply } => {
+                    let _ = reply.send(self.acquire_n_by_key(&key, n));
+                }
+                Command::InsertKey { key, quota } => self.insert_key(&key, quota),
+                Command::Query { key, reply } => {
+                    let _ = reply.send(self.check_key(&key));
+                }
+                Command::Stats { reply } => {
+                    let _ = reply.send(self.stats_snapshot());
+                }
+            }
+        }
+    }
+}
+
+impl<K> ActorHandle<K> {
+    /// Consumes a permit for `key`, like [`RateLimiter::acquire_by_key`].
+    pub fn acquire_by_key<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + ?Sized,
+    {
+        self.acquire_n_by_key(key, 1)
+    }
+
+    /// Consumes `n` permits for `key`, like
+    /// [`RateLimiter::acquire_n_by_key`].
+    pub fn acquire_n_by_key<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + ?Sized,
+    {
+        self.request(|reply| Command::Acquire {
+            key: key.to_owned(),
+            n,
+            reply,
+        })
+    }
+
+    /// Configures `key` without waiting for the actor to apply it. Commands
+    /// from one handle are applied in the order they were sent.
+    pub fn insert_key<Q>(&self, key: &Q, quota: impl Into<Quota>)
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + ?Sized,
+    {
+        self.send(Command::InsertKey {
+            key: key.to_owned(),
+            quota: quota.into(),
+        });
+    }
+
+    /// Reports whether a permit would be granted for `key`, like
+    /// [`RateLimiter::check_key`].
+    pub fn check_key<Q>(&self, key: &Q) -> Result<u64, AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: ToOwned<Owned = K> + ?Sized,
+    {
+        self.request(|reply| Command::Query {
+            key: key.to_owned(),
+            reply,
+        })
+    }
+
+    pub fn stats_snapshot(&self) -> StatsSnapshot<K> {
+        self.request(|reply| Command::Stats { reply })
+    }
+
+    fn request<T>(&self, command: impl FnOnce(SyncSender<T>) -> Command<K>) -> T {
+        let (reply, response) = mpsc::sync_channel(1);
+        self.send(command(reply));
+        response.recv().expect("rate limiter actor stopped")
+    }
+
+    /// The actor only stops once every handle is gone, or if it panicked.
+    fn send(&self, command: Command<K>) {
+        self.commands
+            .send(command)
+            .expect("rate limiter actor stopped");
+    }
+}
+
+impl<K> Clone for ActorHandle<K> {
+    fn clone(&self) -> Self {
+        Self {
+            commands: self.commands.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MonotonicClock;
+
+    #[test]
+    fn test_actor_serves_commands() {
+        let handle = RateLimiter::new(Quota::per_second(1), MonotonicClock).spawn_actor();
+        handle.insert_key("user", Quota::per_minute(100));
+        assert_eq!(handle.check_key("user"), Ok(100));
+
+        // 多个线程通过克隆的句柄发送命令，配额由同一个 actor 维护
+        let granted: usize = thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| {
+                    let handle = handle.clone();
+                    scope.spawn(move || {
+                        (0..50)
+                            .filter(|_| handle.acquire_by_key("user").is_ok())
+                            .count()
+                    })
+                })
+                .collect();
+            workers.into_iter().map(|w| w.join().unwrap()).sum()
+        });
+        assert_eq!(granted, 100);
+        assert_eq!(
+            handle.acquire_by_key("unknown"),
+            Err(AcquireError::UnknownKey)
+        );
+        assert_eq!(handle.stats_snapshot().keys.len(), 1);
+    }
+}
@@ -0,0 +1,168 @@
+use axum::{
+    Json, Router,
+    extract::{Path, State as Shared},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, handle::RateLimiterHandle, quota::Quota, state::State,
+};
+
+/// An axum [`Router`] administering a keyed limiter at runtime, to be
+/// nested into an application's own server rather than run on its own.
+///
+/// Relative to where it is nested, it serves:
+///
+/// - `GET /keys`: the [`StatsSnapshot`](crate::StatsSnapshot) of the base
+///   state and every tracked key, as JSON.
+/// - `GET /keys/{key}`: the [`KeyStats`](crate::KeyStats) of one key.
+/// - `PUT /keys/{key}`: configures the key with the [`Quota`] in the JSON
+///   body, e.g. `{"allowed": 100, "window": "1m"}`, as
+///   [`insert_key`](crate::RateLimiter::insert_key) does, and responds with
+///   its stats.
+/// - `DELETE /keys/{key}`: [removes](crate::RateLimiter::remove_key) the key.
+/// - `POST /reset/{key}`: [resets](crate::RateLimiter::reset_key) the key to
+///   its full quota.
+///
+/// Keys may contain `/`. Unknown keys get `404 Not Found`, and removals and
+/// resets an empty `204 No Content`. Requests aren't authenticated, so guard
+/// the router with the application's own middleware. Being a tower service,
+/// the router can also be served by hyper directly.
+///
+/// ```
+/// use axum::{Router, routing::get};
+/// use ratelimit::{MonotonicClock, Quota, RateLimiter, State, admin_router};
+///
+/// let limiter = RateLimiter::keyed(State::new(Quota::per_second(1000), MonotonicClock))
+///     .with_default_key_quota(Quota::per_second(10))
+///     .into_handle();
+/// let app: Router = Router::new()
+///     .route("/", get(|| async { "hello" }))
+///     .nest("/admin/ratelimit", admin_router(limiter));
+/// ```
+pub fn admin_router<C, S>(limiter: RateLimiterHandle<C, S, String>) -> Router
+where
+    C: Clock + Send + Sync + 'static,
+    C::Instant: Send + Sync,
+    S: Algorithm<C> + Send + Sync + 'static,
+{
+    Router::new()
+        .route("/keys", get(list_keys))
+        .route("/keys/{*key}", get(get_key).put(put_key).delete(delete_key))
+        .route("/reset/{*key}", post(reset_key))
+        .with_state(limiter)
+}
+
+type Limiter<C, S = State<C>> = Shared<RateLimiterHandle<C, S, String>>;
+
+async fn list_keys<C: Clock, S: Algorithm<C>>(Shared(limiter): Limiter<C, S>) -> Response {
+    Json(limiter.stats_snapshot()).into_response()
+}
+
+async fn get_key<C: Clock, S: Algorithm<C>>(
+    Shared(limiter): Limiter<C, S>,
+    Path(key): Path<String>,
+) -> Response {
+    match limiter.key_stats(&key) {
+        Some(stats) => Json(stats).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn put_key<C: Clock, S: Algorithm<C>>(
+    Shared(limiter): Limiter<C, S>,
+    Path(key): Path<String>,
+    Json(quota): Json<Quota>,
+) -> Response {
+    limiter.insert_key(&key, quota);
+    Json(limiter.key_stats(&key)).into_response()
+}
+
+async fn delete_key<C: Clock, S: Algorithm<C>>(
+    Shared(limiter): Limiter<C, S>,
+    Path(key): Path<String>,
+) -> StatusCode {
+    found(limiter.remove_key(&key))
+}
+
+async fn reset_key<C: Clock, S: Algorithm<C>>(
+    Shared(limiter): Limiter<C, S>,
+    Path(key): Path<String>,
+) -> StatusCode {
+    found(limiter.reset_key(&key))
+}
+
+fn found(tracked: bool) -> StatusCode {
+    if tracked {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::{Body, to_bytes};
+    use http::Request;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, limiter::RateLimiter};
+
+    async fn send(app: &Router, method: &str, uri: &str, body: &str) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_owned()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_admin_router() {
+        let limiter = RateLimiter::keyed(State::new(
+            Quota::per_second(10),
+            FakeRelativeClock::default(),
+        ))
+        .with_default_key_quota(Quota::per_second(2))
+        .into_handle();
+        limiter.acquire_by_key("tenant/a").unwrap();
+        let app = Router::new().nest("/admin", admin_router(limiter.clone()));
+
+        let (status, body) = send(&app, "GET", "/admin/keys", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["base_remaining"], 10);
+        assert_eq!(body["keys"][0]["key"], "tenant/a");
+
+        let (status, body) = send(&app, "GET", "/admin/keys/tenant/a", "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["remaining"], 1);
+        let (status, _) = send(&app, "GET", "/admin/keys/tenant/b", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // 重置后配额回满
+        let (status, _) = send(&app, "POST", "/admin/reset/tenant/a", "").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(limiter.check_key("tenant/a"), Ok(2));
+
+        let quota = json!({"allowed": 5, "window": "1m"}).to_string();
+        let (status, body) = send(&app, "PUT", "/admin/keys/tenant/b", &quota).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["allowed"], 5);
+        assert_eq!(limiter.key_quota("tenant/b"), Some(Quota::per_minute(5)));
+        let (status, _) = send(&app, "PUT", "/admin/keys/tenant/b", "{}").await;
+        assert!(status.is_client_error());
+
+        let (status, _) = send(&app, "DELETE", "/admin/keys/tenant/b", "").await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(&app, "DELETE", "/admin/keys/tenant/b", "").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use crate::{
+    clock::{Clock, Reference},
+    error::InsufficientCapacity,
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+};
+
+/// A rate limiting algorithm.
+///
+/// Implementations can be used standalone or as the per-key state of a
+/// [`RateLimiter`](crate::RateLimiter). Methods taking `now` evaluate the
+/// state at that instant instead of reading the clock, so callers can apply
+/// one consistent reading across many states.
+pub trait Algorithm<C: Clock>: Sized {
+    /// Creates a state enforcing `quota`, reading time from `clock`.
+    fn from_quota(quota: Quota, clock: C) -> Self;
+
+    /// Creates a state with the same configuration and clock but no history.
+    fn fresh(&self) -> Self;
+
+    fn clock(&self) -> &C;
+
+    /// The quota the state was configured with.
+    fn quota(&self) -> Quota;
+
+    /// The maximum number of permits the state can grant without waiting.
+    fn capacity(&self) -> u64;
+
+    /// Consumes a permit at `now`, or reports when one may become available.
+    fn try_acquire_at(&mut self, now: C::Instant) -> Result<(), NotUntil<C::Instant>>;
+
+    /// Consumes `n` permits at `now` all at once, or reports when all `n`
+    /// may become available; nothing is consumed on denial. Fails with
+    /// [`InsufficientCapacity`] if `n` exceeds the [`capacity`](Self::capacity).
+    fn try_acquire_n_at(
+        &mut self,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity>;
+
+    /// Reports whether a permit would be granted at `now` without consuming
+    /// it: the number of permits remaining, or when one may become available.
+    fn check_at(&self, now: C::Instant) -> Result<u64, NotUntil<C::Instant>>;
+
+    /// The number of permits that could be granted at `now`.
+    fn remaining_at(&self, now: C::Instant) -> u64;
+
+    /// How long after `now` the state will have fully recovered its capacity.
+    fn reset_after_at(&self, now: C::Instant) -> Nanos;
+
+    fn acquire(&mut self) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.clock().now();
+        self.try_acquire_at(now)
+    }
+
+    fn acquire_n(
+        &mut self,
+        n: u64,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        let now = self.clock().now();
+        self.try_acquire_n_at(n, now)
+    }
+
+    /// Blocks until a permit is granted, sleeping on the state's clock for
+    /// as long as each denial asks.
+    ///
+    /// With `max_wait`, gives up and returns the denial as soon as the permit
+    /// could not be granted within `max_wait` of the call, without sleeping
+    /// for the rest of it. A state with no capacity, or a denial that
+    /// [overflowed](NotUntil::overflowed), is never waited on.
+    fn acquire_wait(&mut self, max_wait: Option<Duration>) -> Result<(), NotUntil<C::Instant>> {
+        let start = self.clock().now();
+        loop {
+            let now = self.clock().now();
+            let not_until = match self.try_acquire_at(now) {
+                Ok(()) => return Ok(()),
+                Err(not_until) => not_until,
+            };
+            let wait = not_until.wait_time_from(now);
+            let waited = Duration::from(now.duration_since(start));
+            let never = self.capacity() == 0 || not_until.overflowed();
+            if never || max_wait.is_some_and(|max| waited + wait > max) {
+                return Err(not_until);
+            }
+            self.clock().sleep(wait);
+        }
+    }
+
+    fn check(&self) -> Result<u64, NotUntil<C::Instant>> {
+        self.check_at(self.clock().now())
+    }
+
+    /// The number of permits that could be granted now.
+    fn remaining(&self) -> u64 {
+        self.remaining_at(self.clock().now())
+    }
+
+    /// When the state will have fully recovered its capacity, which is now
+    /// for a state that hasn't been used lately.
+    fn resets_at(&self) -> C::Instant {
+        let now = self.clock().now();
+        now + self.reset_after_at(now)
+    }
+
+    /// Whether the state is indistinguishable from a [`fresh`](Self::fresh)
+    /// one at `now`.
+    fn is_idle_at(&self, now: C::Instant) -> bool {
+        self.reset_after_at(now) == Nanos::ZERO
+    }
+}
@@ -0,0 +1,299 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, Reference},
+    error::InsufficientCapacity,
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+};
+
+/// A [`GcraState`](crate::GcraState) whose theoretical arrival time lives in
+/// a single [`AtomicU64`], so permits are taken through `&self` with a
+/// compare-and-swap loop instead of a lock.
+///
+/// An uncontended acquire costs one load and one compare-and-swap, and the
+/// state can be shared between threads as is, e.g. in an `Arc` or a
+/// `static`. It admits exactly what a [`GcraState`](crate::GcraState) with
+/// the same quota would, as long as its TAT stays within about 584 years of
+/// its creation; beyond that the TAT saturates and the state denies until
+/// time catches up.
+#[derive(Debug)]
+pub struct AtomicGcraState<C: Clock> {
+    quota: Quota,
+    emission_interval: Nanos,
+    start: C::Instant,
+    /// Nanoseconds since `start`.
+    tat: AtomicU64,
+    clock: C,
+}
+
+impl<C: Clock> AtomicGcraState<C> {
+    /// # Panics
+    ///
+    /// Panics if `emission_interval` is zero.
+    pub fn new(burst: u64, emission_interval: Nanos, clock: C) -> Self {
+        assert!(
+            emission_interval > Nanos::ZERO,
+            "emission interval must be non-zero"
+        );
+        Self::from_quota(Quota::new(1, emission_interval).allow_burst(burst), clock)
+    }
+
+    /// Admits a permit, or on denial reports when the request would conform.
+    pub fn acquire(&self) -> Result<(), NotUntil<C::Instant>> {
+        self.take_at(1, self.clock.now())
+    }
+
+    /// Admits `n` permits at once, or none of them. On denial reports when
+    /// all `n` permits would conform.
+    pub fn acquire_n(
+        &self,
+        n: u64,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        if n > self.quota.burst() {
+            return Err(InsufficientCapacity(self.quota.burst()));
+        }
+        Ok(self.take_at(n, self.clock.now()))
+    }
+
+    /// Reports whether a permit would be admitted without taking it.
+    pub fn check(&self) -> Result<u64, NotUntil<C::Instant>> {
+        self.check_at(self.clock.now())
+    }
+
+    /// Takes as many of `n` permits as are available, at least one, and
+    /// returns how many were taken and the TAT that taking them produced.
+    pub(crate) fn claim(&self, n: u64) -> Result<(u64, u64), NotUntil<C::Instant>> {
+        let now = self.clock.now();
+        let n = n.min(self.remaining_at(now)).max(1);
+        match self.take_tat_at(n, now) {
+            Ok(tat) => Ok((n, tat)),
+            // Raced with another claim; settle for a single permit.
+            Err(_) if n > 1 => self.take_tat_at(1, now).map(|tat| (1, tat)),
+            Err(not_until) => Err(not_until),
+        }
+    }
+
+    /// Gives back `n` permits of a claim that produced `claim_tat` but were
+    /// never used. Only the part of the claim still ahead of the clock is
+    /// refunded, as the time slots already passed would have been
+    /// replenished anyway.
+    pub(crate) fn release(&self, n: u64, claim_tat: u64) {
+        let unexpired = u128::from(claim_tat).saturating_sub(self.offset(self.clock.now()));
+        let refund = self.emission_interval.widening_mul(n).min(unexpired);
+        let refund = Nanos::saturating_from_wide(refund).as_u64();
+        let _ = self
+            .tat
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |tat| {
+                Some(tat.saturating_sub(refund))
+            });
+    }
+
+    fn tolerance(&self) -> u128 {
+        self.emission_interval
+            .widening_mul(self.quota.burst().saturating_sub(1))
+    }
+
+    fn offset(&self, now: C::Instant) -> u128 {
+        u128::from(now.duration_since(self.start).as_u64())
+    }
+
+    fn tat(&self) -> u64 {
+        self.tat.load(Ordering::Acquire)
+    }
+
+    fn remaining_with(&self, tat: u64, now_offset: u128) -> u64 {
+        let interval = u128::from(self.emission_interval.as_u64());
+        let backlog = u128::from(tat).saturating_sub(now_offset);
+        let remaining = (self.tolerance() + interval).saturating_sub(backlog) / interval;
+        remaining.try_into().unwrap_or(u64::MAX)
+    }
+
+    fn take_at(&self, n: u64, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.take_tat_at(n, now).map(|_| ())
+    }
+
+    /// Admits `n` permits if the TAT after adding them stays within the
+    /// tolerance, retrying whenever another thread moved the TAT first, and
+    /// returns the TAT it stored.
+    fn take_tat_at(&self, n: u64, now: C::Instant) -> Result<u64, NotUntil<C::Instant>> {
+        if n == 0 {
+            return Ok(self.tat());
+        }
+        if self.quota.burst() == 0 {
+            return Err(NotUntil::after(now, self.emission_interval, self.quota, 0));
+        }
+
+        let now_offset = self.offset(now);
+        let increment = self.emission_interval.widening_mul(n - 1);
+        let mut tat = self.tat();
+        loop {
+            let earliest = (u128::from(tat) + increment).saturating_sub(self.tolerance());
+            if now_offset < earliest {
+                let remaining = self.remaining_with(tat, now_offset);
+                return Err(NotUntil::after_wide(
+                    self.start, earliest, self.quota, remaining,
+                ));
+            }
+            let next = u128::from(tat).max(now_offset)
+                + increment
+                + u128::from(self.emission_interval.as_u64());
+            let next = Nanos::saturating_from_wide(next).as_u64();
+            match self
+                .tat
+                .compare_exchange_weak(tat, next, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Ok(next),
+                Err(actual) => tat = actual,
+            }
+        }
+    }
+}
+
+impl<C: Clock> Algorithm<C> for AtomicGcraState<C> {
+    fn from_quota(quota: Quota, clock: C) -> Self {
+        Self {
+            quota,
+            emission_interval: quota.replenish_interval(),
+            start: clock.now(),
+            tat: AtomicU64::new(0),
+            clock,
+        }
+    }
+
+    fn fresh(&self) -> Self {
+        Self::from_quota(self.quota, self.clock.clone())
+    }
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    fn capacity(&self) -> u64 {
+        self.quota.burst()
+    }
+
+    fn try_acquire_at(&mut self, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.take_at(1, now)
+    }
+
+    fn try_acquire_n_at(
+        &mut self,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        if n > self.quota.burst() {
+            return Err(InsufficientCapacity(self.quota.burst()));
+        }
+        Ok(self.take_at(n, now))
+    }
+
+    fn check_at(&self, now: C::Instant) -> Result<u64, NotUntil<C::Instant>> {
+        if self.quota.burst() == 0 {
+            return Err(NotUntil::after(now, self.emission_interval, self.quota, 0));
+        }
+        let tat = self.tat();
+        let earliest = u128::from(tat).saturating_sub(self.tolerance());
+        if self.offset(now) < earliest {
+            return Err(NotUntil::after_wide(self.start, earliest, self.quota, 0));
+        }
+        Ok(self.remaining_with(tat, self.offset(now)))
+    }
+
+    fn remaining_at(&self, now: C::Instant) -> u64 {
+        if self.quota.burst() == 0 {
+            return 0;
+        }
+        self.remaining_with(self.tat(), self.offset(now))
+    }
+
+    fn reset_after_at(&self, now: C::Instant) -> Nanos {
+        Nanos::saturating_from_wide(u128::from(self.tat()).saturating_sub(self.offset(now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        clock::{FakeRelativeClock, MonotonicClock},
+        gcra::GcraState,
+        scenario::{self, Op, checked_acquire, op_strategy},
+    };
+
+    #[test]
+    fn test_atomic_gcra_burst_then_spacing() {
+        let clock = FakeRelativeClock::default();
+        // 突发 3 个，之后每 100ms 放行 1 个，全部通过 &self 调用
+        let gcra = AtomicGcraState::new(3, Nanos::new(100_000_000), clock.clone());
+
+        for _ in 0..3 {
+            assert!(gcra.acquire().is_ok());
+        }
+        let not_until = gcra.acquire().unwrap_err();
+        assert_eq!(
+            not_until.wait_time_from(clock.now()),
+            Duration::from_millis(100)
+        );
+        assert_eq!(gcra.acquire_n(4), Err(InsufficientCapacity(3)));
+
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(gcra.check(), Ok(2));
+        assert_eq!(gcra.acquire_n(2), Ok(Ok(())));
+        assert!(gcra.acquire().is_err());
+    }
+
+    #[test]
+    fn test_atomic_gcra_shared_between_threads() {
+        let gcra = AtomicGcraState::new(1_000, Nanos::new(60_000_000_000), MonotonicClock);
+
+        // 并发竞争时不会超发
+        let granted: usize = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| (0..500).filter(|_| gcra.acquire().is_ok()).count()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(granted, 1_000);
+    }
+
+    proptest! {
+        #![proptest_config(scenario::config())]
+
+        #[test]
+        fn prop_atomic_gcra_matches_gcra(
+            burst in 0..10u64,
+            interval_ms in 1..500u64,
+            ops in prop::collection::vec(op_strategy(), 0..500),
+        ) {
+            let clock = FakeRelativeClock::default();
+            let interval = Nanos::new(interval_ms * 1_000_000);
+            let mut atomic = AtomicGcraState::new(burst, interval, clock.clone());
+            let mut gcra = GcraState::new(burst, interval, clock.clone());
+
+            // 与加锁版本逐个请求的结果完全一致
+            for op in &ops {
+                match op {
+                    Op::Advance(ms) => clock.advance(Duration::from_millis(*ms)),
+                    Op::Acquire => {
+                        prop_assert_eq!(checked_acquire(&mut atomic), checked_acquire(&mut gcra));
+                        prop_assert_eq!(
+                            atomic.reset_after_at(clock.now()),
+                            gcra.reset_after_at(clock.now())
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
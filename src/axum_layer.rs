@@ -0,0 +1,431 @@
+use std::{
+    convert::Infallible,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{body::Body, extract::MatchedPath, response::Response};
+use http::{Request, StatusCode};
+use pin_project_lite::pin_project;
+use tower::{Layer, Service};
+
+use crate::{
+    algorithm::Algorithm,
+    clock::Clock,
+    error::AcquireError,
+    extract::KeyExtractor,
+    handle::RateLimiterHandle,
+    headers::{HeaderStyle, RateLimitHeaders},
+    limiter::RateLimiter,
+    state::State,
+};
+
+/// An axum middleware that limits each request under the key derived from it
+/// by a [`KeyExtractor`].
+///
+/// Requests without a key, and requests whose key the limiter doesn't know
+/// because it has no [default key
+/// quota](crate::RateLimiter::with_default_key_quota), are limited on the
+/// base state. Denied requests get an empty `429 Too Many Requests`
+/// response, or `503 Service Unavailable` while the limiter is disabled,
+/// unless [`with_rejection`](Self::with_rejection) builds them another. The
+/// [`RateLimitHeaders`] are added in the [chosen
+/// style](Self::with_header_style) either way.
+///
+/// Add it with `Router::route_layer` to key on route parameters through
+/// [`PathParam`].
+///
+/// ```
+/// use axum::{Router, routing::get};
+/// use ratelimit::{AxumRateLimitLayer, MonotonicClock, PeerIp, Quota, RateLimiter, State};
+///
+/// let limiter = RateLimiter::keyed(State::new(Quota::per_second(1000), MonotonicClock))
+///     .with_default_key_quota(Quota::per_second(10))
+///     .into_handle();
+/// let app: Router = Router::new()
+///     .route("/", get(|| async { "hello" }))
+///     .layer(AxumRateLimitLayer::new(limiter, PeerIp));
+/// ```
+pub struct AxumRateLimitLayer<X, C, S = State<C>>
+where
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    limiter: RateLimiterHandle<C, S, X::Key>,
+    extractor: X,
+    header_style: HeaderStyle,
+    rejection: Option<RejectionFn>,
+}
+
+type RejectionFn = Arc<dyn Fn(&AcquireError) -> Response + Send + Sync>;
+
+impl<X: KeyExtractor, C: Clock, S: Algorithm<C>> AxumRateLimitLayer<X, C, S> {
+    pub fn new(limiter: RateLimiterHandle<C, S, X::Key>, extractor: X) -> Self {
+        Self {
+            limiter,
+            extractor,
+            header_style: HeaderStyle::default(),
+            rejection: None,
+        }
+    }
+
+    /// Builds the responses of denied requests with `rejection`, e.g. to
+    /// match an API's error envelope. Headers it sets take precedence over
+    /// the rate limiting headers.
+    ///
+    /// ```
+    /// use axum::response::{IntoResponse, Response};
+    /// use http::{StatusCode, header::CONTENT_TYPE};
+    /// # use ratelimit::{AxumRateLimitLayer, MonotonicClock, PeerIp, Quota, RateLimiter, State};
+    /// # let limiter = RateLimiter::keyed(State::new(Quota::per_second(10), MonotonicClock))
+    /// #     .into_handle();
+    ///
+    /// let layer = AxumRateLimitLayer::new(limiter, PeerIp).with_rejection(|err| {
+    ///     let body = format!(r#"{{"error":{{"message":"{err}"}}}}"#);
+    ///     (StatusCode::TOO_MANY_REQUESTS, [(CONTENT_TYPE, "application/json")], body)
+    ///         .into_response()
+    /// });
+    /// # drop(layer);
+    /// ```
+    pub fn with_rejection<F>(mut self, rejection: F) -> Self
+    where
+        F: Fn(&AcquireError) -> Response + Send + Sync + 'static,
+    {
+        self.rejection = Some(Arc::new(rejection));
+        self
+    }
+
+    /// Names the rate limiting headers of denied requests' responses in
+    /// `style`.
+    pub fn with_header_style(mut self, style: HeaderStyle) -> Self {
+        self.header_style = style;
+        self
+    }
+
+    /// Consumes a permit for `request`, or reports why it was denied.
+    fn admit<B>(&self, request: &Request<B>) -> Result<(), AcquireError> {
+        let key = self.extractor.extract(request);
+        self.limiter.acquire_by_key_or_base(key.as_ref())
+    }
+
+    /// The response denying a request with `err`.
+    fn rejection(&self, err: &AcquireError) -> Response {
+        let mut response = match &self.rejection {
+            Some(rejection) => rejection(err),
+            None => {
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = match err {
+                    AcquireError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::TOO_MANY_REQUESTS,
+                };
+                response
+            }
+        };
+        if let Some(headers) = RateLimitHeaders::denied(err) {
+            headers.write_to(response.headers_mut(), self.header_style);
+        }
+        response
+    }
+}
+
+impl<X, C, S> Clone for AxumRateLimitLayer<X, C, S>
+where
+    X: KeyExtractor + Clone,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            limiter: self.limiter.clone(),
+            extractor: self.extractor.clone(),
+            header_style: self.header_style,
+            rejection: self.rejection.clone(),
+        }
+    }
+}
+
+impl<X, C, S> fmt::Debug for AxumRateLimitLayer<X, C, S>
+where
+    X: KeyExtractor + fmt::Debug,
+    C: Clock,
+    S: Algorithm<C>,
+    RateLimiter<C, S, X::Key>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AxumRateLimitLayer")
+            .field("limiter", &self.limiter)
+            .field("extractor", &self.extractor)
+            .field("header_style", &self.header_style)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Svc, X, C, S> Layer<Svc> for AxumRateLimitLayer<X, C, S>
+where
+    X: KeyExtractor + Clone,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    type Service = AxumRateLimitService<Svc, X, C, S>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        AxumRateLimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service limited by an [`AxumRateLimitLayer`].
+pub struct AxumRateLimitService<Svc, X, C, S = State<C>>
+where
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    inner: Svc,
+    layer: AxumRateLimitLayer<X, C, S>,
+}
+
+impl<Svc, X, C, S> Clone for AxumRateLimitService<Svc, X, C, S>
+where
+    Svc: Clone,
+    X: KeyExtractor + Clone,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<Svc, X, C, S> fmt::Debug for AxumRateLimitService<Svc, X, C, S>
+where
+    Svc: fmt::Debug,
+    X: KeyExtractor + fmt::Debug,
+    C: Clock,
+    S: Algorithm<C>,
+    RateLimiter<C, S, X::Key>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AxumRateLimitService")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<Svc, B, X, C, S> Service<Request<B>> for AxumRateLimitService<Svc, X, C, S>
+where
+    Svc: Service<Request<B>, Response = Response, Error = Infallible>,
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = AxumResponseFuture<Svc::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        match self.layer.admit(&request) {
+            Ok(()) => AxumResponseFuture::Inner {
+                future: self.inner.call(request),
+            },
+            Err(err) => AxumResponseFuture::Rejected {
+                response: Some(self.layer.rejection(&err)),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of an [`AxumRateLimitService`].
+    #[project = AxumResponseFutureProj]
+    pub enum AxumResponseFuture<F> {
+        Inner {
+            #[pin]
+            future: F,
+        },
+        Rejected {
+            response: Option<Response>,
+        },
+    }
+}
+
+impl<F> Future for AxumResponseFuture<F>
+where
+    F: Future<Output = Result<Response, Infallible>>,
+{
+    type Output = Result<Response, Infallible>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            AxumResponseFutureProj::Inner { future } => future.poll(cx),
+            AxumResponseFutureProj::Rejected { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+/// Keys requests by a parameter of the matched route, e.g. `id` in
+/// `/users/{id}`.
+///
+/// The route is only known after routing, so the middleware must be added
+/// with `Router::route_layer`. The parameter is taken verbatim from the
+/// path, without percent-decoding; catch-all parameters are not supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathParam {
+    segment: String,
+}
+
+impl PathParam {
+    pub fn new(name: &str) -> Self {
+        Self {
+            segment: format!("{{{name}}}"),
+        }
+    }
+}
+
+impl KeyExtractor for PathParam {
+    type Key = String;
+
+    fn extract<B>(&self, request: &Request<B>) -> Option<String> {
+        let route = request.extensions().get::<MatchedPath>()?;
+        let index = route.as_str().split('/').position(|s| s == self.segment)?;
+        let value = request.uri().path().split('/').nth(index)?;
+        Some(value.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use axum::{Router, extract::ConnectInfo, response::IntoResponse, routing::get};
+    use http::header::{CONTENT_TYPE, RETRY_AFTER};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, extract::PeerIp, quota::Quota};
+
+    fn get_from(uri: &str, peer: &str) -> Request<Body> {
+        let mut request = Request::get(uri).body(Body::empty()).unwrap();
+        let peer: SocketAddr = peer.parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer));
+        request
+    }
+
+    #[tokio::test]
+    async fn test_axum_layer_keys_by_peer_ip() {
+        let limiter = RateLimiter::keyed(State::new(
+            Quota::per_second(100),
+            FakeRelativeClock::default(),
+        ))
+        .with_default_key_quota(Quota::per_second(1))
+        .into_handle();
+        let app = Router::new()
+            .route("/", get(|| async { "hello" }))
+            .layer(AxumRateLimitLayer::new(limiter, PeerIp));
+
+        let response = app
+            .clone()
+            .oneshot(get_from("/", "10.0.0.1:1000"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app
+            .clone()
+            .oneshot(get_from("/", "10.0.0.1:2000"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "1");
+        assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+
+        // 不同的 IP 有各自的配额
+        let response = app.oneshot(get_from("/", "10.0.0.2:1000")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_axum_layer_keys_by_path_param() {
+        let limiter = RateLimiter::new(Quota::per_second(100), FakeRelativeClock::default())
+            .with_default_key_quota(Quota::per_second(1))
+            .into_handle();
+        let app = Router::new()
+            .route("/users/{id}/posts", get(|| async { "posts" }))
+            .route_layer(AxumRateLimitLayer::new(limiter, PathParam::new("id")));
+
+        let status = |uri| {
+            let app = app.clone();
+            async move {
+                app.oneshot(get_from(uri, "10.0.0.1:1000"))
+                    .await
+                    .unwrap()
+                    .status()
+            }
+        };
+        assert_eq!(status("/users/1/posts").await, StatusCode::OK);
+        assert_eq!(
+            status("/users/1/posts").await,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(status("/users/2/posts").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_axum_layer_without_key_uses_base_state() {
+        let limiter = RateLimiter::keyed(State::new(
+            Quota::per_second(1),
+            FakeRelativeClock::default(),
+        ))
+        .into_handle();
+        let app = Router::new()
+            .route("/", get(|| async { "hello" }))
+            .layer(AxumRateLimitLayer::new(limiter, PeerIp));
+
+        let request = || Request::get("/").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn test_axum_layer_with_rejection() {
+        let limiter = RateLimiter::keyed(State::new(
+            Quota::per_second(1),
+            FakeRelativeClock::default(),
+        ))
+        .into_handle();
+        let layer = AxumRateLimitLayer::new(limiter, PeerIp).with_rejection(|err| {
+            let body = format!(r#"{{"error":{{"code":429,"message":"{err}"}}}}"#);
+            let headers = [(CONTENT_TYPE, "application/json"), (RETRY_AFTER, "30")];
+            (StatusCode::TOO_MANY_REQUESTS, headers, body).into_response()
+        });
+        let app = Router::new()
+            .route("/", get(|| async { "hello" }))
+            .layer(layer);
+
+        let request = || Request::get("/").body(Body::empty()).unwrap();
+        app.clone().oneshot(request()).await.unwrap();
+        let response = app.oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        // 回调设置的头优先，其余限流头照常补上
+        assert_eq!(response.headers()[RETRY_AFTER], "30");
+        assert_eq!(response.headers()["x-ratelimit-remaining"], "0");
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{atomic_gcra::AtomicGcraState, clock::Clock, not_until::NotUntil};
+
+/// Hands out permits claimed from a shared [`AtomicGcraState`] in batches,
+/// so a tight loop touches the shared atomic once per batch rather than
+/// once per permit.
+///
+/// Created by [`AtomicGcraState::batched`]. Permits claimed but not handed
+/// out are returned to the state by [`flush`](Self::flush) or on drop. A
+/// batch is only as large as the state can grant at once, so batching never
+/// takes more than acquiring one at a time would, only earlier.
+#[derive(Debug)]
+pub struct BatchedAcquirer<'a, C: Clock> {
+    state: &'a AtomicGcraState<C>,
+    batch: u64,
+    local: u64,
+    /// The TAT left by the last claim, bounding what its unused permits may
+    /// give back.
+    claim_tat: u64,
+}
+
+impl<C: Clock> AtomicGcraState<C> {
+    /// Creates an acquirer claiming up to `batch` permits at a time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `batch` is zero.
+    pub fn batched(&self, batch: u64) -> BatchedAcquirer<'_, C> {
+        assert!(batch > 0, "batch size must be non-zero");
+        BatchedAcquirer {
+            state: self,
+            batch,
+            local: 0,
+            claim_tat: 0,
+        }
+    }
+}
+
+impl<C: Clock> BatchedAcquirer<'_, C> {
+    /// Hands out a locally buffered permit, claiming a new batch from the
+    /// shared state once the buffer is empty.
+    pub fn acquire(&mut self) -> Result<(), NotUntil<C::Instant>> {
+        if self.local == 0 {
+            (self.local, self.claim_tat) = self.state.claim(self.batch)?;
+        }
+        self.local -= 1;
+        Ok(())
+    }
+
+    /// The permits claimed but not yet handed out.
+    pub fn buffered(&self) -> u64 {
+        self.local
+    }
+
+    /// Returns the buffered permits to the shared state.
+    pub fn flush(&mut self) {
+        self.state
+            .release(std::mem::take(&mut self.local), self.claim_tat);
+    }
+}
+
+impl<C: Clock> Drop for BatchedAcquirer<'_, C> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{algorithm::Algorithm, clock::FakeRelativeClock, nanos::Nanos};
+
+    #[test]
+    fn test_batched_acquirer_buffers_and_returns_permits() {
+        let clock = FakeRelativeClock::default();
+        let state = AtomicGcraState::new(10, Nanos::new(100_000_000), clock.clone());
+
+        let mut batched = state.batched(4);
+        assert!(batched.acquire().is_ok());
+        // 一次原子操作领取了 4 个，本地还剩 3 个
+        assert_eq!(batched.buffered(), 3);
+        assert_eq!(state.remaining_at(clock.now()), 6);
+        for _ in 0..3 {
+            assert!(batched.acquire().is_ok());
+        }
+        assert!(batched.acquire().is_ok());
+        assert_eq!(state.remaining_at(clock.now()), 2);
+
+        // 共享状态不足一整批时只领取剩余的
+        drop(batched);
+        assert_eq!(state.remaining_at(clock.now()), 5);
+        let mut batched = state.batched(8);
+        assert!(batched.acquire().is_ok());
+        assert_eq!(batched.buffered(), 4);
+        for _ in 0..4 {
+            assert!(batched.acquire().is_ok());
+        }
+        let not_until = batched.acquire().unwrap_err();
+        assert_eq!(
+            not_until.wait_time_from(clock.now()),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[test]
+    fn test_batched_acquirer_flush_after_time_passed() {
+        let clock = FakeRelativeClock::default();
+        let state = AtomicGcraState::new(5, Nanos::new(100_000_000), clock.clone());
+
+        let mut batched = state.batched(5);
+        assert!(batched.acquire().is_ok());
+        assert_eq!(batched.buffered(), 4);
+
+        // 批次领取的时间段已经过去，归还时不能再多放行
+        clock.advance(Duration::from_millis(500));
+        for _ in 0..5 {
+            assert!(state.acquire().is_ok());
+        }
+        batched.flush();
+        assert!(state.acquire().is_err());
+
+        // 只过去一部分时，只归还尚未到期的部分
+        clock.advance(Duration::from_millis(500));
+        let mut batched = state.batched(5);
+        assert!(batched.acquire().is_ok());
+        clock.advance(Duration::from_millis(200));
+        drop(batched);
+        assert_eq!(state.remaining_at(clock.now()), 5);
+        for _ in 0..5 {
+            assert!(state.acquire().is_ok());
+        }
+        assert!(state.acquire().is_err());
+    }
+}
@@ -1,26 +1,104 @@
-use std::{fmt::Debug, ops::Add, sync::{Arc, atomic::{AtomicU64, Ordering}}, time::{Duration, Instant}};
+use std::{
+    fmt::Debug,
+    ops::Add,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 
 use super::nanos::Nanos;
 
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for std::time::Instant {}
+    impl Sealed for crate::nanos::Nanos {}
+    #[cfg(feature = "quanta")]
+    impl Sealed for quanta::Instant {}
+    #[cfg(feature = "tokio")]
+    impl Sealed for tokio::time::Instant {}
+}
+
+/// A point in time as measured by a [`Clock`].
+///
+/// This trait is sealed: the algorithms rely on the exact arithmetic of the
+/// instant types, so it is only implemented for the instants of this
+/// crate's clocks.
 pub trait Reference:
-    Sized + Add<Nanos, Output = Self> + PartialEq + Eq + Ord + Copy + Clone + Send + Sync + Debug
+    sealed::Sealed
+    + Sized
+    + Add<Nanos, Output = Self>
+    + PartialEq
+    + Eq
+    + Ord
+    + Copy
+    + Clone
+    + Send
+    + Sync
+    + Debug
 {
     fn duration_since(&self, earlier: Self) -> Nanos;
     fn saturating_sub(&self, duration: Nanos) -> Self;
+
+    /// The instant `duration` later, or `None` if the instant type can't
+    /// represent it.
+    fn checked_add(&self, duration: Nanos) -> Option<Self>;
+
+    /// The time elapsed since `earlier`, or `None` if `earlier` is later.
+    fn checked_duration_since(&self, earlier: Self) -> Option<Nanos>;
+
+    /// The start of the `period`-long window containing `self`, with windows
+    /// counted from `origin`. An instant before `origin` falls in the first
+    /// window.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    fn window_start(&self, origin: Self, period: Nanos) -> Self {
+        origin + self.duration_since(origin).round_down_to(period)
+    }
 }
 
 pub trait Clock: Clone {
     type Instant: Reference;
     fn now(&self) -> Self::Instant;
+
+    /// Blocks the current thread for `duration` as measured by this clock.
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
 }
 
+/// A [`Clock`] whose instants can be mapped to wall-clock time, e.g. to
+/// report when a denied request may be retried as an HTTP `Retry-After`
+/// date or a reset timestamp.
+///
+/// Wall clocks map their instants exactly. Monotonic clocks map them
+/// relative to the current [`SystemTime`], so the result shifts with any
+/// adjustment of the wall clock.
+pub trait ReasonablyRealtime: Clock {
+    /// The wall-clock time at which this clock will read, or read, `instant`.
+    fn to_system_time(&self, instant: Self::Instant) -> SystemTime;
+}
+
+/// Maps `instant` of a monotonic clock currently reading `now` to the
+/// wall-clock time the same distance away from the current one.
+fn relative_system_time<P: Reference>(instant: P, now: P) -> SystemTime {
+    let wall_now = SystemTime::now();
+    match instant.checked_duration_since(now) {
+        Some(ahead) => wall_now + Duration::from(ahead),
+        None => wall_now - Duration::from(now.duration_since(instant)),
+    }
+}
 
-#[derive(Clone,Debug,Default)]
+#[derive(Clone, Debug, Default)]
 pub struct MonotonicClock;
 
 impl Reference for Instant {
     fn duration_since(&self, earlier: Self) -> Nanos {
-        if earlier<*self{
+        if earlier < *self {
             (*self - earlier).into()
         } else {
             Nanos::from(Duration::new(0, 0))
@@ -30,6 +108,14 @@ impl Reference for Instant {
     fn saturating_sub(&self, duration: Nanos) -> Self {
         self.checked_sub(duration.into()).unwrap_or(*self)
     }
+
+    fn checked_add(&self, duration: Nanos) -> Option<Self> {
+        Instant::checked_add(self, duration.into())
+    }
+
+    fn checked_duration_since(&self, earlier: Self) -> Option<Nanos> {
+        Instant::checked_duration_since(self, earlier).map(Nanos::from)
+    }
 }
 
 impl Add<Nanos> for Instant {
@@ -40,7 +126,7 @@ impl Add<Nanos> for Instant {
         self + other
     }
 }
-    
+
 impl Clock for MonotonicClock {
     type Instant = Instant;
 
@@ -48,36 +134,493 @@ impl Clock for MonotonicClock {
         Instant::now()
     }
 }
-    
 
-#[derive(Debug,Clone,Default)]
-pub struct FakeRelativeClock{
-    now:Arc<AtomicU64>,
+impl ReasonablyRealtime for MonotonicClock {
+    fn to_system_time(&self, instant: Self::Instant) -> SystemTime {
+        relative_system_time(instant, self.now())
+    }
+}
+
+/// A wall clock reading [`SystemTime`], with instants measured as [`Nanos`]
+/// since the UNIX epoch.
+///
+/// Unlike [`MonotonicClock`]'s instants, these mean the same thing in every
+/// process and after a restart, so states can be persisted or shared and
+/// windows aligned with calendar time. The wall clock may be adjusted
+/// backwards, e.g. by NTP; a state then sees no time pass until the clock
+/// catches up again. Readings before the epoch are clamped to it.
+#[derive(Clone, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    type Instant = Nanos;
+
+    fn now(&self) -> Self::Instant {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .into()
+    }
+}
+
+impl ReasonablyRealtime for SystemClock {
+    fn to_system_time(&self, instant: Self::Instant) -> SystemTime {
+        UNIX_EPOCH + Duration::from(instant)
+    }
+}
+
+/// A monotonic clock reading the CPU's timestamp counter through
+/// [`quanta`], which is much cheaper than [`Instant::now`] where a stable
+/// counter is available, and falls back to the OS clock where not.
+///
+/// Readings are calibrated against the OS clock when the first
+/// `QuantaClock` is created, so they can drift slightly from it.
+#[cfg(feature = "quanta")]
+#[derive(Clone, Debug)]
+pub struct QuantaClock {
+    clock: quanta::Clock,
+}
+
+#[cfg(feature = "quanta")]
+impl Default for QuantaClock {
+    fn default() -> Self {
+        Self {
+            clock: quanta::Clock::new(),
+        }
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Reference for quanta::Instant {
+    fn duration_since(&self, earlier: Self) -> Nanos {
+        self.saturating_duration_since(earlier).into()
+    }
+
+    fn saturating_sub(&self, duration: Nanos) -> Self {
+        self.checked_sub(duration.into()).unwrap_or(*self)
+    }
+
+    fn checked_add(&self, duration: Nanos) -> Option<Self> {
+        quanta::Instant::checked_add(self, duration.into())
+    }
+
+    fn checked_duration_since(&self, earlier: Self) -> Option<Nanos> {
+        quanta::Instant::checked_duration_since(self, earlier).map(Nanos::from)
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Add<Nanos> for quanta::Instant {
+    type Output = Self;
+
+    fn add(self, other: Nanos) -> Self::Output {
+        let other: Duration = other.into();
+        self + other
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl Clock for QuantaClock {
+    type Instant = quanta::Instant;
+
+    fn now(&self) -> Self::Instant {
+        self.clock.now()
+    }
+}
+
+#[cfg(feature = "quanta")]
+impl ReasonablyRealtime for QuantaClock {
+    fn to_system_time(&self, instant: Self::Instant) -> SystemTime {
+        relative_system_time(instant, self.now())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FakeRelativeClock {
+    now: Arc<AtomicU64>,
+    start: Nanos,
 }
 
 impl FakeRelativeClock {
-    pub fn advance(&self, by:Duration){
-        let by:u64 = by
+    /// Creates a clock whose first reading is `start` instead of zero.
+    pub fn new_at(start: Duration) -> Self {
+        let start = Nanos::from(start);
+        Self {
+            now: Arc::new(AtomicU64::new(start.as_u64())),
+            start,
+        }
+    }
+
+    /// Total time this clock has been advanced since it was created.
+    pub fn elapsed(&self) -> Duration {
+        self.now().saturating_sub(self.start).into()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let by: u64 = by
             .as_nanos()
             .try_into()
             .expect("Cannot represent duration greater than 584 years");
 
         let mut prev = self.now.load(Ordering::Acquire);
         let mut next = prev + by;
-        while let Err(e)= self.now.compare_exchange_weak(prev, next, Ordering::Relaxed, Ordering::Relaxed){
+        while let Err(e) =
+            self.now
+                .compare_exchange_weak(prev, next, Ordering::Relaxed, Ordering::Relaxed)
+        {
             prev = e;
             next = prev + by;
         }
     }
 }
 
-
 impl Clock for FakeRelativeClock {
     type Instant = Nanos;
 
     fn now(&self) -> Self::Instant {
         self.now.load(Ordering::Acquire).into()
     }
+
+    /// Advances the clock by `duration` instead of blocking.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// A monotonic clock reading `CLOCK_MONOTONIC_COARSE`, with instants
+/// measured as [`Nanos`] since boot.
+///
+/// The kernel only updates the coarse clock once per scheduler tick, so
+/// readings have a resolution of a few milliseconds, but they are served
+/// from the vDSO without reading any hardware counter and are much cheaper
+/// than [`MonotonicClock`]'s. Windows shorter than the resolution are not
+/// enforced accurately.
+#[cfg(all(feature = "coarse", target_os = "linux"))]
+#[derive(Clone, Debug, Default)]
+pub struct CoarseMonotonicClock;
+
+#[cfg(all(feature = "coarse", target_os = "linux"))]
+impl Clock for CoarseMonotonicClock {
+    type Instant = Nanos;
+
+    fn now(&self) -> Self::Instant {
+        let mut ts = libc::timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        // SAFETY: `ts` is a valid, writable timespec and the clock id is
+        // supported on every Linux since 2.6.32.
+        let ret = unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC_COARSE, &mut ts) };
+        assert_eq!(ret, 0, "CLOCK_MONOTONIC_COARSE is unavailable");
+        Nanos::from(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+    }
+}
+
+#[cfg(all(feature = "coarse", target_os = "linux"))]
+impl ReasonablyRealtime for CoarseMonotonicClock {
+    fn to_system_time(&self, instant: Self::Instant) -> SystemTime {
+        relative_system_time(instant, self.now())
+    }
+}
+
+/// A clock reading [`tokio::time::Instant`], so it follows tokio's virtual
+/// time: under `tokio::time::pause` it only moves when the runtime
+/// advances it, which makes async tests deterministic without a fake clock.
+///
+/// Only the async acquires advance paused time while waiting. The blocking
+/// [`sleep`](Clock::sleep) waits for real time, so a blocking acquire on a
+/// paused runtime never sees its permit become available.
+#[cfg(feature = "tokio")]
+#[derive(Clone, Debug, Default)]
+pub struct TokioClock;
+
+#[cfg(feature = "tokio")]
+impl Reference for tokio::time::Instant {
+    fn duration_since(&self, earlier: Self) -> Nanos {
+        self.saturating_duration_since(earlier).into()
+    }
+
+    fn saturating_sub(&self, duration: Nanos) -> Self {
+        self.checked_sub(duration.into()).unwrap_or(*self)
+    }
+
+    fn checked_add(&self, duration: Nanos) -> Option<Self> {
+        tokio::time::Instant::checked_add(self, duration.into())
+    }
+
+    fn checked_duration_since(&self, earlier: Self) -> Option<Nanos> {
+        tokio::time::Instant::checked_duration_since(self, earlier).map(Nanos::from)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Add<Nanos> for tokio::time::Instant {
+    type Output = Self;
+
+    fn add(self, other: Nanos) -> Self::Output {
+        let other: Duration = other.into();
+        self + other
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl Clock for TokioClock {
+    type Instant = tokio::time::Instant;
+
+    fn now(&self) -> Self::Instant {
+        tokio::time::Instant::now()
+    }
+}
+
+/// Maps instants relative to the real wall clock, even while tokio's time
+/// is paused.
+#[cfg(feature = "tokio")]
+impl ReasonablyRealtime for TokioClock {
+    fn to_system_time(&self, instant: Self::Instant) -> SystemTime {
+        relative_system_time(instant, self.now())
+    }
+}
+
+/// A monotonic counter of ticks at a fixed frequency, such as an embedded
+/// timer or a CPU cycle counter, for [`TickClock`].
+///
+/// Implemented for closures returning the current tick count.
+pub trait TickSource: Clone {
+    /// The ticks elapsed since some fixed origin. Must never decrease, so a
+    /// narrow hardware counter that wraps has to be extended to 64 bits.
+    fn ticks(&self) -> u64;
+}
+
+impl<F: Fn() -> u64 + Clone> TickSource for F {
+    fn ticks(&self) -> u64 {
+        self()
+    }
+}
+
+/// A clock over a user-supplied [`TickSource`] counting at `frequency_hz`,
+/// e.g. to limit a radio's duty cycle on a microcontroller. Instants are
+/// [`Nanos`] since the source's origin.
+///
+/// Sleeping blocks the thread as usual; override it by wrapping the clock
+/// if the target has no threads.
+#[derive(Debug, Clone)]
+pub struct TickClock<T> {
+    source: T,
+    frequency_hz: u64,
+}
+
+impl<T: TickSource> TickClock<T> {
+    /// # Panics
+    ///
+    /// Panics if `frequency_hz` is zero.
+    pub fn new(source: T, frequency_hz: u64) -> Self {
+        assert!(frequency_hz > 0, "tick frequency must be non-zero");
+        Self {
+            source,
+            frequency_hz,
+        }
+    }
+}
+
+impl<T: TickSource> Clock for TickClock<T> {
+    type Instant = Nanos;
+
+    fn now(&self) -> Self::Instant {
+        let nanos = u128::from(self.source.ticks()) * 1_000_000_000 / u128::from(self.frequency_hz);
+        Nanos::new(nanos.try_into().unwrap_or(u64::MAX))
+    }
+}
+
+/// A clock reading [`Nanos`] from a closure, for time sources such as
+/// simulation frameworks or hardware timestamps without writing a [`Clock`]
+/// implementation. The closure must never go backwards.
+#[derive(Clone)]
+pub struct FnClock<F> {
+    now: F,
+}
+
+impl<F: Fn() -> Nanos + Clone> FnClock<F> {
+    pub fn new(now: F) -> Self {
+        Self { now }
+    }
+}
+
+impl<F> Debug for FnClock<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnClock").finish_non_exhaustive()
+    }
+}
+
+impl<F: Fn() -> Nanos + Clone> Clock for FnClock<F> {
+    type Instant = Nanos;
+
+    fn now(&self) -> Self::Instant {
+        (self.now)()
+    }
+}
+
+/// A fake [`SystemClock`] for tests: a wall clock that only moves when it is
+/// set or advanced, with instants measured as [`Nanos`] since the UNIX
+/// epoch. Clones share the same time.
+///
+/// Unlike [`FakeRelativeClock`], it can be [set](Self::set) to any time,
+/// including backwards, to exercise calendar alignment, persistence and
+/// wall-clock adjustments deterministically.
+#[derive(Debug, Clone, Default)]
+pub struct FakeAbsoluteClock {
+    now: Arc<AtomicU64>,
+}
+
+impl FakeAbsoluteClock {
+    /// Creates a clock reading `time`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` is before the UNIX epoch or too far after it to be
+    /// represented as [`Nanos`].
+    pub fn new(time: SystemTime) -> Self {
+        let clock = Self::default();
+        clock.set(time);
+        clock
+    }
+
+    /// The current reading as a [`SystemTime`].
+    pub fn system_time(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from(self.now())
+    }
+
+    /// Sets the current time, possibly backwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `time` is before the UNIX epoch or too far after it to be
+    /// represented as [`Nanos`].
+    pub fn set(&self, time: SystemTime) {
+        let since_epoch = time
+            .duration_since(UNIX_EPOCH)
+            .expect("time must not precede the UNIX epoch");
+        let nanos: u64 = since_epoch
+            .as_nanos()
+            .try_into()
+            .expect("Cannot represent duration greater than 584 years");
+        self.now.store(nanos, Ordering::Release);
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let by: u64 = by
+            .as_nanos()
+            .try_into()
+            .expect("Cannot represent duration greater than 584 years");
+        self.now.fetch_add(by, Ordering::AcqRel);
+    }
+}
+
+impl Clock for FakeAbsoluteClock {
+    type Instant = Nanos;
+
+    fn now(&self) -> Self::Instant {
+        self.now.load(Ordering::Acquire).into()
+    }
+
+    /// Advances the clock by `duration` instead of blocking.
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+impl ReasonablyRealtime for FakeAbsoluteClock {
+    fn to_system_time(&self, instant: Self::Instant) -> SystemTime {
+        UNIX_EPOCH + Duration::from(instant)
+    }
+}
+
+/// A clock chosen at runtime, so the clock generic need not spread through
+/// an application's types: e.g. a `RateLimiter<AnyClock>` can run on
+/// [`MonotonicClock`] in production and on a [`FakeRelativeClock`] in tests.
+///
+/// Instants are [`Nanos`]: since the clock was created for the monotonic
+/// clock, since the UNIX epoch for the wall clocks, and as read for the fake
+/// relative clock. Sleeping delegates to the wrapped clock, so the fakes
+/// advance instead of blocking.
+#[derive(Clone, Debug)]
+pub struct AnyClock {
+    inner: AnyClockInner,
+}
+
+#[derive(Clone, Debug)]
+enum AnyClockInner {
+    Monotonic { origin: Instant },
+    System,
+    FakeRelative(FakeRelativeClock),
+    FakeAbsolute(FakeAbsoluteClock),
+}
+
+impl AnyClock {
+    /// A [`MonotonicClock`] measuring from now.
+    pub fn monotonic() -> Self {
+        Self {
+            inner: AnyClockInner::Monotonic {
+                origin: Instant::now(),
+            },
+        }
+    }
+}
+
+impl Default for AnyClock {
+    fn default() -> Self {
+        Self::monotonic()
+    }
+}
+
+impl From<MonotonicClock> for AnyClock {
+    fn from(_: MonotonicClock) -> Self {
+        Self::monotonic()
+    }
+}
+
+impl From<SystemClock> for AnyClock {
+    fn from(_: SystemClock) -> Self {
+        Self {
+            inner: AnyClockInner::System,
+        }
+    }
+}
+
+impl From<FakeRelativeClock> for AnyClock {
+    fn from(clock: FakeRelativeClock) -> Self {
+        Self {
+            inner: AnyClockInner::FakeRelative(clock),
+        }
+    }
+}
+
+impl From<FakeAbsoluteClock> for AnyClock {
+    fn from(clock: FakeAbsoluteClock) -> Self {
+        Self {
+            inner: AnyClockInner::FakeAbsolute(clock),
+        }
+    }
+}
+
+impl Clock for AnyClock {
+    type Instant = Nanos;
+
+    fn now(&self) -> Self::Instant {
+        match &self.inner {
+            AnyClockInner::Monotonic { origin } => origin.elapsed().into(),
+            AnyClockInner::System => SystemClock.now(),
+            AnyClockInner::FakeRelative(clock) => clock.now(),
+            AnyClockInner::FakeAbsolute(clock) => clock.now(),
+        }
+    }
+
+    fn sleep(&self, duration: Duration) {
+        match &self.inner {
+            AnyClockInner::Monotonic { .. } | AnyClockInner::System => std::thread::sleep(duration),
+            AnyClockInner::FakeRelative(clock) => clock.sleep(duration),
+            AnyClockInner::FakeAbsolute(clock) => clock.sleep(duration),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -90,10 +633,10 @@ mod tests {
     fn test_fake_relative_clock() {
         let clock = Arc::new(FakeRelativeClock::default());
         let threads = std::iter::repeat_n((), 10)
-            .map(move |()|{
+            .map(move |()| {
                 let clock = clock.clone();
-                thread::spawn(move||{
-                    for _ in 0..1_000_000{
+                thread::spawn(move || {
+                    for _ in 0..1_000_000 {
                         let now = clock.now();
                         clock.advance(Duration::from_nanos(1));
                         assert!(clock.now() > now);
@@ -106,4 +649,191 @@ mod tests {
             thread.join().unwrap();
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_fake_relative_clock_elapsed() {
+        let clock = FakeRelativeClock::default();
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        clock.advance(Duration::from_millis(250));
+        clock.clone().advance(Duration::from_secs(1));
+        assert_eq!(clock.elapsed(), Duration::from_millis(1_250));
+        assert_eq!(Duration::from(clock.now()), clock.elapsed());
+    }
+
+    #[test]
+    fn test_system_clock_since_epoch() {
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let now = Duration::from(SystemClock.now());
+        let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        // 读数是自 UNIX 纪元以来的时间，与 SystemTime 一致
+        assert!(before <= now && now <= after);
+    }
+
+    #[test]
+    fn test_fake_absolute_clock() {
+        let noon = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let clock = FakeAbsoluteClock::new(noon);
+        assert_eq!(clock.system_time(), noon);
+        assert_eq!(
+            Duration::from(clock.now()),
+            Duration::from_secs(1_700_000_000)
+        );
+
+        clock.clone().advance(Duration::from_millis(1_500));
+        assert_eq!(clock.system_time(), noon + Duration::from_millis(1_500));
+
+        // 可以把时间拨回去，模拟 NTP 校时
+        clock.set(noon - Duration::from_secs(60));
+        assert_eq!(clock.system_time(), noon - Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_reasonably_realtime() {
+        let clock = FakeAbsoluteClock::new(UNIX_EPOCH + Duration::from_secs(1_000));
+        let in_a_minute = clock.now() + Nanos::from(Duration::from_secs(60));
+        assert_eq!(
+            clock.to_system_time(in_a_minute),
+            UNIX_EPOCH + Duration::from_secs(1_060)
+        );
+
+        // 单调时钟相对当前墙上时间换算
+        let clock = MonotonicClock;
+        let before = SystemTime::now();
+        let wall = clock.to_system_time(clock.now() + Nanos::from(Duration::from_secs(60)));
+        let after = SystemTime::now();
+        assert!(before + Duration::from_secs(60) <= wall);
+        assert!(wall <= after + Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_fn_clock() {
+        let time = Arc::new(AtomicU64::new(0));
+        let clock = FnClock::new({
+            let time = time.clone();
+            move || Nanos::new(time.load(Ordering::Relaxed))
+        });
+        let limiter = crate::RateLimiter::new((1, Duration::from_secs(1)), clock);
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_err());
+
+        // 由闭包背后的时间源驱动
+        time.store(1_000_000_000, Ordering::Relaxed);
+        assert!(limiter.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_any_clock() {
+        fn limiter(clock: impl Into<AnyClock>) -> crate::RateLimiter<AnyClock> {
+            crate::RateLimiter::new((1, Duration::from_secs(1)), clock.into())
+        }
+
+        let fake = FakeRelativeClock::default();
+        let limiter_on_fake = limiter(fake.clone());
+        assert!(limiter_on_fake.acquire().is_ok());
+        assert!(limiter_on_fake.acquire().is_err());
+        // 同一类型既可以跑在假时钟上，也可以跑在真实时钟上
+        fake.advance(Duration::from_secs(1));
+        assert!(limiter_on_fake.acquire().is_ok());
+
+        let limiter_on_monotonic = limiter(MonotonicClock);
+        assert!(limiter_on_monotonic.acquire().is_ok());
+        assert!(limiter_on_monotonic.acquire().is_err());
+
+        // 假时钟的 sleep 只是推进时间
+        AnyClock::from(fake.clone()).sleep(Duration::from_secs(5));
+        assert_eq!(fake.elapsed(), Duration::from_secs(6));
+    }
+
+    #[test]
+    fn test_window_start() {
+        let origin = Nanos::from_secs(10);
+        let period = Nanos::from_secs(60);
+        assert_eq!(Nanos::from_secs(10).window_start(origin, period), origin);
+        assert_eq!(
+            Nanos::from_secs(69).window_start(origin, period),
+            Nanos::from_secs(10)
+        );
+        assert_eq!(
+            Nanos::from_secs(70).window_start(origin, period),
+            Nanos::from_secs(70)
+        );
+        assert_eq!(Nanos::from_secs(5).window_start(origin, period), origin);
+
+        let start = Instant::now();
+        let now = start + Nanos::from_millis(2_500);
+        assert_eq!(
+            now.window_start(start, Nanos::from_secs(1)),
+            start + Nanos::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn test_tick_clock() {
+        let ticks = Arc::new(AtomicU64::new(0));
+        let source = {
+            let ticks = ticks.clone();
+            move || ticks.load(Ordering::Relaxed)
+        };
+        // 32.768kHz 的低速晶振
+        let clock = TickClock::new(source, 32_768);
+        assert_eq!(clock.now(), Nanos::ZERO);
+
+        ticks.store(16_384, Ordering::Relaxed);
+        assert_eq!(Duration::from(clock.now()), Duration::from_millis(500));
+        ticks.store(32_768, Ordering::Relaxed);
+        assert_eq!(Duration::from(clock.now()), Duration::from_secs(1));
+
+        // 不会因为乘法溢出而回绕
+        ticks.store(u64::MAX, Ordering::Relaxed);
+        assert!(clock.now() > Nanos::ZERO);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_tokio_clock_follows_paused_time() {
+        let limiter = crate::RateLimiter::new((1, Duration::from_secs(1)), TokioClock);
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_err());
+
+        // 暂停的运行时只有在 advance 时才会前进
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(limiter.acquire().is_ok());
+    }
+
+    #[cfg(all(feature = "coarse", target_os = "linux"))]
+    #[test]
+    fn test_coarse_monotonic_clock() {
+        let clock = CoarseMonotonicClock;
+        let start = clock.now();
+        thread::sleep(Duration::from_millis(50));
+        // 分辨率只有几毫秒，但仍然单调前进
+        let elapsed = clock.now().duration_since(start);
+        assert!(elapsed >= Nanos::from(Duration::from_millis(40)));
+    }
+
+    #[cfg(feature = "quanta")]
+    #[test]
+    fn test_quanta_clock_in_rate_limiter() {
+        let clock = QuantaClock::default();
+        let start = clock.now();
+        let limiter = crate::RateLimiter::new((2, Duration::from_secs(60)), clock.clone());
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_err());
+        // 读数单调递增
+        assert!(clock.now() >= start);
+    }
+
+    #[test]
+    fn test_fake_relative_clock_new_at() {
+        let start = Duration::from_secs(400 * 365 * 24 * 60 * 60);
+        let clock = FakeRelativeClock::new_at(start);
+        assert_eq!(clock.now(), Nanos::from(start));
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+
+        clock.advance(Duration::from_secs(3));
+        assert_eq!(Duration::from(clock.now()), start + Duration::from_secs(3));
+        assert_eq!(clock.elapsed(), Duration::from_secs(3));
+    }
+}
@@ -0,0 +1,541 @@
+//! Envoy's [rate limit service] protocol, so that a keyed [`RateLimiter`]
+//! can serve as the global rate limit service of an Envoy or Istio mesh.
+//!
+//! Only the messages and fields this crate reads or writes are defined;
+//! protobuf skips the others.
+//!
+//! [rate limit service]: https://www.envoyproxy.io/docs/envoy/latest/api-v3/service/ratelimit/v3/rls.proto
+
+use std::{
+    convert::Infallible,
+    fmt,
+    future::{self, Future},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{Request, Response};
+use tonic::{Status, body::Body, server::NamedService};
+use tonic_prost::ProstCodec;
+use tower::Service;
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, error::AcquireError, handle::RateLimiterHandle,
+    limiter::RateLimiter, nanos::Nanos, quota::Quota, state::State,
+};
+
+/// `envoy.service.ratelimit.v3.RateLimitRequest`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitRequest {
+    #[prost(string, tag = "1")]
+    pub domain: String,
+    #[prost(message, repeated, tag = "2")]
+    pub descriptors: Vec<RateLimitDescriptor>,
+    /// The permits each descriptor takes, with zero meaning one.
+    #[prost(uint32, tag = "3")]
+    pub hits_addend: u32,
+}
+
+/// `envoy.extensions.common.ratelimit.v3.RateLimitDescriptor`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitDescriptor {
+    #[prost(message, repeated, tag = "1")]
+    pub entries: Vec<DescriptorEntry>,
+    /// A quota for the descriptor chosen by Envoy's configuration.
+    #[prost(message, optional, tag = "2")]
+    pub limit: Option<RateLimitOverride>,
+    /// The permits this descriptor takes, instead of the request's.
+    #[prost(message, optional, tag = "3")]
+    pub hits_addend: Option<u64>,
+}
+
+/// `envoy.extensions.common.ratelimit.v3.RateLimitDescriptor.Entry`.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct DescriptorEntry {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+/// `envoy.extensions.common.ratelimit.v3.RateLimitDescriptor.RateLimitOverride`.
+#[derive(Clone, Copy, PartialEq, Eq, prost::Message)]
+pub struct RateLimitOverride {
+    #[prost(uint32, tag = "1")]
+    pub requests_per_unit: u32,
+    #[prost(enumeration = "RateLimitUnit", tag = "2")]
+    pub unit: i32,
+}
+
+/// `envoy.type.v3.RateLimitUnit`, with the same values as
+/// `envoy.service.ratelimit.v3.RateLimitResponse.RateLimit.Unit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum RateLimitUnit {
+    Unknown = 0,
+    Second = 1,
+    Minute = 2,
+    Hour = 3,
+    Day = 4,
+    Month = 5,
+    Year = 6,
+    Week = 7,
+}
+
+impl RateLimitUnit {
+    /// The unit's length; months are 30 days and years 365.
+    pub fn window(self) -> Option<Nanos> {
+        let secs = match self {
+            Self::Unknown => return None,
+            Self::Second => 1,
+            Self::Minute => 60,
+            Self::Hour => 60 * 60,
+            Self::Day => 24 * 60 * 60,
+            Self::Week => 7 * 24 * 60 * 60,
+            Self::Month => 30 * 24 * 60 * 60,
+            Self::Year => 365 * 24 * 60 * 60,
+        };
+        Some(Nanos::from_secs(secs))
+    }
+
+    /// The unit exactly `window` long, if any.
+    pub fn from_window(window: Nanos) -> Option<Self> {
+        [
+            Self::Second,
+            Self::Minute,
+            Self::Hour,
+            Self::Day,
+            Self::Week,
+            Self::Month,
+            Self::Year,
+        ]
+        .into_iter()
+        .find(|unit| unit.window() == Some(window))
+    }
+}
+
+/// `envoy.service.ratelimit.v3.RateLimitResponse.Code`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum RateLimitCode {
+    Unknown = 0,
+    Ok = 1,
+    OverLimit = 2,
+}
+
+/// `envoy.service.ratelimit.v3.RateLimitResponse`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitResponse {
+    /// Over the limit if any descriptor is.
+    #[prost(enumeration = "RateLimitCode", tag = "1")]
+    pub overall_code: i32,
+    /// One per descriptor of the request, in order.
+    #[prost(message, repeated, tag = "2")]
+    pub statuses: Vec<DescriptorStatus>,
+}
+
+/// `envoy.service.ratelimit.v3.RateLimitResponse.DescriptorStatus`.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DescriptorStatus {
+    #[prost(enumeration = "RateLimitCode", tag = "1")]
+    pub code: i32,
+    /// The descriptor's quota, if it has one that fits a unit.
+    #[prost(message, optional, tag = "2")]
+    pub current_limit: Option<CurrentLimit>,
+    #[prost(uint32, tag = "3")]
+    pub limit_remaining: u32,
+    #[prost(message, optional, tag = "4")]
+    pub duration_until_reset: Option<prost_types::Duration>,
+}
+
+/// `envoy.service.ratelimit.v3.RateLimitResponse.RateLimit`.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct CurrentLimit {
+    #[prost(uint32, tag = "1")]
+    pub requests_per_unit: u32,
+    #[prost(enumeration = "RateLimitUnit", tag = "2")]
+    pub unit: i32,
+    #[prost(string, tag = "3")]
+    pub name: String,
+}
+
+impl CurrentLimit {
+    fn from_quota(quota: Quota) -> Option<Self> {
+        Some(Self {
+            requests_per_unit: saturating_u32(quota.allowed()),
+            unit: RateLimitUnit::from_window(quota.window())? as i32,
+            name: String::new(),
+        })
+    }
+}
+
+/// Maps a request's domain and one of its descriptors to the key it is
+/// limited under, or `None` to leave the descriptor unlimited.
+pub type KeyMapping = Arc<dyn Fn(&str, &RateLimitDescriptor) -> Option<String> + Send + Sync>;
+
+/// The `envoy.service.ratelimit.v3.RateLimitService` gRPC service, deciding
+/// every descriptor on a keyed [`RateLimiter`].
+///
+/// Each descriptor is limited under its own key, by default the domain and
+/// entries joined as `domain/key=value/key=value`. Configure the quotas on
+/// the limiter: with [key rules](RateLimiter::with_key_rule) such as
+/// `"edge/remote_address=*"` for a quota per client, and with
+/// [`with_key_separator('/')`](RateLimiter::with_key_separator) so that a
+/// descriptor also passes the quotas of its shorter prefixes, as nested
+/// descriptors do in Envoy's own service. A quota override in the
+/// descriptor replaces the key's quota without pinning the key, so keys
+/// per client stay bounded by [`with_max_keys`](RateLimiter::with_max_keys)
+/// and the idle TTL. Descriptors whose key the limiter
+/// doesn't know are not limited.
+///
+/// As in Envoy's service, each descriptor is counted on its own, even when
+/// another one of the request is over its limit.
+///
+/// ```
+/// use ratelimit::{EnvoyRateLimitService, MonotonicClock, Quota, RateLimiter, State};
+///
+/// let limiter = RateLimiter::keyed(State::new(Quota::per_second(10_000), MonotonicClock))
+///     .with_key_separator('/')
+///     .with_key_rule("edge/remote_address=*", Quota::per_minute(60))
+///     .with_key_rule("edge/remote_address=*/path=/login", Quota::per_minute(5));
+/// let service = EnvoyRateLimitService::new(limiter.into_handle());
+/// // tonic::transport::Server::builder().add_service(service)...
+/// # drop(service);
+/// ```
+pub struct EnvoyRateLimitService<C: Clock, S: Algorithm<C> = State<C>> {
+    limiter: RateLimiterHandle<C, S, String>,
+    key_mapping: Option<KeyMapping>,
+}
+
+impl<C: Clock, S: Algorithm<C>> EnvoyRateLimitService<C, S> {
+    pub fn new(limiter: RateLimiterHandle<C, S, String>) -> Self {
+        Self {
+            limiter,
+            key_mapping: None,
+        }
+    }
+
+    /// Limits descriptors under the keys `mapping` derives from the domain
+    /// and the descriptor, instead of the default ones.
+    pub fn with_key_mapping<F>(mut self, mapping: F) -> Self
+    where
+        F: Fn(&str, &RateLimitDescriptor) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key_mapping = Some(Arc::new(mapping));
+        self
+    }
+
+    pub fn limiter(&self) -> &RateLimiterHandle<C, S, String> {
+        &self.limiter
+    }
+
+    /// Decides `request`, as the `ShouldRateLimit` RPC does.
+    pub fn should_rate_limit(&self, request: &RateLimitRequest) -> RateLimitResponse {
+        let hits = u64::from(request.hits_addend.max(1));
+        let statuses: Vec<_> = request
+            .descriptors
+            .iter()
+            .map(|descriptor| self.descriptor_status(&request.domain, descriptor, hits))
+            .collect();
+        let over_limit = statuses
+            .iter()
+            .any(|status| status.code == RateLimitCode::OverLimit as i32);
+        RateLimitResponse {
+            overall_code: if over_limit {
+                RateLimitCode::OverLimit
+            } else {
+                RateLimitCode::Ok
+            } as i32,
+            statuses,
+        }
+    }
+
+    fn descriptor_status(
+        &self,
+        domain: &str,
+        descriptor: &RateLimitDescriptor,
+        hits: u64,
+    ) -> DescriptorStatus {
+        let key = match &self.key_mapping {
+            Some(mapping) => mapping(domain, descriptor),
+            None => Some(default_key(domain, descriptor)),
+        };
+        let Some(key) = key else {
+            return unlimited();
+        };
+        if let Some(quota) = descriptor.limit.as_ref().and_then(override_quota)
+            && self.limiter.key_quota(&key) != Some(quota)
+        {
+            self.limiter.set_key_quota(&key, quota);
+        }
+
+        let hits = descriptor.hits_addend.unwrap_or(hits);
+        let decided = if hits == 0 {
+            self.limiter.check_key(&key).map(|_| ())
+        } else {
+            self.limiter.acquire_n_by_key(&key, hits)
+        };
+        match decided {
+            Ok(()) => DescriptorStatus {
+                code: RateLimitCode::Ok as i32,
+                current_limit: self
+                    .limiter
+                    .key_quota(&key)
+                    .and_then(CurrentLimit::from_quota),
+                limit_remaining: saturating_u32(self.limiter.check_key(&key).unwrap_or(0)),
+                duration_until_reset: None,
+            },
+            Err(AcquireError::UnknownKey) => unlimited(),
+            Err(AcquireError::NotAllowed {
+                retry_after,
+                quota,
+                remaining,
+                ..
+            }) => DescriptorStatus {
+                code: RateLimitCode::OverLimit as i32,
+                current_limit: CurrentLimit::from_quota(quota),
+                limit_remaining: saturating_u32(remaining),
+                duration_until_reset: prost_types::Duration::try_from(retry_after).ok(),
+            },
+            Err(_) => DescriptorStatus {
+                code: RateLimitCode::OverLimit as i32,
+                current_limit: self
+                    .limiter
+                    .key_quota(&key)
+                    .and_then(CurrentLimit::from_quota),
+                limit_remaining: 0,
+                duration_until_reset: None,
+            },
+        }
+    }
+}
+
+/// `domain/key=value/key=value`, for the descriptor's entries in order.
+fn default_key(domain: &str, descriptor: &RateLimitDescriptor) -> String {
+    let mut key = domain.to_owned();
+    for entry in &descriptor.entries {
+        key.push('/');
+        key.push_str(&entry.key);
+        key.push('=');
+        key.push_str(&entry.value);
+    }
+    key
+}
+
+fn override_quota(limit: &RateLimitOverride) -> Option<Quota> {
+    let window = RateLimitUnit::try_from(limit.unit).ok()?.window()?;
+    Some(Quota::new(u64::from(limit.requests_per_unit), window))
+}
+
+fn unlimited() -> DescriptorStatus {
+    DescriptorStatus {
+        code: RateLimitCode::Ok as i32,
+        ..DescriptorStatus::default()
+    }
+}
+
+fn saturating_u32(n: u64) -> u32 {
+    n.try_into().unwrap_or(u32::MAX)
+}
+
+impl<C: Clock, S: Algorithm<C>> Clone for EnvoyRateLimitService<C, S> {
+    fn clone(&self) -> Self {
+        Self {
+            limiter: self.limiter.clone(),
+            key_mapping: self.key_mapping.clone(),
+        }
+    }
+}
+
+impl<C, S> fmt::Debug for EnvoyRateLimitService<C, S>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    RateLimiter<C, S, String>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EnvoyRateLimitService")
+            .field("limiter", &self.limiter)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>> NamedService for EnvoyRateLimitService<C, S> {
+    const NAME: &'static str = "envoy.service.ratelimit.v3.RateLimitService";
+}
+
+/// The `ShouldRateLimit` method, as tonic's server machinery calls it.
+struct ShouldRateLimit<C: Clock, S: Algorithm<C>>(EnvoyRateLimitService<C, S>);
+
+impl<C: Clock, S: Algorithm<C>> tonic::server::UnaryService<RateLimitRequest>
+    for ShouldRateLimit<C, S>
+{
+    type Response = RateLimitResponse;
+    type Future = future::Ready<Result<tonic::Response<RateLimitResponse>, Status>>;
+
+    fn call(&mut self, request: tonic::Request<RateLimitRequest>) -> Self::Future {
+        let response = self.0.should_rate_limit(request.get_ref());
+        future::ready(Ok(tonic::Response::new(response)))
+    }
+}
+
+impl<C, S, B> Service<Request<B>> for EnvoyRateLimitService<C, S>
+where
+    C: Clock + Send + Sync + 'static,
+    C::Instant: Send + Sync,
+    S: Algorithm<C> + Send + Sync + 'static,
+    B: http_body::Body + Send + 'static,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>> + Send,
+{
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        if request.uri().path() != "/envoy.service.ratelimit.v3.RateLimitService/ShouldRateLimit" {
+            let response = Status::unimplemented("unknown method").into_http();
+            return Box::pin(future::ready(Ok(response)));
+        }
+        let method = ShouldRateLimit(self.clone());
+        Box::pin(async move {
+            let mut grpc = tonic::server::Grpc::new(ProstCodec::default());
+            Ok(grpc.unary(method, request).await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::FakeRelativeClock;
+
+    fn descriptor(entries: &[(&str, &str)]) -> RateLimitDescriptor {
+        RateLimitDescriptor {
+            entries: entries
+                .iter()
+                .map(|&(key, value)| DescriptorEntry {
+                    key: key.to_owned(),
+                    value: value.to_owned(),
+                })
+                .collect(),
+            ..RateLimitDescriptor::default()
+        }
+    }
+
+    fn request(descriptors: Vec<RateLimitDescriptor>) -> RateLimitRequest {
+        RateLimitRequest {
+            domain: "edge".to_owned(),
+            descriptors,
+            hits_addend: 0,
+        }
+    }
+
+    fn service() -> EnvoyRateLimitService<FakeRelativeClock> {
+        let limiter = RateLimiter::keyed(State::new(
+            Quota::per_second(100),
+            FakeRelativeClock::default(),
+        ))
+        .with_key_rule("edge/remote_address=*", Quota::per_minute(2));
+        EnvoyRateLimitService::new(limiter.into_handle())
+    }
+
+    #[test]
+    fn test_envoy_descriptors() {
+        let service = service();
+        let client = descriptor(&[("remote_address", "10.0.0.1")]);
+        let unknown = descriptor(&[("path", "/")]);
+
+        let response = service.should_rate_limit(&request(vec![client.clone(), unknown]));
+        assert_eq!(response.overall_code, RateLimitCode::Ok as i32);
+        assert_eq!(
+            response.statuses[0].current_limit,
+            Some(CurrentLimit {
+                requests_per_unit: 2,
+                unit: RateLimitUnit::Minute as i32,
+                name: String::new(),
+            })
+        );
+        assert_eq!(response.statuses[0].limit_remaining, 1);
+        // 没有配置的描述符不限流
+        assert_eq!(response.statuses[1], unlimited());
+
+        service.should_rate_limit(&request(vec![client.clone()]));
+        let response = service.should_rate_limit(&request(vec![client]));
+        assert_eq!(response.overall_code, RateLimitCode::OverLimit as i32);
+        assert_eq!(
+            response.statuses[0].duration_until_reset,
+            Some(Duration::from_secs(60).try_into().unwrap())
+        );
+
+        // 其他客户端有自己的配额
+        let other = descriptor(&[("remote_address", "10.0.0.2")]);
+        let response = service.should_rate_limit(&request(vec![other]));
+        assert_eq!(response.overall_code, RateLimitCode::Ok as i32);
+    }
+
+    #[test]
+    fn test_envoy_override_and_hits() {
+        let service = service();
+        let mut login = descriptor(&[("path", "/login")]);
+        login.limit = Some(RateLimitOverride {
+            requests_per_unit: 3,
+            unit: RateLimitUnit::Hour as i32,
+        });
+
+        let mut batch = request(vec![login.clone()]);
+        batch.hits_addend = 3;
+        let response = service.should_rate_limit(&batch);
+        assert_eq!(response.overall_code, RateLimitCode::Ok as i32);
+        assert_eq!(response.statuses[0].limit_remaining, 0);
+
+        // 描述符自带的 hits_addend 为 0 时只检查不消耗
+        login.hits_addend = Some(0);
+        let response = service.should_rate_limit(&request(vec![login]));
+        assert_eq!(response.overall_code, RateLimitCode::OverLimit as i32);
+        assert_eq!(
+            service.limiter().key_quota("edge/path=/login"),
+            Some(Quota::per_hour(3))
+        );
+    }
+
+    #[test]
+    fn test_envoy_overrides_stay_bounded() {
+        let limiter = RateLimiter::keyed(State::new(
+            Quota::per_second(100),
+            FakeRelativeClock::default(),
+        ))
+        .with_max_keys(10)
+        .with_key_rule("edge/remote_address=*", Quota::per_minute(2));
+        let service = EnvoyRateLimitService::new(limiter.into_handle());
+
+        // 每个客户端各自覆盖配额，键的数量仍受 max_keys 限制
+        for i in 0..100 {
+            let mut client = descriptor(&[("remote_address", &format!("10.0.0.{i}"))]);
+            client.limit = Some(RateLimitOverride {
+                requests_per_unit: 3,
+                unit: RateLimitUnit::Hour as i32,
+            });
+            let response = service.should_rate_limit(&request(vec![client]));
+            assert_eq!(response.overall_code, RateLimitCode::Ok as i32);
+        }
+        assert_eq!(service.limiter().len(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_envoy_unknown_method() {
+        let mut service = service();
+        let request = Request::post("/envoy.service.ratelimit.v2.RateLimitService/ShouldRateLimit")
+            .body(String::new())
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        let status = Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), tonic::Code::Unimplemented);
+    }
+}
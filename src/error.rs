@@ -0,0 +1,94 @@
+use std::{
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use crate::{clock::Reference, not_until::NotUntil, quota::Quota};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AcquireError {
+    /// The key has not been configured.
+    UnknownKey,
+    /// The key, or the whole limiter, has been disabled.
+    Disabled,
+    /// The quota is exhausted; the request may succeed after `retry_after`.
+    /// `remaining` permits were still available, fewer than requested.
+    ///
+    /// `backoff` is the suggested delay before retrying: `retry_after`, or
+    /// longer after repeated denials under
+    /// [escalating backoff](crate::RateLimiter::with_escalating_backoff).
+    NotAllowed {
+        retry_after: Duration,
+        quota: Quota,
+        remaining: u64,
+        backoff: Duration,
+    },
+    /// More permits were requested at once than the key can ever grant.
+    InsufficientCapacity { capacity: u64 },
+    /// The request could only be granted beyond the range of the clock's
+    /// instants. See [`NotUntil::overflowed`].
+    TimeOverflow { quota: Quota },
+    /// The [`StateStore`](crate::StateStore) holding the key's state failed,
+    /// e.g. because it could not be reached.
+    StoreUnavailable(String),
+}
+
+impl Display for AcquireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownKey => write!(f, "unknown key"),
+            Self::Disabled => write!(f, "rate limiting key is disabled"),
+            Self::NotAllowed { retry_after, .. } => {
+                write!(f, "rate limited, retry after {retry_after:?}")
+            }
+            Self::InsufficientCapacity { capacity } => {
+                write!(f, "request exceeds the capacity of {capacity} permits")
+            }
+            Self::TimeOverflow { .. } => {
+                write!(f, "rate limited beyond the clock's range")
+            }
+            Self::StoreUnavailable(reason) => write!(f, "state store unavailable: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for AcquireError {}
+
+impl AcquireError {
+    /// Converts a denial into a [`NotAllowed`](Self::NotAllowed) error, with
+    /// the wait measured from `now`, or a [`TimeOverflow`](Self::TimeOverflow)
+    /// one if the denial overflowed.
+    pub(crate) fn not_allowed<P: Reference>(not_until: NotUntil<P>, now: P) -> Self {
+        if not_until.overflowed() {
+            return Self::TimeOverflow {
+                quota: not_until.quota(),
+            };
+        }
+        let retry_after = not_until.wait_time_from(now);
+        Self::NotAllowed {
+            retry_after,
+            quota: not_until.quota(),
+            remaining: not_until.remaining(),
+            backoff: retry_after,
+        }
+    }
+}
+
+impl From<InsufficientCapacity> for AcquireError {
+    fn from(InsufficientCapacity(capacity): InsufficientCapacity) -> Self {
+        Self::InsufficientCapacity { capacity }
+    }
+}
+
+/// More permits were requested at once than a state can ever grant. Holds
+/// the state's capacity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsufficientCapacity(pub u64);
+
+impl Display for InsufficientCapacity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "request exceeds the capacity of {} permits", self.0)
+    }
+}
+
+impl std::error::Error for InsufficientCapacity {}
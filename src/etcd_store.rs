@@ -0,0 +1,361 @@
+use std::{fmt, future::Future, marker::PhantomData, sync::Arc, time::Duration};
+
+use etcd_client::{Client, Compare, CompareOp, ConnectOptions, PutOptions, Txn, TxnOp};
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::runtime::{self, Runtime};
+
+use crate::{
+    headers::ceil_secs,
+    store::{Degraded, DegradedMode, StateStore, Versioned},
+};
+
+/// An error from an [`EtcdStore`].
+#[derive(Debug)]
+pub enum EtcdStoreError {
+    /// The store's runtime could not be started.
+    Runtime(std::io::Error),
+    Etcd(etcd_client::Error),
+    /// A stored value could not be decoded, or a value encoded.
+    Encoding(serde_json::Error),
+}
+
+impl fmt::Display for EtcdStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Runtime(err) => write!(f, "no etcd runtime: {err}"),
+            Self::Etcd(err) => write!(f, "etcd: {err}"),
+            Self::Encoding(err) => write!(f, "invalid stored state: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for EtcdStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Runtime(err) => Some(err),
+            Self::Etcd(err) => Some(err),
+            Self::Encoding(err) => Some(err),
+        }
+    }
+}
+
+impl EtcdStoreError {
+    /// Whether etcd couldn't be reached, rather than misbehaving.
+    fn is_unreachable(&self) -> bool {
+        match self {
+            Self::Etcd(etcd_client::Error::IoError(_) | etcd_client::Error::TransportError(_)) => {
+                true
+            }
+            Self::Etcd(etcd_client::Error::GRpcStatus(status)) => matches!(
+                status.code(),
+                tonic::Code::Unavailable | tonic::Code::DeadlineExceeded
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// A [`StateStore`] in etcd, so that the replicas of a service share their
+/// quotas through the cluster's own control plane instead of a separate
+/// cache.
+///
+/// Each key's value is stored as JSON under the
+/// [key prefix](Self::with_prefix), and its version is the key's
+/// modification revision. A write is a transaction that only puts the value
+/// if that revision is unchanged, or if the key was never created, and with
+/// a [TTL](Self::with_ttl) attaches the key to a fresh lease, so that etcd
+/// deletes it once idle.
+///
+/// The etcd client is asynchronous, so the store drives it on a runtime of
+/// its own, and must not be used from within an async runtime; run the
+/// limiter on a blocking thread there, e.g. with
+/// `tokio::task::spawn_blocking`. While etcd is unreachable the store
+/// behaves as its [`DegradedMode`] says.
+///
+/// ```no_run
+/// use ratelimit::{Algorithm, EtcdStore, GcraState, Quota, StoredRateLimiter, SystemClock};
+///
+/// let store = EtcdStore::connect(["http://etcd:2379"], None)?.with_prefix("/ratelimit/");
+/// let limiter = StoredRateLimiter::new(store, GcraState::from_quota(Quota::per_second(10), SystemClock));
+/// limiter.acquire_by_key("tenant-a")?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct EtcdStore<V> {
+    runtime: Runtime,
+    client: Client,
+    prefix: String,
+    ttl: Option<Duration>,
+    degraded: Degraded<V>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V> EtcdStore<V> {
+    /// Connects to the etcd cluster at `endpoints`, lazily, so that this
+    /// only fails for invalid arguments.
+    pub fn connect<E: AsRef<str>>(
+        endpoints: impl AsRef<[E]>,
+        options: Option<ConnectOptions>,
+    ) -> Result<Self, EtcdStoreError> {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(EtcdStoreError::Runtime)?;
+        let client = runtime
+            .block_on(Client::connect(endpoints, options))
+            .map_err(EtcdStoreError::Etcd)?;
+        Ok(Self {
+            runtime,
+            client,
+            prefix: String::new(),
+            ttl: None,
+            degraded: Degraded::new(DegradedMode::default()),
+            _value: PhantomData,
+        })
+    }
+
+    /// Prefixes every etcd key with `prefix`, e.g. `/ratelimit/`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Expires keys not updated for `ttl`, rounded up to whole seconds,
+    /// which should be at least as long as a state takes to fully recover.
+    ///
+    /// Every write then grants a lease first, costing a round trip.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_degraded_mode(mut self, mode: DegradedMode) -> Self {
+        self.degraded.set_mode(mode);
+        self
+    }
+
+    /// Calls `listener` with `true` when etcd becomes unreachable and the
+    /// store starts answering as its [`DegradedMode`] says, and with `false`
+    /// once it answers again, e.g. to alert on a degraded limiter.
+    pub fn with_degraded_listener<F>(mut self, listener: F) -> Self
+    where
+        F: Fn(bool) + Send + Sync + 'static,
+    {
+        self.degraded.set_listener(Arc::new(listener));
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn etcd_key(&self, key: &impl fmt::Display) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    /// Runs a request with a handle to the client to completion.
+    fn block_on<T, F>(&self, request: impl FnOnce(Client) -> F) -> Result<T, EtcdStoreError>
+    where
+        F: Future<Output = Result<T, etcd_client::Error>>,
+    {
+        self.runtime
+            .block_on(request(self.client.clone()))
+            .map_err(EtcdStoreError::Etcd)
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> EtcdStore<V> {
+    fn encode(value: &V) -> Result<Vec<u8>, EtcdStoreError> {
+        serde_json::to_vec(value).map_err(EtcdStoreError::Encoding)
+    }
+
+    fn decode(data: &[u8]) -> Result<V, EtcdStoreError> {
+        serde_json::from_slice(data).map_err(EtcdStoreError::Encoding)
+    }
+
+    fn try_load(&self, key: &str) -> Result<Option<Versioned<V>>, EtcdStoreError> {
+        let response = self.block_on(|mut client| async move { client.get(key, None).await })?;
+        let Some(kv) = response.kvs().first() else {
+            return Ok(None);
+        };
+        Ok(Some(Versioned {
+            value: Self::decode(kv.value())?,
+            version: kv.mod_revision().cast_unsigned(),
+        }))
+    }
+
+    fn try_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<u64>,
+        value: &V,
+    ) -> Result<bool, EtcdStoreError> {
+        let data = Self::encode(value)?;
+        let compare = match expected {
+            Some(version) => Compare::mod_revision(key, CompareOp::Equal, version.cast_signed()),
+            None => Compare::create_revision(key, CompareOp::Equal, 0),
+        };
+        let ttl = self
+            .ttl
+            .map(|ttl| i64::try_from(ceil_secs(ttl)).unwrap_or(i64::MAX));
+        let response = self.block_on(|mut client| async move {
+            let mut options = PutOptions::new();
+            if let Some(ttl) = ttl {
+                options = options.with_lease(client.lease_grant(ttl, None).await?.id());
+            }
+            let txn = Txn::new()
+                .when([compare])
+                .and_then([TxnOp::put(key, data, Some(options))]);
+            client.txn(txn).await
+        })?;
+        Ok(response.succeeded())
+    }
+}
+
+impl<K, V> StateStore<K, V> for EtcdStore<V>
+where
+    K: fmt::Display,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    type Error = EtcdStoreError;
+
+    fn load(&self, key: &K) -> Result<Option<Versioned<V>>, EtcdStoreError> {
+        let key = self.etcd_key(key);
+        match self.try_load(&key) {
+            Err(err) if err.is_unreachable() => self.degraded.load(&key, err),
+            loaded => {
+                self.degraded.reached();
+                loaded
+            }
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &K,
+        expected: Option<u64>,
+        value: V,
+    ) -> Result<bool, EtcdStoreError> {
+        let key = self.etcd_key(key);
+        match self.try_compare_and_swap(&key, expected, &value) {
+            Err(err) if err.is_unreachable() => {
+                self.degraded.compare_and_swap(&key, expected, value, err)
+            }
+            swapped => {
+                self.degraded.reached();
+                swapped
+            }
+        }
+    }
+
+    fn remove(&self, key: &K) -> Result<bool, EtcdStoreError> {
+        let key = self.etcd_key(key);
+        let local = self.degraded.remove(&key);
+        let removed = self.block_on(|mut client| async move { client.delete(key, None).await });
+        match removed {
+            Ok(removed) => {
+                self.degraded.reached();
+                Ok(removed.deleted() > 0 || local)
+            }
+            Err(err) if err.is_unreachable() => self.degraded.unreachable(local, err),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<V> fmt::Debug for EtcdStore<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EtcdStore")
+            .field("prefix", &self.prefix)
+            .field("ttl", &self.ttl)
+            .field("degraded", &self.degraded.mode())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nanos::Nanos, state::WindowSnapshot};
+
+    type Store = EtcdStore<WindowSnapshot<Nanos>>;
+
+    /// A store on the etcd cluster at `ETCD_ENDPOINT`, or a local one, with
+    /// keys of its own.
+    fn live() -> EtcdStore<u64> {
+        let endpoint =
+            std::env::var("ETCD_ENDPOINT").unwrap_or_else(|_| "http://127.0.0.1:2379".to_owned());
+        let prefix = format!("/ratelimit-test/{}/", std::process::id());
+        EtcdStore::connect([endpoint], None)
+            .unwrap()
+            .with_prefix(prefix)
+    }
+
+    #[test]
+    fn test_etcd_key_and_encoding() {
+        let store: Store = EtcdStore::connect(["http://127.0.0.1:1"], None)
+            .unwrap()
+            .with_prefix("/ratelimit/");
+        assert_eq!(store.etcd_key(&"user-42"), "/ratelimit/user-42");
+
+        let snapshot = WindowSnapshot {
+            start: Nanos::new(1_500),
+            acquired: 2,
+        };
+        let data = Store::encode(&snapshot).unwrap();
+        assert_eq!(data, br#"{"start":1500,"acquired":2}"#);
+        assert_eq!(Store::decode(&data).unwrap(), snapshot);
+        assert!(matches!(
+            Store::decode(b"{}"),
+            Err(EtcdStoreError::Encoding(_))
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs an etcd server, at ETCD_ENDPOINT or 127.0.0.1:2379"]
+    fn test_etcd_compare_and_swap() {
+        let store = live();
+        let key = "k".to_owned();
+        store.remove(&key).unwrap();
+        assert_eq!(store.load(&key).unwrap(), None);
+
+        // 只有键从未创建时才能按空版本写入
+        assert!(store.compare_and_swap(&key, None, 1).unwrap());
+        assert!(!store.compare_and_swap(&key, None, 2).unwrap());
+        let loaded = store.load(&key).unwrap().unwrap();
+        assert_eq!(loaded.value, 1);
+
+        // 版本是修改修订号，过期的修订号写入失败
+        assert!(
+            store
+                .compare_and_swap(&key, Some(loaded.version), 3)
+                .unwrap()
+        );
+        assert!(
+            !store
+                .compare_and_swap(&key, Some(loaded.version), 4)
+                .unwrap()
+        );
+        let updated = store.load(&key).unwrap().unwrap();
+        assert_eq!(updated.value, 3);
+        assert!(updated.version > loaded.version);
+
+        // 设置 TTL 后写入会绑定租约
+        let etcd_key = store.etcd_key(&key);
+        let lease = |store: &EtcdStore<u64>| {
+            let key = etcd_key.clone();
+            let response = store
+                .block_on(|mut client| async move { client.get(key, None).await })
+                .unwrap();
+            response.kvs()[0].lease()
+        };
+        assert_eq!(lease(&store), 0);
+        let store = store.with_ttl(Duration::from_secs(60));
+        assert!(
+            store
+                .compare_and_swap(&key, Some(updated.version), 5)
+                .unwrap()
+        );
+        assert_ne!(lease(&store), 0);
+        assert!(store.remove(&key).unwrap());
+    }
+}
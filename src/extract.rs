@@ -0,0 +1,321 @@
+use std::{
+    hash::Hash,
+    net::{IpAddr, SocketAddr},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use http::{
+    HeaderName, Request,
+    header::{AUTHORIZATION, FORWARDED},
+};
+
+/// Derives the key an HTTP request is rate limited under, for the HTTP
+/// integrations such as [`AxumRateLimitLayer`](crate::AxumRateLimitLayer).
+pub trait KeyExtractor {
+    type Key: Hash + Eq + Clone;
+
+    /// The key `request` is limited under, or `None` to limit it on the
+    /// limiter's base state.
+    fn extract<B>(&self, request: &Request<B>) -> Option<Self::Key>;
+}
+
+/// Keys requests by the IP address of the connected peer.
+///
+/// The peer address is read from a `SocketAddr` request extension, from
+/// axum's `ConnectInfo<SocketAddr>` when the `axum` feature is enabled, or
+/// from tonic's `TcpConnectInfo` when the `tonic` feature is enabled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerIp;
+
+impl KeyExtractor for PeerIp {
+    type Key = IpAddr;
+
+    fn extract<B>(&self, request: &Request<B>) -> Option<IpAddr> {
+        let extensions = request.extensions();
+        #[cfg(feature = "axum")]
+        if let Some(info) = extensions.get::<axum::extract::ConnectInfo<SocketAddr>>() {
+            return Some(info.0.ip());
+        }
+        #[cfg(feature = "tonic")]
+        if let Some(info) = extensions.get::<tonic::transport::server::TcpConnectInfo>() {
+            return info.remote_addr().map(|addr| addr.ip());
+        }
+        extensions.get::<SocketAddr>().map(SocketAddr::ip)
+    }
+}
+
+/// Keys requests by the value of a header, e.g. an API key. Requests
+/// without the header, or with a value that isn't visible ASCII, have no
+/// key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderKey {
+    name: HeaderName,
+}
+
+impl HeaderKey {
+    pub fn new(name: HeaderName) -> Self {
+        Self { name }
+    }
+}
+
+impl KeyExtractor for HeaderKey {
+    type Key = String;
+
+    fn extract<B>(&self, request: &Request<B>) -> Option<String> {
+        let value = request.headers().get(&self.name)?;
+        value.to_str().ok().map(str::to_owned)
+    }
+}
+
+/// Keys requests by the IP address of the client behind reverse proxies,
+/// taken from the one header the proxies are configured to append to:
+/// either `X-Forwarded-For` or `Forwarded`.
+///
+/// Each proxy appends the address it received the request from, so only
+/// the last `trusted_proxies` entries can be trusted; anything before them
+/// may have been sent by the client. The client is the entry added by the
+/// outermost trusted proxy, or the first entry if there are fewer. The
+/// other header is ignored, since a proxy passes it through as the client
+/// sent it. Requests without the header are keyed by their [peer](PeerIp),
+/// e.g. when a proxy is bypassed.
+///
+/// ```
+/// use ratelimit::ForwardedIp;
+///
+/// // Behind a single load balancer appending to `X-Forwarded-For`.
+/// let extractor = ForwardedIp::x_forwarded_for(1);
+/// # drop(extractor);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForwardedIp {
+    header: ForwardedHeader,
+    trusted_proxies: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ForwardedHeader {
+    XForwardedFor,
+    Forwarded,
+}
+
+impl ForwardedIp {
+    /// Reads the client from `X-Forwarded-For`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `trusted_proxies` is zero; use [`PeerIp`] without proxies.
+    pub fn x_forwarded_for(trusted_proxies: usize) -> Self {
+        Self::with_header(ForwardedHeader::XForwardedFor, trusted_proxies)
+    }
+
+    /// Reads the client from the `for` parameters of `Forwarded`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `trusted_proxies` is zero; use [`PeerIp`] without proxies.
+    pub fn forwarded(trusted_proxies: usize) -> Self {
+        Self::with_header(ForwardedHeader::Forwarded, trusted_proxies)
+    }
+
+    fn with_header(header: ForwardedHeader, trusted_proxies: usize) -> Self {
+        assert!(trusted_proxies > 0, "ForwardedIp needs a trusted proxy");
+        Self {
+            header,
+            trusted_proxies,
+        }
+    }
+}
+
+impl KeyExtractor for ForwardedIp {
+    type Key = IpAddr;
+
+    fn extract<B>(&self, request: &Request<B>) -> Option<IpAddr> {
+        let headers = request.headers();
+        let chain: Vec<&str> = match self.header {
+            ForwardedHeader::XForwardedFor => headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .collect(),
+            ForwardedHeader::Forwarded => headers
+                .get_all(FORWARDED)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .filter_map(|element| {
+                    element.split(';').find_map(|pair| {
+                        let (name, value) = pair.trim().split_once('=')?;
+                        name.eq_ignore_ascii_case("for").then_some(value)
+                    })
+                })
+                .collect(),
+        };
+        if chain.is_empty() {
+            return PeerIp.extract(request);
+        }
+        let index = chain.len().saturating_sub(self.trusted_proxies);
+        parse_node(chain[index])
+    }
+}
+
+/// Parses a forwarded node, such as `192.0.2.1`, `"[2001:db8::1]:4711"` or
+/// `192.0.2.1:80`. Obfuscated and `unknown` nodes have no address.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+/// Keys requests by the subject of their `Authorization: Bearer` token: the
+/// `sub` claim of a JWT, or the token itself if it is opaque.
+///
+/// The token is not verified, so this must run after the request has been
+/// authenticated, or a client could pick its own key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BearerSubject;
+
+impl KeyExtractor for BearerSubject {
+    type Key = String;
+
+    fn extract<B>(&self, request: &Request<B>) -> Option<String> {
+        let value = request.headers().get(AUTHORIZATION)?.to_str().ok()?;
+        let (scheme, token) = value.split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("bearer") {
+            return None;
+        }
+        let token = token.trim();
+        let mut parts = token.split('.');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(_), Some(payload), Some(_), None) => {
+                let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+                let claims: serde_json::Value = serde_json::from_slice(&payload).ok()?;
+                claims.get("sub")?.as_str().map(str::to_owned)
+            }
+            _ if token.is_empty() => None,
+            _ => Some(token.to_owned()),
+        }
+    }
+}
+
+/// Keys requests by both keys, e.g. `(RpcMethod, PeerIp)` to limit each
+/// client on each method. Requests lacking either key have no key.
+impl<A: KeyExtractor, B: KeyExtractor> KeyExtractor for (A, B) {
+    type Key = (A::Key, B::Key);
+
+    fn extract<Body>(&self, request: &Request<Body>) -> Option<Self::Key> {
+        Some((self.0.extract(request)?, self.1.extract(request)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_ip() {
+        let mut request = Request::new(());
+        assert_eq!(PeerIp.extract(&request), None);
+
+        let addr: SocketAddr = "10.0.0.1:4000".parse().unwrap();
+        request.extensions_mut().insert(addr);
+        assert_eq!(PeerIp.extract(&request), Some(addr.ip()));
+    }
+
+    #[test]
+    fn test_header_key() {
+        let extractor = HeaderKey::new(HeaderName::from_static("x-api-key"));
+        let request = Request::builder()
+            .header("x-api-key", "secret")
+            .body(())
+            .unwrap();
+        assert_eq!(extractor.extract(&request).as_deref(), Some("secret"));
+        assert_eq!(extractor.extract(&Request::new(())), None);
+    }
+
+    #[test]
+    fn test_forwarded_ip() {
+        let request = |name, value| Request::builder().header(name, value).body(()).unwrap();
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+
+        let xff = request("x-forwarded-for", "203.0.113.7, 10.0.0.2, 10.0.0.3");
+        let xff_ip = ForwardedIp::x_forwarded_for;
+        assert_eq!(xff_ip(1).extract(&xff), ip("10.0.0.3"));
+        assert_eq!(xff_ip(2).extract(&xff), ip("10.0.0.2"));
+        // 链比信任的代理数短时取最左边的地址
+        assert_eq!(xff_ip(5).extract(&xff), ip("203.0.113.7"));
+
+        let forwarded = request(
+            "forwarded",
+            "for=192.0.2.60;proto=http, for=\"[2001:db8:cafe::17]:4711\"",
+        );
+        assert_eq!(
+            ForwardedIp::forwarded(1).extract(&forwarded),
+            ip("2001:db8:cafe::17")
+        );
+        assert_eq!(
+            ForwardedIp::forwarded(2).extract(&forwarded),
+            ip("192.0.2.60")
+        );
+        let unknown = request("forwarded", "for=unknown");
+        assert_eq!(ForwardedIp::forwarded(1).extract(&unknown), None);
+
+        // 没有转发头时退回到对端地址
+        let mut direct = Request::new(());
+        direct
+            .extensions_mut()
+            .insert("10.0.0.9:1234".parse::<SocketAddr>().unwrap());
+        assert_eq!(xff_ip(1).extract(&direct), ip("10.0.0.9"));
+    }
+
+    #[test]
+    fn test_forwarded_ip_ignores_other_header() {
+        let ip = |s: &str| Some(s.parse::<IpAddr>().unwrap());
+        let mut spoofed = Request::builder()
+            .header("forwarded", "for=198.51.100.1")
+            .header("x-forwarded-for", "203.0.113.7")
+            .body(())
+            .unwrap();
+        spoofed
+            .extensions_mut()
+            .insert("10.0.0.9:1234".parse::<SocketAddr>().unwrap());
+
+        // 只追加 X-Forwarded-For 的代理会原样转发客户端伪造的 Forwarded
+        assert_eq!(
+            ForwardedIp::x_forwarded_for(1).extract(&spoofed),
+            ip("203.0.113.7")
+        );
+        // 反之亦然，也不会退回到另一个头
+        spoofed.headers_mut().remove("forwarded");
+        assert_eq!(ForwardedIp::forwarded(1).extract(&spoofed), ip("10.0.0.9"));
+    }
+
+    #[test]
+    fn test_bearer_subject() {
+        let request = |auth: &str| {
+            Request::builder()
+                .header(AUTHORIZATION, auth)
+                .body(())
+                .unwrap()
+        };
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"user-42","exp":0}"#);
+        let jwt = format!("Bearer eyJhbGciOiJIUzI1NiJ9.{payload}.c2ln");
+
+        assert_eq!(
+            BearerSubject.extract(&request(&jwt)).as_deref(),
+            Some("user-42")
+        );
+        assert_eq!(
+            BearerSubject
+                .extract(&request("bearer opaque-token"))
+                .as_deref(),
+            Some("opaque-token")
+        );
+        assert_eq!(BearerSubject.extract(&request("Basic dXNlcjpwdw==")), None);
+        assert_eq!(BearerSubject.extract(&Request::new(())), None);
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, VecDeque},
+    future::poll_fn,
+    hash::Hash,
+    sync::{Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    algorithm::Algorithm,
+    clock::Clock,
+    error::AcquireError,
+    limiter::RateLimiter,
+    sleeper::{AsyncSleeper, DefaultSleeper},
+    state::State,
+};
+
+/// A [`RateLimiter`] shared between tasks that grants permits to async
+/// waiters in arrival order.
+///
+/// Waiters line up in a queue and only the one at its front tries to acquire,
+/// so a long-waiting task is never overtaken by a stream of newcomers that
+/// happen to be polled right after a window resets. The base state and each
+/// key have their own queue, so a saturated key neither delays nor wakes
+/// tasks waiting on other keys. Synchronous calls made through
+/// [`with_limiter`](Self::with_limiter) do not queue.
+///
+/// Waiters may declare a [`Priority`], e.g. to let interactive traffic
+/// jump ahead of batch traffic sharing the same quota. A queue is ordered
+/// by priority and then by arrival, except that the waiter already trying
+/// to acquire keeps its place.
+#[derive(Debug)]
+pub struct FairRateLimiter<C: Clock, S: Algorithm<C> = State<C>, K: Hash + Eq + Clone = String> {
+    shared: Mutex<Shared<C, S, K>>,
+}
+
+#[derive(Debug)]
+struct Shared<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> {
+    limiter: RateLimiter<C, S, K>,
+    base_queue: WaitQueue,
+    /// Only keys with waiters have a queue.
+    key_queues: HashMap<K, WaitQueue>,
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> Shared<C, S, K> {
+    fn queue(&mut self, key: Option<&K>) -> Option<&mut WaitQueue> {
+        match key {
+            Some(key) => self.key_queues.get_mut(key),
+            None => Some(&mut self.base_queue),
+        }
+    }
+}
+
+/// The class of a waiter; higher classes are served first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+#[derive(Debug, Default)]
+struct WaitQueue {
+    next_ticket: u64,
+    waiters: VecDeque<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    ticket: u64,
+    priority: Priority,
+    /// Reached the front and is acquiring, so it can't be overtaken.
+    admitted: bool,
+    waker: Option<Waker>,
+}
+
+impl WaitQueue {
+    /// Queues a waiter behind every admitted waiter and every waiter of at
+    /// least the same priority.
+    fn push(&mut self, priority: Priority) -> u64 {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        let position = self
+            .waiters
+            .iter()
+            .position(|waiter| !waiter.admitted && waiter.priority < priority)
+            .unwrap_or(self.waiters.len());
+        self.waiters.insert(
+            position,
+            Waiter {
+                ticket,
+                priority,
+                admitted: false,
+                waker: None,
+            },
+        );
+        ticket
+    }
+
+    fn position(&self, ticket: u64) -> Option<usize> {
+        self.waiters
+            .iter()
+            .position(|waiter| waiter.ticket == ticket)
+    }
+
+    /// Removes `ticket`, waking the next waiter if it was at the front.
+    fn remove(&mut self, ticket: u64) {
+        let Some(position) = self.position(ticket) else {
+            return;
+        };
+        self.waiters.remove(position);
+        if position == 0
+            && let Some(waker) = self
+                .waiters
+                .front_mut()
+                .and_then(|waiter| waiter.waker.take())
+        {
+            waker.wake();
+        }
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> FairRateLimiter<C, S, K> {
+    pub fn new(limiter: RateLimiter<C, S, K>) -> Self {
+        Self {
+            shared: Mutex::new(Shared {
+                limiter,
+                base_queue: WaitQueue::default(),
+                key_queues: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Runs `f` on the underlying limiter, bypassing the waiter queues.
+    pub fn with_limiter<R>(&self, f: impl FnOnce(&mut RateLimiter<C, S, K>) -> R) -> R {
+        f(&mut self.lock().limiter)
+    }
+
+    pub fn into_inner(self) -> RateLimiter<C, S, K> {
+        self.shared
+            .into_inner()
+            .unwrap_or_else(PoisonError::into_inner)
+            .limiter
+    }
+
+    /// Waits in line until the base state grants a permit.
+    ///
+    /// Cancel safe: dropping the future leaves the queue without consuming a
+    /// permit and lets the next waiter move up.
+    pub async fn until_ready(&self) -> Result<(), AcquireError> {
+        self.until_n_ready_with_priority(1, Priority::Normal).await
+    }
+
+    /// Like [`until_ready`](Self::until_ready), but queues with `priority`.
+    pub async fn until_ready_with_priority(&self, priority: Priority) -> Result<(), AcquireError> {
+        self.until_n_ready_with_priority(1, priority).await
+    }
+
+    /// Waits in line until the base state grants `n` permits at once. See
+    /// [`RateLimiter::until_n_ready`].
+    pub async fn until_n_ready(&self, n: u64) -> Result<(), AcquireError> {
+        self.until_n_ready_with_priority(n, Priority::Normal).await
+    }
+
+    /// Like [`until_n_ready`](Self::until_n_ready), but queues with
+    /// `priority`.
+    pub async fn until_n_ready_with_priority(
+        &self,
+        n: u64,
+        priority: Priority,
+    ) -> Result<(), AcquireError> {
+        let turn = Turn::enqueue(self, None, priority);
+        poll_fn(|cx| turn.poll_front(cx)).await;
+        loop {
+            let retry_after = match self.lock().limiter.acquire_n(n) {
+                Ok(()) => return Ok(()),
+                Err(AcquireError::NotAllowed { retry_after, .. }) => retry_after,
+                Err(err) => return Err(err),
+            };
+            DefaultSleeper::sleep(retry_after).await;
+        }
+    }
+
+    /// Waits in the queue of `key` until it is granted a permit. Waiters on
+    /// other keys are unaffected.
+    pub async fn until_key_ready<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.until_key_ready_with_priority(key, Priority::Normal)
+            .await
+    }
+
+    /// Like [`until_key_ready`](Self::until_key_ready), but queues with
+    /// `priority`.
+    pub async fn until_key_ready_with_priority<Q>(
+        &self,
+        key: &Q,
+        priority: Priority,
+    ) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let turn = Turn::enqueue(self, Some(key.to_owned()), priority);
+        poll_fn(|cx| turn.poll_front(cx)).await;
+        loop {
+            let retry_after = match self.lock().limiter.acquire_by_key(key) {
+                Ok(()) => return Ok(()),
+                Err(AcquireError::NotAllowed { retry_after, .. }) => retry_after,
+                Err(err) => return Err(err),
+            };
+            DefaultSleeper::sleep(retry_after).await;
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Shared<C, S, K>> {
+        self.shared.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A place in the queue of the base state or of a key, given up when
+/// dropped.
+struct Turn<'a, C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> {
+    limiter: &'a FairRateLimiter<C, S, K>,
+    key: Option<K>,
+    ticket: u64,
+}
+
+impl<'a, C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> Turn<'a, C, S, K> {
+    fn enqueue(limiter: &'a FairRateLimiter<C, S, K>, key: Option<K>, priority: Priority) -> Self {
+        let mut shared = limiter.lock();
+        let queue = match &key {
+            Some(key) => shared.key_queues.entry(key.clone()).or_default(),
+            None => &mut shared.base_queue,
+        };
+        let ticket = queue.push(priority);
+        drop(shared);
+        Self {
+            limiter,
+            key,
+            ticket,
+        }
+    }
+
+    fn poll_front(&self, cx: &mut Context<'_>) -> Poll<()> {
+        let mut shared = self.limiter.lock();
+        let queue = shared
+            .queue(self.key.as_ref())
+            .expect("a queued turn has a queue");
+        let position = queue
+            .position(self.ticket)
+            .expect("a queued turn is in its queue");
+        if position == 0 {
+            queue.waiters[0].admitted = true;
+            return Poll::Ready(());
+        }
+        queue.waiters[position].waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> Drop for Turn<'_, C, S, K> {
+    fn drop(&mut self) {
+        let mut shared = self.limiter.lock();
+        let Some(queue) = shared.queue(self.key.as_ref()) else {
+            return;
+        };
+        queue.remove(self.ticket);
+        if let Some(key) = &self.key
+            && queue.waiters.is_empty()
+        {
+            shared.key_queues.remove(key);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio::{sync::mpsc, time::Instant};
+
+    use super::*;
+    use crate::{clock::TokioClock, quota::Quota};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fair_waiters_served_in_arrival_order() {
+        let start = Instant::now();
+        let limiter = Arc::new(FairRateLimiter::new(RateLimiter::new(
+            Quota::per_second(1),
+            TokioClock,
+        )));
+        limiter.until_ready().await.unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for id in 0..3 {
+            let limiter = Arc::clone(&limiter);
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                limiter.until_ready().await.unwrap();
+                tx.send((id, start.elapsed().as_millis())).unwrap();
+            });
+            // 让任务按顺序入队
+            tokio::task::yield_now().await;
+        }
+        drop(tx);
+
+        // 后来者在窗口重置时也不能插队
+        tokio::time::sleep(Duration::from_millis(999)).await;
+        let newcomer = {
+            let limiter = Arc::clone(&limiter);
+            tokio::spawn(async move {
+                limiter.until_ready().await.unwrap();
+                start.elapsed().as_millis()
+            })
+        };
+
+        let mut served = Vec::new();
+        while let Some(entry) = rx.recv().await {
+            served.push(entry);
+        }
+        assert_eq!(served, vec![(0, 1_000), (1, 2_000), (2, 3_000)]);
+        assert_eq!(newcomer.await.unwrap(), 4_000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fair_higher_priority_served_first() {
+        let start = Instant::now();
+        let limiter = Arc::new(FairRateLimiter::new(RateLimiter::new(
+            Quota::per_second(1),
+            TokioClock,
+        )));
+        limiter.until_ready().await.unwrap();
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let priorities = [
+            Priority::Low,
+            Priority::Low,
+            Priority::Normal,
+            Priority::High,
+        ];
+        for (id, priority) in priorities.into_iter().enumerate() {
+            let limiter = Arc::clone(&limiter);
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                limiter.until_ready_with_priority(priority).await.unwrap();
+                tx.send((id, start.elapsed().as_millis())).unwrap();
+            });
+            tokio::task::yield_now().await;
+        }
+        drop(tx);
+
+        // 第一个等待者已在队首，不会被插队；其余按优先级、同级按到达顺序
+        let mut served = Vec::new();
+        while let Some(entry) = rx.recv().await {
+            served.push(entry);
+        }
+        assert_eq!(served, vec![(0, 1_000), (3, 2_000), (2, 3_000), (1, 4_000)]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fair_key_queues_are_independent() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new(Quota::per_second(1), TokioClock);
+        limiter.insert_key("slow", Quota::per_minute(1));
+        limiter.insert_key("fast", Quota::per_second(10));
+        let limiter = Arc::new(FairRateLimiter::new(limiter));
+        limiter.until_key_ready("slow").await.unwrap();
+
+        // slow 已经饱和，排队等待的任务不影响 fast
+        let blocked = {
+            let limiter = Arc::clone(&limiter);
+            tokio::spawn(async move { limiter.until_key_ready("slow").await })
+        };
+        tokio::task::yield_now().await;
+        for _ in 0..10 {
+            limiter.until_key_ready("fast").await.unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(limiter.lock().key_queues.len(), 1);
+
+        blocked.await.unwrap().unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(60));
+        assert!(limiter.lock().key_queues.is_empty());
+        assert_eq!(
+            limiter.until_key_ready("unknown").await,
+            Err(AcquireError::UnknownKey)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_fair_cancelled_waiter_leaves_queue() {
+        let limiter = FairRateLimiter::new(RateLimiter::new(Quota::per_second(1), TokioClock));
+        limiter.until_ready().await.unwrap();
+
+        // 队首被取消后，下一个等待者接替且许可未被消耗
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(500), limiter.until_ready()).await;
+        assert!(cancelled.is_err());
+        limiter.until_ready().await.unwrap();
+        assert_eq!(
+            limiter.with_limiter(|limiter| limiter.check()),
+            Err(AcquireError::NotAllowed {
+                retry_after: Duration::from_secs(1),
+                quota: Quota::per_second(1),
+                remaining: 0,
+                backoff: Duration::from_secs(1),
+            })
+        );
+        assert!(limiter.lock().base_queue.waiters.is_empty());
+    }
+}
@@ -0,0 +1,351 @@
+use std::{borrow::Borrow, hash::Hash, time::Duration};
+
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, Reference},
+    error::AcquireError,
+    jitter::Jitter,
+    limiter::{Attempt, RateLimiter},
+    sleeper::{AsyncSleeper, DefaultSleeper},
+};
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> RateLimiter<C, S, K> {
+    /// Waits until the base state grants a permit, sleeping on the
+    /// [`DefaultSleeper`] for as long as each denial asks.
+    ///
+    /// Fails without waiting if the limiter is disabled or the base state has
+    /// no capacity.
+    ///
+    /// # Cancel safety
+    ///
+    /// Permits are only taken in the poll that resolves the future, so
+    /// dropping it early, e.g. in a losing `select!` branch or on a timeout,
+    /// never consumes one. The same holds for every async wait on
+    /// [`RateLimiter`].
+    pub async fn until_ready(&self) -> Result<(), AcquireError> {
+        let started = self.clock().now();
+        let result = self
+            .until(None, Jitter::NONE, || self.try_acquire_base(1))
+            .await;
+        self.report_base(1, Some(started), result)
+    }
+
+    /// Waits until `key` is granted a permit, like
+    /// [`until_ready`](Self::until_ready).
+    pub async fn until_key_ready<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let started = self.clock().now();
+        let result = self
+            .until(None, Jitter::NONE, || self.try_acquire_key(key, 1))
+            .await;
+        self.report_key(key, 1, Some(started), result)
+    }
+
+    /// Waits until the base state can grant `n` permits at once, for jobs
+    /// that must reserve a whole chunk of quota before starting.
+    ///
+    /// Fails without waiting with
+    /// [`InsufficientCapacity`](AcquireError::InsufficientCapacity) if `n`
+    /// exceeds what the base state can ever grant at once. Cancel safe: the
+    /// `n` permits are taken together in the poll that resolves the future.
+    pub async fn until_n_ready(&self, n: u64) -> Result<(), AcquireError> {
+        let started = self.clock().now();
+        let result = self
+            .until(None, Jitter::NONE, || self.try_acquire_base(n))
+            .await;
+        self.report_base(n, Some(started), result)
+    }
+
+    /// Waits until `key` can be granted `n` permits at once, like
+    /// [`until_n_ready`](Self::until_n_ready).
+    pub async fn until_key_n_ready<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let started = self.clock().now();
+        let result = self
+            .until(None, Jitter::NONE, || self.try_acquire_key(key, n))
+            .await;
+        self.report_key(key, n, Some(started), result)
+    }
+
+    /// Like [`until_ready`](Self::until_ready), but extends every wait by
+    /// `jitter` so that tasks woken by the same window reset spread out.
+    pub async fn until_ready_with_jitter(&self, jitter: Jitter) -> Result<(), AcquireError> {
+        let started = self.clock().now();
+        let result = self.until(None, jitter, || self.try_acquire_base(1)).await;
+        self.report_base(1, Some(started), result)
+    }
+
+    /// Like [`until_key_ready`](Self::until_key_ready), but extends every
+    /// wait by `jitter`.
+    pub async fn until_key_ready_with_jitter<Q>(
+        &self,
+        key: &Q,
+        jitter: Jitter,
+    ) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let started = self.clock().now();
+        let result = self
+            .until(None, jitter, || self.try_acquire_key(key, 1))
+            .await;
+        self.report_key(key, 1, Some(started), result)
+    }
+
+    /// Like [`until_ready`](Self::until_ready), but gives up as soon as the
+    /// permit could not be granted within `timeout` of the call.
+    pub async fn until_ready_or_timeout(&self, timeout: Duration) -> Result<(), AcquireError> {
+        let started = self.clock().now();
+        let result = self
+            .until(Some(timeout), Jitter::NONE, || self.try_acquire_base(1))
+            .await;
+        self.report_base(1, Some(started), result)
+    }
+
+    /// Like [`until_key_ready`](Self::until_key_ready), but gives up as soon
+    /// as the permit could not be granted within `timeout` of the call.
+    pub async fn until_key_ready_or_timeout<Q>(
+        &self,
+        key: &Q,
+        timeout: Duration,
+    ) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let started = self.clock().now();
+        let result = self
+            .until(Some(timeout), Jitter::NONE, || self.try_acquire_key(key, 1))
+            .await;
+        self.report_key(key, 1, Some(started), result)
+    }
+
+    /// The async counterpart of [`Algorithm::acquire_wait`], retrying
+    /// `attempt` until it succeeds or fails outright. No lock is held while
+    /// sleeping.
+    async fn until(
+        &self,
+        max_wait: Option<Duration>,
+        jitter: Jitter,
+        mut attempt: impl FnMut() -> Attempt<C::Instant>,
+    ) -> Result<(), AcquireError> {
+        let start = self.clock().now();
+        loop {
+            let result = attempt()?;
+            let now = self.clock().now();
+            let not_until = match result {
+                Ok(()) => return Ok(()),
+                Err(not_until) => not_until,
+            };
+            let wait = not_until.wait_time_from(now);
+            let waited = Duration::from(now.duration_since(start));
+            if not_until.overflowed() || max_wait.is_some_and(|max| waited + wait > max) {
+                return Err(AcquireError::not_allowed(not_until, now));
+            }
+            DefaultSleeper::sleep(jitter.apply(wait)).await;
+        }
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::{TokenBucketState, clock::TokioClock, nanos::Nanos, quota::Quota};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_ready_waits_for_window() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new(Quota::per_second(2), TokioClock);
+
+        for _ in 0..2 {
+            limiter.until_ready().await.unwrap();
+        }
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // 第 3 个许可等到下一个窗口
+        limiter.until_ready().await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_ready_with_jitter() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new(Quota::per_second(1), TokioClock);
+        let jitter = Jitter::new(Duration::from_millis(10), Duration::from_millis(100));
+
+        // 有许可时不等待，也就没有抖动
+        limiter.until_ready_with_jitter(jitter).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        limiter.until_ready_with_jitter(jitter).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(1_010));
+        assert!(start.elapsed() <= Duration::from_millis(1_110));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_key_ready() {
+        let start = Instant::now();
+        let base = TokenBucketState::new(1, Nanos::new(100_000_000), TokioClock);
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", Quota::per_second(4));
+
+        for _ in 0..6 {
+            limiter.until_key_ready("user").await.unwrap();
+        }
+        // 4 个突发之后每 250ms 补充 1 个
+        assert_eq!(start.elapsed(), Duration::from_millis(500));
+
+        assert_eq!(
+            limiter.until_key_ready("unknown").await,
+            Err(AcquireError::UnknownKey)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_key_ready_waits_for_ancestor() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new(Quota::per_second(100), TokioClock).with_key_separator('/');
+        limiter.insert_key("acme", Quota::per_second(1));
+        limiter.insert_key("acme/alice", Quota::per_second(10));
+
+        // 用户还有配额，但要等租户的下一个窗口
+        limiter.until_key_ready("acme/alice").await.unwrap();
+        limiter.until_key_ready("acme/alice").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_n_ready() {
+        let start = Instant::now();
+        let base = TokenBucketState::new(5, Nanos::new(100_000_000), TokioClock);
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", Quota::per_second(4));
+
+        limiter.until_n_ready(4).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // 只剩 1 个令牌，要等补齐 3 个才能一次拿到 4 个
+        limiter.until_n_ready(4).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(300));
+
+        assert_eq!(
+            limiter.until_n_ready(6).await,
+            Err(AcquireError::InsufficientCapacity { capacity: 5 })
+        );
+
+        // key 每 250ms 补充 1 个，剩 1 个时再等 1 个即可拿到 2 个
+        limiter.until_key_n_ready("user", 3).await.unwrap();
+        limiter.until_key_n_ready("user", 2).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_millis(550));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_ready_or_timeout() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new(Quota::per_second(1), TokioClock);
+        limiter.insert_key("user", Quota::per_second(1));
+        limiter.until_ready().await.unwrap();
+
+        // 超时不足以等到下一个窗口，立即失败且不消耗许可
+        let result = limiter
+            .until_ready_or_timeout(Duration::from_millis(500))
+            .await;
+        assert!(matches!(
+            result,
+            Err(AcquireError::NotAllowed { retry_after, .. }) if retry_after == Duration::from_secs(1)
+        ));
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        limiter
+            .until_ready_or_timeout(Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // 被外部超时取消的等待不会消耗 key 的许可
+        limiter.until_key_ready("user").await.unwrap();
+        let cancelled =
+            tokio::time::timeout(Duration::from_millis(100), limiter.until_key_ready("user")).await;
+        assert!(cancelled.is_err());
+        limiter
+            .until_key_ready_or_timeout("user", Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    // 以下测试验证取消安全：select! 中落败的等待被丢弃后不消耗任何许可
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_ready_cancelled_by_select() {
+        let limiter = RateLimiter::new(Quota::per_second(2), TokioClock);
+        limiter.until_ready().await.unwrap();
+        limiter.until_ready().await.unwrap();
+
+        let won = tokio::select! {
+            _ = limiter.until_ready() => false,
+            _ = tokio::time::sleep(Duration::from_millis(500)) => true,
+        };
+        assert!(won);
+
+        // 窗口重置后两个许可都还在
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert_eq!(limiter.check(), Ok(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_n_ready_cancelled_by_select() {
+        let base = TokenBucketState::new(4, Nanos::new(100_000_000), TokioClock);
+        let limiter = RateLimiter::from_state(base);
+        limiter.until_n_ready(3).await.unwrap();
+
+        // 等待 4 个令牌期间被取消，已有的令牌不会被部分扣除
+        let won = tokio::select! {
+            _ = limiter.until_n_ready(4) => false,
+            _ = tokio::time::sleep(Duration::from_millis(150)) => true,
+        };
+        assert!(won);
+        assert_eq!(limiter.check(), Ok(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_key_ready_cancelled_by_select() {
+        let limiter = RateLimiter::new(Quota::per_second(10), TokioClock);
+        limiter.insert_key("user", Quota::per_second(1));
+        limiter.until_key_ready("user").await.unwrap();
+
+        for _ in 0..3 {
+            tokio::select! {
+                biased;
+                _ = tokio::time::sleep(Duration::from_millis(100)) => {}
+                _ = limiter.until_key_ready_with_jitter("user", Jitter::up_to(Duration::from_millis(10))) => {
+                    panic!("permit granted before the window reset");
+                }
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(700)).await;
+        assert_eq!(limiter.check_key("user"), Ok(1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_until_ready_fails_fast() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new(Quota::per_second(0), TokioClock);
+        assert!(limiter.until_ready().await.is_err());
+
+        limiter.set_enabled(false);
+        assert_eq!(limiter.until_ready().await, Err(AcquireError::Disabled));
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+}
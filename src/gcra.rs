@@ -0,0 +1,412 @@
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, Reference},
+    error::InsufficientCapacity,
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+    store::StoredState,
+};
+
+/// The generic cell rate algorithm (GCRA), a leaky bucket that admits one
+/// permit every `emission_interval` while tolerating bursts of up to `burst`.
+///
+/// The whole state is a single theoretical arrival time (TAT), stored as
+/// nanoseconds since the state was created. Permits are spaced out evenly
+/// instead of being released all at once when a window resets.
+///
+/// The TAT and the tolerance are kept in 128 bits, so a long emission
+/// interval times a large burst, e.g. 1 per 30 days with a burst of 10 000,
+/// never overflows.
+#[derive(Debug)]
+pub struct GcraState<C: Clock> {
+    quota: Quota,
+    emission_interval: Nanos,
+    start: C::Instant,
+    tat: u128,
+    clock: C,
+}
+
+impl<C: Clock> GcraState<C> {
+    /// # Panics
+    ///
+    /// Panics if `emission_interval` is zero.
+    pub fn new(burst: u64, emission_interval: Nanos, clock: C) -> Self {
+        assert!(
+            emission_interval > Nanos::ZERO,
+            "emission interval must be non-zero"
+        );
+        Self::from_quota(Quota::new(1, emission_interval).allow_burst(burst), clock)
+    }
+
+    /// Admits a permit, or on denial reports when the request would conform.
+    pub fn acquire(&mut self) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.clock.now();
+        self.try_acquire_at(now)
+    }
+
+    /// Admits `n` permits at once, or none of them. On denial reports when
+    /// all `n` permits would conform.
+    pub fn acquire_n(
+        &mut self,
+        n: u64,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        let now = self.clock.now();
+        self.try_acquire_n_at(n, now)
+    }
+
+    /// How far ahead of the TAT a request may arrive and still conform.
+    fn tolerance(&self) -> u128 {
+        self.emission_interval
+            .widening_mul(self.quota.burst().saturating_sub(1))
+    }
+
+    /// The tolerance plus one emission interval: how far the TAT may run
+    /// ahead of the current time.
+    fn limit(&self) -> u128 {
+        self.tolerance() + u128::from(self.emission_interval.as_u64())
+    }
+
+    /// Verifies the internal bookkeeping in debug builds; compiles out in release.
+    fn check_invariants(&self, now_offset: u128) {
+        debug_assert!(
+            self.tat.saturating_sub(now_offset) <= self.limit(),
+            "TAT {:?} is more than a burst ahead of {:?}",
+            self.tat,
+            now_offset
+        );
+    }
+
+    fn offset(&self, now: C::Instant) -> u128 {
+        u128::from(now.duration_since(self.start).as_u64())
+    }
+
+    /// Admits `n` permits if the TAT after adding them stays within the
+    /// tolerance, i.e. if the last of them would conform.
+    fn take_at(&mut self, n: u64, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        let now_offset = self.offset(now);
+        if n == 0 {
+            return Ok(());
+        }
+        if self.quota.burst() == 0 {
+            return Err(NotUntil::after(now, self.emission_interval, self.quota, 0));
+        }
+
+        let increment = self.emission_interval.widening_mul(n - 1);
+        let earliest = self
+            .tat
+            .saturating_add(increment)
+            .saturating_sub(self.tolerance());
+        if now_offset < earliest {
+            let remaining = self.remaining_at(now);
+            return Err(NotUntil::after_wide(
+                self.start, earliest, self.quota, remaining,
+            ));
+        }
+        self.tat = self
+            .tat
+            .max(now_offset)
+            .saturating_add(increment)
+            .saturating_add(u128::from(self.emission_interval.as_u64()));
+        self.check_invariants(now_offset);
+        Ok(())
+    }
+}
+
+/// The [snapshot](StoredState::Snapshot) of a [`GcraState`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GcraSnapshot<P> {
+    /// The instant the TAT is measured from.
+    pub start: P,
+    /// The theoretical arrival time, in nanoseconds after `start`.
+    pub tat: u128,
+}
+
+impl<C: Clock> StoredState<C> for GcraState<C> {
+    type Snapshot = GcraSnapshot<C::Instant>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        GcraSnapshot {
+            start: self.start,
+            tat: self.tat,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Self::Snapshot) {
+        let tat = match snapshot.start.checked_duration_since(self.start) {
+            Some(later) => snapshot.tat.saturating_add(u128::from(later.as_u64())),
+            None => {
+                let earlier = self.start.duration_since(snapshot.start);
+                snapshot.tat.saturating_sub(u128::from(earlier.as_u64()))
+            }
+        };
+        // Never more than a burst ahead, even if the quota has changed.
+        let now_offset = self.offset(self.clock.now());
+        self.tat = tat.min(now_offset + self.limit());
+    }
+}
+
+impl<C: Clock> Algorithm<C> for GcraState<C> {
+    /// Tolerates a burst of `quota.burst()` and then admits `quota.allowed()`
+    /// permits evenly spaced over `quota.window()`.
+    fn from_quota(quota: Quota, clock: C) -> Self {
+        Self {
+            quota,
+            emission_interval: quota.replenish_interval(),
+            start: clock.now(),
+            tat: 0,
+            clock,
+        }
+    }
+
+    fn fresh(&self) -> Self {
+        Self::from_quota(self.quota, self.clock.clone())
+    }
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    fn capacity(&self) -> u64 {
+        self.quota.burst()
+    }
+
+    fn try_acquire_at(&mut self, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.take_at(1, now)
+    }
+
+    fn try_acquire_n_at(
+        &mut self,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        if n > self.quota.burst() {
+            return Err(InsufficientCapacity(self.quota.burst()));
+        }
+        Ok(self.take_at(n, now))
+    }
+
+    fn check_at(&self, now: C::Instant) -> Result<u64, NotUntil<C::Instant>> {
+        if self.quota.burst() == 0 {
+            return Err(NotUntil::after(now, self.emission_interval, self.quota, 0));
+        }
+        let earliest = self.tat.saturating_sub(self.tolerance());
+        if self.offset(now) < earliest {
+            return Err(NotUntil::after_wide(self.start, earliest, self.quota, 0));
+        }
+        Ok(self.remaining_at(now))
+    }
+
+    fn remaining_at(&self, now: C::Instant) -> u64 {
+        if self.quota.burst() == 0 {
+            return 0;
+        }
+        let backlog = self.tat.saturating_sub(self.offset(now));
+        let remaining =
+            self.limit().saturating_sub(backlog) / u128::from(self.emission_interval.as_u64());
+        remaining.try_into().unwrap_or(u64::MAX)
+    }
+
+    fn reset_after_at(&self, now: C::Instant) -> Nanos {
+        Nanos::saturating_from_wide(self.tat.saturating_sub(self.offset(now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        clock::{FakeAbsoluteClock, FakeRelativeClock},
+        limiter::RateLimiter,
+        scenario::{self, checked_acquire, max_accepted_in_any_window, op_strategy, run_scenario},
+    };
+
+    #[test]
+    fn test_gcra_burst_then_spacing() {
+        let clock = FakeRelativeClock::default();
+        // 突发 3 个，之后每 100ms 放行 1 个
+        let mut gcra = GcraState::new(3, Nanos::new(100_000_000), clock.clone());
+
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_err());
+
+        clock.advance(Duration::from_millis(99));
+        assert!(gcra.acquire().is_err());
+        clock.advance(Duration::from_millis(1));
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_err());
+    }
+
+    #[test]
+    fn test_gcra_clock_stepped_back() {
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(100));
+        let mut gcra = GcraState::new(2, Nanos::new(100_000_000), clock.clone());
+        clock.advance(Duration::from_secs(1));
+        assert!(gcra.acquire().is_ok());
+
+        // 时钟回拨到创建之前也只是看不到时间流逝
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_secs(50));
+        assert!(gcra.acquire().is_err());
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_millis(101_000));
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_err());
+    }
+
+    #[test]
+    fn test_gcra_remaining_and_reset_after() {
+        let clock = FakeRelativeClock::default();
+        let mut gcra = GcraState::new(4, Nanos::new(100_000_000), clock.clone());
+        assert_eq!(gcra.remaining_at(clock.now()), 4);
+        assert_eq!(gcra.reset_after_at(clock.now()), Nanos::ZERO);
+
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_ok());
+        assert_eq!(gcra.remaining_at(clock.now()), 2);
+        assert_eq!(gcra.reset_after_at(clock.now()), Nanos::new(200_000_000));
+
+        clock.advance(Duration::from_millis(150));
+        assert_eq!(gcra.remaining_at(clock.now()), 3);
+        assert_eq!(gcra.reset_after_at(clock.now()), Nanos::new(50_000_000));
+
+        clock.advance(Duration::from_millis(50));
+        assert!(gcra.is_idle_at(clock.now()));
+    }
+
+    #[test]
+    fn test_gcra_not_until() {
+        let clock = FakeRelativeClock::default();
+        let mut gcra = GcraState::new(1, Nanos::new(1_000_000_000), clock.clone());
+        assert!(gcra.acquire().is_ok());
+
+        clock.advance(Duration::from_millis(300));
+        let not_until = gcra.acquire().unwrap_err();
+        assert_eq!(
+            not_until.wait_time_from(clock.now()),
+            Duration::from_millis(700)
+        );
+
+        clock.advance(Duration::from_millis(700));
+        assert!(gcra.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_gcra_idle_does_not_accumulate_beyond_burst() {
+        let clock = FakeRelativeClock::default();
+        let mut gcra = GcraState::new(2, Nanos::new(100_000_000), clock.clone());
+
+        clock.advance(Duration::from_secs(60));
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_ok());
+        assert!(gcra.acquire().is_err());
+    }
+
+    #[test]
+    fn test_gcra_acquire_n() {
+        let clock = FakeRelativeClock::default();
+        let mut gcra = GcraState::new(4, Nanos::new(100_000_000), clock.clone());
+        assert_eq!(gcra.acquire_n(3), Ok(Ok(())));
+
+        // 还能突发 1 个，3 个需要等到再补充 2 个
+        let not_until = gcra.acquire_n(3).unwrap().unwrap_err();
+        assert_eq!(not_until.earliest_possible(), Nanos::new(200_000_000));
+        assert_eq!(gcra.remaining_at(clock.now()), 1);
+
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(gcra.acquire_n(3), Ok(Ok(())));
+        assert!(gcra.acquire().is_err());
+        assert_eq!(gcra.acquire_n(5), Err(InsufficientCapacity(4)));
+    }
+
+    #[test]
+    fn test_gcra_long_interval_large_burst() {
+        let clock = FakeRelativeClock::default();
+        let month = Nanos::from(Duration::from_secs(30 * 24 * 60 * 60));
+        // 容差 = 30 天 × 9999，超出 u64 纳秒的表示范围
+        let mut gcra = GcraState::new(10_000, month, clock.clone());
+        for _ in 0..10_000 {
+            assert!(gcra.acquire().is_ok());
+        }
+        let not_until = gcra.acquire().unwrap_err();
+        assert_eq!(not_until.earliest_possible(), month);
+        assert_eq!(gcra.remaining_at(clock.now()), 0);
+        assert_eq!(gcra.reset_after_at(clock.now()), Nanos::MAX);
+    }
+
+    #[test]
+    fn test_gcra_zero_burst() {
+        let clock = FakeRelativeClock::default();
+        let mut gcra = GcraState::new(0, Nanos::new(100_000_000), clock.clone());
+        assert!(gcra.acquire().is_err());
+        clock.advance(Duration::from_secs(10));
+        assert!(gcra.acquire().is_err());
+        assert_eq!(gcra.remaining_at(clock.now()), 0);
+    }
+
+    #[test]
+    fn test_gcra_in_rate_limiter() {
+        let clock = FakeRelativeClock::default();
+        let base = GcraState::from_quota((10, Duration::from_secs(1)).into(), clock.clone());
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", (2, Duration::from_secs(1)));
+
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_err());
+
+        // 每 500ms 放行 1 个
+        clock.advance(Duration::from_millis(500));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_err());
+    }
+
+    #[test]
+    fn test_gcra_quota_burst() {
+        let clock = FakeRelativeClock::default();
+        let base = GcraState::from_quota(Quota::per_second(10), clock.clone());
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", Quota::per_second(2).allow_burst(4));
+
+        // 允许一次突发 4 个，之后仍按每 500ms 1 个放行
+        for _ in 0..4 {
+            assert!(limiter.acquire_by_key("user").is_ok());
+        }
+        assert!(limiter.acquire_by_key("user").is_err());
+        clock.advance(Duration::from_millis(500));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_err());
+    }
+
+    proptest! {
+        #![proptest_config(scenario::config())]
+
+        #[test]
+        fn prop_gcra_admits_at_most_burst_plus_emissions(
+            burst in 0..10u64,
+            interval_ms in 1..500u64,
+            window_ms in 1..2_000u64,
+            ops in prop::collection::vec(op_strategy(), 0..500),
+        ) {
+            let clock = FakeRelativeClock::default();
+            let interval = Nanos::new(interval_ms * 1_000_000);
+            let mut gcra = GcraState::new(burst, interval, clock.clone());
+            let accepted = run_scenario(&ops, &clock, || checked_acquire(&mut gcra));
+
+            // 任意窗口内最多是突发容量加上窗口内按间隔放行的数量
+            let window = Nanos::new(window_ms * 1_000_000);
+            let emissions = window_ms.div_ceil(interval_ms);
+            let bound = if burst == 0 { 0 } else { burst + emissions };
+            prop_assert!(max_accepted_in_any_window(&accepted, window) <= bound);
+        }
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc,
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    clock::ReasonablyRealtime, error::AcquireError, nanos::Nanos, not_until::NotUntil, quota::Quota,
+};
+
+/// How a [`GossipRateLimiter`] sends its counts to its peers.
+///
+/// Digests carry a node's full view of every key it has seen in the current
+/// window, so they may be lost, duplicated or reordered: the next one makes
+/// up for a lost one, and merging the same digest twice changes nothing.
+/// That makes unreliable transports such as UDP datagrams a good fit.
+pub trait GossipTransport<K> {
+    type Error: std::error::Error;
+
+    /// Sends `digest` to some or all of the peers.
+    fn send(&self, digest: GossipDigest<K>) -> Result<(), Self::Error>;
+}
+
+/// Sends digests over a channel, e.g. to a thread that forwards them to the
+/// peers, or straight to another limiter in tests.
+impl<K> GossipTransport<K> for mpsc::Sender<GossipDigest<K>> {
+    type Error = mpsc::SendError<GossipDigest<K>>;
+
+    fn send(&self, digest: GossipDigest<K>) -> Result<(), Self::Error> {
+        mpsc::Sender::send(self, digest)
+    }
+}
+
+/// A node's counts for the keys it has seen, as exchanged between the nodes
+/// of a [`GossipRateLimiter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct GossipDigest<K> {
+    /// The node that sent the digest.
+    pub node: u64,
+    pub keys: Vec<KeyCounts<K>>,
+}
+
+/// The permits each node has granted for one key within one window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeyCounts<K> {
+    pub key: K,
+    /// The window's index, counted in the key's window length since the
+    /// UNIX epoch.
+    pub window: u64,
+    /// The permits granted by each node, by node id.
+    pub counts: Vec<(u64, u64)>,
+}
+
+/// A keyed fixed-window limiter for many nodes that share a quota without a
+/// central store, for rates at which a round trip per request is too slow.
+///
+/// Every node grants permits from its own memory, counting them per key in
+/// a grow-only counter with one entry per node, and regularly
+/// [gossips](Self::gossip) its counters to its peers through a
+/// [`GossipTransport`]. Received digests are [merged](Self::merge) by taking
+/// each node's largest count, so all nodes converge on the global count of
+/// each key no matter how digests are delivered. A key is denied once the
+/// sum over all nodes reaches its quota.
+///
+/// Limiting is approximate: permits granted elsewhere since the last digest
+/// are unknown, so a window can exceed its quota by up to what the other
+/// nodes grant within a gossip interval. Windows are aligned to wall-clock
+/// time, so the nodes' clocks must be roughly synchronized, and every node
+/// must be configured with the same quotas and a distinct node id.
+///
+/// ```
+/// use std::sync::mpsc;
+///
+/// use ratelimit::{GossipRateLimiter, Quota, SystemClock};
+///
+/// let (to_b, from_a) = mpsc::channel();
+/// let (to_a, _from_b) = mpsc::channel();
+/// let a = GossipRateLimiter::new(1, Quota::per_minute(10), SystemClock, to_b);
+/// let b = GossipRateLimiter::new(2, Quota::per_minute(10), SystemClock, to_a);
+///
+/// for _ in 0..6 {
+///     a.acquire_by_key("user-42").unwrap();
+/// }
+/// // Usually on a timer, or while acquiring once the interval has passed.
+/// a.gossip().unwrap();
+/// // b now counts a's permits against the shared quota.
+/// b.merge(from_a.recv().unwrap());
+/// ```
+#[derive(Debug)]
+pub struct GossipRateLimiter<T, C: ReasonablyRealtime, K: Hash + Eq = String> {
+    node: u64,
+    quota: Quota,
+    key_quotas: HashMap<K, Quota>,
+    keys: DashMap<K, Counter>,
+    transport: T,
+    clock: C,
+    gossip_interval: Nanos,
+    /// Wall-clock nanoseconds since the UNIX epoch of the last gossip.
+    last_gossip: AtomicU64,
+}
+
+/// A key's per-node counts within its current window.
+#[derive(Debug, Default)]
+struct Counter {
+    window: u64,
+    counts: HashMap<u64, u64>,
+}
+
+impl Counter {
+    fn total(&self) -> u64 {
+        self.counts
+            .values()
+            .fold(0, |total, &n| total.saturating_add(n))
+    }
+}
+
+impl<T, C, K> GossipRateLimiter<T, C, K>
+where
+    T: GossipTransport<K>,
+    C: ReasonablyRealtime,
+    K: Hash + Eq + Clone,
+{
+    /// Creates the limiter of node `node`, limiting every key to `quota`
+    /// and gossiping through `transport`.
+    pub fn new(node: u64, quota: impl Into<Quota>, clock: C, transport: T) -> Self {
+        let quota = quota.into();
+        let created = wall_time(&clock, clock.now());
+        Self {
+            node,
+            quota,
+            key_quotas: HashMap::new(),
+            keys: DashMap::new(),
+            transport,
+            clock,
+            gossip_interval: Nanos::new((quota.window().as_u64() / 10).max(1)),
+            last_gossip: AtomicU64::new(created.as_u64()),
+        }
+    }
+
+    /// Limits `key` to `quota` instead of the default quota.
+    pub fn with_key_quota<Q>(mut self, key: &Q, quota: impl Into<Quota>) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.key_quotas.insert(key.to_owned(), quota.into());
+        self
+    }
+
+    /// Gossips while acquiring at most once per `interval`. Defaults to a
+    /// tenth of the default quota's window; shorter intervals converge
+    /// faster at the cost of more traffic.
+    pub fn with_gossip_interval(mut self, interval: Duration) -> Self {
+        self.gossip_interval = interval.into();
+        self
+    }
+
+    pub fn node(&self) -> u64 {
+        self.node
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Consumes a permit for `key`.
+    pub fn acquire_by_key<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.acquire_n_by_key(key, 1)
+    }
+
+    /// Consumes `n` permits for `key`, or none of them, as far as this node
+    /// knows the key's global count. Gossips first if the interval has
+    /// passed; a failure to send is ignored, as the next digest makes up
+    /// for it.
+    pub fn acquire_n_by_key<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let quota = self.quota_of(key);
+        if n > quota.allowed() {
+            return Err(AcquireError::InsufficientCapacity {
+                capacity: quota.allowed(),
+            });
+        }
+        let now = self.clock.now();
+        let wall = wall_time(&self.clock, now);
+        self.try_gossip(wall);
+
+        let window = wall / quota.window();
+        let mut counter = self.keys.entry(key.to_owned()).or_default();
+        if counter.window < window {
+            *counter = Counter {
+                window,
+                counts: HashMap::new(),
+            };
+        }
+        let total = counter.total();
+        if total.saturating_add(n) > quota.allowed() {
+            let next_window = quota.window().saturating_mul(window + 1);
+            let not_until = NotUntil::after(
+                now,
+                next_window.saturating_sub(wall),
+                quota,
+                quota.allowed().saturating_sub(total),
+            );
+            return Err(AcquireError::not_allowed(not_until, now));
+        }
+        *counter.counts.entry(self.node).or_default() += n;
+        Ok(())
+    }
+
+    /// The permits left for `key` in the current window, as far as this
+    /// node knows.
+    pub fn check_key<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let quota = self.quota_of(key);
+        let window = wall_time(&self.clock, self.clock.now()) / quota.window();
+        let used = self
+            .keys
+            .get(key)
+            .filter(|counter| counter.window == window)
+            .map_or(0, |counter| counter.total());
+        quota.allowed().saturating_sub(used)
+    }
+
+    /// Sends this node's counts for every key in its current window to the
+    /// peers, forgetting keys whose window has passed.
+    pub fn gossip(&self) -> Result<(), T::Error> {
+        let wall = wall_time(&self.clock, self.clock.now());
+        self.last_gossip.store(wall.as_u64(), Ordering::Release);
+        self.keys
+            .retain(|key, counter| counter.window >= wall / self.quota_of(key).window());
+        let keys = self
+            .keys
+            .iter()
+            .map(|entry| KeyCounts {
+                key: entry.key().clone(),
+                window: entry.window,
+                counts: entry.counts.iter().map(|(&node, &n)| (node, n)).collect(),
+            })
+            .collect();
+        self.transport.send(GossipDigest {
+            node: self.node,
+            keys,
+        })
+    }
+
+    /// Merges a digest received from a peer, keeping each node's largest
+    /// count per key. Counts for windows that have passed are ignored.
+    pub fn merge(&self, digest: GossipDigest<K>) {
+        let wall = wall_time(&self.clock, self.clock.now());
+        for KeyCounts {
+            key,
+            window,
+            counts,
+        } in digest.keys
+        {
+            if window < wall / self.quota_of(&key).window() {
+                continue;
+            }
+            let mut counter = self.keys.entry(key).or_default();
+            if counter.window > window {
+                continue;
+            }
+            if counter.window < window {
+                *counter = Counter {
+                    window,
+                    counts: HashMap::new(),
+                };
+            }
+            for (node, n) in counts {
+                let count = counter.counts.entry(node).or_default();
+                *count = (*count).max(n);
+            }
+        }
+    }
+
+    fn quota_of<Q>(&self, key: &Q) -> Quota
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.key_quotas.get(key).copied().unwrap_or(self.quota)
+    }
+
+    /// Gossips if the interval has passed since the last time, unless
+    /// another thread claims it first.
+    fn try_gossip(&self, wall: Nanos) {
+        let last = self.last_gossip.load(Ordering::Acquire);
+        if wall.saturating_sub(Nanos::new(last)) < self.gossip_interval {
+            return;
+        }
+        if self
+            .last_gossip
+            .compare_exchange(last, wall.as_u64(), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok()
+        {
+            let _ = self.gossip();
+        }
+    }
+}
+
+/// `now` as wall-clock time since the UNIX epoch, which all nodes agree on.
+pub(crate) fn wall_time<C: ReasonablyRealtime>(clock: &C, now: C::Instant) -> Nanos {
+    clock
+        .to_system_time(now)
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::clock::FakeAbsoluteClock;
+
+    type Limiter = GossipRateLimiter<mpsc::Sender<GossipDigest<String>>, FakeAbsoluteClock>;
+
+    fn pair(clock: &FakeAbsoluteClock) -> (Limiter, Limiter, mpsc::Receiver<GossipDigest<String>>) {
+        let (to_b, from_a) = mpsc::channel();
+        let (to_a, _) = mpsc::channel();
+        let quota = Quota::per_second(10);
+        let a = GossipRateLimiter::new(1, quota, clock.clone(), to_b)
+            .with_gossip_interval(Duration::from_secs(60));
+        let b = GossipRateLimiter::new(2, quota, clock.clone(), to_a)
+            .with_gossip_interval(Duration::from_secs(60));
+        (a, b, from_a)
+    }
+
+    #[test]
+    fn test_gossip_converges() {
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000));
+        let (a, b, from_a) = pair(&clock);
+        let key = "k".to_owned();
+
+        for _ in 0..6 {
+            a.acquire_by_key(&key).unwrap();
+        }
+        b.acquire_by_key(&key).unwrap();
+        assert_eq!(b.check_key(&key), 9);
+
+        // 合并 a 的计数后，b 只剩全局余量
+        a.gossip().unwrap();
+        let digest = from_a.recv().unwrap();
+        b.merge(digest.clone());
+        b.merge(digest);
+        assert_eq!(b.check_key(&key), 3);
+        for _ in 0..3 {
+            b.acquire_by_key(&key).unwrap();
+        }
+        let Err(AcquireError::NotAllowed {
+            retry_after,
+            remaining,
+            ..
+        }) = b.acquire_by_key(&key)
+        else {
+            panic!("expected a denial");
+        };
+        assert_eq!(retry_after, Duration::from_secs(1));
+        assert_eq!(remaining, 0);
+
+        // 新窗口重新计数，过期的摘要被忽略
+        a.gossip().unwrap();
+        let stale = from_a.recv().unwrap();
+        clock.advance(Duration::from_secs(1));
+        b.merge(stale);
+        assert_eq!(b.check_key(&key), 10);
+        assert!(matches!(
+            b.acquire_n_by_key(&key, 11),
+            Err(AcquireError::InsufficientCapacity { capacity: 10 })
+        ));
+    }
+
+    #[test]
+    fn test_gossip_while_acquiring() {
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000));
+        let (a, _, from_a) = pair(&clock);
+        let a = a.with_gossip_interval(Duration::from_millis(100));
+        let key = "k".to_owned();
+
+        // 间隔未到时不发送摘要
+        a.acquire_by_key(&key).unwrap();
+        a.acquire_by_key(&key).unwrap();
+        assert_eq!(from_a.try_iter().count(), 0);
+
+        // 间隔到了之后只发送一次
+        clock.advance(Duration::from_millis(100));
+        a.acquire_by_key(&key).unwrap();
+        a.acquire_by_key(&key).unwrap();
+        let digests: Vec<_> = from_a.try_iter().collect();
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0].node, 1);
+        assert_eq!(digests[0].keys[0].counts, [(1, 2)]);
+    }
+}
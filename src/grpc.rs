@@ -0,0 +1,286 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Request, Response};
+use pin_project_lite::pin_project;
+use tonic::{Status, metadata::MetadataValue};
+use tower::{Layer, Service};
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, error::AcquireError, extract::KeyExtractor,
+    handle::RateLimiterHandle, headers::ceil_secs, limiter::RateLimiter, state::State,
+};
+
+/// Keys gRPC requests by their method, as the request path, e.g.
+/// `/helloworld.Greeter/SayHello`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RpcMethod;
+
+impl KeyExtractor for RpcMethod {
+    type Key = String;
+
+    fn extract<B>(&self, request: &Request<B>) -> Option<String> {
+        Some(request.uri().path().to_owned())
+    }
+}
+
+/// A [`Layer`] that limits the RPCs of a tonic server, each under the key
+/// derived from it by a [`KeyExtractor`].
+///
+/// It applies to the HTTP requests beneath tonic, once per call, so unary
+/// and streaming RPCs are limited alike and the method is known, unlike in
+/// a tonic interceptor. Key on [`RpcMethod`], [`PeerIp`](crate::PeerIp), or
+/// both with `(RpcMethod, PeerIp)`. RPCs without a key, or whose key the
+/// limiter doesn't know, are limited on the base state.
+///
+/// Denied RPCs fail with `RESOURCE_EXHAUSTED` and a `retry-after` metadata
+/// entry in whole seconds, or `UNAVAILABLE` while the limiter is disabled.
+///
+/// ```
+/// use ratelimit::{GrpcRateLimitLayer, MonotonicClock, PeerIp, Quota, RateLimiter, RpcMethod, State};
+///
+/// let limiter = RateLimiter::keyed(State::new(Quota::per_second(1000), MonotonicClock))
+///     .with_default_key_quota(Quota::per_second(10))
+///     .into_handle();
+/// let layer = GrpcRateLimitLayer::new(limiter, (RpcMethod, PeerIp));
+/// // tonic::transport::Server::builder().layer(layer)...
+/// # drop(layer);
+/// ```
+pub struct GrpcRateLimitLayer<X, C, S = State<C>>
+where
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    limiter: RateLimiterHandle<C, S, X::Key>,
+    extractor: X,
+}
+
+impl<X: KeyExtractor, C: Clock, S: Algorithm<C>> GrpcRateLimitLayer<X, C, S> {
+    pub fn new(limiter: RateLimiterHandle<C, S, X::Key>, extractor: X) -> Self {
+        Self { limiter, extractor }
+    }
+}
+
+/// The status failing an RPC denied with `err`.
+fn rejection(err: &AcquireError) -> Status {
+    let mut status = match err {
+        AcquireError::Disabled => Status::unavailable(err.to_string()),
+        _ => Status::resource_exhausted(err.to_string()),
+    };
+    if let AcquireError::NotAllowed { retry_after, .. } = err {
+        // Round up, so that clients honoring the entry aren't denied again.
+        let secs = ceil_secs(*retry_after);
+        status
+            .metadata_mut()
+            .insert("retry-after", MetadataValue::from(secs));
+    }
+    status
+}
+
+impl<X, C, S> Clone for GrpcRateLimitLayer<X, C, S>
+where
+    X: KeyExtractor + Clone,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            limiter: self.limiter.clone(),
+            extractor: self.extractor.clone(),
+        }
+    }
+}
+
+impl<X, C, S> fmt::Debug for GrpcRateLimitLayer<X, C, S>
+where
+    X: KeyExtractor + fmt::Debug,
+    C: Clock,
+    S: Algorithm<C>,
+    RateLimiter<C, S, X::Key>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrpcRateLimitLayer")
+            .field("limiter", &self.limiter)
+            .field("extractor", &self.extractor)
+            .finish()
+    }
+}
+
+impl<Svc, X, C, S> Layer<Svc> for GrpcRateLimitLayer<X, C, S>
+where
+    X: KeyExtractor + Clone,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    type Service = GrpcRateLimitService<Svc, X, C, S>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        GrpcRateLimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service limited by a [`GrpcRateLimitLayer`].
+pub struct GrpcRateLimitService<Svc, X, C, S = State<C>>
+where
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    inner: Svc,
+    layer: GrpcRateLimitLayer<X, C, S>,
+}
+
+impl<Svc, X, C, S> Clone for GrpcRateLimitService<Svc, X, C, S>
+where
+    Svc: Clone,
+    X: KeyExtractor + Clone,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<Svc, X, C, S> fmt::Debug for GrpcRateLimitService<Svc, X, C, S>
+where
+    Svc: fmt::Debug,
+    X: KeyExtractor + fmt::Debug,
+    C: Clock,
+    S: Algorithm<C>,
+    RateLimiter<C, S, X::Key>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrpcRateLimitService")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<Svc, ReqBody, ResBody, X, C, S> Service<Request<ReqBody>>
+    for GrpcRateLimitService<Svc, X, C, S>
+where
+    Svc: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    type Response = Response<ResBody>;
+    type Error = Svc::Error;
+    type Future = GrpcResponseFuture<Svc::Future, ResBody>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<ReqBody>) -> Self::Future {
+        let key = self.layer.extractor.extract(&request);
+        match self.layer.limiter.acquire_by_key_or_base(key.as_ref()) {
+            Ok(()) => GrpcResponseFuture::Inner {
+                future: self.inner.call(request),
+            },
+            Err(err) => GrpcResponseFuture::Rejected {
+                response: Some(rejection(&err).into_http()),
+            },
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of a [`GrpcRateLimitService`].
+    #[project = GrpcResponseFutureProj]
+    pub enum GrpcResponseFuture<F, B> {
+        Inner {
+            #[pin]
+            future: F,
+        },
+        Rejected {
+            response: Option<Response<B>>,
+        },
+    }
+}
+
+impl<F, B, E> Future for GrpcResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            GrpcResponseFutureProj::Inner { future } => future.poll(cx),
+            GrpcResponseFutureProj::Rejected { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, net::SocketAddr};
+
+    use tonic::Code;
+    use tower::{ServiceExt, service_fn};
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, extract::PeerIp, quota::Quota};
+
+    fn call(method: &str, peer: &str) -> Request<()> {
+        let mut request = Request::post(method).body(()).unwrap();
+        request
+            .extensions_mut()
+            .insert(peer.parse::<SocketAddr>().unwrap());
+        request
+    }
+
+    #[tokio::test]
+    async fn test_grpc_layer_per_method_and_peer() {
+        let limiter = RateLimiter::keyed(State::new(
+            Quota::per_second(100),
+            FakeRelativeClock::default(),
+        ))
+        .with_default_key_quota(Quota::per_second(1))
+        .into_handle();
+        let service = GrpcRateLimitLayer::new(limiter, (RpcMethod, PeerIp)).layer(service_fn(
+            |_: Request<()>| async { Ok::<_, Infallible>(Response::new(String::new())) },
+        ));
+        let status = |method, peer| {
+            let service = service.clone();
+            async move {
+                let response = service.oneshot(call(method, peer)).await.unwrap();
+                Status::from_header_map(response.headers())
+            }
+        };
+
+        assert!(
+            status("/pkg.Greeter/SayHello", "10.0.0.1:1")
+                .await
+                .is_none()
+        );
+        let denied = status("/pkg.Greeter/SayHello", "10.0.0.1:2").await.unwrap();
+        assert_eq!(denied.code(), Code::ResourceExhausted);
+        assert_eq!(denied.metadata().get("retry-after").unwrap(), "1");
+
+        // 其他方法和其他客户端不受影响
+        assert!(status("/pkg.Greeter/SayBye", "10.0.0.1:1").await.is_none());
+        assert!(
+            status("/pkg.Greeter/SayHello", "10.0.0.2:1")
+                .await
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::{fmt, hash::Hash, ops::Deref, sync::Arc};
+
+use crate::{algorithm::Algorithm, clock::Clock, limiter::RateLimiter, state::State};
+
+/// A cheaply clonable, shared handle to a [`RateLimiter`].
+///
+/// Clones refer to the same limiter, so a handle can be handed to every
+/// request handler or task. All of the limiter's `&self` methods are
+/// available through [`Deref`].
+pub struct RateLimiterHandle<C: Clock, S: Algorithm<C> = State<C>, K: Hash + Eq + Clone = String> {
+    limiter: Arc<RateLimiter<C, S, K>>,
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> RateLimiterHandle<C, S, K> {
+    pub fn new(limiter: RateLimiter<C, S, K>) -> Self {
+        Self {
+            limiter: Arc::new(limiter),
+        }
+    }
+
+    /// Whether both handles refer to the same limiter.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.limiter, &other.limiter)
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> RateLimiter<C, S, K> {
+    /// Moves the limiter behind a [`RateLimiterHandle`] for sharing.
+    pub fn into_handle(self) -> RateLimiterHandle<C, S, K> {
+        RateLimiterHandle::new(self)
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> Clone for RateLimiterHandle<C, S, K> {
+    fn clone(&self) -> Self {
+        Self {
+            limiter: Arc::clone(&self.limiter),
+        }
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> Deref for RateLimiterHandle<C, S, K> {
+    type Target = RateLimiter<C, S, K>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.limiter
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> From<RateLimiter<C, S, K>>
+    for RateLimiterHandle<C, S, K>
+{
+    fn from(limiter: RateLimiter<C, S, K>) -> Self {
+        Self::new(limiter)
+    }
+}
+
+impl<C, S, K> fmt::Debug for RateLimiterHandle<C, S, K>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    RateLimiter<C, S, K>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RateLimiterHandle")
+            .field(&self.limiter)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::{clock::MonotonicClock, quota::Quota};
+
+    #[test]
+    fn test_handle_clones_share_limiter() {
+        let handle = RateLimiter::new(Quota::per_second(100), MonotonicClock).into_handle();
+        handle.insert_key("user", Quota::per_minute(300));
+
+        // 每个线程拿到一个克隆，共享同一份配额
+        let workers: Vec<_> = (0..4)
+            .map(|_| {
+                let handle = handle.clone();
+                thread::spawn(move || {
+                    (0..100)
+                        .filter(|_| handle.acquire_by_key("user").is_ok())
+                        .count()
+                })
+            })
+            .collect();
+        let granted: usize = workers.into_iter().map(|w| w.join().unwrap()).sum();
+        assert_eq!(granted, 300);
+        assert!(handle.ptr_eq(&handle.clone()));
+        assert_eq!(handle.check_key("user").ok(), None);
+    }
+}
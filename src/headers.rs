@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use crate::{error::AcquireError, quota::Quota};
+
+/// Which names [`RateLimitHeaders`] are written under.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HeaderStyle {
+    /// `X-RateLimit-Limit`, `X-RateLimit-Remaining` and `X-RateLimit-Reset`,
+    /// as sent by most existing APIs.
+    #[default]
+    Legacy,
+    /// `RateLimit-Limit`, `RateLimit-Remaining`, `RateLimit-Reset` and
+    /// `RateLimit-Policy`, from the IETF rate limit headers draft.
+    Draft,
+    /// Both of the above.
+    Both,
+}
+
+/// The standard rate limiting response headers describing one decision, so
+/// that every integration formats them the same way.
+///
+/// Durations are written as whole seconds, rounded up so that clients
+/// waiting them out aren't denied again. `Retry-After` is only written for
+/// denials.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use ratelimit::{HeaderStyle, Quota, RateLimitHeaders};
+///
+/// let headers = RateLimitHeaders::allowed(Quota::per_minute(60), 59, Duration::from_millis(1500));
+/// assert_eq!(
+///     headers.to_pairs(HeaderStyle::Legacy),
+///     [
+///         ("x-ratelimit-limit", "60".to_owned()),
+///         ("x-ratelimit-remaining", "59".to_owned()),
+///         ("x-ratelimit-reset", "2".to_owned()),
+///     ],
+/// );
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitHeaders {
+    pub quota: Quota,
+    pub remaining: u64,
+    /// How long until the quota has recovered, or for a denial, until the
+    /// request may succeed.
+    pub reset: Duration,
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimitHeaders {
+    /// Headers for a granted request, with `remaining` permits left and the
+    /// quota fully recovered after `reset`.
+    pub fn allowed(quota: Quota, remaining: u64, reset: Duration) -> Self {
+        Self {
+            quota,
+            remaining,
+            reset,
+            retry_after: None,
+        }
+    }
+
+    /// Headers for a request denied with `err`, or `None` if the denial
+    /// carries no quota to describe.
+    pub fn denied(err: &AcquireError) -> Option<Self> {
+        match *err {
+            AcquireError::NotAllowed {
+                retry_after,
+                quota,
+                remaining,
+                ..
+            } => Some(Self {
+                quota,
+                remaining,
+                reset: retry_after,
+                retry_after: Some(retry_after),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The headers as lowercase names and values, in `style`.
+    pub fn to_pairs(&self, style: HeaderStyle) -> Vec<(&'static str, String)> {
+        let limit = self.quota.allowed().to_string();
+        let remaining = self.remaining.to_string();
+        let reset = ceil_secs(self.reset).to_string();
+        let mut pairs = Vec::new();
+        if matches!(style, HeaderStyle::Legacy | HeaderStyle::Both) {
+            pairs.push(("x-ratelimit-limit", limit.clone()));
+            pairs.push(("x-ratelimit-remaining", remaining.clone()));
+            pairs.push(("x-ratelimit-reset", reset.clone()));
+        }
+        if matches!(style, HeaderStyle::Draft | HeaderStyle::Both) {
+            let window = ceil_secs(self.quota.window().into());
+            pairs.push(("ratelimit-policy", format!("{limit};w={window}")));
+            pairs.push(("ratelimit-limit", limit));
+            pairs.push(("ratelimit-remaining", remaining));
+            pairs.push(("ratelimit-reset", reset));
+        }
+        if let Some(retry_after) = self.retry_after {
+            pairs.push(("retry-after", ceil_secs(retry_after).to_string()));
+        }
+        pairs
+    }
+
+    /// Inserts the headers into `headers`, in `style`, keeping any that are
+    /// already set, e.g. by a custom rejection response.
+    #[cfg(feature = "http")]
+    pub fn write_to(&self, headers: &mut http::HeaderMap, style: HeaderStyle) {
+        for (name, value) in self.to_pairs(style) {
+            let value = http::HeaderValue::try_from(value).expect("header values are numeric");
+            headers
+                .entry(http::HeaderName::from_static(name))
+                .or_insert(value);
+        }
+    }
+}
+
+/// `duration` in whole seconds, rounded up.
+pub(crate) fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_denied_headers() {
+        let err = AcquireError::NotAllowed {
+            retry_after: Duration::from_millis(200),
+            quota: Quota::per_minute(10),
+            remaining: 0,
+            backoff: Duration::from_millis(200),
+        };
+        let headers = RateLimitHeaders::denied(&err).unwrap();
+        assert_eq!(
+            headers.to_pairs(HeaderStyle::Draft),
+            [
+                ("ratelimit-policy", "10;w=60".to_owned()),
+                ("ratelimit-limit", "10".to_owned()),
+                ("ratelimit-remaining", "0".to_owned()),
+                ("ratelimit-reset", "1".to_owned()),
+                ("retry-after", "1".to_owned()),
+            ]
+        );
+        assert_eq!(headers.to_pairs(HeaderStyle::Both).len(), 8);
+
+        // 其他错误没有可描述的配额
+        assert_eq!(RateLimitHeaders::denied(&AcquireError::Disabled), None);
+    }
+}
@@ -0,0 +1,45 @@
+/// Splits keys like `tenant/user` into the ancestors whose quotas they must
+/// also pass.
+#[derive(Debug)]
+pub(crate) struct KeyHierarchy<K> {
+    separator: char,
+    key_text: fn(&K) -> &str,
+    make_key: fn(&str) -> K,
+}
+
+impl<K> KeyHierarchy<K> {
+    pub(crate) fn new(separator: char, key_text: fn(&K) -> &str, make_key: fn(&str) -> K) -> Self {
+        Self {
+            separator,
+            key_text,
+            make_key,
+        }
+    }
+
+    /// The ancestors of `key` from the root down, e.g. `a` and `a/b` for
+    /// `a/b/c`. Empty segments don't make an ancestor.
+    pub(crate) fn ancestors(&self, key: &K) -> Vec<K> {
+        let text = (self.key_text)(key);
+        text.match_indices(self.separator)
+            .map(|(index, _)| &text[..index])
+            .filter(|ancestor| !ancestor.is_empty() && !ancestor.ends_with(self.separator))
+            .map(self.make_key)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ancestors() {
+        let hierarchy = KeyHierarchy::new('/', String::as_str, str::to_owned);
+        let ancestors = |key: &str| hierarchy.ancestors(&key.to_string());
+
+        assert_eq!(ancestors("acme/eng/alice"), ["acme", "acme/eng"]);
+        assert_eq!(ancestors("acme"), Vec::<String>::new());
+        // 空段不算上级
+        assert_eq!(ancestors("/acme//alice"), ["/acme"]);
+    }
+}
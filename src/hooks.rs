@@ -0,0 +1,327 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use crate::{error::AcquireError, quota::Quota};
+
+/// Callbacks a [`RateLimiter`](crate::RateLimiter) invokes on every
+/// decision, e.g. to feed metrics, logs or anomaly detection.
+///
+/// `key` is `None` for the base state, and `quota` is what the key or base
+/// state is limited by, or `None` for keys that are unlimited. A wait is
+/// one decision, reported once it is granted or given up on. Requests for
+/// keys the limiter doesn't know aren't decisions and aren't reported.
+///
+/// Decisions and key changes are reported on the calling thread, after the
+/// limiter's locks are released, so hooks may call back into the limiter but
+/// should be quick. Every method does nothing by default.
+///
+/// ```
+/// use std::sync::atomic::{AtomicU64, Ordering};
+///
+/// use ratelimit::{AcquireError, Hooks, MonotonicClock, Quota, RateLimiter};
+///
+/// #[derive(Default)]
+/// struct Denials(AtomicU64);
+///
+/// impl Hooks<String> for Denials {
+///     fn on_denied(&self, _key: Option<&String>, _quota: Option<Quota>, _err: &AcquireError) {
+///         self.0.fetch_add(1, Ordering::Relaxed);
+///     }
+/// }
+///
+/// let limiter = RateLimiter::new(Quota::per_second(1), MonotonicClock)
+///     .with_hooks(Denials::default());
+/// # drop(limiter);
+/// ```
+pub trait Hooks<K>: Send + Sync {
+    /// `permits` were granted.
+    #[allow(unused_variables)]
+    fn on_allowed(&self, key: Option<&K>, quota: Option<Quota>, permits: u64) {}
+
+    /// A request was denied, or failed, with `err`.
+    #[allow(unused_variables)]
+    fn on_denied(&self, key: Option<&K>, quota: Option<Quota>, err: &AcquireError) {}
+
+    /// `key` was created on first use, limited by `quota`, under a
+    /// [default key quota](crate::RateLimiter::with_default_key_quota), a
+    /// key rule or auto-pruning. Keys configured through
+    /// [`insert_key`](crate::RateLimiter::insert_key) are reported as quota
+    /// changes instead.
+    #[allow(unused_variables)]
+    fn on_key_created(&self, key: &K, quota: Quota) {}
+
+    /// `key` was dropped to bound memory: pruned once recovered, expired
+    /// after the idle TTL, or evicted as the least recently used. Explicit
+    /// removals aren't reported.
+    #[allow(unused_variables)]
+    fn on_evicted(&self, key: &K) {}
+
+    /// A wait ended after `waited`, just before its outcome is reported.
+    #[allow(unused_variables)]
+    fn on_waited(&self, key: Option<&K>, waited: Duration) {}
+
+    /// `key` was configured with `quota` through
+    /// [`insert_key`](crate::RateLimiter::insert_key), or removed through
+    /// [`remove_key`](crate::RateLimiter::remove_key) or
+    /// [`clear`](crate::RateLimiter::clear) for `None`.
+    #[allow(unused_variables)]
+    fn on_quota_changed(&self, key: &K, quota: Option<Quota>) {}
+}
+
+impl<K, H: Hooks<K> + ?Sized> Hooks<K> for Arc<H> {
+    fn on_allowed(&self, key: Option<&K>, quota: Option<Quota>, permits: u64) {
+        (**self).on_allowed(key, quota, permits);
+    }
+
+    fn on_denied(&self, key: Option<&K>, quota: Option<Quota>, err: &AcquireError) {
+        (**self).on_denied(key, quota, err);
+    }
+
+    fn on_key_created(&self, key: &K, quota: Quota) {
+        (**self).on_key_created(key, quota);
+    }
+
+    fn on_evicted(&self, key: &K) {
+        (**self).on_evicted(key);
+    }
+
+    fn on_waited(&self, key: Option<&K>, waited: Duration) {
+        (**self).on_waited(key, waited);
+    }
+
+    fn on_quota_changed(&self, key: &K, quota: Option<Quota>) {
+        (**self).on_quota_changed(key, quota);
+    }
+}
+
+/// The hooks of a limiter, called in the order they were added.
+pub(crate) struct HookList<K>(Vec<Arc<dyn Hooks<K>>>);
+
+impl<K> HookList<K> {
+    pub(crate) fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    pub(crate) fn push(&mut self, hooks: Arc<dyn Hooks<K>>) {
+        self.0.push(hooks);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Reports the outcome of a request for `permits`.
+    pub(crate) fn report(
+        &self,
+        key: Option<&K>,
+        quota: Option<Quota>,
+        permits: u64,
+        result: &Result<(), AcquireError>,
+    ) {
+        for hooks in &self.0 {
+            match result {
+                Ok(()) => hooks.on_allowed(key, quota, permits),
+                Err(err) => hooks.on_denied(key, quota, err),
+            }
+        }
+    }
+
+    pub(crate) fn key_created(&self, key: &K, quota: Quota) {
+        for hooks in &self.0 {
+            hooks.on_key_created(key, quota);
+        }
+    }
+
+    pub(crate) fn evicted(&self, key: &K) {
+        for hooks in &self.0 {
+            hooks.on_evicted(key);
+        }
+    }
+
+    pub(crate) fn waited(&self, key: Option<&K>, waited: Duration) {
+        for hooks in &self.0 {
+            hooks.on_waited(key, waited);
+        }
+    }
+
+    pub(crate) fn quota_changed(&self, key: &K, quota: Option<Quota>) {
+        for hooks in &self.0 {
+            hooks.on_quota_changed(key, quota);
+        }
+    }
+}
+
+impl<K> fmt::Debug for HookList<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HookList")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Mutex, OnceLock},
+        time::Duration,
+    };
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, handle::RateLimiterHandle, limiter::RateLimiter};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl Hooks<String> for Recorder {
+        fn on_allowed(&self, key: Option<&String>, quota: Option<Quota>, permits: u64) {
+            let quota = quota.map(|quota| quota.allowed());
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("allowed {key:?} {quota:?} {permits}"));
+        }
+
+        fn on_denied(&self, key: Option<&String>, quota: Option<Quota>, err: &AcquireError) {
+            let quota = quota.map(|quota| quota.allowed());
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("denied {key:?} {quota:?} {err}"));
+        }
+    }
+
+    #[test]
+    fn test_hooks_see_every_decision() {
+        let recorder = Arc::new(Recorder::default());
+        let limiter = RateLimiter::new(Quota::per_second(1), FakeRelativeClock::default())
+            .with_hooks(recorder.clone());
+        limiter.insert_key("user", Quota::per_second(2));
+
+        limiter.acquire().unwrap();
+        limiter.acquire_n_by_key("user", 2).unwrap();
+        assert!(limiter.acquire_by_key("user").is_err());
+        // 未知 key 不算一次决策
+        assert!(limiter.acquire_by_key("other").is_err());
+        // 等待只在最终结果时报告一次
+        assert!(
+            limiter
+                .acquire_wait_by_key("user", Some(Duration::ZERO))
+                .is_err()
+        );
+        limiter.set_key_enabled("user", false);
+        assert!(limiter.acquire_by_key("user").is_err());
+
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            [
+                "allowed None Some(1) 1",
+                "allowed Some(\"user\") Some(2) 2",
+                "denied Some(\"user\") Some(2) rate limited, retry after 1s",
+                "denied Some(\"user\") Some(2) rate limited, retry after 1s",
+                "denied Some(\"user\") Some(2) rate limiting key is disabled",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hooks_see_key_lifecycle() {
+        #[derive(Default)]
+        struct Lifecycle(Mutex<Vec<String>>);
+
+        impl Hooks<String> for Lifecycle {
+            fn on_key_created(&self, key: &String, _quota: Quota) {
+                self.0.lock().unwrap().push(format!("+{key}"));
+            }
+
+            fn on_evicted(&self, key: &String) {
+                self.0.lock().unwrap().push(format!("-{key}"));
+            }
+        }
+
+        let clock = FakeRelativeClock::default();
+        let lifecycle = Arc::new(Lifecycle::default());
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone())
+            .with_auto_prune(true)
+            .with_max_keys(1)
+            .with_hooks(lifecycle.clone());
+        limiter.acquire_by_key("a").unwrap();
+        limiter.acquire_by_key("b").unwrap();
+        clock.advance(Duration::from_secs(1));
+        limiter.maintain();
+        // 显式删除不算驱逐
+        limiter.insert_key("c", Quota::per_second(1));
+        limiter.remove_key("c");
+        assert_eq!(*lifecycle.0.lock().unwrap(), ["+a", "-a", "+b", "-b"]);
+    }
+
+    #[test]
+    fn test_hooks_may_call_back_into_limiter() {
+        type Handle = RateLimiterHandle<FakeRelativeClock>;
+
+        /// Records how many keys the limiter tracks at every key change.
+        #[derive(Default)]
+        struct KeyCounts {
+            limiter: OnceLock<Handle>,
+            counts: Mutex<Vec<usize>>,
+        }
+
+        impl KeyCounts {
+            fn record(&self) {
+                let len = self.limiter.get().unwrap().len();
+                self.counts.lock().unwrap().push(len);
+            }
+        }
+
+        impl Hooks<String> for KeyCounts {
+            fn on_key_created(&self, _key: &String, _quota: Quota) {
+                self.record();
+            }
+
+            fn on_evicted(&self, _key: &String) {
+                self.record();
+            }
+        }
+
+        let clock = FakeRelativeClock::default();
+        let counts = Arc::new(KeyCounts::default());
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone())
+            .with_auto_prune(true)
+            .with_max_keys(1)
+            .with_hooks(counts.clone())
+            .into_handle();
+        counts.limiter.set(limiter.clone()).unwrap();
+
+        // 回调在释放锁之后执行，不会死锁
+        limiter.acquire_by_key("a").unwrap();
+        limiter.acquire_by_key("b").unwrap();
+        clock.advance(Duration::from_secs(1));
+        limiter.maintain();
+        assert_eq!(*counts.counts.lock().unwrap(), [1, 1, 1, 0]);
+    }
+
+    #[test]
+    fn test_hooks_see_quota_changes() {
+        #[derive(Default)]
+        struct Changes(Mutex<Vec<String>>);
+
+        impl Hooks<String> for Changes {
+            fn on_quota_changed(&self, key: &String, quota: Option<Quota>) {
+                let quota = quota.map(|quota| quota.allowed());
+                self.0.lock().unwrap().push(format!("{key} {quota:?}"));
+            }
+        }
+
+        let changes = Arc::new(Changes::default());
+        let limiter = RateLimiter::new(Quota::per_second(1), FakeRelativeClock::default())
+            .with_hooks(changes.clone());
+        limiter.insert_key("a", Quota::per_second(2));
+        limiter.insert_key("b", Quota::per_second(3));
+        limiter.remove_key("a");
+        // 删除不存在的 key 不报告
+        limiter.remove_key("a");
+        limiter.clear();
+        assert_eq!(
+            *changes.0.lock().unwrap(),
+            ["a Some(2)", "b Some(3)", "a None", "b None"]
+        );
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    mem,
+    sync::{Arc, Weak},
+    thread,
+    time::Duration,
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    clock::ReasonablyRealtime,
+    error::AcquireError,
+    gossip::wall_time,
+    not_until::NotUntil,
+    quota::Quota,
+    store::{MAX_ATTEMPTS, StateStore},
+};
+
+/// The permits granted for a key within one window by all the limiters
+/// sharing a central store, as a [`HybridRateLimiter`] stores them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowCount {
+    /// The window's index, counted in the key's window length since the
+    /// UNIX epoch.
+    pub window: u64,
+    pub count: u64,
+}
+
+/// A keyed fixed-window limiter that admits requests against its own
+/// memory and reconciles with a central [`StateStore`] in the background,
+/// for near-global limits without a round trip per request.
+///
+/// Each limiter counts the permits it grants per key, and every
+/// [reconciliation](Self::reconcile) adds the ones granted since the last to
+/// the key's [`WindowCount`] in the store, and learns the count all the
+/// limiters have reached in return. A key is denied once that count plus
+/// the permits granted since reaches its quota.
+///
+/// Limiting is approximate: a window can exceed its quota by up to what the
+/// other limiters grant between two reconciliations, and a key is only
+/// learned from the store after its first local request. Windows are
+/// aligned to wall-clock time, so the limiters' clocks must be roughly
+/// synchronized, and all of them must be configured with the same quotas.
+/// Use a store, or a key prefix, of its own, since the values differ from
+/// those of a [`StoredRateLimiter`](crate::StoredRateLimiter).
+///
+/// ```
+/// use std::{sync::Arc, time::Duration};
+///
+/// use ratelimit::{HybridRateLimiter, MemoryStore, Quota, SystemClock};
+///
+/// let store = Arc::new(MemoryStore::new());
+/// let limiter = Arc::new(HybridRateLimiter::new(store, Quota::per_minute(600), SystemClock));
+/// limiter.spawn_reconciler(Duration::from_millis(500));
+///
+/// limiter.acquire_by_key("tenant-a").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct HybridRateLimiter<St, C: ReasonablyRealtime, K: Hash + Eq = String> {
+    store: St,
+    quota: Quota,
+    key_quotas: HashMap<K, Quota>,
+    keys: DashMap<K, Budget>,
+    clock: C,
+}
+
+/// A key's standing within its current window.
+#[derive(Debug, Default)]
+struct Budget {
+    window: u64,
+    /// The key's count in the store as of the last reconciliation.
+    central: u64,
+    /// Permits granted here since then.
+    pending: u64,
+}
+
+impl<St, C, K> HybridRateLimiter<St, C, K>
+where
+    St: StateStore<K, WindowCount>,
+    C: ReasonablyRealtime,
+    K: Hash + Eq + Clone,
+{
+    /// Creates a limiter limiting every key to `quota`, reconciling with
+    /// `store`.
+    pub fn new(store: St, quota: impl Into<Quota>, clock: C) -> Self {
+        Self {
+            store,
+            quota: quota.into(),
+            key_quotas: HashMap::new(),
+            keys: DashMap::new(),
+            clock,
+        }
+    }
+
+    /// Limits `key` to `quota` instead of the default quota.
+    pub fn with_key_quota<Q>(mut self, key: &Q, quota: impl Into<Quota>) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.key_quotas.insert(key.to_owned(), quota.into());
+        self
+    }
+
+    pub fn store(&self) -> &St {
+        &self.store
+    }
+
+    /// Consumes a permit for `key`.
+    pub fn acquire_by_key<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.acquire_n_by_key(key, 1)
+    }
+
+    /// Consumes `n` permits for `key`, or none of them, as far as this
+    /// limiter knows the key's global count. Never touches the store.
+    pub fn acquire_n_by_key<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let quota = self.quota_of(key);
+        if n > quota.allowed() {
+            return Err(AcquireError::InsufficientCapacity {
+                capacity: quota.allowed(),
+            });
+        }
+        let now = self.clock.now();
+        let wall = wall_time(&self.clock, now);
+        let window = wall / quota.window();
+        let mut budget = self.keys.entry(key.to_owned()).or_default();
+        if budget.window < window {
+            *budget = Budget {
+                window,
+                ..Budget::default()
+            };
+        }
+        let used = budget.central.saturating_add(budget.pending);
+        if used.saturating_add(n) > quota.allowed() {
+            let next_window = quota.window().saturating_mul(window + 1);
+            let not_until = NotUntil::after(
+                now,
+                next_window.saturating_sub(wall),
+                quota,
+                quota.allowed().saturating_sub(used),
+            );
+            return Err(AcquireError::not_allowed(not_until, now));
+        }
+        budget.pending += n;
+        Ok(())
+    }
+
+    /// The permits left for `key` in the current window, as far as this
+    /// limiter knows.
+    pub fn check_key<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let quota = self.quota_of(key);
+        let window = wall_time(&self.clock, self.clock.now()) / quota.window();
+        let used = self
+            .keys
+            .get(key)
+            .filter(|budget| budget.window == window)
+            .map_or(0, |budget| budget.central.saturating_add(budget.pending));
+        quota.allowed().saturating_sub(used)
+    }
+
+    /// Adds the permits granted here since the last reconciliation to the
+    /// store, and takes up the global counts it holds, forgetting keys
+    /// whose window has passed.
+    ///
+    /// Every key is reconciled even if some fail; their permits are kept
+    /// for the next round, and the first error is returned. A key that
+    /// keeps changing under contention is likewise left for the next round.
+    pub fn reconcile(&self) -> Result<(), St::Error> {
+        let wall = wall_time(&self.clock, self.clock.now());
+        self.keys
+            .retain(|key, budget| budget.window >= wall / self.quota_of(key).window());
+        let keys: Vec<K> = self.keys.iter().map(|entry| entry.key().clone()).collect();
+        let mut result = Ok(());
+        for key in keys {
+            if let Err(err) = self.reconcile_key(&key) {
+                result = result.and(Err(err));
+            }
+        }
+        result
+    }
+
+    fn reconcile_key(&self, key: &K) -> Result<(), St::Error> {
+        let Some((window, pending)) = self
+            .keys
+            .get_mut(key)
+            .map(|mut budget| (budget.window, mem::take(&mut budget.pending)))
+        else {
+            return Ok(());
+        };
+        let central = match self.publish(key, window, pending) {
+            Ok(Some(central)) => central,
+            Ok(None) => {
+                self.restore(key, window, pending);
+                return Ok(());
+            }
+            Err(err) => {
+                self.restore(key, window, pending);
+                return Err(err);
+            }
+        };
+        if let Some(mut budget) = self.keys.get_mut(key)
+            && budget.window == window
+        {
+            budget.central = central;
+        }
+        Ok(())
+    }
+
+    /// Adds `pending` permits to the stored count of `key` in `window`,
+    /// returning the count, or `None` if it lost too many races.
+    fn publish(&self, key: &K, window: u64, pending: u64) -> Result<Option<u64>, St::Error> {
+        for _ in 0..MAX_ATTEMPTS {
+            let stored = self.store.load(key)?;
+            let version = stored.as_ref().map(|stored| stored.version);
+            let count = match stored {
+                Some(stored) if stored.value.window == window => stored.value.count,
+                // Other limiters have moved on, so the permits are moot.
+                Some(stored) if stored.value.window > window => return Ok(Some(0)),
+                _ => 0,
+            };
+            if pending == 0 {
+                return Ok(Some(count));
+            }
+            let value = WindowCount {
+                window,
+                count: count.saturating_add(pending),
+            };
+            if self.store.compare_and_swap(key, version, value)? {
+                return Ok(Some(value.count));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Puts back permits of `window` that couldn't be reconciled.
+    fn restore(&self, key: &K, window: u64, pending: u64) {
+        if let Some(mut budget) = self.keys.get_mut(key)
+            && budget.window == window
+        {
+            budget.pending += pending;
+        }
+    }
+
+    fn quota_of<Q>(&self, key: &Q) -> Quota
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.key_quotas.get(key).copied().unwrap_or(self.quota)
+    }
+}
+
+impl<St, C, K> HybridRateLimiter<St, C, K>
+where
+    St: StateStore<K, WindowCount> + Send + Sync + 'static,
+    C: ReasonablyRealtime + Send + Sync + 'static,
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+{
+    /// Reconciles every `interval` on a thread of its own, until the
+    /// limiter is dropped. Failures are retried on the next round.
+    pub fn spawn_reconciler(self: &Arc<Self>, interval: Duration) -> thread::JoinHandle<()> {
+        let limiter = Arc::downgrade(self);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(interval);
+                let Some(limiter) = Weak::upgrade(&limiter) else {
+                    return;
+                };
+                let _ = limiter.reconcile();
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::{clock::FakeAbsoluteClock, store::MemoryStore};
+
+    type Limiter = HybridRateLimiter<Arc<MemoryStore<String, WindowCount>>, FakeAbsoluteClock>;
+
+    fn pair(clock: &FakeAbsoluteClock) -> (Limiter, Limiter) {
+        let store = Arc::new(MemoryStore::new());
+        let quota = Quota::per_second(10);
+        (
+            HybridRateLimiter::new(store.clone(), quota, clock.clone()),
+            HybridRateLimiter::new(store, quota, clock.clone()),
+        )
+    }
+
+    #[test]
+    fn test_hybrid_reconciles() {
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000));
+        let (a, b) = pair(&clock);
+        let key = "k".to_owned();
+
+        for _ in 0..6 {
+            a.acquire_by_key(&key).unwrap();
+        }
+        b.acquire_by_key(&key).unwrap();
+        assert_eq!(b.check_key(&key), 9);
+
+        // 对账后双方都只剩全局余量
+        a.reconcile().unwrap();
+        b.reconcile().unwrap();
+        assert_eq!(
+            a.store().load(&key).unwrap().unwrap().value,
+            WindowCount {
+                window: 1_000,
+                count: 7
+            }
+        );
+        assert_eq!(b.check_key(&key), 3);
+        a.reconcile().unwrap();
+        assert_eq!(a.check_key(&key), 3);
+        for _ in 0..3 {
+            b.acquire_by_key(&key).unwrap();
+        }
+        let Err(AcquireError::NotAllowed { retry_after, .. }) = b.acquire_by_key(&key) else {
+            panic!("expected a denial");
+        };
+        assert_eq!(retry_after, Duration::from_secs(1));
+
+        // 新窗口重新计数，旧窗口的计数被覆盖
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(b.check_key(&key), 10);
+        a.acquire_by_key(&key).unwrap();
+        a.reconcile().unwrap();
+        b.reconcile().unwrap();
+        assert_eq!(b.check_key(&key), 10);
+        assert_eq!(a.store().load(&key).unwrap().unwrap().value.count, 1);
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    fmt,
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Request, Response, StatusCode};
+use hyper::service::Service;
+use pin_project_lite::pin_project;
+
+use crate::{
+    algorithm::Algorithm,
+    clock::Clock,
+    error::AcquireError,
+    extract::KeyExtractor,
+    handle::RateLimiterHandle,
+    headers::{HeaderStyle, RateLimitHeaders},
+    limiter::RateLimiter,
+    state::State,
+};
+
+/// Builds the responses a [`HyperRateLimit`] denies requests with.
+///
+/// Implemented by [`DefaultRejection`] and by closures over the denial, e.g.
+/// to answer with a JSON error body. Headers the response already has take
+/// precedence over the rate limiting headers added to it.
+pub trait Rejection<B> {
+    fn response(&self, err: &AcquireError) -> Response<B>;
+}
+
+/// Rejects requests with `429 Too Many Requests`, or `503 Service
+/// Unavailable` while the limiter is disabled, and the body type's default,
+/// e.g. an empty body.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DefaultRejection;
+
+impl<B: Default> Rejection<B> for DefaultRejection {
+    fn response(&self, err: &AcquireError) -> Response<B> {
+        let mut response = Response::new(B::default());
+        *response.status_mut() = match err {
+            AcquireError::Disabled => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::TOO_MANY_REQUESTS,
+        };
+        response
+    }
+}
+
+impl<B, F: Fn(&AcquireError) -> Response<B>> Rejection<B> for F {
+    fn response(&self, err: &AcquireError) -> Response<B> {
+        self(err)
+    }
+}
+
+/// Wraps a hyper [`Service`] to limit requests before they reach it, for
+/// servers not built on tower.
+///
+/// Each request is limited under the key derived from it by a
+/// [`KeyExtractor`], or on the base state if it has no key or the limiter
+/// doesn't know the key. Denied requests get the response built by
+/// [`with_rejection`](Self::with_rejection), [`DefaultRejection`] unless
+/// set, with [`RateLimitHeaders`] in the [chosen
+/// style](Self::with_header_style).
+///
+/// Connections can be limited as well, per peer IP address, by creating the
+/// service for each accepted connection through [`accept`](Self::accept).
+pub struct HyperRateLimit<Svc, X, C, S = State<C>, R = DefaultRejection>
+where
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    inner: Svc,
+    limiter: RateLimiterHandle<C, S, X::Key>,
+    extractor: X,
+    connections: Option<RateLimiterHandle<C, S, IpAddr>>,
+    peer: Option<SocketAddr>,
+    header_style: HeaderStyle,
+    rejection: R,
+}
+
+impl<Svc, X: KeyExtractor, C: Clock, S: Algorithm<C>> HyperRateLimit<Svc, X, C, S> {
+    pub fn new(inner: Svc, limiter: RateLimiterHandle<C, S, X::Key>, extractor: X) -> Self {
+        Self {
+            inner,
+            limiter,
+            extractor,
+            connections: None,
+            peer: None,
+            header_style: HeaderStyle::default(),
+            rejection: DefaultRejection,
+        }
+    }
+}
+
+impl<Svc, X, C, S, R> HyperRateLimit<Svc, X, C, S, R>
+where
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    /// Builds denied requests' responses with `rejection`.
+    pub fn with_rejection<R2>(self, rejection: R2) -> HyperRateLimit<Svc, X, C, S, R2> {
+        HyperRateLimit {
+            inner: self.inner,
+            limiter: self.limiter,
+            extractor: self.extractor,
+            connections: self.connections,
+            peer: self.peer,
+            header_style: self.header_style,
+            rejection,
+        }
+    }
+
+    /// Names the rate limiting headers of denied requests' responses in
+    /// `style`.
+    pub fn with_header_style(mut self, style: HeaderStyle) -> Self {
+        self.header_style = style;
+        self
+    }
+
+    /// Limits the connections [accepted](Self::accept) from each peer IP
+    /// address with `limiter`.
+    pub fn with_connection_limit(mut self, limiter: RateLimiterHandle<C, S, IpAddr>) -> Self {
+        self.connections = Some(limiter);
+        self
+    }
+
+    /// Admits a connection from `peer` under the [connection
+    /// limit](Self::with_connection_limit), if any, returning the service to
+    /// serve it with. Its requests carry `peer` as a `SocketAddr` extension,
+    /// so that [`PeerIp`](crate::PeerIp) can key on it.
+    ///
+    /// Fails with the denial if the connection should be closed instead.
+    pub fn accept(&self, peer: SocketAddr) -> Result<Self, AcquireError>
+    where
+        Svc: Clone,
+        X: Clone,
+        R: Clone,
+    {
+        if let Some(connections) = &self.connections {
+            connections.acquire_by_key_or_base(Some(&peer.ip()))?;
+        }
+        Ok(Self {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            extractor: self.extractor.clone(),
+            connections: self.connections.clone(),
+            peer: Some(peer),
+            header_style: self.header_style,
+            rejection: self.rejection.clone(),
+        })
+    }
+}
+
+impl<Svc, X, C, S, R> Clone for HyperRateLimit<Svc, X, C, S, R>
+where
+    Svc: Clone,
+    X: KeyExtractor + Clone,
+    C: Clock,
+    S: Algorithm<C>,
+    R: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            limiter: self.limiter.clone(),
+            extractor: self.extractor.clone(),
+            connections: self.connections.clone(),
+            peer: self.peer,
+            header_style: self.header_style,
+            rejection: self.rejection.clone(),
+        }
+    }
+}
+
+impl<Svc, X, C, S, R> fmt::Debug for HyperRateLimit<Svc, X, C, S, R>
+where
+    Svc: fmt::Debug,
+    X: KeyExtractor + fmt::Debug,
+    C: Clock,
+    S: Algorithm<C>,
+    RateLimiter<C, S, X::Key>: fmt::Debug,
+    RateLimiter<C, S, IpAddr>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HyperRateLimit")
+            .field("inner", &self.inner)
+            .field("limiter", &self.limiter)
+            .field("extractor", &self.extractor)
+            .field("connections", &self.connections)
+            .field("peer", &self.peer)
+            .field("header_style", &self.header_style)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Svc, ReqBody, ResBody, X, C, S, R> Service<Request<ReqBody>>
+    for HyperRateLimit<Svc, X, C, S, R>
+where
+    Svc: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+    R: Rejection<ResBody>,
+{
+    type Response = Response<ResBody>;
+    type Error = Svc::Error;
+    type Future = HyperResponseFuture<Svc::Future, ResBody>;
+
+    fn call(&self, mut request: Request<ReqBody>) -> Self::Future {
+        if let Some(peer) = self.peer {
+            request.extensions_mut().insert(peer);
+        }
+        let key = self.extractor.extract(&request);
+        match self.limiter.acquire_by_key_or_base(key.as_ref()) {
+            Ok(()) => HyperResponseFuture::Inner {
+                future: self.inner.call(request),
+            },
+            Err(err) => HyperResponseFuture::Rejected {
+                response: Some(self.rejection(&err)),
+            },
+        }
+    }
+}
+
+impl<Svc, X, C, S, R> HyperRateLimit<Svc, X, C, S, R>
+where
+    X: KeyExtractor,
+    C: Clock,
+    S: Algorithm<C>,
+{
+    /// The response denying a request with `err`.
+    fn rejection<B>(&self, err: &AcquireError) -> Response<B>
+    where
+        R: Rejection<B>,
+    {
+        let mut response = self.rejection.response(err);
+        if let Some(headers) = RateLimitHeaders::denied(err) {
+            headers.write_to(response.headers_mut(), self.header_style);
+        }
+        response
+    }
+}
+
+pin_project! {
+    /// The response future of a [`HyperRateLimit`].
+    #[project = HyperResponseFutureProj]
+    pub enum HyperResponseFuture<F, B> {
+        Inner {
+            #[pin]
+            future: F,
+        },
+        Rejected {
+            response: Option<Response<B>>,
+        },
+    }
+}
+
+impl<F, B, E> Future for HyperResponseFuture<F, B>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project() {
+            HyperResponseFutureProj::Inner { future } => future.poll(cx),
+            HyperResponseFutureProj::Rejected { response } => {
+                Poll::Ready(Ok(response.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use http::header::CONTENT_TYPE;
+    use hyper::service::service_fn;
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, extract::PeerIp, quota::Quota};
+
+    fn hello()
+    -> impl Service<Request<String>, Response = Response<String>, Error = Infallible> + Clone {
+        service_fn(|_: Request<String>| async { Ok(Response::new("hello".to_owned())) })
+    }
+
+    fn limiter<K: std::hash::Hash + Eq + Clone>(
+        quota: Quota,
+    ) -> RateLimiterHandle<FakeRelativeClock, State<FakeRelativeClock>, K> {
+        RateLimiter::keyed(State::new(
+            Quota::per_second(100),
+            FakeRelativeClock::default(),
+        ))
+        .with_default_key_quota(quota)
+        .into_handle()
+    }
+
+    #[tokio::test]
+    async fn test_hyper_rejection() {
+        let service = HyperRateLimit::new(hello(), limiter(Quota::per_second(1)), PeerIp)
+            .with_rejection(|err: &AcquireError| {
+                Response::builder()
+                    .status(StatusCode::TOO_MANY_REQUESTS)
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(format!("{{\"error\":\"{err}\"}}"))
+                    .unwrap()
+            });
+        let service = service.accept("10.0.0.1:1000".parse().unwrap()).unwrap();
+
+        let response = service.call(Request::new(String::new())).await.unwrap();
+        assert_eq!(response.body(), "hello");
+        let response = service.call(Request::new(String::new())).await.unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "1");
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+        assert!(response.body().starts_with("{\"error\":\"rate limited"));
+    }
+
+    #[tokio::test]
+    async fn test_hyper_connection_limit() {
+        let service = HyperRateLimit::new(hello(), limiter(Quota::per_second(100)), PeerIp)
+            .with_connection_limit(limiter(Quota::per_second(2)));
+        let peer = "10.0.0.1:1000".parse().unwrap();
+
+        // 每个 IP 每秒最多建立两个连接
+        assert!(service.accept(peer).is_ok());
+        assert!(service.accept(peer).is_ok());
+        assert!(matches!(
+            service.accept(peer),
+            Err(AcquireError::NotAllowed { .. })
+        ));
+        assert!(service.accept("10.0.0.2:1000".parse().unwrap()).is_ok());
+    }
+}
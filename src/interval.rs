@@ -0,0 +1,141 @@
+use crate::{
+    clock::{Clock, Reference},
+    nanos::Nanos,
+};
+
+/// What an [`Interval`] does with deadlines that passed while no one was
+/// ticking it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MissedTicks {
+    /// Yields every missed deadline back to back until caught up, so the
+    /// number of ticks over time matches the period.
+    #[default]
+    Burst,
+    /// Yields one tick and schedules the next a full period after it is
+    /// observed, shifting the schedule.
+    Delay,
+    /// Yields one tick and drops the other missed deadlines, keeping the
+    /// original schedule.
+    Skip,
+}
+
+/// Successive deadlines `period` apart on a [`Clock`], e.g. to pace work or
+/// drive a refill schedule.
+///
+/// The first deadline is the clock's reading on creation, so the first tick
+/// completes immediately.
+#[derive(Debug, Clone)]
+pub struct Interval<C: Clock> {
+    clock: C,
+    period: Nanos,
+    next: C::Instant,
+    missed: MissedTicks,
+}
+
+impl<C: Clock> Interval<C> {
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(period: Nanos, clock: C) -> Self {
+        assert!(period > Nanos::ZERO, "interval period must be non-zero");
+        Self {
+            next: clock.now(),
+            clock,
+            period,
+            missed: MissedTicks::default(),
+        }
+    }
+
+    pub fn with_missed_ticks(mut self, missed: MissedTicks) -> Self {
+        self.missed = missed;
+        self
+    }
+
+    pub fn period(&self) -> Nanos {
+        self.period
+    }
+
+    /// The deadline the next tick completes at.
+    pub fn next_deadline(&self) -> C::Instant {
+        self.next
+    }
+
+    /// Completes the next tick if its deadline has passed, returning the
+    /// deadline, or returns `None` without waiting.
+    pub fn poll_tick(&mut self) -> Option<C::Instant> {
+        let now = self.clock.now();
+        if now < self.next {
+            return None;
+        }
+        let deadline = self.next;
+        self.next = match self.missed {
+            MissedTicks::Burst => deadline + self.period,
+            MissedTicks::Delay => now + self.period,
+            MissedTicks::Skip => {
+                let behind = now.duration_since(deadline);
+                let periods = behind / self.period + 1;
+                deadline + self.period.saturating_mul(periods)
+            }
+        };
+        Some(deadline)
+    }
+
+    /// Sleeps on the clock until the next deadline, then completes the tick
+    /// and returns the deadline.
+    pub fn tick(&mut self) -> C::Instant {
+        loop {
+            if let Some(deadline) = self.poll_tick() {
+                return deadline;
+            }
+            let wait = self.next.duration_since(self.clock.now());
+            self.clock.sleep(wait.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::FakeRelativeClock;
+
+    fn ticks(interval: &mut Interval<FakeRelativeClock>) -> Vec<u64> {
+        std::iter::from_fn(|| interval.poll_tick())
+            .map(|deadline| deadline.as_millis())
+            .collect()
+    }
+
+    #[test]
+    fn test_interval_tick() {
+        let clock = FakeRelativeClock::default();
+        let mut interval = Interval::new(Nanos::from_millis(100), clock.clone());
+        assert_eq!(interval.tick(), Nanos::ZERO);
+        assert_eq!(interval.poll_tick(), None);
+
+        // 假时钟的 sleep 会推进时间
+        assert_eq!(interval.tick(), Nanos::from_millis(100));
+        assert_eq!(interval.tick(), Nanos::from_millis(200));
+        assert_eq!(clock.elapsed(), Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_interval_missed_ticks() {
+        let run = |missed| {
+            let clock = FakeRelativeClock::default();
+            let mut interval =
+                Interval::new(Nanos::from_millis(100), clock.clone()).with_missed_ticks(missed);
+            assert_eq!(ticks(&mut interval), [0]);
+            clock.advance(Duration::from_millis(350));
+            let caught_up = ticks(&mut interval);
+            (caught_up, interval.next_deadline().as_millis())
+        };
+
+        // 补齐所有错过的 tick
+        assert_eq!(run(MissedTicks::Burst), (vec![100, 200, 300], 400));
+        // 从观察到的时刻重新计时
+        assert_eq!(run(MissedTicks::Delay), (vec![100], 450));
+        // 丢弃错过的 tick，但保持原有节奏
+        assert_eq!(run(MissedTicks::Skip), (vec![100], 400));
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    hash::Hash,
+    io,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use pin_project_lite::pin_project;
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::Sleep,
+};
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, error::AcquireError, jitter::Jitter, limiter::RateLimiter,
+};
+
+pin_project! {
+    /// A reader charging every byte read against the base state of a
+    /// [`RateLimiter`], e.g. one built from `Quota::per_second(10 << 20)` for
+    /// 10 MiB/s.
+    ///
+    /// Each read is capped at the permits available at that moment, and only
+    /// the bytes actually read are charged. While no permit is available, or
+    /// the limiter is disabled, reads stay pending.
+    pub struct ThrottledReader<'a, R, C, S, K = String>
+    where
+        C: Clock,
+        S: Algorithm<C>,
+        K: Hash,
+        K: Eq,
+        K: Clone,
+    {
+        #[pin]
+        inner: R,
+        limiter: &'a mut RateLimiter<C, S, K>,
+        delay: Option<Pin<Box<Sleep>>>,
+        jitter: Jitter,
+    }
+}
+
+impl<'a, R, C, S, K> ThrottledReader<'a, R, C, S, K>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    pub fn new(inner: R, limiter: &'a mut RateLimiter<C, S, K>) -> Self {
+        Self {
+            inner,
+            limiter,
+            delay: None,
+            jitter: Jitter::NONE,
+        }
+    }
+
+    /// Extends every wait for a permit by `jitter`.
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl<R, C, S, K> AsyncRead for ThrottledReader<'_, R, C, S, K>
+where
+    R: AsyncRead,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        let this = self.project();
+        let available = ready!(poll_available(this.limiter, this.delay, *this.jitter, cx));
+        let limit = buf
+            .remaining()
+            .min(usize::try_from(available).unwrap_or(usize::MAX));
+
+        let mut limited = ReadBuf::new(buf.initialize_unfilled_to(limit));
+        ready!(this.inner.poll_read(cx, &mut limited))?;
+        let read = limited.filled().len();
+        buf.advance(read);
+        charge(this.limiter, read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+pin_project! {
+    /// A writer charging every byte written against the base state of a
+    /// [`RateLimiter`].
+    ///
+    /// Each write is capped at the permits available at that moment, and only
+    /// the bytes the inner writer accepts are charged. While no permit is
+    /// available, or the limiter is disabled, writes stay pending.
+    pub struct ThrottledWriter<'a, W, C, S, K = String>
+    where
+        C: Clock,
+        S: Algorithm<C>,
+        K: Hash,
+        K: Eq,
+        K: Clone,
+    {
+        #[pin]
+        inner: W,
+        limiter: &'a mut RateLimiter<C, S, K>,
+        delay: Option<Pin<Box<Sleep>>>,
+        jitter: Jitter,
+    }
+}
+
+impl<'a, W, C, S, K> ThrottledWriter<'a, W, C, S, K>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    pub fn new(inner: W, limiter: &'a mut RateLimiter<C, S, K>) -> Self {
+        Self {
+            inner,
+            limiter,
+            delay: None,
+            jitter: Jitter::NONE,
+        }
+    }
+
+    /// Extends every wait for a permit by `jitter`.
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl<W, C, S, K> AsyncWrite for ThrottledWriter<'_, W, C, S, K>
+where
+    W: AsyncWrite,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let this = self.project();
+        let available = ready!(poll_available(this.limiter, this.delay, *this.jitter, cx));
+        let limit = buf
+            .len()
+            .min(usize::try_from(available).unwrap_or(usize::MAX));
+
+        let written = ready!(this.inner.poll_write(cx, &buf[..limit]))?;
+        charge(this.limiter, written);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// Waits until the base state has at least one permit and returns how many
+/// it has.
+fn poll_available<C, S, K>(
+    limiter: &mut RateLimiter<C, S, K>,
+    delay: &mut Option<Pin<Box<Sleep>>>,
+    jitter: Jitter,
+    cx: &mut Context<'_>,
+) -> Poll<u64>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    loop {
+        if let Some(sleep) = delay.as_mut() {
+            ready!(sleep.as_mut().poll(cx));
+            *delay = None;
+        }
+        match limiter.check() {
+            Ok(available) => return Poll::Ready(available),
+            Err(AcquireError::NotAllowed { retry_after, .. }) => {
+                *delay = Some(Box::pin(tokio::time::sleep(jitter.apply(retry_after))));
+            }
+            Err(_) => return Poll::Pending,
+        }
+    }
+}
+
+/// Charges `bytes` that were checked to be available. Availability only
+/// grows over time and the limiter is borrowed exclusively, so the charge
+/// cannot be denied.
+fn charge<C, S, K>(limiter: &mut RateLimiter<C, S, K>, bytes: usize)
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    let charged = limiter.acquire_n(bytes as u64);
+    debug_assert!(charged.is_ok(), "checked bytes were denied: {charged:?}");
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::{clock::TokioClock, quota::Quota};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_reader() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(Quota::per_second(4), TokioClock);
+        let data: &[u8] = b"0123456789";
+        let mut reader = ThrottledReader::new(data, &mut limiter);
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+
+        // 每秒 4 字节，10 字节需要 3 个窗口
+        assert_eq!(out, data);
+        assert_eq!(start.elapsed().as_secs(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_throttled_writer() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(Quota::per_second(4), TokioClock);
+        let mut out = Vec::new();
+        let mut writer = ThrottledWriter::new(&mut out, &mut limiter);
+
+        // 单次写入被截断到当前可用的字节数
+        assert_eq!(writer.write(b"0123456789").await.unwrap(), 4);
+        writer.write_all(b"456789").await.unwrap();
+        writer.flush().await.unwrap();
+
+        assert_eq!(out, b"0123456789");
+        assert_eq!(start.elapsed().as_secs(), 2);
+    }
+}
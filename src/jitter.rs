@@ -0,0 +1,64 @@
+use std::time::Duration;
+
+/// A bounded random delay added to each async wake-up, so tasks waiting on
+/// the same limiter don't all retry at the same instant.
+///
+/// Each wait is extended by `min` plus a uniformly random share of
+/// `interval`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Jitter {
+    min: Duration,
+    interval: Duration,
+}
+
+impl Jitter {
+    /// Adds no delay.
+    pub const NONE: Self = Self::new(Duration::ZERO, Duration::ZERO);
+
+    /// Adds between `min` and `min + interval` to each wait.
+    pub const fn new(min: Duration, interval: Duration) -> Self {
+        Self { min, interval }
+    }
+
+    /// Adds up to `max` to each wait.
+    pub const fn up_to(max: Duration) -> Self {
+        Self::new(Duration::ZERO, max)
+    }
+
+    pub const fn min(&self) -> Duration {
+        self.min
+    }
+
+    pub const fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Extends `wait` by a random delay within the bounds.
+    pub(crate) fn apply(&self, wait: Duration) -> Duration {
+        let nanos = u64::try_from(self.interval.as_nanos()).unwrap_or(u64::MAX);
+        let extra = if nanos == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos(rand::random_range(0..=nanos))
+        };
+        wait.saturating_add(self.min).saturating_add(extra)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jitter_bounds() {
+        let wait = Duration::from_secs(1);
+        assert_eq!(Jitter::NONE.apply(wait), wait);
+
+        let jitter = Jitter::new(Duration::from_millis(10), Duration::from_millis(50));
+        for _ in 0..1_000 {
+            let jittered = jitter.apply(wait);
+            assert!(jittered >= Duration::from_millis(1_010));
+            assert!(jittered <= Duration::from_millis(1_060));
+        }
+    }
+}
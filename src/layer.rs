@@ -0,0 +1,304 @@
+use std::{
+    fmt,
+    future::Future,
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tower::{BoxError, Layer, Service};
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, error::AcquireError, handle::RateLimiterHandle,
+    limiter::RateLimiter, state::State,
+};
+
+/// What a [`RateLimitService`] does with a call the limiter denies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Reject,
+    /// Waits for a permit, giving up after the given duration if any.
+    Delay(Option<Duration>),
+}
+
+/// A [`Layer`] that limits calls to the wrapped services with the base state
+/// of a shared [`RateLimiter`](crate::RateLimiter).
+///
+/// Denied calls fail with the [`AcquireError`], or the error built by
+/// [`with_rejection`](Self::with_rejection), without reaching the inner
+/// service; with [`delay`](Self::delay) they wait for a permit instead.
+/// Errors are boxed, as is usual for tower middleware.
+///
+/// ```
+/// use ratelimit::{MonotonicClock, Quota, RateLimitLayer, RateLimiter};
+/// use tower::ServiceBuilder;
+///
+/// let limiter = RateLimiter::new(Quota::per_second(100), MonotonicClock).into_handle();
+/// let service = ServiceBuilder::new()
+///     .layer(RateLimitLayer::new(limiter))
+///     .service(tower::service_fn(|request: String| async move {
+///         Ok::<_, std::convert::Infallible>(request)
+///     }));
+/// # drop(service);
+/// ```
+pub struct RateLimitLayer<C, S = State<C>, K = String, R = fn(AcquireError) -> AcquireError>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    limiter: RateLimiterHandle<C, S, K>,
+    mode: Mode,
+    reject: R,
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> RateLimitLayer<C, S, K> {
+    /// Rejects denied calls with the [`AcquireError`].
+    pub fn new(limiter: RateLimiterHandle<C, S, K>) -> Self {
+        Self {
+            limiter,
+            mode: Mode::Reject,
+            reject: |err| err,
+        }
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone, R> RateLimitLayer<C, S, K, R> {
+    /// Makes denied calls wait for a permit before reaching the inner
+    /// service. Calls are only rejected if no permit can ever be granted.
+    pub fn delay(mut self) -> Self {
+        self.mode = Mode::Delay(None);
+        self
+    }
+
+    /// Like [`delay`](Self::delay), but rejects calls whose permit could not
+    /// be granted within `max_wait`.
+    pub fn delay_at_most(mut self, max_wait: Duration) -> Self {
+        self.mode = Mode::Delay(Some(max_wait));
+        self
+    }
+
+    /// Builds the error that rejected calls fail with from the denial.
+    pub fn with_rejection<R2, E>(self, reject: R2) -> RateLimitLayer<C, S, K, R2>
+    where
+        R2: Fn(AcquireError) -> E,
+        E: Into<BoxError>,
+    {
+        RateLimitLayer {
+            limiter: self.limiter,
+            mode: self.mode,
+            reject,
+        }
+    }
+}
+
+impl<C, S, K, R> Clone for RateLimitLayer<C, S, K, R>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    R: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            limiter: self.limiter.clone(),
+            mode: self.mode,
+            reject: self.reject.clone(),
+        }
+    }
+}
+
+impl<C, S, K, R> fmt::Debug for RateLimitLayer<C, S, K, R>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    RateLimiter<C, S, K>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitLayer")
+            .field("limiter", &self.limiter)
+            .field("mode", &self.mode)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<Svc, C, S, K, R> Layer<Svc> for RateLimitLayer<C, S, K, R>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    R: Clone,
+{
+    type Service = RateLimitService<Svc, C, S, K, R>;
+
+    fn layer(&self, inner: Svc) -> Self::Service {
+        RateLimitService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// A service limited by a [`RateLimitLayer`].
+pub struct RateLimitService<Svc, C, S = State<C>, K = String, R = fn(AcquireError) -> AcquireError>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+{
+    inner: Svc,
+    layer: RateLimitLayer<C, S, K, R>,
+}
+
+impl<Svc, C, S, K, R> Clone for RateLimitService<Svc, C, S, K, R>
+where
+    Svc: Clone,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    R: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            layer: self.layer.clone(),
+        }
+    }
+}
+
+impl<Svc, C, S, K, R> fmt::Debug for RateLimitService<Svc, C, S, K, R>
+where
+    Svc: fmt::Debug,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    RateLimiter<C, S, K>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimitService")
+            .field("inner", &self.inner)
+            .field("layer", &self.layer)
+            .finish()
+    }
+}
+
+impl<Svc, Req, C, S, K, R, E> Service<Req> for RateLimitService<Svc, C, S, K, R>
+where
+    Svc: Service<Req> + Clone + Send + 'static,
+    Svc::Future: Send,
+    Svc::Error: Into<BoxError>,
+    Req: Send + 'static,
+    C: Clock + Send + Sync + 'static,
+    C::Instant: Send + Sync,
+    S: Algorithm<C> + Send + 'static,
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    R: Fn(AcquireError) -> E + Clone + Send + 'static,
+    E: Into<BoxError>,
+{
+    type Response = Svc::Response;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Svc::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        // The ready service is the one polled above; leave a clone in its
+        // place, as the call may wait before reaching it.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let acquired = match layer.mode {
+                Mode::Reject => layer.limiter.acquire(),
+                Mode::Delay(None) => layer.limiter.until_ready().await,
+                Mode::Delay(Some(max_wait)) => layer.limiter.until_ready_or_timeout(max_wait).await,
+            };
+            if let Err(err) = acquired {
+                return Err((layer.reject)(err).into());
+            }
+            inner.call(request).await.map_err(Into::into)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::Infallible, fmt};
+
+    use tokio::time::Instant;
+    use tower::{ServiceExt, service_fn};
+
+    use super::*;
+    use crate::{clock::TokioClock, quota::Quota};
+
+    fn echo() -> impl Service<u32, Response = u32, Error = Infallible, Future: Send> + Clone + Send
+    {
+        service_fn(|request: u32| async move { Ok(request) })
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_layer_rejects() {
+        let limiter = RateLimiter::new(Quota::per_second(2), TokioClock).into_handle();
+        let mut service = RateLimitLayer::new(limiter).layer(echo());
+
+        assert_eq!(service.ready().await.unwrap().call(1).await.unwrap(), 1);
+        assert_eq!(service.ready().await.unwrap().call(2).await.unwrap(), 2);
+        let err = service.ready().await.unwrap().call(3).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AcquireError>(),
+            Some(AcquireError::NotAllowed { .. })
+        ));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_layer_custom_rejection() {
+        #[derive(Debug)]
+        struct TooManyRequests;
+
+        impl fmt::Display for TooManyRequests {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "too many requests")
+            }
+        }
+
+        impl std::error::Error for TooManyRequests {}
+
+        let limiter = RateLimiter::new(Quota::per_second(1), TokioClock).into_handle();
+        let layer = RateLimitLayer::new(limiter).with_rejection(|_| TooManyRequests);
+        let service = layer.layer(echo());
+
+        service.clone().oneshot(1).await.unwrap();
+        let err = service.oneshot(2).await.unwrap_err();
+        assert!(err.is::<TooManyRequests>());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_layer_delays() {
+        let start = Instant::now();
+        let limiter = RateLimiter::new(Quota::per_second(2), TokioClock).into_handle();
+        let service = RateLimitLayer::new(limiter).delay().layer(echo());
+
+        for request in 0..5 {
+            assert_eq!(service.clone().oneshot(request).await.unwrap(), request);
+        }
+        // 第 5 次调用要等到第三个窗口
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_layer_delay_at_most() {
+        let limiter = RateLimiter::new(Quota::per_second(1), TokioClock).into_handle();
+        let service = RateLimitLayer::new(limiter)
+            .delay_at_most(Duration::from_millis(500))
+            .layer(echo());
+
+        service.clone().oneshot(1).await.unwrap();
+        // 下一个许可在 1s 后，超过了最长等待
+        let err = service.oneshot(2).await.unwrap_err();
+        assert!(err.is::<AcquireError>());
+    }
+}
@@ -1,5 +1,192 @@
+//! Rate limiting with pluggable clocks.
+//!
+//! A [`State`] enforces a [`Quota`] over fixed windows, and a [`RateLimiter`]
+//! combines a base state with independently limited keys. Other algorithms,
+//! such as [`TokenBucketState`], implement [`Algorithm`] and can be used in
+//! place of [`State`] through [`RateLimiter::from_state`].
+//!
+//! ```
+//! use std::time::Duration;
+//!
+//! use ratelimit::{AcquireError, MonotonicClock, Quota, RateLimiter};
+//!
+//! let limiter = RateLimiter::new(Quota::per_second(100), MonotonicClock);
+//! limiter.insert_key("user", Quota::per_second(2));
+//!
+//! assert!(limiter.acquire_by_key("user").is_ok());
+//! assert!(limiter.acquire_by_key("user").is_ok());
+//!
+//! // Denials say how long to wait, e.g. for a `Retry-After` header.
+//! match limiter.acquire_by_key("user") {
+//!     Err(AcquireError::NotAllowed { retry_after, .. }) => {
+//!         assert!(retry_after <= Duration::from_secs(1));
+//!     }
+//!     other => panic!("unexpected {other:?}"),
+//! }
+//! ```
+
+#[cfg(feature = "actix-web")]
+mod actix;
+mod actor;
+#[cfg(feature = "admin")]
+mod admin;
+mod algorithm;
+mod atomic_gcra;
+#[cfg(feature = "axum")]
+mod axum_layer;
+mod batched;
 mod clock;
+#[cfg(feature = "envoy")]
+pub mod envoy;
+mod error;
+#[cfg(feature = "etcd")]
+mod etcd_store;
+#[cfg(feature = "http")]
+mod extract;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+mod fair;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+mod future;
+mod gcra;
+mod gossip;
+#[cfg(feature = "tonic")]
+mod grpc;
+mod handle;
+mod headers;
+mod hierarchy;
+mod hooks;
+mod hybrid;
+#[cfg(feature = "hyper")]
+mod hyper_service;
+mod interval;
+#[cfg(feature = "tokio")]
+mod io;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+mod jitter;
+#[cfg(feature = "tower")]
+mod layer;
+mod limiter;
+#[cfg(feature = "memcache")]
+mod memcache_store;
+#[cfg(feature = "prometheus")]
+mod metrics;
 mod nanos;
+mod not_until;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+mod outbound;
+mod per_core;
+#[cfg(feature = "postgres")]
+mod postgres_store;
+mod quota;
+mod rate;
+#[cfg(feature = "redis")]
+mod redis_store;
+mod rejection_logger;
+mod rules;
+#[cfg(test)]
+mod scenario;
+mod sharded;
+#[cfg(feature = "shared-memory")]
+mod shared_memory;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+mod sink;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+mod sleeper;
+mod sliding_log;
+mod sliding_window;
+mod state;
+mod stats;
+mod store;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+mod stream;
+mod sync;
+mod token_bucket;
+#[cfg(feature = "tracing")]
+mod trace;
 
-pub use nanos::Nanos;
-pub use clock::{MonotonicClock, Clock, FakeRelativeClock, Reference};
\ No newline at end of file
+#[cfg(feature = "actix-web")]
+pub use actix::{ActixRateLimit, ActixRateLimitMiddleware};
+pub use actor::ActorHandle;
+#[cfg(feature = "admin")]
+pub use admin::admin_router;
+pub use algorithm::Algorithm;
+pub use atomic_gcra::AtomicGcraState;
+#[cfg(feature = "axum")]
+pub use axum_layer::{AxumRateLimitLayer, AxumRateLimitService, AxumResponseFuture, PathParam};
+pub use batched::BatchedAcquirer;
+#[cfg(all(feature = "coarse", target_os = "linux"))]
+pub use clock::CoarseMonotonicClock;
+#[cfg(feature = "quanta")]
+pub use clock::QuantaClock;
+#[cfg(feature = "tokio")]
+pub use clock::TokioClock;
+pub use clock::{
+    AnyClock, Clock, FakeAbsoluteClock, FakeRelativeClock, FnClock, MonotonicClock,
+    ReasonablyRealtime, Reference, SystemClock, TickClock, TickSource,
+};
+#[cfg(feature = "envoy")]
+pub use envoy::EnvoyRateLimitService;
+pub use error::{AcquireError, InsufficientCapacity};
+#[cfg(feature = "etcd")]
+pub use etcd_store::{EtcdStore, EtcdStoreError};
+#[cfg(feature = "http")]
+pub use extract::{BearerSubject, ForwardedIp, HeaderKey, KeyExtractor, PeerIp};
+#[cfg(any(feature = "tokio", feature = "smol"))]
+pub use fair::{FairRateLimiter, Priority};
+pub use gcra::{GcraSnapshot, GcraState};
+pub use gossip::{GossipDigest, GossipRateLimiter, GossipTransport, KeyCounts};
+#[cfg(feature = "tonic")]
+pub use grpc::{GrpcRateLimitLayer, GrpcRateLimitService, GrpcResponseFuture, RpcMethod};
+pub use handle::RateLimiterHandle;
+pub use headers::{HeaderStyle, RateLimitHeaders};
+pub use hooks::Hooks;
+pub use hybrid::{HybridRateLimiter, WindowCount};
+#[cfg(feature = "hyper")]
+pub use hyper_service::{DefaultRejection, HyperRateLimit, HyperResponseFuture, Rejection};
+pub use interval::{Interval, MissedTicks};
+#[cfg(feature = "tokio")]
+pub use io::{ThrottledReader, ThrottledWriter};
+#[cfg(any(feature = "tokio", feature = "smol"))]
+pub use jitter::Jitter;
+#[cfg(feature = "tower")]
+pub use layer::{RateLimitLayer, RateLimitService};
+pub use limiter::{KeyStats, RateLimiter, StatsSnapshot};
+#[cfg(feature = "memcache")]
+pub use memcache_store::{MemcachedStore, MemcachedStoreError};
+#[cfg(feature = "prometheus")]
+pub use metrics::PrometheusMetrics;
+pub use nanos::{Nanos, ParseError};
+pub use not_until::NotUntil;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+pub use outbound::HostThrottle;
+pub use per_core::PerCoreRateLimiter;
+#[cfg(feature = "postgres")]
+pub use postgres_store::{PostgresStore, PostgresStoreError};
+pub use quota::Quota;
+pub use rate::Rate;
+#[cfg(feature = "redis")]
+pub use redis_store::{RedisStore, RedisStoreError};
+pub use rejection_logger::RejectionLogger;
+pub use rules::KeyLimit;
+pub use sharded::ShardedRateLimiter;
+#[cfg(feature = "shared-memory")]
+pub use shared_memory::SharedMemoryRateLimiter;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+pub use sink::{RateLimitedSink, SinkRateLimitExt};
+#[cfg(feature = "smol")]
+pub use sleeper::AsyncIoSleeper;
+#[cfg(feature = "tokio")]
+pub use sleeper::TokioSleeper;
+#[cfg(any(feature = "tokio", feature = "smol"))]
+pub use sleeper::{AsyncSleeper, DefaultSleeper};
+pub use sliding_log::SlidingWindowLog;
+pub use sliding_window::SlidingWindowState;
+pub use state::{State, WindowSnapshot};
+pub use stats::{DecisionStats, DecisionTotals, KeyTotals, WaitHistogram, WaitHistogramSnapshot};
+pub use store::{DegradedMode, MemoryStore, StateStore, StoredRateLimiter, StoredState, Versioned};
+#[cfg(any(feature = "tokio", feature = "smol"))]
+pub use stream::{RateLimitedStream, StreamRateLimitExt};
+pub use sync::SyncBackend;
+pub use token_bucket::TokenBucketState;
+#[cfg(feature = "tracing")]
+pub use trace::TracingHooks;
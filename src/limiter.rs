@@ -0,0 +1,1992 @@
+use std::{
+    borrow::Borrow,
+    collections::{BTreeMap, HashMap, HashSet},
+    hash::Hash,
+    ops::{Deref, DerefMut},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, Reference},
+    error::AcquireError,
+    hierarchy::KeyHierarchy,
+    hooks::{HookList, Hooks},
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+    rules::{KeyLimit, KeyRules},
+    state::State,
+    sync::{Guard, Lock, SyncBackend},
+};
+
+/// A base state plus independently limited keys, all using the algorithm `S`.
+///
+/// Keys are `String`s by default; any `K: Hash + Eq + Clone`, such as `u64`
+/// user IDs, `IpAddr`s or tuples, can be used through
+/// [`keyed`](Self::keyed). Methods taking a key accept any borrowed form of
+/// it, e.g. `&str` for `String` keys, so lookups don't allocate.
+///
+/// Acquiring and managing keys take `&self`, so a limiter can be shared
+/// between threads, e.g. through a clonable
+/// [`RateLimiterHandle`](crate::RateLimiterHandle). The base state and the keys are
+/// guarded by separate locks, each held only for the duration of a call and
+/// never while waiting.
+#[derive(Debug)]
+pub struct RateLimiter<C: Clock, S: Algorithm<C> = State<C>, K: Hash + Eq + Clone = String> {
+    base_state: Lock<S>,
+    keys: Lock<Keys<K, S, C::Instant>>,
+    clock: C,
+    auto_prune: bool,
+    default_key_quota: Option<Quota>,
+    key_rules: Option<KeyRules<K>>,
+    hierarchy: Option<KeyHierarchy<K>>,
+    idle_ttl: Option<Nanos>,
+    max_keys: Option<usize>,
+    backoff: Option<Backoff>,
+    enabled: AtomicBool,
+    hooks: HookList<K>,
+}
+
+impl<C: Clock> RateLimiter<C> {
+    /// Creates a fixed-window limiter whose base state and keys default to
+    /// `quota`. Use [`from_state`](Self::from_state) for other algorithms.
+    pub fn new(quota: impl Into<Quota>, clock: C) -> Self {
+        Self::from_state(State::new(quota, clock))
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>> RateLimiter<C, S> {
+    pub fn from_state(base_state: S) -> Self {
+        Self::keyed(base_state)
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> RateLimiter<C, S, K> {
+    /// Creates a limiter keyed on `K` rather than `String`.
+    pub fn keyed(base_state: S) -> Self {
+        Self {
+            clock: base_state.clock().clone(),
+            base_state: Lock::new(SyncBackend::Std, base_state),
+            keys: Lock::new(
+                SyncBackend::Std,
+                Keys {
+                    entries: HashMap::new(),
+                    pinned: HashSet::new(),
+                    lru: BTreeMap::new(),
+                    next_tick: 0,
+                    disabled: HashSet::new(),
+                    events: Vec::new(),
+                },
+            ),
+            auto_prune: false,
+            default_key_quota: None,
+            key_rules: None,
+            hierarchy: None,
+            idle_ttl: None,
+            max_keys: None,
+            backoff: None,
+            enabled: AtomicBool::new(true),
+            hooks: HookList::new(),
+        }
+    }
+
+    pub(crate) fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    /// Drops idle keys to bound memory to the active working set.
+    ///
+    /// With auto-pruning enabled, unknown keys are created on first use with
+    /// the [default key quota](Self::with_default_key_quota), or else the base
+    /// state's configuration, and [`maintain`](Self::maintain)
+    /// removes such keys once they have fully recovered, since they are then
+    /// indistinguishable from freshly created ones. Keys configured through
+    /// [`insert_key`](Self::insert_key) are never pruned.
+    pub fn with_auto_prune(mut self, enabled: bool) -> Self {
+        self.auto_prune = enabled;
+        self
+    }
+
+    /// Creates unknown keys on first use with their own state enforcing
+    /// `quota`, instead of rejecting them with
+    /// [`UnknownKey`](AcquireError::UnknownKey). Unlike keys configured
+    /// through [`insert_key`](Self::insert_key), such keys can be pruned.
+    pub fn with_default_key_quota(mut self, quota: impl Into<Quota>) -> Self {
+        self.default_key_quota = Some(quota.into());
+        self
+    }
+
+    /// Evicts keys that have not been used for `ttl` during
+    /// [`maintain`](Self::maintain), whether or not auto-pruning is enabled.
+    ///
+    /// Only keys created on first use are evicted, never those configured
+    /// through [`insert_key`](Self::insert_key). An evicted key starts over
+    /// with a fresh state, so a `ttl` shorter than the time a key needs to
+    /// recover lets it exceed its quota.
+    pub fn with_idle_ttl(mut self, ttl: Duration) -> Self {
+        self.idle_ttl = Some(ttl.into());
+        self
+    }
+
+    /// Caps the number of keys created on first use at `max`, evicting the
+    /// least recently used of them to make room for a new one. Keys
+    /// configured through [`insert_key`](Self::insert_key) don't count
+    /// toward the cap and are never evicted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is zero.
+    pub fn with_max_keys(mut self, max: usize) -> Self {
+        assert!(max > 0, "max keys must be non-zero");
+        self.max_keys = Some(max);
+        self
+    }
+
+    /// Suggests an escalating backoff to keys that keep getting denied: the
+    /// `backoff` of a [`NotAllowed`](AcquireError::NotAllowed) denial starts
+    /// at `base` and doubles with every consecutive denial of the key, up to
+    /// `max`, but is never shorter than its `retry_after`. A granted request
+    /// resets the key's count.
+    ///
+    /// Denials are counted per tracked key by
+    /// [`acquire_by_key`](Self::acquire_by_key) and its `_n` and `_at`
+    /// variants; waiting acquires and the base state always suggest
+    /// `retry_after`.
+    pub fn with_escalating_backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.backoff = Some(Backoff { base, max });
+        self
+    }
+
+    /// Guards the base state and the keys with locks from `backend` instead
+    /// of the standard library's.
+    pub fn with_sync_backend(mut self, backend: SyncBackend) -> Self {
+        self.base_state = self.base_state.with_backend(backend);
+        self.keys = self.keys.with_backend(backend);
+        self
+    }
+
+    /// Reports every decision to `hooks`, after any hooks added before.
+    pub fn with_hooks(mut self, hooks: impl Hooks<K> + 'static) -> Self {
+        self.hooks.push(Arc::new(hooks));
+        self
+    }
+
+    /// Pre-sizes the keyed map to hold at least `capacity` keys without
+    /// rehashing.
+    pub fn with_key_capacity(self, capacity: usize) -> Self {
+        self.keys().entries.reserve(capacity);
+        self
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.keys().entries.capacity()
+    }
+
+    /// Releases memory held by the keyed map beyond what its current keys
+    /// need, e.g. after a spike of keys has been pruned.
+    pub fn shrink_to_fit(&self) {
+        self.keys().entries.shrink_to_fit();
+    }
+
+    pub fn insert_key<Q>(&self, key: &Q, quota: impl Into<Quota>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let quota = quota.into();
+        let state = S::from_quota(quota, self.clock().clone());
+        let mut keys = self.keys();
+        let entry = KeyEntry::new(state, self.clock().now(), keys.next_tick());
+        if let Some(replaced) = keys.entries.insert(key.to_owned(), entry) {
+            keys.lru.remove(&replaced.tick);
+        }
+        keys.pinned.insert(key.to_owned());
+        drop(keys);
+        if !self.hooks.is_empty() {
+            self.hooks.quota_changed(&key.to_owned(), Some(quota));
+        }
+    }
+
+    /// Limits `key` by `quota` as if it had been created on first use with
+    /// it: unlike a key configured through [`insert_key`](Self::insert_key),
+    /// it counts toward [`max_keys`](Self::with_max_keys) and is pruned or
+    /// expires like other such keys, after which it starts over with the
+    /// quota it would otherwise get. A key already configured through
+    /// `insert_key` stays configured.
+    pub fn set_key_quota<Q>(&self, key: &Q, quota: impl Into<Quota>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let quota = quota.into();
+        let state = S::from_quota(quota, self.clock().clone());
+        let mut keys = self.keys();
+        let pinned = keys.pinned.contains(key);
+        match keys.entries.remove(key) {
+            Some(replaced) => {
+                keys.lru.remove(&replaced.tick);
+            }
+            None if !pinned => {
+                if let Some(max) = self.max_keys {
+                    keys.evict_least_recently_used(max, !self.hooks.is_empty());
+                }
+            }
+            None => {}
+        }
+        let tick = keys.next_tick();
+        if self.max_keys.is_some() && !pinned {
+            keys.lru.insert(tick, key.to_owned());
+        }
+        let entry = KeyEntry::new(state, self.clock().now(), tick);
+        keys.entries.insert(key.to_owned(), entry);
+        drop(keys);
+        if !self.hooks.is_empty() {
+            self.hooks.quota_changed(&key.to_owned(), Some(quota));
+        }
+    }
+
+    /// Removes `key` and its state, returning whether it was tracked. Whether
+    /// the key is disabled is remembered separately and left unchanged.
+    pub fn remove_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let mut keys = self.keys();
+        keys.pinned.remove(key);
+        let Some((key, entry)) = keys.entries.remove_entry(key) else {
+            return false;
+        };
+        keys.lru.remove(&entry.tick);
+        drop(keys);
+        self.hooks.quota_changed(&key, None);
+        true
+    }
+
+    /// Whether `key` currently has a state, configured or created on use.
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys().entries.contains_key(key)
+    }
+
+    /// The quota the base state is limited by.
+    pub fn quota(&self) -> Quota {
+        self.base().quota()
+    }
+
+    /// The number of permits the base state could grant now.
+    pub fn remaining(&self) -> u64 {
+        self.base().remaining()
+    }
+
+    /// When the base state will have fully recovered its capacity.
+    pub fn resets_at(&self) -> C::Instant {
+        self.base().resets_at()
+    }
+
+    /// The quota `key` is limited by, if it is tracked.
+    pub fn key_quota<Q>(&self, key: &Q) -> Option<Quota>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys()
+            .entries
+            .get(key)
+            .map(|entry| entry.state.quota())
+    }
+
+    /// The number of permits `key` could be granted now, if it is tracked.
+    ///
+    /// Unlike [`check_key`](Self::check_key), this is the key's own state
+    /// only, whether or not it or the limiter is disabled.
+    pub fn key_remaining<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys()
+            .entries
+            .get(key)
+            .map(|entry| entry.state.remaining())
+    }
+
+    /// When `key` will have fully recovered its capacity, if it is tracked.
+    pub fn key_resets_at<Q>(&self, key: &Q) -> Option<C::Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys()
+            .entries
+            .get(key)
+            .map(|entry| entry.state.resets_at())
+    }
+
+    /// The current usage of `key`, if it is tracked, as in a
+    /// [`stats_snapshot`](Self::stats_snapshot).
+    pub fn key_stats<Q>(&self, key: &Q) -> Option<KeyStats<K>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let now = self.clock().now();
+        let keys = self.keys();
+        let (key, entry) = keys.entries.get_key_value(key)?;
+        Some(KeyStats {
+            key: key.clone(),
+            allowed: entry.state.capacity(),
+            remaining: entry.state.remaining_at(now),
+            reset_after: entry.state.reset_after_at(now),
+        })
+    }
+
+    /// Gives `key` a fresh state under its current quota, as if it had never
+    /// been used, returning whether it was tracked. Unlike removing and
+    /// reinserting it, a key created on use stays prunable.
+    pub fn reset_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self.keys().entries.get_mut(key) {
+            Some(entry) => {
+                entry.state = S::from_quota(entry.state.quota(), self.clock().clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The number of tracked keys.
+    pub fn len(&self) -> usize {
+        self.keys().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys().entries.is_empty()
+    }
+
+    /// Removes every key, configured or created on use. The base state and
+    /// disabled keys are left unchanged.
+    pub fn clear(&self) {
+        let mut keys = self.keys();
+        let removed: Vec<K> = if self.hooks.is_empty() {
+            keys.entries.clear();
+            Vec::new()
+        } else {
+            keys.entries.drain().map(|(key, _)| key).collect()
+        };
+        keys.pinned.clear();
+        keys.lru.clear();
+        drop(keys);
+        for key in &removed {
+            self.hooks.quota_changed(key, None);
+        }
+    }
+
+    /// Enables or disables the whole limiter. While disabled every request,
+    /// keyed or not, is blocked.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Enables or disables a single key. The key need not be configured yet;
+    /// a disabled key stays blocked even if it is pruned and recreated.
+    pub fn set_key_enabled<Q>(&self, key: &Q, enabled: bool)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let mut keys = self.keys();
+        if enabled {
+            keys.disabled.remove(key);
+        } else {
+            keys.disabled.insert(key.to_owned());
+        }
+    }
+
+    /// Consumes a permit from the base state, or reports why it was denied.
+    pub fn acquire(&self) -> Result<(), AcquireError> {
+        self.acquire_n(1)
+    }
+
+    /// Consumes `n` permits from the base state, or none of them.
+    pub fn acquire_n(&self, n: u64) -> Result<(), AcquireError> {
+        let result = self.try_acquire_base(n).and_then(|result| {
+            result.map_err(|not_until| AcquireError::not_allowed(not_until, self.clock().now()))
+        });
+        self.report_base(n, None, result)
+    }
+
+    /// Consumes a permit for `key`, or reports why it was denied.
+    pub fn acquire_by_key<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.acquire_n_by_key(key, 1)
+    }
+
+    /// Consumes a permit for `key`, or from the base state if there is no
+    /// key or the limiter doesn't know it, e.g. for requests that may lack a
+    /// client identity.
+    pub fn acquire_by_key_or_base<Q>(&self, key: Option<&Q>) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        match key.map(|key| self.acquire_by_key(key)) {
+            Some(Err(AcquireError::UnknownKey)) | None => self.acquire(),
+            Some(acquired) => acquired,
+        }
+    }
+
+    /// Consumes `n` permits from the base state as of `now` rather than the
+    /// clock's reading, e.g. to replay historical events or process an
+    /// event-time stream deterministically. A denial's `retry_after` is
+    /// measured from `now`.
+    ///
+    /// An instant before a state's last update, or before its creation, is
+    /// treated as that update, so no time passes; out-of-order events are
+    /// charged but never refill permits early. To replay events, pair the
+    /// limiter with a clock that stays at the start of the replay, such as an
+    /// unadvanced
+    /// [`FakeRelativeClock`](crate::FakeRelativeClock), whose instants are
+    /// [`Nanos`] since that start.
+    pub fn acquire_n_at(&self, n: u64, now: C::Instant) -> Result<(), AcquireError> {
+        let result = self.try_acquire_base_at(n, Some(now)).and_then(|result| {
+            result.map_err(|not_until| AcquireError::not_allowed(not_until, now))
+        });
+        self.report_base(n, None, result)
+    }
+
+    /// Consumes a permit from the base state as of `now`. See
+    /// [`acquire_n_at`](Self::acquire_n_at).
+    pub fn acquire_at(&self, now: C::Instant) -> Result<(), AcquireError> {
+        self.acquire_n_at(1, now)
+    }
+
+    /// Consumes `n` permits for `key` as of `now`. Keys created on first use
+    /// record `now` as their last use. See
+    /// [`acquire_n_at`](Self::acquire_n_at).
+    pub fn acquire_n_by_key_at<Q>(
+        &self,
+        key: &Q,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let result = self
+            .try_acquire_key_at(key, n, Some(now))
+            .and_then(|result| self.advise(key, result, now));
+        self.report_key(key, n, None, result)
+    }
+
+    /// Consumes a permit for `key` as of `now`. See
+    /// [`acquire_n_at`](Self::acquire_n_at).
+    pub fn acquire_by_key_at<Q>(&self, key: &Q, now: C::Instant) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.acquire_n_by_key_at(key, 1, now)
+    }
+
+    /// Blocks until the base state grants a permit, giving up once it could
+    /// not be granted within `max_wait`. See [`Algorithm::acquire_wait`].
+    pub fn acquire_wait(&self, max_wait: Option<Duration>) -> Result<(), AcquireError> {
+        let started = self.clock().now();
+        let result = self.wait(max_wait, || self.try_acquire_base(1));
+        self.report_base(1, Some(started), result)
+    }
+
+    /// Blocks until `key` is granted a permit, giving up once it could not be
+    /// granted within `max_wait`. See [`Algorithm::acquire_wait`].
+    pub fn acquire_wait_by_key<Q>(
+        &self,
+        key: &Q,
+        max_wait: Option<Duration>,
+    ) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let started = self.clock().now();
+        let result = self.wait(max_wait, || self.try_acquire_key(key, 1));
+        self.report_key(key, 1, Some(started), result)
+    }
+
+    /// Retries `attempt`, sleeping on the clock in between, until it succeeds
+    /// or fails outright. No lock is held while sleeping.
+    fn wait(
+        &self,
+        max_wait: Option<Duration>,
+        mut attempt: impl FnMut() -> Attempt<C::Instant>,
+    ) -> Result<(), AcquireError> {
+        let start = self.clock().now();
+        loop {
+            let result = attempt()?;
+            let now = self.clock().now();
+            let not_until = match result {
+                Ok(()) => return Ok(()),
+                Err(not_until) => not_until,
+            };
+            let wait = not_until.wait_time_from(now);
+            let waited = Duration::from(now.duration_since(start));
+            if not_until.overflowed() || max_wait.is_some_and(|max| waited + wait > max) {
+                return Err(AcquireError::not_allowed(not_until, now));
+            }
+            self.clock().sleep(wait);
+        }
+    }
+
+    /// Consumes `n` permits for `key`, or none of them. On denial reports how
+    /// long until all `n` permits could be granted.
+    pub fn acquire_n_by_key<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let result = self
+            .try_acquire_key(key, n)
+            .and_then(|result| self.advise(key, result, self.clock().now()));
+        self.report_key(key, n, None, result)
+    }
+
+    /// Passes the outcome of a request for `n` permits from the base state
+    /// to the hooks, with how long it waited if it `started` waiting.
+    pub(crate) fn report_base(
+        &self,
+        n: u64,
+        started: Option<C::Instant>,
+        result: Result<(), AcquireError>,
+    ) -> Result<(), AcquireError> {
+        if !self.hooks.is_empty() {
+            if let Some(started) = started {
+                self.hooks.waited(None, self.waited_since(started));
+            }
+            let quota = self.base().quota();
+            self.hooks.report(None, Some(quota), n, &result);
+        }
+        result
+    }
+
+    /// Passes the outcome of a request for `n` permits for `key` to the
+    /// hooks, unless the key is unknown, like
+    /// [`report_base`](Self::report_base).
+    pub(crate) fn report_key<Q>(
+        &self,
+        key: &Q,
+        n: u64,
+        started: Option<C::Instant>,
+        result: Result<(), AcquireError>,
+    ) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if !self.hooks.is_empty() && !matches!(result, Err(AcquireError::UnknownKey)) {
+            let key = key.to_owned();
+            if let Some(started) = started {
+                self.hooks.waited(Some(&key), self.waited_since(started));
+            }
+            let quota = self.key_quota::<K>(&key);
+            self.hooks.report(Some(&key), quota, n, &result);
+        }
+        result
+    }
+
+    fn waited_since(&self, started: C::Instant) -> Duration {
+        self.clock().now().duration_since(started).into()
+    }
+
+    /// Converts the outcome of an acquire for `key` into a denial measured
+    /// from `now`, counting consecutive denials of the key to suggest an
+    /// [escalating backoff](Self::with_escalating_backoff).
+    fn advise<Q>(
+        &self,
+        key: &Q,
+        result: Result<(), NotUntil<C::Instant>>,
+        now: C::Instant,
+    ) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let Some(backoff) = &self.backoff else {
+            return result.map_err(|not_until| AcquireError::not_allowed(not_until, now));
+        };
+        let denials = match self.keys().entries.get_mut(key) {
+            Some(entry) if result.is_ok() => {
+                entry.denials = 0;
+                0
+            }
+            Some(entry) => {
+                entry.denials = entry.denials.saturating_add(1);
+                entry.denials
+            }
+            None => 0,
+        };
+        result.map_err(|not_until| {
+            let mut err = AcquireError::not_allowed(not_until, now);
+            if let AcquireError::NotAllowed {
+                retry_after,
+                backoff: advice,
+                ..
+            } = &mut err
+            {
+                *advice = backoff.after(denials).max(*retry_after);
+            }
+            err
+        })
+    }
+
+    /// Reports whether the base state would grant a permit, and how many
+    /// remain, without consuming one.
+    pub fn check(&self) -> Result<u64, AcquireError> {
+        if !self.is_enabled() {
+            return Err(AcquireError::Disabled);
+        }
+        self.check_state(&self.base())
+    }
+
+    /// Reports whether [`acquire_by_key`](Self::acquire_by_key) would grant a
+    /// permit for `key`, and how many remain, without consuming one. An
+    /// unknown key that would be created on use reports its full capacity.
+    pub fn check_key<Q>(&self, key: &Q) -> Result<u64, AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let keys = self.keys();
+        let mut remaining = self.check_level(&keys, key)?;
+        for ancestor in self.ancestors(key) {
+            match self.check_level::<K>(&keys, &ancestor) {
+                Ok(ancestor_remaining) => remaining = remaining.min(ancestor_remaining),
+                Err(AcquireError::UnknownKey) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(remaining)
+    }
+
+    fn check_level<Q>(&self, keys: &KeyMap<K, S, C>, key: &Q) -> Result<u64, AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if !self.is_enabled() || keys.disabled.contains(key) {
+            return Err(AcquireError::Disabled);
+        }
+        match keys.entries.get(key) {
+            Some(entry) => self.check_state(&entry.state),
+            None => match self.new_key_state(&key.to_owned()) {
+                Some(NewKey::Limited(state)) => self.check_state(&state),
+                Some(NewKey::Unlimited) => Ok(u64::MAX),
+                None => Err(AcquireError::UnknownKey),
+            },
+        }
+    }
+
+    fn check_state(&self, state: &S) -> Result<u64, AcquireError> {
+        let now = state.clock().now();
+        state
+            .check_at(now)
+            .map_err(|not_until| AcquireError::not_allowed(not_until, now))
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    fn base(&self) -> Guard<'_, S> {
+        self.base_state.lock()
+    }
+
+    fn keys(&self) -> KeysGuard<'_, K, S, C::Instant> {
+        KeysGuard {
+            keys: Some(self.keys.lock()),
+            hooks: &self.hooks,
+        }
+    }
+
+    /// Takes `n` permits from the base state, like
+    /// [`try_acquire_key`](Self::try_acquire_key).
+    pub(crate) fn try_acquire_base(&self, n: u64) -> Attempt<C::Instant> {
+        self.try_acquire_base_at(n, None)
+    }
+
+    /// Like [`try_acquire_base`](Self::try_acquire_base), as of `at` if
+    /// given and else as of the clock's reading.
+    fn try_acquire_base_at(&self, n: u64, at: Option<C::Instant>) -> Attempt<C::Instant> {
+        if !self.is_enabled() {
+            return Err(AcquireError::Disabled);
+        }
+        let mut state = self.base();
+        let now = at.unwrap_or_else(|| state.clock().now());
+        take(&mut *state, n, now)
+    }
+
+    /// How the unknown `key` is limited if it is accepted: by the most
+    /// specific matching rule, else the default key quota, else under
+    /// auto-pruning like the base state.
+    fn new_key_state(&self, key: &K) -> Option<NewKey<S>> {
+        let limit = self
+            .key_rules
+            .as_ref()
+            .and_then(|rules| rules.limit_for(key))
+            .or(self.default_key_quota.map(KeyLimit::Limited));
+        match limit {
+            Some(KeyLimit::Limited(quota)) => {
+                Some(NewKey::Limited(S::from_quota(quota, self.clock().clone())))
+            }
+            Some(KeyLimit::Unlimited) => Some(NewKey::Unlimited),
+            None if self.auto_prune => Some(NewKey::Limited(self.base().fresh())),
+            None => None,
+        }
+    }
+
+    /// Looks up the state for `key`, creating it if unknown keys are
+    /// accepted. `None` means the key is unlimited.
+    fn key_state<'k, Q>(
+        &self,
+        keys: &'k mut KeyMap<K, S, C>,
+        key: &Q,
+        at: Option<C::Instant>,
+    ) -> Result<Option<&'k mut S>, AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if !self.is_enabled() || keys.disabled.contains(key) {
+            return Err(AcquireError::Disabled);
+        }
+        let now = at.unwrap_or_else(|| self.clock().now());
+        if !keys.entries.contains_key(key) {
+            let key = key.to_owned();
+            match self.new_key_state(&key) {
+                Some(NewKey::Limited(state)) => {
+                    if let Some(max) = self.max_keys {
+                        keys.evict_least_recently_used(max, !self.hooks.is_empty());
+                    }
+                    let tick = keys.next_tick();
+                    if self.max_keys.is_some() {
+                        keys.lru.insert(tick, key.clone());
+                    }
+                    if !self.hooks.is_empty() {
+                        keys.events
+                            .push(KeyEvent::Created(key.clone(), state.quota()));
+                    }
+                    keys.entries.insert(key, KeyEntry::new(state, now, tick));
+                }
+                Some(NewKey::Unlimited) => return Ok(None),
+                None => return Err(AcquireError::UnknownKey),
+            }
+        }
+        let tick = keys.next_tick();
+        let Keys { entries, lru, .. } = keys;
+        let entry = entries.get_mut(key).ok_or(AcquireError::UnknownKey)?;
+        entry.last_used = now;
+        if let Some(key) = lru.remove(&entry.tick) {
+            entry.tick = tick;
+            lru.insert(tick, key);
+        }
+        Ok(Some(&mut entry.state))
+    }
+
+    /// Takes `n` permits for `key` and each of its ancestors, or none of
+    /// them. A denial reports the latest instant any level could grant them;
+    /// a level that never can, e.g. one with no capacity, is an error rather
+    /// than a denial to wait out.
+    ///
+    /// Unknown ancestors that aren't created on first use impose no limit.
+    pub(crate) fn try_acquire_key<Q>(&self, key: &Q, n: u64) -> Attempt<C::Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.try_acquire_key_at(key, n, None)
+    }
+
+    /// Like [`try_acquire_key`](Self::try_acquire_key), as of `at` if given
+    /// and else as of the clock's reading once the key's state exists.
+    fn try_acquire_key_at<Q>(&self, key: &Q, n: u64, at: Option<C::Instant>) -> Attempt<C::Instant>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let ancestors = self.ancestors(key);
+        let mut keys = self.keys();
+        if ancestors.is_empty() {
+            return match self.key_state(&mut keys, key, at)? {
+                Some(state) => {
+                    let now = at.unwrap_or_else(|| state.clock().now());
+                    take(state, n, now)
+                }
+                None => Ok(Ok(())),
+            };
+        }
+
+        let mut levels = Vec::with_capacity(ancestors.len() + 1);
+        for ancestor in ancestors {
+            match self.key_state::<K>(&mut keys, &ancestor, at) {
+                Ok(Some(_)) => levels.push(ancestor),
+                Ok(None) | Err(AcquireError::UnknownKey) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        if self.key_state(&mut keys, key, at)?.is_some() {
+            levels.push(key.to_owned());
+        }
+
+        let now = at.unwrap_or_else(|| self.clock().now());
+        let mut denial: Option<NotUntil<C::Instant>> = None;
+        for level in &levels {
+            let Some(entry) = keys.entries.get_mut::<K>(level) else {
+                continue;
+            };
+            let state = &mut entry.state;
+            if n > state.capacity() {
+                return Err(AcquireError::InsufficientCapacity {
+                    capacity: state.capacity(),
+                });
+            }
+            // A short level denies without consuming anything.
+            if state.remaining_at(now) < n
+                && let Err(not_until) = take(state, n, now)?
+                && denial
+                    .as_ref()
+                    .is_none_or(|denial| denial.earliest_possible() < not_until.earliest_possible())
+            {
+                denial = Some(not_until);
+            }
+        }
+        if let Some(not_until) = denial {
+            return Ok(Err(not_until));
+        }
+        for level in &levels {
+            if let Some(entry) = keys.entries.get_mut::<K>(level) {
+                let granted = take(&mut entry.state, n, now);
+                debug_assert!(matches!(granted, Ok(Ok(()))), "checked level denied");
+            }
+        }
+        Ok(Ok(()))
+    }
+
+    fn ancestors<Q>(&self, key: &Q) -> Vec<K>
+    where
+        Q: ToOwned<Owned = K> + ?Sized,
+    {
+        self.hierarchy
+            .as_ref()
+            .map_or_else(Vec::new, |hierarchy| hierarchy.ancestors(&key.to_owned()))
+    }
+
+    /// Removes automatically created keys that are no longer needed,
+    /// returning how many were removed: under auto-pruning those that have
+    /// fully recovered, and with an [idle TTL](Self::with_idle_ttl) those
+    /// unused for longer than it.
+    pub fn maintain(&self) -> usize {
+        if !self.auto_prune && self.idle_ttl.is_none() {
+            return 0;
+        }
+        let now = self.clock().now();
+        let mut keys = self.keys();
+        let Keys {
+            entries,
+            pinned,
+            lru,
+            events,
+            ..
+        } = &mut *keys;
+        let before = entries.len();
+        entries.retain(|key, entry| {
+            let recovered = self.auto_prune && entry.state.is_idle_at(now);
+            let expired = self
+                .idle_ttl
+                .is_some_and(|ttl| now.duration_since(entry.last_used) >= ttl);
+            let keep = pinned.contains(key) || !(recovered || expired);
+            if !keep {
+                lru.remove(&entry.tick);
+                if !self.hooks.is_empty() {
+                    events.push(KeyEvent::Evicted(key.clone()));
+                }
+            }
+            keep
+        });
+        before - entries.len()
+    }
+
+    /// Acquires a permit for each key independently, returning one outcome
+    /// per key in the same order. Earlier grants are kept even if later keys
+    /// are denied; a key repeated in `keys` is charged once per occurrence.
+    pub fn acquire_batch<Q>(&self, keys: &[&Q]) -> Vec<Result<(), AcquireError>>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        keys.iter().map(|key| self.acquire_by_key(key)).collect()
+    }
+
+    /// Iterates over every tracked key with the permits it has remaining and
+    /// the instant it will have fully recovered, in no particular order.
+    ///
+    /// Like [`stats_snapshot`](Self::stats_snapshot), the clock is read once
+    /// and nothing is mutated; unlike it, nothing is allocated. Taking
+    /// `&mut self` lets the keys be borrowed without holding their lock.
+    pub fn key_usage(&mut self) -> impl Iterator<Item = (&K, u64, C::Instant)> {
+        let now = self.clock().now();
+        let keys = self.keys.get_mut();
+        keys.entries.iter().map(move |(key, entry)| {
+            let resets_at = now + entry.state.reset_after_at(now);
+            (key, entry.state.remaining_at(now), resets_at)
+        })
+    }
+
+    /// Returns the current usage of the base state and of every configured key.
+    ///
+    /// This is O(n) in the number of keys. The clock is read once and every
+    /// entry is evaluated against that reading, so the snapshot is internally
+    /// coherent. Nothing is mutated: expired windows are reported as fully
+    /// available but are not reset. Keys are listed in no particular order.
+    pub fn stats_snapshot(&self) -> StatsSnapshot<K> {
+        let now = self.clock().now();
+        let keys = self
+            .keys()
+            .entries
+            .iter()
+            .map(|(key, entry)| KeyStats {
+                key: key.clone(),
+                allowed: entry.state.capacity(),
+                remaining: entry.state.remaining_at(now),
+                reset_after: entry.state.reset_after_at(now),
+            })
+            .collect();
+
+        let base = self.base();
+        StatsSnapshot {
+            base_remaining: base.remaining_at(now),
+            base_reset_after: base.reset_after_at(now),
+            keys,
+        }
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone + AsRef<str>> RateLimiter<C, S, K> {
+    /// Limits unknown keys matching `pattern`, where `*` matches any run of
+    /// characters, e.g. `"/api/v1/*"` or `"admin:*"`.
+    ///
+    /// A key is limited by the most specific matching rule: the one with
+    /// the most literal characters, preferring exact patterns, then the
+    /// earliest added. Keys matched by a rule are created on first use, like
+    /// those under a [default key quota](Self::with_default_key_quota),
+    /// which applies only when no rule matches. Keys configured through
+    /// [`insert_key`](Self::insert_key) are never matched against rules.
+    pub fn with_key_rule(mut self, pattern: &str, limit: impl Into<KeyLimit>) -> Self {
+        self.key_rules
+            .get_or_insert_with(|| KeyRules::new(|key: &K| key.as_ref()))
+            .add(pattern, limit.into());
+        self
+    }
+
+    /// Treats keys as paths split by `separator`, so that acquiring for
+    /// `tenant/user` must pass both the `tenant` and the `tenant/user`
+    /// quota, and is granted by both or neither.
+    ///
+    /// Every ancestor is limited like any other key: configured through
+    /// [`insert_key`](Self::insert_key) or created on first use, and skipped
+    /// if neither. Applies to every keyed acquire, wait and check.
+    pub fn with_key_separator(mut self, separator: char) -> Self
+    where
+        K: for<'a> From<&'a str>,
+    {
+        self.hierarchy = Some(KeyHierarchy::new(
+            separator,
+            |key: &K| key.as_ref(),
+            |text: &str| K::from(text),
+        ));
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct StatsSnapshot<K = String> {
+    pub base_remaining: u64,
+    pub base_reset_after: Nanos,
+    pub keys: Vec<KeyStats<K>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct KeyStats<K = String> {
+    pub key: K,
+    pub allowed: u64,
+    pub remaining: u64,
+    pub reset_after: Nanos,
+}
+
+pub(crate) type Attempt<P> = Result<Result<(), NotUntil<P>>, AcquireError>;
+
+/// Takes `n` permits from `state` at `now`. A state with no capacity can
+/// never grant them, which is an error rather than a denial.
+fn take<C: Clock, S: Algorithm<C>>(state: &mut S, n: u64, now: C::Instant) -> Attempt<C::Instant> {
+    let result = if n == 1 {
+        state.try_acquire_at(now)
+    } else {
+        state.try_acquire_n_at(n, now)?
+    };
+    match result {
+        Err(not_until) if state.capacity() == 0 => Err(AcquireError::not_allowed(not_until, now)),
+        result => Ok(result),
+    }
+}
+
+/// The state of a newly accepted key.
+enum NewKey<S> {
+    Limited(S),
+    Unlimited,
+}
+
+/// The keys of a limiter, guarded together by one lock.
+#[derive(Debug)]
+struct Keys<K, S, P> {
+    entries: HashMap<K, KeyEntry<S, P>>,
+    /// Keys configured through `insert_key`, which are never pruned.
+    pinned: HashSet<K>,
+    /// Keys created on first use by recency of use, tracked under
+    /// `max_keys`.
+    lru: BTreeMap<u64, K>,
+    next_tick: u64,
+    disabled: HashSet<K>,
+    /// Key lifecycle events to report once the lock is released.
+    events: Vec<KeyEvent<K>>,
+}
+
+#[derive(Debug)]
+enum KeyEvent<K> {
+    Created(K, Quota),
+    Evicted(K),
+}
+
+type KeyMap<K, S, C> = Keys<K, S, <C as Clock>::Instant>;
+
+impl<K: Hash + Eq, S, P> Keys<K, S, P> {
+    fn next_tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+
+    /// Evicts keys created on first use until a new one fits under `max`,
+    /// recording the evictions if `report` is set.
+    fn evict_least_recently_used(&mut self, max: usize, report: bool) {
+        while self.lru.len() >= max
+            && let Some((_, key)) = self.lru.pop_first()
+        {
+            self.entries.remove(&key);
+            if report {
+                self.events.push(KeyEvent::Evicted(key));
+            }
+        }
+    }
+}
+
+/// The locked keys of a limiter. Lifecycle events recorded while they are
+/// locked are reported once the lock is released, so that hooks may call
+/// back into the limiter.
+struct KeysGuard<'a, K: Hash + Eq, S, P> {
+    keys: Option<Guard<'a, Keys<K, S, P>>>,
+    hooks: &'a HookList<K>,
+}
+
+impl<K: Hash + Eq, S, P> Deref for KeysGuard<'_, K, S, P> {
+    type Target = Keys<K, S, P>;
+
+    fn deref(&self) -> &Self::Target {
+        self.keys.as_ref().expect("keys are locked until dropped")
+    }
+}
+
+impl<K: Hash + Eq, S, P> DerefMut for KeysGuard<'_, K, S, P> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.keys.as_mut().expect("keys are locked until dropped")
+    }
+}
+
+impl<K: Hash + Eq, S, P> Drop for KeysGuard<'_, K, S, P> {
+    fn drop(&mut self) {
+        let Some(mut keys) = self.keys.take() else {
+            return;
+        };
+        let events = std::mem::take(&mut keys.events);
+        drop(keys);
+        for event in events {
+            match event {
+                KeyEvent::Created(key, quota) => self.hooks.key_created(&key, quota),
+                KeyEvent::Evicted(key) => self.hooks.evicted(&key),
+            }
+        }
+    }
+}
+
+/// A key's state and when it was last used, for idle eviction.
+#[derive(Debug)]
+struct KeyEntry<S, P> {
+    state: S,
+    last_used: P,
+    /// Position in the LRU order.
+    tick: u64,
+    /// Consecutive denials, for escalating backoff.
+    denials: u32,
+}
+
+impl<S, P> KeyEntry<S, P> {
+    fn new(state: S, last_used: P, tick: u64) -> Self {
+        Self {
+            state,
+            last_used,
+            tick,
+            denials: 0,
+        }
+    }
+}
+
+/// Configuration of [escalating backoff](RateLimiter::with_escalating_backoff).
+#[derive(Debug, Clone, Copy)]
+struct Backoff {
+    base: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    /// The backoff suggested after `denials` consecutive denials.
+    fn after(&self, denials: u32) -> Duration {
+        let Some(doublings) = denials.checked_sub(1) else {
+            return Duration::ZERO;
+        };
+        let factor = 1u32.checked_shl(doublings).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, sync::Arc, thread, time::Duration};
+
+    use super::*;
+    use crate::clock::{FakeRelativeClock, MonotonicClock};
+
+    #[test]
+    fn test_rate_limiter_acquire_by_key() {
+        let limiter = RateLimiter::new(Quota::per_second(1), MonotonicClock);
+
+        // 为特定 key 配置限流
+        limiter.insert_key("vip_user", (5, Duration::from_secs(1)));
+
+        // 配置过的 key 可以正常使用
+        assert!(limiter.acquire_by_key("vip_user").is_ok());
+        assert!(limiter.acquire_by_key("vip_user").is_ok());
+
+        // 未配置的 key 直接拒绝
+        assert!(limiter.acquire_by_key("unknown_user").is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_independent_limits() {
+        let limiter = RateLimiter::new(Quota::per_second(1), MonotonicClock);
+
+        limiter.insert_key("user1", (2, Duration::from_secs(1)));
+        limiter.insert_key("user2", (3, Duration::from_secs(1)));
+
+        // user1 和 user2 的限流是独立的
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        assert!(limiter.acquire_by_key("user1").is_err()); // user1 用完配额
+
+        // user2 不受影响
+        assert!(limiter.acquire_by_key("user2").is_ok());
+        assert!(limiter.acquire_by_key("user2").is_ok());
+        assert!(limiter.acquire_by_key("user2").is_ok());
+        assert!(limiter.acquire_by_key("user2").is_err()); // user2 用完配额
+    }
+
+    #[test]
+    fn test_rate_limiter_base_state() {
+        let limiter = RateLimiter::new(Quota::per_second(1), MonotonicClock);
+
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_err());
+    }
+
+    #[test]
+    fn test_stats_snapshot_after_partial_consumption() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(4), clock.clone());
+        limiter.insert_key("user1", (5, Duration::from_secs(1)));
+        limiter.insert_key("user2", (3, Duration::from_millis(500)));
+
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        assert!(limiter.acquire_by_key("user2").is_ok());
+
+        clock.advance(std::time::Duration::from_millis(200));
+        let snapshot = limiter.stats_snapshot();
+        assert_eq!(snapshot.base_remaining, 3);
+        assert_eq!(snapshot.base_reset_after, Nanos::new(800_000_000));
+
+        let mut keys = snapshot.keys;
+        keys.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(
+            keys,
+            vec![
+                KeyStats {
+                    key: "user1".to_string(),
+                    allowed: 5,
+                    remaining: 3,
+                    reset_after: Nanos::new(800_000_000),
+                },
+                KeyStats {
+                    key: "user2".to_string(),
+                    allowed: 3,
+                    remaining: 2,
+                    reset_after: Nanos::new(300_000_000),
+                },
+            ]
+        );
+
+        // 快照不修改状态，窗口过期后报告为完全可用
+        clock.advance(std::time::Duration::from_millis(300));
+        let snapshot = limiter.stats_snapshot();
+        let user2 = snapshot.keys.iter().find(|k| k.key == "user2").unwrap();
+        assert_eq!(user2.remaining, 3);
+        assert_eq!(user2.reset_after, Nanos::ZERO);
+    }
+
+    #[test]
+    fn test_key_usage() {
+        let clock = FakeRelativeClock::default();
+        let mut limiter = RateLimiter::new(Quota::per_second(1), clock.clone());
+        limiter.insert_key("user1", Quota::per_second(3));
+        limiter.insert_key("user2", Quota::per_second(2));
+        clock.advance(Duration::from_millis(100));
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        clock.advance(Duration::from_millis(200));
+
+        let mut usage: Vec<_> = limiter.key_usage().collect();
+        usage.sort_by_key(|(key, ..)| key.as_str());
+        // 固定窗口从插入时开始，两个 key 都在 1s 时重置
+        assert_eq!(
+            usage,
+            vec![
+                (&"user1".to_string(), 2, Nanos::new(1_000_000_000)),
+                (&"user2".to_string(), 2, Nanos::new(1_000_000_000)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_key_with_quota() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone());
+        limiter.insert_key("user1", Quota::new(1, Nanos::new(100_000_000)));
+
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        assert!(limiter.acquire_by_key("user1").is_err());
+
+        // key 使用限流器的时钟
+        clock.advance(Duration::from_millis(100));
+        assert!(limiter.acquire_by_key("user1").is_ok());
+    }
+
+    #[test]
+    fn test_non_string_keys() {
+        let clock = FakeRelativeClock::default();
+        let by_id: RateLimiter<_, _, u64> =
+            RateLimiter::keyed(State::new(Quota::per_second(10), clock.clone()));
+        by_id.insert_key(&42, Quota::per_second(1));
+        assert!(by_id.acquire_by_key(&42).is_ok());
+        assert!(by_id.acquire_by_key(&42).is_err());
+        assert_eq!(by_id.acquire_by_key(&7), Err(AcquireError::UnknownKey));
+
+        // 元组 key 与 IP 地址，配合自动创建
+        let ip = IpAddr::from([127, 0, 0, 1]);
+        let by_route =
+            RateLimiter::<_, _, (IpAddr, u16)>::keyed(State::new(Quota::per_second(1), clock))
+                .with_auto_prune(true);
+        assert!(by_route.acquire_by_key(&(ip, 80)).is_ok());
+        assert!(by_route.acquire_by_key(&(ip, 443)).is_ok());
+        assert!(by_route.acquire_by_key(&(ip, 80)).is_err());
+        assert_eq!(by_route.stats_snapshot().keys.len(), 2);
+    }
+
+    #[test]
+    fn test_acquire_batch() {
+        let limiter = RateLimiter::new(Quota::per_second(1), MonotonicClock);
+        limiter.insert_key("user1", (1, Duration::from_secs(1)));
+        limiter.insert_key("user2", (2, Duration::from_secs(1)));
+
+        // 每个 key 独立判断，部分成功是正常的
+        let outcomes = limiter.acquire_batch(&["user1", "unknown", "user2", "user1", "user2"]);
+        assert_eq!(
+            outcomes.iter().map(Result::is_ok).collect::<Vec<_>>(),
+            vec![true, false, true, false, true]
+        );
+        assert_eq!(outcomes[1], Err(AcquireError::UnknownKey));
+        assert!(limiter.acquire_batch(&["user2"])[0].is_err());
+        assert!(limiter.acquire_batch::<str>(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_auto_prune_removes_idle_keys() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(2), clock.clone()).with_auto_prune(true);
+        limiter.insert_key("vip", (10, Duration::from_secs(1)));
+
+        // 未知 key 按基础配额自动创建
+        assert!(limiter.acquire_by_key("idle").is_ok());
+        assert!(limiter.acquire_by_key("vip").is_ok());
+        assert_eq!(limiter.maintain(), 0);
+
+        clock.advance(Duration::from_millis(600));
+        assert!(limiter.acquire_by_key("active").is_ok());
+        clock.advance(Duration::from_millis(600));
+
+        // idle 的窗口已经过去，active 的窗口仍在进行中，vip 使用自定义配额
+        assert_eq!(limiter.maintain(), 1);
+        assert!(!limiter.contains_key("idle"));
+        assert!(limiter.contains_key("active"));
+        assert!(limiter.contains_key("vip"));
+
+        // 被清理的 key 下次使用时重新创建
+        assert!(limiter.acquire_by_key("idle").is_ok());
+        assert!(limiter.acquire_by_key("idle").is_ok());
+        assert!(limiter.acquire_by_key("idle").is_err());
+    }
+
+    #[test]
+    fn test_default_key_quota() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(100), clock.clone())
+            .with_default_key_quota(Quota::per_second(2));
+        limiter.insert_key("vip", Quota::per_second(5));
+
+        // 未知 key 使用默认配额创建，而不是基础配额
+        assert_eq!(limiter.check_key("user1"), Ok(2));
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        assert!(limiter.acquire_by_key("user1").is_ok());
+        assert!(limiter.acquire_by_key("user1").is_err());
+        assert!(limiter.acquire_by_key("user2").is_ok());
+        assert_eq!(limiter.check_key("vip"), Ok(5));
+
+        // 配合自动清理，空闲后可以被回收
+        let limiter = limiter.with_auto_prune(true);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.maintain(), 2);
+        assert!(limiter.contains_key("vip"));
+        assert_eq!(limiter.check_key("user1"), Ok(2));
+    }
+
+    #[test]
+    fn test_idle_ttl_evicts_unused_keys() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(100), clock.clone())
+            .with_default_key_quota(Quota::per_minute(2))
+            .with_idle_ttl(Duration::from_secs(30));
+        limiter.insert_key("vip", Quota::per_second(1));
+
+        assert!(limiter.acquire_by_key("idle").is_ok());
+        assert!(limiter.acquire_by_key("busy").is_ok());
+        clock.advance(Duration::from_secs(20));
+        assert!(limiter.acquire_by_key("busy").is_ok());
+        assert_eq!(limiter.maintain(), 0);
+
+        // idle 超过 30 秒未使用被淘汰，busy 在 20 秒时用过，vip 是配置的 key
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(limiter.maintain(), 1);
+        assert!(!limiter.contains_key("idle"));
+        assert!(limiter.contains_key("busy"));
+        assert!(limiter.contains_key("vip"));
+
+        // 重新创建的 key 拥有全新的配额，busy 的配额仍然被保留
+        assert!(limiter.acquire_by_key("idle").is_ok());
+        assert!(limiter.acquire_by_key("idle").is_ok());
+        assert!(limiter.acquire_by_key("idle").is_err());
+        assert!(limiter.acquire_by_key("busy").is_err());
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(limiter.maintain(), 2);
+        assert_eq!(limiter.stats_snapshot().keys.len(), 1);
+    }
+
+    #[test]
+    fn test_set_key_quota_keeps_key_prunable() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(100), clock.clone())
+            .with_default_key_quota(Quota::per_second(1))
+            .with_max_keys(2);
+        limiter.insert_key("pinned", Quota::per_second(1));
+        limiter.set_key_quota("pinned", Quota::per_second(3));
+        limiter.set_key_quota("a", Quota::per_second(3));
+        limiter.set_key_quota("b", Quota::per_second(3));
+        assert_eq!(limiter.key_quota("a"), Some(Quota::per_second(3)));
+
+        // 覆盖配额的键仍受 max_keys 约束，配置过的键不受影响
+        limiter.set_key_quota("c", Quota::per_second(3));
+        assert!(!limiter.contains_key("a"));
+        assert_eq!(limiter.len(), 3);
+        assert_eq!(limiter.key_quota("pinned"), Some(Quota::per_second(3)));
+        assert!(limiter.keys().pinned.contains("pinned"));
+    }
+
+    #[test]
+    fn test_max_keys_evicts_least_recently_used() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone())
+            .with_auto_prune(true)
+            .with_max_keys(2);
+        limiter.insert_key("vip", Quota::per_second(5));
+
+        assert!(limiter.acquire_by_key("a").is_ok());
+        assert!(limiter.acquire_by_key("b").is_ok());
+        assert!(limiter.acquire_by_key("a").is_err());
+
+        // a 最近被访问过，新 key c 挤掉最久未使用的 b；vip 不计入上限
+        assert!(limiter.acquire_by_key("c").is_ok());
+        assert!(limiter.contains_key("a"));
+        assert!(!limiter.contains_key("b"));
+        assert!(limiter.contains_key("vip"));
+        assert_eq!(limiter.len(), 3);
+
+        // 大量新 key 也不会突破上限
+        for i in 0..100 {
+            assert!(limiter.acquire_by_key(&format!("attacker-{i}")).is_ok());
+        }
+        assert_eq!(limiter.len(), 3);
+        assert_eq!(limiter.keys().lru.len(), 2);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.maintain(), 2);
+        assert!(limiter.keys().lru.is_empty());
+    }
+
+    #[test]
+    fn test_key_management() {
+        let limiter = RateLimiter::new(Quota::per_second(1), MonotonicClock)
+            .with_default_key_quota(Quota::per_minute(10))
+            .with_max_keys(10);
+        limiter.insert_key("vip", Quota::per_second(5));
+        assert!(limiter.acquire_by_key("user").is_ok());
+
+        assert_eq!(limiter.len(), 2);
+        assert!(limiter.contains_key("vip"));
+        assert!(!limiter.contains_key("other"));
+        assert_eq!(limiter.key_quota("vip"), Some(Quota::per_second(5)));
+        assert_eq!(limiter.key_quota("user"), Some(Quota::per_minute(10)));
+        assert_eq!(limiter.key_quota("other"), None);
+
+        // 删除配置的 key 后，再次使用时按默认配额重新创建
+        assert!(limiter.remove_key("vip"));
+        assert!(!limiter.remove_key("vip"));
+        assert!(limiter.acquire_by_key("vip").is_ok());
+        assert_eq!(limiter.key_quota("vip"), Some(Quota::per_minute(10)));
+        assert_eq!(limiter.keys().lru.len(), 2);
+
+        limiter.set_key_enabled("user", false);
+        limiter.clear();
+        assert!(limiter.is_empty());
+        assert!(limiter.keys().lru.is_empty());
+        assert_eq!(limiter.acquire_by_key("user"), Err(AcquireError::Disabled));
+        assert!(limiter.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_reset_key() {
+        let limiter = RateLimiter::new(Quota::per_second(1), FakeRelativeClock::default())
+            .with_default_key_quota(Quota::per_minute(2));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_ok());
+        let stats = limiter.key_stats("user").unwrap();
+        assert_eq!((stats.allowed, stats.remaining), (2, 0));
+        assert_eq!(stats.reset_after, Nanos::from_secs(60));
+
+        // 重置后配额回满，但仍是按需创建的 key
+        assert!(limiter.reset_key("user"));
+        assert_eq!(limiter.check_key("user"), Ok(2));
+        assert!(!limiter.keys().pinned.contains("user"));
+        assert!(!limiter.reset_key("other"));
+        assert_eq!(limiter.key_stats("other"), None);
+    }
+
+    #[test]
+    fn test_introspection() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(2), clock.clone());
+        limiter.insert_key("user", Quota::per_minute(3));
+        assert_eq!(limiter.quota(), Quota::per_second(2));
+        assert_eq!(limiter.remaining(), 2);
+
+        limiter.acquire().unwrap();
+        limiter.acquire_by_key("user").unwrap();
+        clock.advance(Duration::from_secs(10));
+        assert_eq!((limiter.remaining(), limiter.resets_at()), (2, clock.now()));
+        assert_eq!(limiter.key_remaining("user"), Some(2));
+        assert_eq!(limiter.key_resets_at("user"), Some(Nanos::from_secs(60)));
+        assert_eq!(limiter.key_quota("user"), Some(Quota::per_minute(3)));
+
+        // 未跟踪的 key 没有状态
+        assert_eq!(limiter.key_remaining("other"), None);
+        assert_eq!(limiter.key_resets_at("other"), None);
+    }
+
+    #[test]
+    fn test_key_rules() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone())
+            .with_default_key_quota(Quota::per_second(1))
+            .with_key_rule("/api/*", Quota::per_second(2))
+            .with_key_rule("/api/v1/*", Quota::per_second(3))
+            .with_key_rule("admin:*", KeyLimit::Unlimited);
+        limiter.insert_key("/api/v1/pinned", Quota::per_second(5));
+
+        // 按最具体的规则创建 key
+        assert_eq!(limiter.check_key("/api/v2/users"), Ok(2));
+        assert_eq!(limiter.acquire_n_by_key("/api/v1/users", 3), Ok(()));
+        assert!(limiter.acquire_by_key("/api/v1/users").is_err());
+        assert_eq!(
+            limiter.key_quota("/api/v1/users"),
+            Some(Quota::per_second(3))
+        );
+        assert_eq!(limiter.check_key("/api/v1/pinned"), Ok(5));
+
+        // 不匹配任何规则时使用默认配额
+        assert!(limiter.acquire_by_key("/static/app.js").is_ok());
+        assert!(limiter.acquire_by_key("/static/app.js").is_err());
+
+        // 不限流的 key 不保存状态
+        for _ in 0..1_000 {
+            assert!(limiter.acquire_by_key("admin:root").is_ok());
+        }
+        assert_eq!(limiter.check_key("admin:root"), Ok(u64::MAX));
+        assert!(!limiter.contains_key("admin:root"));
+        limiter.set_key_enabled("admin:root", false);
+        assert_eq!(
+            limiter.acquire_by_key("admin:root"),
+            Err(AcquireError::Disabled)
+        );
+    }
+
+    #[test]
+    fn test_hierarchical_keys() {
+        let clock = FakeRelativeClock::default();
+        let limiter =
+            RateLimiter::new(Quota::per_second(100), clock.clone()).with_key_separator('/');
+        limiter.insert_key("acme", Quota::per_second(3));
+        limiter.insert_key("acme/alice", Quota::per_second(2));
+        limiter.insert_key("acme/bob", Quota::per_second(2));
+
+        // 用户配额和租户配额都要满足
+        assert_eq!(limiter.check_key("acme/alice"), Ok(2));
+        assert!(limiter.acquire_by_key("acme/alice").is_ok());
+        assert!(limiter.acquire_by_key("acme/alice").is_ok());
+        assert!(limiter.acquire_by_key("acme/alice").is_err());
+        assert_eq!(limiter.check_key("acme/bob"), Ok(1));
+        assert!(limiter.acquire_by_key("acme/bob").is_ok());
+
+        // 租户用完后，被拒绝的请求不消耗用户配额
+        assert!(matches!(
+            limiter.acquire_by_key("acme/bob"),
+            Err(AcquireError::NotAllowed { retry_after, .. })
+                if retry_after == Duration::from_secs(1)
+        ));
+        assert_eq!(limiter.key_quota("acme/bob"), Some(Quota::per_second(2)));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.acquire_n_by_key("acme/bob", 2), Ok(()));
+        assert_eq!(
+            limiter.acquire_n_by_key("acme/bob", 3),
+            Err(AcquireError::InsufficientCapacity { capacity: 2 })
+        );
+
+        // 未配置的上级不限流，未配置的 key 仍然被拒绝
+        limiter.insert_key("solo/carol", Quota::per_second(1));
+        assert!(limiter.acquire_by_key("solo/carol").is_ok());
+        assert_eq!(
+            limiter.acquire_by_key("acme/dave"),
+            Err(AcquireError::UnknownKey)
+        );
+
+        // 禁用上级会禁用所有下级
+        limiter.set_key_enabled("acme", false);
+        assert_eq!(
+            limiter.acquire_by_key("acme/alice"),
+            Err(AcquireError::Disabled)
+        );
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let limiter = Arc::new(RateLimiter::new(Quota::per_second(100), MonotonicClock));
+        limiter.insert_key("user", Quota::per_minute(1_000));
+
+        // 多个线程共享同一个限流器，总共只放行配额内的请求
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let limiter = Arc::clone(&limiter);
+                thread::spawn(move || {
+                    (0..500)
+                        .filter(|_| limiter.acquire_by_key("user").is_ok())
+                        .count()
+                })
+            })
+            .collect();
+        let granted: usize = handles.into_iter().map(|h| h.join().unwrap()).sum();
+        assert_eq!(granted, 1_000);
+        assert!(limiter.acquire_by_key("user").is_err());
+    }
+
+    #[test]
+    fn test_sync_backends() {
+        let backends = [
+            SyncBackend::Std,
+            #[cfg(feature = "parking_lot")]
+            SyncBackend::ParkingLot,
+        ];
+        for backend in backends {
+            let limiter = RateLimiter::new(Quota::per_second(2), MonotonicClock);
+            limiter.insert_key("user", Quota::per_second(1));
+            // 切换后端保留已有的状态
+            assert!(limiter.acquire_by_key("user").is_ok());
+            let limiter = limiter.with_sync_backend(backend);
+
+            assert!(limiter.acquire_by_key("user").is_err());
+            assert!(limiter.acquire().is_ok());
+            assert!(limiter.acquire().is_ok());
+            assert!(limiter.acquire().is_err());
+        }
+    }
+
+    #[test]
+    fn test_acquire_at_event_time() {
+        // 时钟停在回放起点，按事件时间驱动限流
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(2), clock.clone())
+            .with_default_key_quota(Quota::per_second(1));
+        let at = |ms: u64| Nanos::new(ms * 1_000_000);
+
+        assert!(limiter.acquire_at(at(0)).is_ok());
+        assert!(limiter.acquire_at(at(500)).is_ok());
+        assert_eq!(
+            limiter.acquire_at(at(900)),
+            Err(AcquireError::NotAllowed {
+                retry_after: Duration::from_millis(100),
+                quota: Quota::per_second(2),
+                remaining: 0,
+                backoff: Duration::from_millis(100),
+            })
+        );
+        assert_eq!(limiter.acquire_n_at(2, at(1_000)), Ok(()));
+
+        let events = [("a", 100), ("a", 600), ("b", 700), ("a", 1_200)];
+        let admitted: Vec<_> = events
+            .iter()
+            .map(|&(key, ms)| limiter.acquire_by_key_at(key, at(ms)).is_ok())
+            .collect();
+        assert_eq!(admitted, [true, false, true, true]);
+        assert_eq!(clock.now(), Nanos::ZERO);
+    }
+
+    #[test]
+    fn test_acquire_at_older_event_time() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(2), clock.clone())
+            .with_default_key_quota(Quota::per_second(2));
+        clock.advance(Duration::from_secs(2));
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire_by_key("user").is_ok());
+
+        // 早于状态上次更新（或创建）的事件时间不算时间流逝，也不会提前补充许可
+        let past = Nanos::new(500_000_000);
+        assert!(limiter.acquire_at(past).is_ok());
+        assert!(limiter.acquire_at(past).is_err());
+        assert!(limiter.acquire_by_key_at("user", past).is_ok());
+        assert!(limiter.acquire_by_key_at("user", past).is_err());
+        assert!(limiter.acquire_by_key_at("new", past).is_ok());
+
+        // 滑动窗口也不会回退到更早的窗口
+        let clock = FakeRelativeClock::default();
+        let base = crate::SlidingWindowState::new(Nanos::new(1_000_000_000), 10, clock.clone());
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", Quota::per_second(2));
+        clock.advance(Duration::from_millis(5_500));
+        assert!(limiter.acquire_n_by_key("user", 2).is_ok());
+        assert!(limiter.acquire_by_key_at("user", past).is_err());
+        clock.advance(Duration::from_millis(100));
+        assert!(limiter.acquire_by_key("user").is_err());
+    }
+
+    #[test]
+    fn test_escalating_backoff() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(10), clock.clone())
+            .with_escalating_backoff(Duration::from_secs(2), Duration::from_secs(5));
+        limiter.insert_key("user", Quota::per_second(1));
+        let backoff = |result: Result<(), AcquireError>| match result {
+            Err(AcquireError::NotAllowed { backoff, .. }) => backoff,
+            other => panic!("unexpected {other:?}"),
+        };
+
+        assert!(limiter.acquire_by_key("user").is_ok());
+        // 连续被拒绝时建议的退避时间翻倍，直到上限
+        let backoffs: Vec<_> = (0..4)
+            .map(|_| backoff(limiter.acquire_by_key("user")).as_secs())
+            .collect();
+        assert_eq!(backoffs, [2, 4, 5, 5]);
+
+        // 放行后重新计数
+        clock.advance(Duration::from_secs(1));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert_eq!(
+            backoff(limiter.acquire_by_key("user")),
+            Duration::from_secs(2)
+        );
+    }
+
+    #[test]
+    fn test_maintain_without_auto_prune() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(2), clock.clone());
+        limiter.insert_key("user", (2, Duration::from_secs(1)));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(limiter.maintain(), 0);
+        assert!(limiter.acquire_by_key("unknown").is_err());
+        assert!(limiter.contains_key("user"));
+    }
+
+    #[test]
+    fn test_with_key_capacity() {
+        let limiter =
+            RateLimiter::new(Quota::per_second(1), MonotonicClock).with_key_capacity(1_000);
+        assert!(limiter.capacity() >= 1_000);
+    }
+
+    #[test]
+    fn test_shrink_to_fit_after_pruning() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone()).with_auto_prune(true);
+        for i in 0..10_000 {
+            assert!(limiter.acquire_by_key(&format!("ip-{i}")).is_ok());
+        }
+        clock.advance(Duration::from_secs(1));
+        assert!(limiter.acquire_by_key("ip-0").is_ok());
+
+        assert_eq!(limiter.maintain(), 9_999);
+        let grown = limiter.capacity();
+        limiter.shrink_to_fit();
+        assert!(limiter.capacity() < grown);
+        assert!(limiter.acquire_by_key("ip-1").is_ok());
+    }
+
+    #[test]
+    fn test_acquire_by_key_errors() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone());
+        limiter.insert_key("user", (1, Duration::from_secs(1)));
+        limiter.insert_key("blocked", (1, Duration::from_secs(1)));
+        limiter.set_key_enabled("blocked", false);
+
+        assert_eq!(limiter.acquire_by_key("user"), Ok(()));
+        clock.advance(Duration::from_millis(400));
+        assert_eq!(
+            limiter.acquire_by_key("user"),
+            Err(AcquireError::NotAllowed {
+                retry_after: Duration::from_millis(600),
+                quota: Quota::from((1, Duration::from_secs(1))),
+                remaining: 0,
+                backoff: Duration::from_millis(600),
+            })
+        );
+        assert_eq!(
+            limiter.acquire_by_key("unknown"),
+            Err(AcquireError::UnknownKey)
+        );
+        assert_eq!(
+            limiter.acquire_by_key("blocked"),
+            Err(AcquireError::Disabled)
+        );
+
+        limiter.set_key_enabled("blocked", true);
+        assert_eq!(limiter.acquire_by_key("blocked"), Ok(()));
+    }
+
+    #[test]
+    fn test_acquire_by_key_or_base() {
+        let limiter = RateLimiter::new(Quota::per_second(1), FakeRelativeClock::default());
+        limiter.insert_key("user", Quota::per_second(1));
+
+        assert_eq!(limiter.acquire_by_key_or_base(Some("user")), Ok(()));
+        // 未知的 key 和没有 key 一样，共用基础配额
+        assert_eq!(limiter.acquire_by_key_or_base(Some("unknown")), Ok(()));
+        assert!(limiter.acquire_by_key_or_base::<str>(None).is_err());
+    }
+
+    #[test]
+    fn test_acquire_n_by_key() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(10), clock.clone());
+        limiter.insert_key("batch", Quota::per_second(5));
+
+        assert_eq!(limiter.acquire_n(10), Ok(()));
+        assert!(limiter.acquire_n(1).is_err());
+
+        assert_eq!(limiter.acquire_n_by_key("batch", 4), Ok(()));
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(
+            limiter.acquire_n_by_key("batch", 2),
+            Err(AcquireError::NotAllowed {
+                retry_after: Duration::from_millis(750),
+                quota: Quota::per_second(5),
+                remaining: 1,
+                backoff: Duration::from_millis(750),
+            })
+        );
+        assert_eq!(
+            limiter.acquire_n_by_key("batch", 6),
+            Err(AcquireError::InsufficientCapacity { capacity: 5 })
+        );
+        assert_eq!(
+            limiter.acquire_n_by_key("unknown", 1),
+            Err(AcquireError::UnknownKey)
+        );
+
+        // 失败的请求不消耗配额
+        assert!(limiter.acquire_n_by_key("batch", 1).is_ok());
+        assert!(limiter.acquire_by_key("batch").is_err());
+    }
+
+    #[test]
+    fn test_acquire_wait() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone());
+        limiter.insert_key("user", Quota::per_second(2));
+
+        // 假时钟的 sleep 直接推进时间
+        assert_eq!(limiter.acquire_wait(None), Ok(()));
+        assert_eq!(limiter.acquire_wait(None), Ok(()));
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+
+        // 等待时间超过上限时立即返回，不消耗等待时间
+        clock.advance(Duration::from_millis(200));
+        assert!(
+            limiter
+                .acquire_wait(Some(Duration::from_millis(700)))
+                .is_err()
+        );
+        assert_eq!(clock.elapsed(), Duration::from_millis(1_200));
+        assert_eq!(
+            limiter.acquire_wait(Some(Duration::from_millis(800))),
+            Ok(())
+        );
+        assert_eq!(clock.elapsed(), Duration::from_secs(2));
+
+        for _ in 0..3 {
+            assert_eq!(limiter.acquire_wait_by_key("user", None), Ok(()));
+        }
+        assert_eq!(clock.elapsed(), Duration::from_secs(3));
+        assert_eq!(
+            limiter.acquire_wait_by_key("unknown", None),
+            Err(AcquireError::UnknownKey)
+        );
+    }
+
+    #[test]
+    fn test_acquire_wait_zero_capacity_does_not_block() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(0), clock.clone());
+        assert!(limiter.acquire_wait(None).is_err());
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_far_future_denial_overflows() {
+        // 时钟接近 Nanos 的表示上限，下一个窗口无法表示
+        let clock = FakeRelativeClock::new_at(Duration::from_nanos(u64::MAX - 1_000_000_000));
+        let limiter = RateLimiter::new((1, Duration::from_secs(2)), clock.clone());
+        assert!(limiter.acquire().is_ok());
+        assert_eq!(
+            limiter.acquire(),
+            Err(AcquireError::TimeOverflow {
+                quota: (1, Duration::from_secs(2)).into(),
+            })
+        );
+        assert!(limiter.acquire_wait(None).is_err());
+        assert_eq!(clock.elapsed(), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_check_does_not_consume() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(2), clock.clone());
+        limiter.insert_key("user", Quota::per_second(1));
+
+        assert_eq!(limiter.check(), Ok(2));
+        assert_eq!(limiter.check(), Ok(2));
+        assert!(limiter.acquire().is_ok());
+        assert_eq!(limiter.check(), Ok(1));
+
+        assert_eq!(limiter.check_key("user"), Ok(1));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(
+            limiter.check_key("user"),
+            Err(AcquireError::NotAllowed {
+                retry_after: Duration::from_millis(900),
+                quota: Quota::per_second(1),
+                remaining: 0,
+                backoff: Duration::from_millis(900),
+            })
+        );
+        assert_eq!(limiter.check_key("unknown"), Err(AcquireError::UnknownKey));
+
+        // 自动清理模式下未知 key 报告完整配额，但不会被创建
+        let limiter = limiter.with_auto_prune(true);
+        assert_eq!(limiter.check_key("unknown"), Ok(2));
+        assert!(!limiter.contains_key("unknown"));
+
+        limiter.set_enabled(false);
+        assert_eq!(limiter.check(), Err(AcquireError::Disabled));
+    }
+
+    #[test]
+    fn test_disabled_limiter_blocks_everything() {
+        let limiter = RateLimiter::new(Quota::per_second(10), MonotonicClock);
+        limiter.insert_key("user", (10, Duration::from_secs(1)));
+        limiter.set_enabled(false);
+
+        assert!(limiter.acquire().is_err());
+        assert!(limiter.acquire_by_key("user").is_err());
+        assert_eq!(
+            limiter.acquire_by_key("unknown"),
+            Err(AcquireError::Disabled)
+        );
+
+        limiter.set_enabled(true);
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire_by_key("user").is_ok());
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use memcache::{Client, CommandError, MemcacheError};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{
+    headers::ceil_secs,
+    store::{Degraded, DegradedMode, StateStore, Versioned},
+};
+
+/// Memcached reads expirations longer than this as UNIX timestamps.
+const MAX_RELATIVE_EXPIRATION: u64 = 30 * 24 * 60 * 60;
+
+/// An error from a [`MemcachedStore`].
+#[derive(Debug)]
+pub enum MemcachedStoreError {
+    Memcache(MemcacheError),
+    /// A stored value could not be decoded, or a value encoded.
+    Encoding(serde_json::Error),
+}
+
+impl fmt::Display for MemcachedStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Memcache(err) => write!(f, "memcached: {err}"),
+            Self::Encoding(err) => write!(f, "invalid stored state: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for MemcachedStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Memcache(err) => Some(err),
+            Self::Encoding(err) => Some(err),
+        }
+    }
+}
+
+impl MemcachedStoreError {
+    /// Whether memcached couldn't be reached, rather than misbehaving.
+    fn is_unreachable(&self) -> bool {
+        matches!(
+            self,
+            Self::Memcache(MemcacheError::IOError(_) | MemcacheError::PoolError(_))
+        )
+    }
+}
+
+/// A [`StateStore`] in memcached, for sharing limits between processes
+/// where a memcached fleet is already running.
+///
+/// Values are stored as JSON under the [key prefix](Self::with_prefix),
+/// which together with the key must form a valid memcached key: at most 250
+/// bytes, without spaces or control characters. Versions are memcached's CAS
+/// tokens, so updates are checked by the server with `cas`, and new keys
+/// are created with `add`. The client must use the binary protocol, its
+/// default, since the ASCII protocol doesn't report a failed `add`.
+///
+/// Connections are pooled by the [`Client`], and while memcached is
+/// unreachable the store behaves as its [`DegradedMode`] says.
+///
+/// ```no_run
+/// use ratelimit::{Algorithm, GcraState, MemcachedStore, Quota, StoredRateLimiter, SystemClock};
+///
+/// let client = memcache::Client::connect("memcache://127.0.0.1:11211").unwrap();
+/// let store = MemcachedStore::new(client).with_prefix("api:");
+/// let limiter = StoredRateLimiter::new(store, GcraState::from_quota(Quota::per_second(10), SystemClock));
+/// limiter.acquire_by_key("user-42")?;
+/// # Ok::<(), ratelimit::AcquireError>(())
+/// ```
+pub struct MemcachedStore<V> {
+    client: Client,
+    prefix: String,
+    ttl: Option<Duration>,
+    degraded: Degraded<V>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V> MemcachedStore<V> {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            prefix: String::new(),
+            ttl: None,
+            degraded: Degraded::new(DegradedMode::default()),
+            _value: PhantomData,
+        }
+    }
+
+    /// Prefixes every memcached key with `prefix`.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Expires keys not updated for `ttl`, rounded up to whole seconds,
+    /// which should be at least as long as a state takes to fully recover.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_degraded_mode(mut self, mode: DegradedMode) -> Self {
+        self.degraded.set_mode(mode);
+        self
+    }
+
+    /// Calls `listener` with `true` when memcached becomes unreachable and the
+    /// store starts answering as its [`DegradedMode`] says, and with `false`
+    /// once it answers again, e.g. to alert on a degraded limiter.
+    pub fn with_degraded_listener<F>(mut self, listener: F) -> Self
+    where
+        F: Fn(bool) + Send + Sync + 'static,
+    {
+        self.degraded.set_listener(Arc::new(listener));
+        self
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn memcached_key(&self, key: &impl fmt::Display) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    /// The expiration to store values with, in memcached's terms.
+    fn expiration(&self) -> u32 {
+        let Some(ttl) = self.ttl else {
+            return 0;
+        };
+        let mut secs = ceil_secs(ttl);
+        if secs > MAX_RELATIVE_EXPIRATION {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            secs = secs.saturating_add(now.as_secs());
+        }
+        secs.try_into().unwrap_or(u32::MAX)
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> MemcachedStore<V> {
+    fn encode(value: &V) -> Result<String, MemcachedStoreError> {
+        serde_json::to_string(value).map_err(MemcachedStoreError::Encoding)
+    }
+
+    fn decode(data: &[u8]) -> Result<V, MemcachedStoreError> {
+        serde_json::from_slice(data).map_err(MemcachedStoreError::Encoding)
+    }
+
+    fn try_load(&self, key: &str) -> Result<Option<Versioned<V>>, MemcachedStoreError> {
+        let loaded: Option<(Vec<u8>, u32, Option<u64>)> = self
+            .client
+            .gets(&[key])
+            .map_err(MemcachedStoreError::Memcache)?
+            .remove(key);
+        let Some((data, _flags, Some(version))) = loaded else {
+            return Ok(None);
+        };
+        Ok(Some(Versioned {
+            value: Self::decode(&data)?,
+            version,
+        }))
+    }
+
+    fn try_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<u64>,
+        value: &V,
+    ) -> Result<bool, MemcachedStoreError> {
+        let data = Self::encode(value)?;
+        let stored = match expected {
+            Some(version) => self.client.cas(key, data, self.expiration(), version),
+            None => self.client.add(key, data, self.expiration()).map(|()| true),
+        };
+        match stored {
+            Ok(stored) => Ok(stored),
+            // Another writer changed, created or removed the key first.
+            Err(MemcacheError::CommandError(
+                CommandError::KeyExists | CommandError::KeyNotFound,
+            )) => Ok(false),
+            Err(err) => Err(MemcachedStoreError::Memcache(err)),
+        }
+    }
+}
+
+impl<K, V> StateStore<K, V> for MemcachedStore<V>
+where
+    K: fmt::Display,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    type Error = MemcachedStoreError;
+
+    fn load(&self, key: &K) -> Result<Option<Versioned<V>>, MemcachedStoreError> {
+        let key = self.memcached_key(key);
+        match self.try_load(&key) {
+            Err(err) if err.is_unreachable() => self.degraded.load(&key, err),
+            loaded => {
+                self.degraded.reached();
+                loaded
+            }
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &K,
+        expected: Option<u64>,
+        value: V,
+    ) -> Result<bool, MemcachedStoreError> {
+        let key = self.memcached_key(key);
+        match self.try_compare_and_swap(&key, expected, &value) {
+            Err(err) if err.is_unreachable() => {
+                self.degraded.compare_and_swap(&key, expected, value, err)
+            }
+            swapped => {
+                self.degraded.reached();
+                swapped
+            }
+        }
+    }
+
+    fn remove(&self, key: &K) -> Result<bool, MemcachedStoreError> {
+        let key = self.memcached_key(key);
+        let local = self.degraded.remove(&key);
+        match self
+            .client
+            .delete(&key)
+            .map_err(MemcachedStoreError::Memcache)
+        {
+            Ok(removed) => {
+                self.degraded.reached();
+                Ok(removed || local)
+            }
+            Err(err) if err.is_unreachable() => self.degraded.unreachable(local, err),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<V> fmt::Debug for MemcachedStore<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemcachedStore")
+            .field("prefix", &self.prefix)
+            .field("ttl", &self.ttl)
+            .field("degraded", &self.degraded.mode())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nanos::Nanos, state::WindowSnapshot};
+
+    type Store = MemcachedStore<WindowSnapshot<Nanos>>;
+
+    /// A store whose memcached is never reached by the tests using it.
+    fn offline() -> Store {
+        let url = memcache::Url::parse("memcache://127.0.0.1:1").unwrap();
+        let pool = r2d2::Pool::builder()
+            .connection_timeout(Duration::from_millis(50))
+            .build_unchecked(memcache::ConnectionManager::new(url));
+        MemcachedStore::new(Client::with_pool(pool).unwrap())
+    }
+
+    /// A store on the memcached server at `MEMCACHED_URL`, or a local one,
+    /// with keys of its own.
+    fn live() -> MemcachedStore<u64> {
+        let url = std::env::var("MEMCACHED_URL")
+            .unwrap_or_else(|_| "memcache://127.0.0.1:11211".to_owned());
+        let prefix = format!("ratelimit-test-{}:", std::process::id());
+        MemcachedStore::new(Client::connect(url).unwrap()).with_prefix(prefix)
+    }
+
+    #[test]
+    fn test_memcached_key_and_encoding() {
+        let store = offline().with_prefix("api:");
+        assert_eq!(store.memcached_key(&"user-42"), "api:user-42");
+
+        let snapshot = WindowSnapshot {
+            start: Nanos::new(1_500),
+            acquired: 2,
+        };
+        let data = Store::encode(&snapshot).unwrap();
+        assert_eq!(data, r#"{"start":1500,"acquired":2}"#);
+        assert_eq!(Store::decode(data.as_bytes()).unwrap(), snapshot);
+        assert!(matches!(
+            Store::decode(b"{}"),
+            Err(MemcachedStoreError::Encoding(_))
+        ));
+    }
+
+    #[test]
+    fn test_memcached_expiration() {
+        let store = offline();
+        assert_eq!(store.expiration(), 0);
+        let store = store.with_ttl(Duration::from_millis(1500));
+        assert_eq!(store.expiration(), 2);
+        // 超过 30 天的过期时间要写成时间戳
+        let store = store.with_ttl(Duration::from_secs(MAX_RELATIVE_EXPIRATION + 1));
+        assert!(u64::from(store.expiration()) > MAX_RELATIVE_EXPIRATION * 12);
+    }
+
+    #[test]
+    #[ignore = "needs a memcached server, at MEMCACHED_URL or 127.0.0.1:11211"]
+    fn test_memcached_compare_and_swap() {
+        let store = live();
+        let key = "k".to_owned();
+        store.remove(&key).unwrap();
+        assert_eq!(store.load(&key).unwrap(), None);
+
+        // 新键用 add 创建，已存在时失败
+        assert!(store.compare_and_swap(&key, None, 1).unwrap());
+        assert!(!store.compare_and_swap(&key, None, 2).unwrap());
+        let loaded = store.load(&key).unwrap().unwrap();
+        assert_eq!(loaded.value, 1);
+
+        // 过期的 CAS 令牌写入失败
+        assert!(
+            store
+                .compare_and_swap(&key, Some(loaded.version), 3)
+                .unwrap()
+        );
+        assert!(
+            !store
+                .compare_and_swap(&key, Some(loaded.version), 4)
+                .unwrap()
+        );
+        assert_eq!(
+            store.load(&key).unwrap().map(|loaded| loaded.value),
+            Some(3)
+        );
+
+        // 键被删除后按版本写入失败
+        assert!(store.remove(&key).unwrap());
+        assert!(
+            !store
+                .compare_and_swap(&key, Some(loaded.version), 5)
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::{fmt, hash::Hash, sync::Arc};
+
+use prometheus::{
+    IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    core::{Collector, Desc},
+    proto::MetricFamily,
+};
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, error::AcquireError, handle::RateLimiterHandle,
+    hooks::Hooks, quota::Quota,
+};
+
+type KeyLabel<K> = Arc<dyn Fn(&K) -> String + Send + Sync>;
+
+/// [`Hooks`] that export a limiter's decisions as Prometheus metrics:
+///
+/// - `ratelimit_allowed_total{key}`: granted requests.
+/// - `ratelimit_denied_total{key}`: denied or failed requests.
+/// - `ratelimit_evictions_total`: keys dropped to bound memory.
+/// - `ratelimit_tracked_keys`: the keys currently tracked, once
+///   [`track_keys`](Self::track_keys) is called.
+///
+/// The `key` label is the key itself, or empty for the base state. Every
+/// distinct label is a time series, so map keys of unbounded cardinality,
+/// such as client addresses, to a few labels with
+/// [`with_key_label`](Self::with_key_label). To export several limiters to
+/// one registry, give each its own prefix with [`Registry::new_custom`].
+///
+/// ```
+/// use prometheus::Registry;
+/// use ratelimit::{MonotonicClock, PrometheusMetrics, Quota, RateLimiter};
+///
+/// let registry = Registry::new();
+/// let metrics = PrometheusMetrics::register(&registry)?
+///     .with_key_label(|key: &String| key.split(':').next().unwrap_or_default().to_owned());
+/// let limiter = RateLimiter::new(Quota::per_second(100), MonotonicClock)
+///     .with_default_key_quota(Quota::per_second(10))
+///     .with_hooks(metrics.clone())
+///     .into_handle();
+/// metrics.track_keys(limiter.clone())?;
+///
+/// limiter.acquire_by_key("tenant-a:alice")?;
+/// assert!(!registry.gather().is_empty());
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Clone)]
+pub struct PrometheusMetrics<K = String> {
+    registry: Registry,
+    allowed: IntCounterVec,
+    denied: IntCounterVec,
+    evictions: IntCounter,
+    key_label: KeyLabel<K>,
+}
+
+impl<K: fmt::Display> PrometheusMetrics<K> {
+    /// Creates the metrics and registers them with `registry`, labelling
+    /// keys as they display.
+    pub fn register(registry: &Registry) -> prometheus::Result<Self> {
+        let allowed = IntCounterVec::new(
+            Opts::new("ratelimit_allowed_total", "Requests granted a permit."),
+            &["key"],
+        )?;
+        let denied = IntCounterVec::new(
+            Opts::new("ratelimit_denied_total", "Requests denied a permit."),
+            &["key"],
+        )?;
+        let evictions =
+            IntCounter::new("ratelimit_evictions_total", "Keys dropped to bound memory.")?;
+        registry.register(Box::new(allowed.clone()))?;
+        registry.register(Box::new(denied.clone()))?;
+        registry.register(Box::new(evictions.clone()))?;
+        Ok(Self {
+            registry: registry.clone(),
+            allowed,
+            denied,
+            evictions,
+            key_label: Arc::new(|key: &K| key.to_string()),
+        })
+    }
+}
+
+impl<K> PrometheusMetrics<K> {
+    /// Labels keys with `label` rather than as they display.
+    pub fn with_key_label<F>(mut self, label: F) -> Self
+    where
+        F: Fn(&K) -> String + Send + Sync + 'static,
+    {
+        self.key_label = Arc::new(label);
+        self
+    }
+
+    /// Exports how many keys `limiter` tracks as `ratelimit_tracked_keys`,
+    /// read whenever the registry is gathered.
+    pub fn track_keys<C, S>(&self, limiter: RateLimiterHandle<C, S, K>) -> prometheus::Result<()>
+    where
+        C: Clock + Send + Sync + 'static,
+        C::Instant: Send + Sync,
+        S: Algorithm<C> + Send + Sync + 'static,
+        K: Hash + Eq + Clone + Send + Sync + 'static,
+    {
+        let gauge = IntGauge::new("ratelimit_tracked_keys", "Keys currently tracked.")?;
+        self.registry
+            .register(Box::new(TrackedKeys { gauge, limiter }))
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    fn label(&self, key: Option<&K>) -> String {
+        key.map(|key| (self.key_label)(key)).unwrap_or_default()
+    }
+}
+
+impl<K> Hooks<K> for PrometheusMetrics<K> {
+    fn on_allowed(&self, key: Option<&K>, _quota: Option<Quota>, _permits: u64) {
+        self.allowed.with_label_values(&[self.label(key)]).inc();
+    }
+
+    fn on_denied(&self, key: Option<&K>, _quota: Option<Quota>, _err: &AcquireError) {
+        self.denied.with_label_values(&[self.label(key)]).inc();
+    }
+
+    fn on_evicted(&self, _key: &K) {
+        self.evictions.inc();
+    }
+}
+
+impl<K> fmt::Debug for PrometheusMetrics<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrometheusMetrics")
+            .field("evictions", &self.evictions.get())
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reads a limiter's key count into a gauge on every gather.
+struct TrackedKeys<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> {
+    gauge: IntGauge,
+    limiter: RateLimiterHandle<C, S, K>,
+}
+
+impl<C, S, K> Collector for TrackedKeys<C, S, K>
+where
+    C: Clock + Send + Sync,
+    C::Instant: Send + Sync,
+    S: Algorithm<C> + Send + Sync,
+    K: Hash + Eq + Clone + Send + Sync,
+{
+    fn desc(&self) -> Vec<&Desc> {
+        self.gauge.desc()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        self.gauge
+            .set(i64::try_from(self.limiter.len()).unwrap_or(i64::MAX));
+        self.gauge.collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use prometheus::{Encoder, TextEncoder};
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, limiter::RateLimiter};
+
+    fn scrape(registry: &Registry) -> String {
+        let mut text = Vec::new();
+        TextEncoder::new()
+            .encode(&registry.gather(), &mut text)
+            .unwrap();
+        String::from_utf8(text).unwrap()
+    }
+
+    #[test]
+    fn test_prometheus_metrics() {
+        let clock = FakeRelativeClock::default();
+        let registry = Registry::new();
+        let metrics = PrometheusMetrics::register(&registry).unwrap();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone())
+            .with_default_key_quota(Quota::per_second(1))
+            .with_auto_prune(true)
+            .with_hooks(metrics.clone())
+            .into_handle();
+        metrics.track_keys(limiter.clone()).unwrap();
+
+        limiter.acquire().unwrap();
+        limiter.acquire_by_key("a").unwrap();
+        assert!(limiter.acquire_by_key("a").is_err());
+        limiter.acquire_by_key("b").unwrap();
+        let text = scrape(&registry);
+        assert!(text.contains("ratelimit_allowed_total{key=\"\"} 1"));
+        assert!(text.contains("ratelimit_allowed_total{key=\"a\"} 1"));
+        assert!(text.contains("ratelimit_denied_total{key=\"a\"} 1"));
+        assert!(text.contains("ratelimit_tracked_keys 2"));
+
+        // 空闲的 key 被清理后计入驱逐数
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.maintain(), 2);
+        let text = scrape(&registry);
+        assert!(text.contains("ratelimit_evictions_total 2"));
+        assert!(text.contains("ratelimit_tracked_keys 0"));
+
+        // 同一个 registry 不能重复注册
+        assert!(PrometheusMetrics::<String>::register(&registry).is_err());
+    }
+}
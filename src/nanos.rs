@@ -1,15 +1,27 @@
 use std::{
-    fmt::Debug,
-    ops::{Add, Div, Mul},
+    fmt::{self, Debug, Display},
+    num::TryFromIntError,
+    ops::{Add, AddAssign, Div, Mul, Rem, Sub, SubAssign},
+    str::FromStr,
     time::Duration,
 };
 
 use crate::clock;
 
-#[derive(PartialEq, Eq, Default, Clone, Copy, PartialOrd, Ord)]
+/// A duration in nanoseconds.
+///
+/// With the `serde` feature it serializes as an integer number of
+/// nanoseconds, and deserializes from either that or a string accepted by
+/// [`Nanos::parse`], such as `"1h30m"`.
+#[derive(PartialEq, Eq, Default, Clone, Copy, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Nanos(u64);
 
 impl Nanos {
+    pub const ZERO: Self = Self(0);
+    /// The longest representable duration, about 584 years.
+    pub const MAX: Self = Self(u64::MAX);
+
     pub const fn as_u64(self) -> u64 {
         self.0
     }
@@ -19,6 +31,40 @@ impl Nanos {
     pub const fn new(u: u64) -> Self {
         Self(u)
     }
+
+    /// # Panics
+    ///
+    /// Panics, or fails to compile in a const context, if `secs` is longer
+    /// than about 584 years.
+    pub const fn from_secs(secs: u64) -> Self {
+        Self(secs * 1_000_000_000)
+    }
+
+    /// # Panics
+    ///
+    /// Panics, or fails to compile in a const context, if `millis` is
+    /// longer than about 584 years.
+    pub const fn from_millis(millis: u64) -> Self {
+        Self(millis * 1_000_000)
+    }
+
+    /// # Panics
+    ///
+    /// Panics, or fails to compile in a const context, if `micros` is
+    /// longer than about 584 years.
+    pub const fn from_micros(micros: u64) -> Self {
+        Self(micros * 1_000)
+    }
+
+    /// The number of whole seconds.
+    pub const fn as_secs(self) -> u64 {
+        self.0 / 1_000_000_000
+    }
+
+    /// The number of whole milliseconds.
+    pub const fn as_millis(self) -> u64 {
+        self.0 / 1_000_000
+    }
 }
 
 impl From<Duration> for Nanos {
@@ -47,6 +93,34 @@ impl Add<Self> for Nanos {
     }
 }
 
+impl AddAssign for Nanos {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub<Self> for Nanos {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Nanos {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Rem<Self> for Nanos {
+    type Output = Self;
+
+    fn rem(self, rhs: Self) -> Self::Output {
+        Self(self.0 % rhs.0)
+    }
+}
+
 impl Mul<u64> for Nanos {
     type Output = Self;
 
@@ -69,6 +143,52 @@ impl From<u64> for Nanos {
     }
 }
 
+/// Fails if `u` exceeds `u64::MAX` nanoseconds, about 584 years.
+impl TryFrom<u128> for Nanos {
+    type Error = TryFromIntError;
+
+    fn try_from(u: u128) -> Result<Self, Self::Error> {
+        u64::try_from(u).map(Self)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl From<Nanos> for chrono::TimeDelta {
+    fn from(n: Nanos) -> Self {
+        let (secs, nanos) = (n.0 / 1_000_000_000, n.0 % 1_000_000_000);
+        Self::new(secs as i64, nanos as u32).expect("584 years are within chrono's range")
+    }
+}
+
+/// Fails if `delta` is negative or longer than about 584 years.
+#[cfg(feature = "chrono")]
+impl TryFrom<chrono::TimeDelta> for Nanos {
+    type Error = TryFromIntError;
+
+    fn try_from(delta: chrono::TimeDelta) -> Result<Self, Self::Error> {
+        let nanos =
+            i128::from(delta.num_seconds()) * 1_000_000_000 + i128::from(delta.subsec_nanos());
+        u64::try_from(nanos).map(Self)
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<Nanos> for time::Duration {
+    fn from(n: Nanos) -> Self {
+        Self::nanoseconds_i128(i128::from(n.0))
+    }
+}
+
+/// Fails if `duration` is negative or longer than about 584 years.
+#[cfg(feature = "time")]
+impl TryFrom<time::Duration> for Nanos {
+    type Error = TryFromIntError;
+
+    fn try_from(duration: time::Duration) -> Result<Self, Self::Error> {
+        u64::try_from(duration.whole_nanoseconds()).map(Self)
+    }
+}
+
 impl From<Nanos> for u64 {
     fn from(n: Nanos) -> Self {
         n.0
@@ -86,6 +206,78 @@ impl Nanos {
     pub const fn saturating_sub(self, rhs: Self) -> Self {
         Self(self.0.saturating_sub(rhs.0))
     }
+
+    #[inline]
+    pub const fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    #[inline]
+    pub const fn saturating_mul(self, rhs: u64) -> Self {
+        Self(self.0.saturating_mul(rhs))
+    }
+
+    /// `self * rhs` widened to 128 bits, which can't overflow. Window math
+    /// on long durations, such as an emission interval of days times a
+    /// large burst, can stay wide and only narrow its result.
+    #[inline]
+    pub const fn widening_mul(self, rhs: u64) -> u128 {
+        self.0 as u128 * rhs as u128
+    }
+
+    /// Narrows a wide intermediate back, saturating at [`Nanos::MAX`].
+    #[inline]
+    pub const fn saturating_from_wide(wide: u128) -> Self {
+        if wide > u64::MAX as u128 {
+            Self::MAX
+        } else {
+            Self(wide as u64)
+        }
+    }
+
+    /// The largest multiple of `step` not above `self`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is zero.
+    #[inline]
+    pub const fn round_down_to(self, step: Self) -> Self {
+        assert!(step.0 > 0, "rounding step must be non-zero");
+        Self(self.0 - self.0 % step.0)
+    }
+
+    /// The smallest multiple of `step` not below `self`, or `None` if that
+    /// exceeds about 584 years. Multiples of `step` are returned unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `step` is zero.
+    #[inline]
+    pub const fn round_up_to(self, step: Self) -> Option<Self> {
+        assert!(step.0 > 0, "rounding step must be non-zero");
+        match self.0 % step.0 {
+            0 => Some(self),
+            rem => Self(self.0 - rem).checked_add(step),
+        }
+    }
+
+    /// `self + rhs`, or `None` if the sum exceeds about 584 years.
+    #[inline]
+    pub const fn checked_add(self, rhs: Self) -> Option<Self> {
+        match self.0.checked_add(rhs.0) {
+            Some(sum) => Some(Self(sum)),
+            None => None,
+        }
+    }
+
+    /// `self * rhs`, or `None` if the product exceeds about 584 years.
+    #[inline]
+    pub const fn checked_mul(self, rhs: u64) -> Option<Self> {
+        match self.0.checked_mul(rhs) {
+            Some(product) => Some(Self(product)),
+            None => None,
+        }
+    }
 }
 
 impl Add<Duration> for Nanos {
@@ -107,4 +299,380 @@ impl clock::Reference for Nanos {
     fn saturating_sub(&self, duration: Nanos) -> Self {
         (*self as Self).saturating_sub(duration)
     }
+
+    #[inline]
+    fn checked_add(&self, duration: Nanos) -> Option<Self> {
+        Nanos::checked_add(*self, duration)
+    }
+
+    #[inline]
+    fn checked_duration_since(&self, earlier: Self) -> Option<Nanos> {
+        self.0.checked_sub(earlier.0).map(Self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Empty,
+    InvalidNumber,
+    MissingUnit,
+    UnknownUnit(String),
+    Overflow,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty duration"),
+            Self::InvalidNumber => write!(f, "invalid duration magnitude"),
+            Self::MissingUnit => write!(f, "missing duration unit"),
+            Self::UnknownUnit(unit) => write!(f, "unknown duration unit `{unit}`"),
+            Self::Overflow => write!(f, "duration is longer than 584 years"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Nanos {
+    /// Parses a duration such as `"500ms"`, `"1.5s"` or `"1h30m"`.
+    ///
+    /// Each component is an unsigned integer or decimal, immediately
+    /// followed by one of `ns`, `us`/`µs`, `ms`, `s`, `m`, `h` or `d`, and
+    /// the components are summed. Fractions finer than a nanosecond are
+    /// truncated.
+    pub fn parse(s: &str) -> Result<Self, ParseError> {
+        let mut rest = s.trim();
+        if rest.is_empty() {
+            return Err(ParseError::Empty);
+        }
+
+        let mut nanos: u128 = 0;
+        while !rest.is_empty() {
+            let split = rest
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .unwrap_or(rest.len());
+            let (magnitude, tail) = rest.split_at(split);
+            let split = tail
+                .find(|c: char| c.is_ascii_digit() || c == '.')
+                .unwrap_or(tail.len());
+            let (unit, tail) = tail.split_at(split);
+            let component = parse_component(magnitude, unit)?;
+            nanos = nanos.checked_add(component).ok_or(ParseError::Overflow)?;
+            rest = tail;
+        }
+
+        u64::try_from(nanos)
+            .map(Self)
+            .map_err(|_| ParseError::Overflow)
+    }
+}
+
+/// Parses one `magnitude` followed by `unit` into nanoseconds.
+fn parse_component(magnitude: &str, unit: &str) -> Result<u128, ParseError> {
+    if magnitude.is_empty() {
+        return Err(ParseError::InvalidNumber);
+    }
+    let unit: u128 = match unit {
+        "" => return Err(ParseError::MissingUnit),
+        "ns" => 1,
+        "us" | "µs" => 1_000,
+        "ms" => 1_000_000,
+        "s" => 1_000_000_000,
+        "m" => 60 * 1_000_000_000,
+        "h" => 60 * 60 * 1_000_000_000,
+        "d" => 24 * 60 * 60 * 1_000_000_000,
+        other => return Err(ParseError::UnknownUnit(other.to_string())),
+    };
+
+    let (whole, fraction) = magnitude.split_once('.').unwrap_or((magnitude, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(ParseError::InvalidNumber);
+    }
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseError::InvalidNumber);
+    }
+
+    let whole: u128 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| ParseError::Overflow)?
+    };
+    let mut nanos = whole.checked_mul(unit).ok_or(ParseError::Overflow)?;
+
+    // 18 位小数已经小于任何单位下的 1 纳秒
+    let fraction = &fraction[..fraction.len().min(18)];
+    if !fraction.is_empty() {
+        let scale = 10u128.pow(fraction.len() as u32);
+        let digits: u128 = fraction.parse().map_err(|_| ParseError::InvalidNumber)?;
+        nanos = nanos
+            .checked_add(digits * unit / scale)
+            .ok_or(ParseError::Overflow)?;
+    }
+    Ok(nanos)
+}
+
+/// Formats the duration as its non-zero components from days down to
+/// nanoseconds, e.g. `1h30m` or `1s500ms`, which [`Nanos::parse`] reads
+/// back. Zero is formatted as `0s`.
+impl Display for Nanos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [(&str, u64); 7] = [
+            ("d", 24 * 60 * 60 * 1_000_000_000),
+            ("h", 60 * 60 * 1_000_000_000),
+            ("m", 60 * 1_000_000_000),
+            ("s", 1_000_000_000),
+            ("ms", 1_000_000),
+            ("us", 1_000),
+            ("ns", 1),
+        ];
+        if self.0 == 0 {
+            return write!(f, "0s");
+        }
+        let mut rest = self.0;
+        for (unit, nanos) in UNITS {
+            if rest >= nanos {
+                write!(f, "{}{unit}", rest / nanos)?;
+                rest %= nanos;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Nanos {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct NanosVisitor;
+
+        impl serde::de::Visitor<'_> for NanosVisitor {
+            type Value = Nanos;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "nanoseconds or a duration such as \"500ms\"")
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Nanos, E> {
+                Ok(Nanos(v))
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Nanos, E> {
+                u64::try_from(v)
+                    .map(Nanos)
+                    .map_err(|_| E::invalid_value(serde::de::Unexpected::Signed(v), &self))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Nanos, E> {
+                Nanos::parse(v).map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(NanosVisitor)
+    }
+}
+
+impl FromStr for Nanos {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_const_constructors() {
+        const WINDOW: Nanos = Nanos::from_secs(60);
+        assert_eq!(WINDOW, Nanos::from(Duration::from_secs(60)));
+        assert_eq!(Nanos::from_millis(1_500), Nanos::new(1_500_000_000));
+        assert_eq!(Nanos::from_micros(7), Nanos::new(7_000));
+
+        // 访问器向下取整
+        assert_eq!(Nanos::from_millis(1_999).as_secs(), 1);
+        assert_eq!(Nanos::from_micros(2_500).as_millis(), 2);
+    }
+
+    #[test]
+    fn test_rounding() {
+        let step = Nanos::from_secs(60);
+        assert_eq!(
+            Nanos::from_secs(119).round_down_to(step),
+            Nanos::from_secs(60)
+        );
+        assert_eq!(
+            Nanos::from_secs(119).round_up_to(step),
+            Some(Nanos::from_secs(120))
+        );
+        // 恰好落在边界上时保持不变
+        assert_eq!(
+            Nanos::from_secs(120).round_down_to(step),
+            Nanos::from_secs(120)
+        );
+        assert_eq!(
+            Nanos::from_secs(120).round_up_to(step),
+            Some(Nanos::from_secs(120))
+        );
+        assert_eq!(Nanos::ZERO.round_up_to(step), Some(Nanos::ZERO));
+        assert_eq!(Nanos::MAX.round_up_to(step), None);
+        assert_eq!(Nanos::MAX.round_down_to(Nanos::MAX), Nanos::MAX);
+    }
+
+    #[test]
+    #[should_panic(expected = "rounding step must be non-zero")]
+    fn test_rounding_zero_step() {
+        let _ = Nanos::from_secs(1).round_down_to(Nanos::ZERO);
+    }
+
+    #[test]
+    fn test_operators() {
+        let mut nanos = Nanos::new(7);
+        nanos += Nanos::new(5);
+        assert_eq!(nanos, Nanos::new(12));
+        nanos -= Nanos::new(2);
+        assert_eq!(nanos - Nanos::new(4), Nanos::new(6));
+        assert_eq!(nanos % Nanos::new(3), Nanos::new(1));
+        assert_eq!(Nanos::ZERO, Nanos::default());
+        assert!(Nanos::MAX > nanos);
+
+        // 可以作为 HashMap 的键
+        let counts = std::collections::HashMap::from([(nanos, 1)]);
+        assert_eq!(counts[&Nanos::new(10)], 1);
+    }
+
+    #[test]
+    fn test_checked_and_saturating() {
+        let max = Nanos::MAX;
+        assert_eq!(
+            Nanos::new(1).checked_add(Nanos::new(2)),
+            Some(Nanos::new(3))
+        );
+        assert_eq!(max.checked_add(Nanos::new(1)), None);
+        assert_eq!(Nanos::new(3).checked_mul(4), Some(Nanos::new(12)));
+        assert_eq!(max.checked_mul(2), None);
+
+        // 饱和运算停在上限而不是溢出
+        assert_eq!(max.saturating_add(Nanos::new(1)), max);
+        assert_eq!(max.saturating_mul(2), max);
+
+        assert_eq!(max.widening_mul(2), u128::from(u64::MAX) * 2);
+        assert_eq!(Nanos::saturating_from_wide(max.widening_mul(2)), max);
+        assert_eq!(Nanos::saturating_from_wide(7), Nanos::new(7));
+
+        assert_eq!(Nanos::try_from(5u128), Ok(Nanos::new(5)));
+        assert!(Nanos::try_from(u128::from(u64::MAX) + 1).is_err());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn test_chrono_conversions() {
+        let delta = chrono::TimeDelta::from(Nanos::new(1_500_000_000));
+        assert_eq!(delta, chrono::TimeDelta::milliseconds(1_500));
+        assert_eq!(Nanos::try_from(delta), Ok(Nanos::new(1_500_000_000)));
+        assert_eq!(
+            Nanos::try_from(chrono::TimeDelta::from(Nanos::MAX)),
+            Ok(Nanos::MAX)
+        );
+        // 负的时长无法表示
+        assert!(Nanos::try_from(chrono::TimeDelta::milliseconds(-1)).is_err());
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn test_time_conversions() {
+        let duration = time::Duration::from(Nanos::new(1_500_000_000));
+        assert_eq!(duration, time::Duration::milliseconds(1_500));
+        assert_eq!(Nanos::try_from(duration), Ok(Nanos::new(1_500_000_000)));
+        assert!(Nanos::try_from(time::Duration::milliseconds(-1)).is_err());
+        assert!(Nanos::try_from(time::Duration::days(250 * 365 * 3)).is_err());
+    }
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(Nanos::parse("7ns"), Ok(Nanos::new(7)));
+        assert_eq!(Nanos::parse("3us"), Ok(Nanos::new(3_000)));
+        assert_eq!(Nanos::parse("3µs"), Ok(Nanos::new(3_000)));
+        assert_eq!(Nanos::parse("500ms"), Ok(Nanos::new(500_000_000)));
+        assert_eq!(Nanos::parse("1s"), Ok(Nanos::new(1_000_000_000)));
+        assert_eq!(Nanos::parse("2m"), Ok(Nanos::new(120_000_000_000)));
+        assert_eq!(Nanos::parse("1h"), Ok(Nanos::new(3_600_000_000_000)));
+        assert_eq!(Nanos::parse("1d"), Ok(Nanos::new(86_400_000_000_000)));
+        assert_eq!(" 10s ".parse::<Nanos>(), Ok(Nanos::new(10_000_000_000)));
+    }
+
+    #[test]
+    fn test_parse_decimal() {
+        assert_eq!(Nanos::parse("1.5s"), Ok(Nanos::new(1_500_000_000)));
+        assert_eq!(Nanos::parse("0.25ms"), Ok(Nanos::new(250_000)));
+        assert_eq!(Nanos::parse(".5h"), Ok(Nanos::new(1_800_000_000_000)));
+        assert_eq!(Nanos::parse("2.s"), Ok(Nanos::new(2_000_000_000)));
+        // 小于 1 纳秒的部分被截断
+        assert_eq!(Nanos::parse("1.9ns"), Ok(Nanos::new(1)));
+    }
+
+    #[test]
+    fn test_parse_overflow() {
+        assert_eq!(Nanos::parse("18446744073709551615ns"), Ok(Nanos::MAX));
+        assert_eq!(
+            Nanos::parse("18446744073709551616ns"),
+            Err(ParseError::Overflow)
+        );
+        assert_eq!(Nanos::parse("214000d"), Err(ParseError::Overflow));
+        assert_eq!(
+            Nanos::parse("99999999999999999999999999999999999999999s"),
+            Err(ParseError::Overflow)
+        );
+        // 整数部分刚好不溢出时，小数部分也不能溢出
+        assert_eq!(
+            Nanos::parse("340282366920938463463374607431.999999999s"),
+            Err(ParseError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_parse_compound() {
+        assert_eq!(Nanos::parse("1h30m"), Ok(Nanos::new(5_400_000_000_000)));
+        assert_eq!(Nanos::parse("1s500ms"), Ok(Nanos::new(1_500_000_000)));
+        assert_eq!(Nanos::parse("1m1.5s"), Ok(Nanos::new(61_500_000_000)));
+        assert_eq!(Nanos::parse("1h30"), Err(ParseError::MissingUnit));
+        assert_eq!(
+            Nanos::parse("18446744073709551615ns1ns"),
+            Err(ParseError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        assert_eq!(Nanos::ZERO.to_string(), "0s");
+        assert_eq!(Nanos::new(150_000_000).to_string(), "150ms");
+        assert_eq!(Nanos::new(2_000_000_000).to_string(), "2s");
+        assert_eq!(Nanos::new(5_400_000_000_000).to_string(), "1h30m");
+        assert_eq!(Nanos::new(1_500_000_007).to_string(), "1s500ms7ns");
+        // 格式化的结果可以原样解析回来
+        for nanos in [0, 1, 999, 86_400_000_000_001, u64::MAX] {
+            let nanos = Nanos::new(nanos);
+            assert_eq!(nanos.to_string().parse(), Ok(nanos));
+        }
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        assert_eq!(Nanos::parse(""), Err(ParseError::Empty));
+        assert_eq!(Nanos::parse("   "), Err(ParseError::Empty));
+        assert_eq!(Nanos::parse("10"), Err(ParseError::MissingUnit));
+        assert_eq!(Nanos::parse("s"), Err(ParseError::InvalidNumber));
+        assert_eq!(Nanos::parse(".s"), Err(ParseError::InvalidNumber));
+        assert_eq!(Nanos::parse("1.2.3s"), Err(ParseError::InvalidNumber));
+        assert_eq!(Nanos::parse("-1s"), Err(ParseError::InvalidNumber));
+        assert_eq!(
+            Nanos::parse("10 s"),
+            Err(ParseError::UnknownUnit(" s".to_string()))
+        );
+        assert_eq!(
+            Nanos::parse("5w"),
+            Err(ParseError::UnknownUnit("w".to_string()))
+        );
+    }
 }
@@ -0,0 +1,163 @@
+use std::{
+    fmt::{self, Display},
+    time::{Duration, SystemTime},
+};
+
+use crate::{
+    clock::{ReasonablyRealtime, Reference},
+    nanos::Nanos,
+    quota::Quota,
+};
+
+/// A denial carrying the earliest instant at which the request may succeed,
+/// along with the quota that denied it and the permits still remaining.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NotUntil<P: Reference> {
+    earliest: P,
+    quota: Quota,
+    remaining: u64,
+    overflowed: bool,
+}
+
+impl<P: Reference> NotUntil<P> {
+    pub fn new(earliest: P, quota: Quota, remaining: u64) -> Self {
+        Self {
+            earliest,
+            quota,
+            remaining,
+            overflowed: false,
+        }
+    }
+
+    /// A denial until `offset` after `base`, or an
+    /// [overflowed](Self::overflowed) one if the instant type can't
+    /// represent that.
+    pub(crate) fn after(base: P, offset: Nanos, quota: Quota, remaining: u64) -> Self {
+        match base.checked_add(offset) {
+            Some(earliest) => Self::new(earliest, quota, remaining),
+            None => Self {
+                earliest: base,
+                quota,
+                remaining,
+                overflowed: true,
+            },
+        }
+    }
+
+    /// Like [`after`](Self::after), with a wide `offset` that overflows if it
+    /// doesn't fit in [`Nanos`].
+    pub(crate) fn after_wide(base: P, offset: u128, quota: Quota, remaining: u64) -> Self {
+        match Nanos::try_from(offset) {
+            Ok(offset) => Self::after(base, offset, quota, remaining),
+            Err(_) => Self {
+                earliest: base,
+                quota,
+                remaining,
+                overflowed: true,
+            },
+        }
+    }
+
+    /// Whether the request could only succeed beyond the range of the
+    /// clock's instants, e.g. under a quota with a window of centuries. The
+    /// [`earliest_possible`](Self::earliest_possible) instant is then only
+    /// a lower bound, and the wait is reported as [`Duration::MAX`].
+    pub fn overflowed(&self) -> bool {
+        self.overflowed
+    }
+
+    pub fn earliest_possible(&self) -> P {
+        self.earliest
+    }
+
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// The permits that could still be granted when the request was denied;
+    /// non-zero only if more were requested at once than remained.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// How long to wait from `from` until a permit may be granted; zero if
+    /// that instant has already passed.
+    pub fn wait_time_from(&self, from: P) -> Duration {
+        if self.overflowed {
+            return Duration::MAX;
+        }
+        self.earliest.duration_since(from).into()
+    }
+
+    /// The wall-clock time at which a permit may be granted, e.g. for an
+    /// HTTP `Retry-After` date, as mapped by `clock`.
+    pub fn earliest_system_time<C>(&self, clock: &C) -> SystemTime
+    where
+        C: ReasonablyRealtime<Instant = P>,
+    {
+        clock.to_system_time(self.earliest)
+    }
+}
+
+impl<P: Reference> Display for NotUntil<P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.overflowed {
+            return write!(f, "rate limited beyond the clock's range");
+        }
+        write!(f, "rate limited, retry at {:?}", self.earliest)
+    }
+}
+
+impl<P: Reference> std::error::Error for NotUntil<P> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nanos::Nanos;
+
+    #[test]
+    fn test_wait_time_from() {
+        let not_until = NotUntil::new(Nanos::new(1_500), Quota::per_second(2), 1);
+        assert_eq!(not_until.earliest_possible(), Nanos::new(1_500));
+        assert_eq!(not_until.quota(), Quota::per_second(2));
+        assert_eq!(not_until.remaining(), 1);
+        assert_eq!(
+            not_until.wait_time_from(Nanos::new(1_000)),
+            Duration::from_nanos(500)
+        );
+        assert_eq!(not_until.wait_time_from(Nanos::new(2_000)), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_earliest_system_time() {
+        use std::time::UNIX_EPOCH;
+
+        use crate::clock::FakeAbsoluteClock;
+
+        let clock = FakeAbsoluteClock::new(UNIX_EPOCH + Duration::from_secs(100));
+        let not_until = NotUntil::new(Nanos::new(101_500_000_000), Quota::per_second(1), 0);
+        assert_eq!(
+            not_until.earliest_system_time(&clock),
+            UNIX_EPOCH + Duration::from_millis(101_500)
+        );
+    }
+
+    #[test]
+    fn test_after_overflow() {
+        let quota = Quota::per_second(1);
+        let not_until = NotUntil::after(Nanos::new(1_000), Nanos::new(500), quota, 0);
+        assert_eq!(not_until, NotUntil::new(Nanos::new(1_500), quota, 0));
+        assert!(!not_until.overflowed());
+
+        // 超出时钟表示范围时不回绕，而是标记为溢出
+        let not_until = NotUntil::after(Nanos::new(1_000), Nanos::MAX, quota, 0);
+        assert!(not_until.overflowed());
+        assert_eq!(not_until.wait_time_from(Nanos::ZERO), Duration::MAX);
+    }
+
+    #[test]
+    fn test_display() {
+        let not_until = NotUntil::new(Nanos::new(1_000_000_000), Quota::per_second(1), 0);
+        assert_eq!(not_until.to_string(), "rate limited, retry at Nanos(1s)");
+    }
+}
@@ -0,0 +1,174 @@
+use std::{fmt, time::Duration};
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, error::AcquireError, handle::RateLimiterHandle,
+    limiter::RateLimiter, state::State,
+};
+
+/// Throttles outbound requests per remote host, so that a client stays
+/// within the limits third-party APIs enforce.
+///
+/// Requests to each host are limited under the host name as the key, or on
+/// the base state if the limiter doesn't know the host; give known APIs
+/// their quota with [`insert_key`](RateLimiter::insert_key) and every other
+/// host one with
+/// [`with_default_key_quota`](RateLimiter::with_default_key_quota). Requests
+/// wait for a permit by default rather than fail.
+///
+/// Use [`throttle`](Self::throttle) before sending with any client, or, with
+/// the `reqwest` feature, install the throttle as `reqwest-middleware`
+/// middleware.
+///
+/// ```
+/// use ratelimit::{HostThrottle, MonotonicClock, Quota, RateLimiter, State};
+///
+/// let limiter = RateLimiter::keyed(State::new(Quota::per_second(100), MonotonicClock))
+///     .with_default_key_quota(Quota::per_second(10))
+///     .into_handle();
+/// limiter.insert_key("api.github.com", Quota::per_hour(5000));
+/// let throttle = HostThrottle::new(limiter);
+/// # async fn send(throttle: &HostThrottle<MonotonicClock>) -> Result<(), ratelimit::AcquireError> {
+/// throttle.throttle(Some("api.github.com")).await?;
+/// // client.get("https://api.github.com/...").send().await
+/// # Ok(())
+/// # }
+/// # drop(throttle);
+/// ```
+pub struct HostThrottle<C: Clock, S: Algorithm<C> = State<C>> {
+    limiter: RateLimiterHandle<C, S, String>,
+    /// How long a request may wait for a permit, if not forever.
+    max_wait: Option<Duration>,
+}
+
+impl<C: Clock, S: Algorithm<C>> HostThrottle<C, S> {
+    /// Makes requests wait for a permit from `limiter`, only failing if none
+    /// can ever be granted.
+    pub fn new(limiter: RateLimiterHandle<C, S, String>) -> Self {
+        Self {
+            limiter,
+            max_wait: None,
+        }
+    }
+
+    /// Fails requests whose permit could not be granted within `max_wait`,
+    /// so that [`Duration::ZERO`] fails them as soon as they are denied.
+    pub fn delay_at_most(mut self, max_wait: Duration) -> Self {
+        self.max_wait = Some(max_wait);
+        self
+    }
+
+    /// The limiter requests are throttled with.
+    pub fn limiter(&self) -> &RateLimiterHandle<C, S, String> {
+        &self.limiter
+    }
+
+    /// Waits until a request to `host` may be sent, or fails with the
+    /// denial. Requests without a host are throttled on the base state.
+    ///
+    /// Cancel safe, like [`RateLimiter::until_ready`].
+    pub async fn throttle(&self, host: Option<&str>) -> Result<(), AcquireError> {
+        if let Some(host) = host {
+            let waited = match self.max_wait {
+                Some(max_wait) => {
+                    self.limiter
+                        .until_key_ready_or_timeout(host, max_wait)
+                        .await
+                }
+                None => self.limiter.until_key_ready(host).await,
+            };
+            if !matches!(waited, Err(AcquireError::UnknownKey)) {
+                return waited;
+            }
+        }
+        match self.max_wait {
+            Some(max_wait) => self.limiter.until_ready_or_timeout(max_wait).await,
+            None => self.limiter.until_ready().await,
+        }
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>> Clone for HostThrottle<C, S> {
+    fn clone(&self) -> Self {
+        Self {
+            limiter: self.limiter.clone(),
+            max_wait: self.max_wait,
+        }
+    }
+}
+
+impl<C, S> fmt::Debug for HostThrottle<C, S>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    RateLimiter<C, S, String>: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostThrottle")
+            .field("limiter", &self.limiter)
+            .field("max_wait", &self.max_wait)
+            .finish()
+    }
+}
+
+#[cfg(feature = "reqwest")]
+#[async_trait::async_trait]
+impl<C, S> reqwest_middleware::Middleware for HostThrottle<C, S>
+where
+    C: Clock + Send + Sync + 'static,
+    C::Instant: Send + Sync,
+    S: Algorithm<C> + Send + Sync + 'static,
+{
+    async fn handle(
+        &self,
+        request: reqwest::Request,
+        extensions: &mut http::Extensions,
+        next: reqwest_middleware::Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        self.throttle(request.url().host_str())
+            .await
+            .map_err(|err| reqwest_middleware::Error::Middleware(err.into()))?;
+        next.run(request, extensions).await
+    }
+}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::{clock::TokioClock, quota::Quota};
+
+    fn throttle() -> HostThrottle<TokioClock> {
+        let limiter = RateLimiter::new(Quota::per_second(100), TokioClock);
+        limiter.insert_key("api.example.com", Quota::per_second(1));
+        HostThrottle::new(limiter.into_handle())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_host_throttle_delays() {
+        let throttle = throttle();
+        let start = Instant::now();
+        throttle.throttle(Some("api.example.com")).await.unwrap();
+        throttle.throttle(Some("api.example.com")).await.unwrap();
+        // 同一主机的第二个请求等到下一个窗口
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        // 未知主机走基础配额，不受影响
+        let start = Instant::now();
+        throttle.throttle(Some("other.example.com")).await.unwrap();
+        throttle.throttle(None).await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_host_throttle_delay_at_most() {
+        let throttle = throttle().delay_at_most(Duration::ZERO);
+        throttle.throttle(Some("api.example.com")).await.unwrap();
+        assert!(matches!(
+            throttle.throttle(Some("api.example.com")).await,
+            Err(AcquireError::NotAllowed { .. })
+        ));
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    clock::{Clock, Reference},
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+};
+
+/// A single fixed-window quota split into per-core sub-budgets, for
+/// throughput where even one shared atomic becomes a bottleneck.
+///
+/// Each thread takes permits from its own shard, so threads on different
+/// cores never touch the same cache line while their shards last. When a
+/// shard runs dry, the unused budget of all shards is pooled and split
+/// evenly again, at most once per
+/// [rebalance interval](Self::with_rebalance_interval). Every window the
+/// shards are refilled with an even share of the quota.
+///
+/// This trades a little accuracy for the lack of contention: a request can
+/// be denied while other shards still hold budget until the next
+/// rebalance, and budget taken or rebalanced while a window resets may be
+/// counted against either window, so a window can slightly exceed its
+/// quota. Within a window, rebalancing never creates budget.
+#[derive(Debug)]
+pub struct PerCoreRateLimiter<C: Clock> {
+    quota: Quota,
+    shards: Box<[Shard]>,
+    start: C::Instant,
+    /// The window the shards were last refilled for.
+    window: AtomicU64,
+    rebalance_interval: Nanos,
+    /// Nanoseconds since `start` of the last rebalance.
+    last_rebalance: AtomicU64,
+    clock: C,
+}
+
+/// Padded to a cache line so that neighbouring shards don't contend.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct Shard {
+    remaining: AtomicU64,
+}
+
+impl<C: Clock> PerCoreRateLimiter<C> {
+    /// Splits `quota` into one shard per available core.
+    pub fn new(quota: impl Into<Quota>, clock: C) -> Self {
+        let shards = thread::available_parallelism().map_or(1, |cores| cores.get());
+        Self::with_shards(quota, clock, shards)
+    }
+
+    /// Splits `quota` into `shards` sub-budgets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is zero.
+    pub fn with_shards(quota: impl Into<Quota>, clock: C, shards: usize) -> Self {
+        assert!(shards > 0, "shard count must be non-zero");
+        let quota = quota.into();
+        let limiter = Self {
+            quota,
+            shards: (0..shards).map(|_| Shard::default()).collect(),
+            start: clock.now(),
+            window: AtomicU64::new(0),
+            rebalance_interval: Nanos::new((quota.window().as_u64() / 10).max(1)),
+            last_rebalance: AtomicU64::new(0),
+            clock,
+        };
+        limiter.distribute(quota.allowed(), |shard, share| {
+            shard.remaining.store(share, Ordering::Release);
+        });
+        limiter
+    }
+
+    /// Rebalances a dry shard at most once per `interval`; zero rebalances
+    /// whenever a shard runs dry. Defaults to a tenth of the window.
+    pub fn with_rebalance_interval(mut self, interval: Duration) -> Self {
+        self.rebalance_interval = interval.into();
+        self
+    }
+
+    /// Takes a permit from the calling thread's shard, or reports when the
+    /// next window starts.
+    pub fn acquire(&self) -> Result<(), NotUntil<C::Instant>> {
+        let offset = self.clock.now().duration_since(self.start);
+        let window = offset / self.quota.window();
+        self.refill(window);
+
+        let shard = &self.shards[local_shard() % self.shards.len()];
+        if take(shard) || (self.try_rebalance(offset) && take(shard)) {
+            return Ok(());
+        }
+        let next_window = self.quota.window().saturating_mul(window + 1);
+        Err(NotUntil::after(self.start, next_window, self.quota, 0))
+    }
+
+    /// The permits left in the current window across all shards.
+    pub fn remaining(&self) -> u64 {
+        let window = self.clock.now().duration_since(self.start) / self.quota.window();
+        self.refill(window);
+        self.shards
+            .iter()
+            .map(|shard| shard.remaining.load(Ordering::Acquire))
+            .sum()
+    }
+
+    /// Pools the unused budget of every shard and splits it evenly again.
+    pub fn rebalance(&self) {
+        let pooled = self
+            .shards
+            .iter()
+            .map(|shard| shard.remaining.swap(0, Ordering::AcqRel))
+            .sum();
+        self.distribute(pooled, |shard, share| {
+            shard.remaining.fetch_add(share, Ordering::AcqRel);
+        });
+    }
+
+    /// Starts `window` if it is newer than the current one. Only the thread
+    /// that advances the window refills the shards.
+    fn refill(&self, window: u64) {
+        let current = self.window.load(Ordering::Acquire);
+        if window > current
+            && self
+                .window
+                .compare_exchange(current, window, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+        {
+            self.distribute(self.quota.allowed(), |shard, share| {
+                shard.remaining.store(share, Ordering::Release);
+            });
+        }
+    }
+
+    fn try_rebalance(&self, offset: Nanos) -> bool {
+        let last = self.last_rebalance.load(Ordering::Acquire);
+        if offset.saturating_sub(Nanos::new(last)) < self.rebalance_interval {
+            return false;
+        }
+        let claimed = self
+            .last_rebalance
+            .compare_exchange(last, offset.as_u64(), Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if claimed {
+            self.rebalance();
+        }
+        claimed
+    }
+
+    /// Splits `total` evenly, giving the remainder to the first shards.
+    fn distribute(&self, total: u64, mut give: impl FnMut(&Shard, u64)) {
+        let count = self.shards.len() as u64;
+        for (index, shard) in (0..).zip(self.shards.iter()) {
+            give(shard, total / count + u64::from(index < total % count));
+        }
+    }
+}
+
+fn take(shard: &Shard) -> bool {
+    shard
+        .remaining
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+            remaining.checked_sub(1)
+        })
+        .is_ok()
+}
+
+/// A per-thread index, assigned round robin so threads spread over shards.
+fn local_shard() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    thread_local! {
+        static SHARD: usize = NEXT.fetch_add(1, Ordering::Relaxed);
+    }
+    SHARD.with(|shard| *shard)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{FakeRelativeClock, MonotonicClock};
+
+    #[test]
+    fn test_per_core_rebalances_dry_shard() {
+        let clock = FakeRelativeClock::default();
+        let limiter = PerCoreRateLimiter::with_shards(Quota::per_second(8), clock.clone(), 4);
+        assert_eq!(limiter.remaining(), 8);
+
+        // 当前线程只用自己的分片，用完后在重平衡间隔内被拒绝
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_ok());
+        let not_until = limiter.acquire().unwrap_err();
+        assert_eq!(
+            not_until.wait_time_from(clock.now()),
+            Duration::from_secs(1)
+        );
+
+        // 过了重平衡间隔后，其他分片未用的预算被重新分配
+        clock.advance(Duration::from_millis(100));
+        let granted = (0..10).filter(|_| limiter.acquire().is_ok()).count();
+        assert!((1..=6).contains(&granted));
+        assert_eq!(limiter.remaining(), 6 - granted as u64);
+
+        clock.advance(Duration::from_millis(900));
+        assert_eq!(limiter.remaining(), 8);
+    }
+
+    #[test]
+    fn test_per_core_never_exceeds_quota() {
+        let limiter = PerCoreRateLimiter::with_shards(Quota::per_minute(1_000), MonotonicClock, 4)
+            .with_rebalance_interval(Duration::ZERO);
+
+        // 多个线程并发获取，总数不超过配额
+        let granted: usize = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| (0..500).filter(|_| limiter.acquire().is_ok()).count()))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert!(granted > 0 && granted <= 1_000);
+        assert_eq!(limiter.remaining(), 1_000 - granted as u64);
+    }
+}
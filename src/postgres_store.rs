@@ -0,0 +1,260 @@
+use std::{fmt, marker::PhantomData, time::Duration};
+
+use postgres::NoTls;
+use r2d2::{ManageConnection, Pool, PooledConnection};
+use r2d2_postgres::PostgresConnectionManager;
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::store::{StateStore, Versioned};
+
+/// The table states are kept in unless configured otherwise.
+const DEFAULT_TABLE: &str = "ratelimit_state";
+
+/// An error from a [`PostgresStore`].
+#[derive(Debug)]
+pub enum PostgresStoreError {
+    /// No connection could be taken from the pool.
+    Pool(r2d2::Error),
+    Postgres(postgres::Error),
+    /// A stored value could not be decoded, or a value encoded.
+    Encoding(serde_json::Error),
+}
+
+impl fmt::Display for PostgresStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pool(err) => write!(f, "no postgres connection: {err}"),
+            Self::Postgres(err) => write!(f, "postgres: {err}"),
+            Self::Encoding(err) => write!(f, "invalid stored state: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for PostgresStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Pool(err) => Some(err),
+            Self::Postgres(err) => Some(err),
+            Self::Encoding(err) => Some(err),
+        }
+    }
+}
+
+/// A [`StateStore`] in a PostgreSQL table, for limits that must hold
+/// exactly across processes where the database is already the source of
+/// truth, such as redeeming invite codes or throttling password resets.
+///
+/// Each key is a row holding its version and its value as JSON. A write is
+/// a single `UPDATE` conditioned on the version, or an `INSERT` that does
+/// nothing if the key exists, so it is atomic without locks or
+/// transactions. Every call is a round trip to the database, which suits
+/// limits checked a few times a second rather than on every request.
+///
+/// The store never degrades: while the database is unreachable, requests
+/// fail with [`StoreUnavailable`](crate::AcquireError::StoreUnavailable).
+/// Connections are taken from an [`r2d2`] pool through the blocking
+/// [`postgres`] client, which must not be used from within an async
+/// runtime; run the limiter on a blocking thread there, e.g. with
+/// `tokio::task::spawn_blocking`.
+///
+/// ```no_run
+/// use ratelimit::{Algorithm, GcraState, PostgresStore, Quota, StoredRateLimiter, SystemClock};
+/// use r2d2_postgres::{PostgresConnectionManager, postgres::NoTls};
+///
+/// let config = "host=localhost user=app".parse().unwrap();
+/// let pool = r2d2::Pool::new(PostgresConnectionManager::new(config, NoTls)).unwrap();
+/// let store = PostgresStore::new(pool).with_table("password_reset_limits");
+/// store.create_table().unwrap();
+/// let limiter = StoredRateLimiter::new(store, GcraState::from_quota(Quota::per_hour(3), SystemClock));
+/// limiter.acquire_by_key(&"user@example.com".to_owned())?;
+/// # Ok::<(), ratelimit::AcquireError>(())
+/// ```
+pub struct PostgresStore<V, M: ManageConnection = PostgresConnectionManager<NoTls>> {
+    pool: Pool<M>,
+    table: String,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V, M> PostgresStore<V, M>
+where
+    M: ManageConnection<Connection = postgres::Client>,
+{
+    pub fn new(pool: Pool<M>) -> Self {
+        Self {
+            pool,
+            table: quote_identifier(DEFAULT_TABLE),
+            _value: PhantomData,
+        }
+    }
+
+    /// Keeps states in the table `table` instead of `ratelimit_state`.
+    ///
+    /// The name is quoted, so it is used exactly as given, and must not be
+    /// qualified with a schema; set the connection's `search_path` for that.
+    pub fn with_table(mut self, table: &str) -> Self {
+        self.table = quote_identifier(table);
+        self
+    }
+
+    pub fn pool(&self) -> &Pool<M> {
+        &self.pool
+    }
+
+    /// Creates the table unless it already exists, e.g. when the
+    /// application starts rather than in a migration.
+    pub fn create_table(&self) -> Result<(), PostgresStoreError> {
+        let statement = format!(
+            "CREATE TABLE IF NOT EXISTS {} (
+                key TEXT PRIMARY KEY,
+                version BIGINT NOT NULL,
+                value TEXT NOT NULL,
+                updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+            self.table
+        );
+        self.connection()?
+            .batch_execute(&statement)
+            .map_err(PostgresStoreError::Postgres)
+    }
+
+    /// Deletes the keys not updated for `idle`, which should be at least as
+    /// long as a state takes to fully recover, returning how many there
+    /// were.
+    ///
+    /// Rows are otherwise kept forever, so call this periodically.
+    pub fn remove_idle(&self, idle: Duration) -> Result<u64, PostgresStoreError> {
+        let statement = format!(
+            "DELETE FROM {} WHERE updated_at < now() - make_interval(secs => $1)",
+            self.table
+        );
+        self.connection()?
+            .execute(&statement, &[&idle.as_secs_f64()])
+            .map_err(PostgresStoreError::Postgres)
+    }
+
+    fn connection(&self) -> Result<PooledConnection<M>, PostgresStoreError> {
+        self.pool.get().map_err(PostgresStoreError::Pool)
+    }
+}
+
+impl<K, V, M> StateStore<K, V> for PostgresStore<V, M>
+where
+    K: fmt::Display,
+    V: Serialize + DeserializeOwned,
+    M: ManageConnection<Connection = postgres::Client>,
+{
+    type Error = PostgresStoreError;
+
+    fn load(&self, key: &K) -> Result<Option<Versioned<V>>, PostgresStoreError> {
+        let statement = format!("SELECT version, value FROM {} WHERE key = $1", self.table);
+        let Some(row) = self
+            .connection()?
+            .query_opt(&statement, &[&key.to_string()])
+            .map_err(PostgresStoreError::Postgres)?
+        else {
+            return Ok(None);
+        };
+        let version: i64 = row.get(0);
+        let value: String = row.get(1);
+        let value = serde_json::from_str(&value).map_err(PostgresStoreError::Encoding)?;
+        Ok(Some(Versioned {
+            value,
+            version: version.cast_unsigned(),
+        }))
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &K,
+        expected: Option<u64>,
+        value: V,
+    ) -> Result<bool, PostgresStoreError> {
+        let value = serde_json::to_string(&value).map_err(PostgresStoreError::Encoding)?;
+        let key = key.to_string();
+        let mut conn = self.connection()?;
+        let stored = match expected {
+            Some(version) => {
+                let statement = format!(
+                    "UPDATE {} SET version = version + 1, value = $3, updated_at = now()
+                    WHERE key = $1 AND version = $2",
+                    self.table
+                );
+                conn.execute(&statement, &[&key, &version.cast_signed(), &value])
+            }
+            None => {
+                let statement = format!(
+                    "INSERT INTO {} (key, version, value) VALUES ($1, 0, $2)
+                    ON CONFLICT (key) DO NOTHING",
+                    self.table
+                );
+                conn.execute(&statement, &[&key, &value])
+            }
+        };
+        Ok(stored.map_err(PostgresStoreError::Postgres)? == 1)
+    }
+
+    fn remove(&self, key: &K) -> Result<bool, PostgresStoreError> {
+        let statement = format!("DELETE FROM {} WHERE key = $1", self.table);
+        let removed = self
+            .connection()?
+            .execute(&statement, &[&key.to_string()])
+            .map_err(PostgresStoreError::Postgres)?;
+        Ok(removed > 0)
+    }
+}
+
+impl<V, M: ManageConnection> fmt::Debug for PostgresStore<V, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostgresStore")
+            .field("table", &self.table)
+            .finish_non_exhaustive()
+    }
+}
+
+/// `name` as a quoted SQL identifier.
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::FakeRelativeClock,
+        error::AcquireError,
+        nanos::Nanos,
+        quota::Quota,
+        state::{State, WindowSnapshot},
+        store::StoredRateLimiter,
+    };
+
+    #[test]
+    fn test_postgres_unreachable() {
+        let config = "host=127.0.0.1 port=1".parse().unwrap();
+        let pool = Pool::builder()
+            .connection_timeout(Duration::from_millis(50))
+            .build_unchecked(PostgresConnectionManager::new(config, NoTls));
+        let store: PostgresStore<WindowSnapshot<Nanos>> =
+            PostgresStore::new(pool).with_table("limits");
+        let limiter = StoredRateLimiter::new(
+            store,
+            State::new(Quota::per_second(1), FakeRelativeClock::default()),
+        );
+
+        // 数据库不可用时总是拒绝
+        assert!(matches!(
+            limiter.acquire_by_key(&"k".to_owned()),
+            Err(AcquireError::StoreUnavailable(_))
+        ));
+        assert!(matches!(
+            limiter.store().remove_idle(Duration::from_secs(60)),
+            Err(PostgresStoreError::Pool(_))
+        ));
+    }
+
+    #[test]
+    fn test_quote_identifier() {
+        assert_eq!(quote_identifier("limits"), r#""limits""#);
+        assert_eq!(quote_identifier(r#"a"; DROP"#), r#""a""; DROP""#);
+    }
+}
@@ -0,0 +1,191 @@
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::nanos::Nanos;
+
+/// How many permits are allowed per window, and how many of them may be
+/// taken at once.
+///
+/// With the `serde` feature it (de)serializes as `allowed`, `window` and
+/// `burst`, where `burst` defaults to `allowed` and `window` may be written
+/// as a string such as `"1m"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "QuotaConfig"))]
+pub struct Quota {
+    allowed: u64,
+    window: Nanos,
+    burst: u64,
+}
+
+impl Quota {
+    /// Creates a quota allowing `allowed` permits per `window`, with a burst
+    /// of `allowed`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    pub const fn new(allowed: u64, window: Nanos) -> Self {
+        assert!(window.as_u64() > 0, "Quota window must be non-zero");
+        Self {
+            allowed,
+            window,
+            burst: allowed,
+        }
+    }
+
+    pub const fn per_second(allowed: u64) -> Self {
+        Self::new(allowed, Nanos::from_secs(1))
+    }
+
+    pub const fn per_minute(allowed: u64) -> Self {
+        Self::new(allowed, Nanos::from_secs(60))
+    }
+
+    pub const fn per_hour(allowed: u64) -> Self {
+        Self::new(allowed, Nanos::from_secs(60 * 60))
+    }
+
+    /// Sets how many permits may be taken back to back once the limiter has
+    /// been idle, without changing the sustained rate.
+    ///
+    /// Only algorithms that replenish permits one at a time, such as
+    /// [`TokenBucketState`](crate::TokenBucketState) and
+    /// [`GcraState`](crate::GcraState), honour the burst; window-based
+    /// states always admit `allowed` per window.
+    pub const fn allow_burst(mut self, burst: u64) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    pub const fn allowed(&self) -> u64 {
+        self.allowed
+    }
+
+    pub const fn window(&self) -> Nanos {
+        self.window
+    }
+
+    pub const fn burst(&self) -> u64 {
+        self.burst
+    }
+
+    /// The time it takes to replenish a single permit when permits are spread
+    /// evenly over the window, rounded down to at least one nanosecond. A
+    /// quota allowing nothing replenishes once per window.
+    pub fn replenish_interval(&self) -> Nanos {
+        self.rate().spacing()
+    }
+}
+
+/// The deserialized form of a [`Quota`], validated on conversion.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct QuotaConfig {
+    allowed: u64,
+    window: Nanos,
+    burst: Option<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<QuotaConfig> for Quota {
+    type Error = &'static str;
+
+    fn try_from(config: QuotaConfig) -> Result<Self, Self::Error> {
+        if config.window == Nanos::ZERO {
+            return Err("Quota window must be non-zero");
+        }
+        let quota = Self::new(config.allowed, config.window);
+        Ok(quota.allow_burst(config.burst.unwrap_or(config.allowed)))
+    }
+}
+
+/// `(allowed, window)`, e.g. `(100, Duration::from_secs(1))` for 100 per second.
+///
+/// # Panics
+///
+/// Panics if the duration is zero or longer than 584 years.
+impl From<(u64, Duration)> for Quota {
+    fn from((allowed, window): (u64, Duration)) -> Self {
+        Self::new(allowed, window.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_from_tuple() {
+        let quota = Quota::from((100, Duration::from_secs(1)));
+        assert_eq!(quota.allowed(), 100);
+        assert_eq!(quota.window(), Nanos::new(1_000_000_000));
+
+        let quota: Quota = (3, Duration::from_millis(250)).into();
+        assert_eq!(quota, Quota::new(3, Nanos::new(250_000_000)));
+    }
+
+    #[test]
+    fn test_replenish_interval() {
+        let quota = Quota::from((4, Duration::from_secs(1)));
+        assert_eq!(quota.replenish_interval(), Nanos::new(250_000_000));
+
+        let quota = Quota::from((0, Duration::from_secs(1)));
+        assert_eq!(quota.replenish_interval(), Nanos::new(1_000_000_000));
+
+        let quota = Quota::new(10, Nanos::new(3));
+        assert_eq!(quota.replenish_interval(), Nanos::new(1));
+    }
+
+    #[test]
+    fn test_quota_constructors() {
+        let quota = Quota::per_second(5);
+        assert_eq!(quota, Quota::from((5, Duration::from_secs(1))));
+        assert_eq!(quota.burst(), 5);
+
+        assert_eq!(
+            Quota::per_minute(60).window(),
+            Nanos::from(Duration::from_secs(60))
+        );
+        assert_eq!(
+            Quota::per_hour(1).window(),
+            Nanos::from(Duration::from_secs(3600))
+        );
+
+        // 突发量只改变 burst，不影响持续速率
+        let quota = Quota::per_minute(60).allow_burst(10);
+        assert_eq!(quota.allowed(), 60);
+        assert_eq!(quota.burst(), 10);
+        assert_eq!(quota.replenish_interval(), Nanos::new(1_000_000_000));
+        assert_ne!(quota, Quota::per_minute(60));
+    }
+
+    #[test]
+    fn test_quota_zero_allowed() {
+        let quota = Quota::from((0, Duration::from_secs(1)));
+        assert_eq!(quota.allowed(), 0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_quota_serde() {
+        let quota = Quota::per_minute(60).allow_burst(10);
+        let json = serde_json::to_string(&quota).unwrap();
+        assert_eq!(json, r#"{"allowed":60,"window":60000000000,"burst":10}"#);
+        assert_eq!(serde_json::from_str::<Quota>(&json).unwrap(), quota);
+
+        // 配置文件里可以省略 burst，并用可读的时长
+        let quota: Quota = serde_json::from_str(r#"{"allowed":5,"window":"1s"}"#).unwrap();
+        assert_eq!(quota, Quota::per_second(5));
+        assert!(serde_json::from_str::<Quota>(r#"{"allowed":5,"window":0}"#).is_err());
+        assert!(serde_json::from_str::<Quota>(r#"{"allowed":5,"window":"5w"}"#).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Quota window must be non-zero")]
+    fn test_quota_from_tuple_zero_duration() {
+        let _ = Quota::from((10, Duration::ZERO));
+    }
+}
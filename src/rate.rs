@@ -0,0 +1,198 @@
+use std::{
+    cmp::Ordering,
+    ops::{Div, Mul},
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{nanos::Nanos, quota::Quota};
+
+/// A sustained rate of `permits` per period, independent of any window or
+/// burst.
+///
+/// Rates compare by value, so 2 per second equals 120 per minute.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "RateConfig"))]
+pub struct Rate {
+    permits: u64,
+    per: Nanos,
+}
+
+impl Rate {
+    /// # Panics
+    ///
+    /// Panics if `per` is zero.
+    pub const fn new(permits: u64, per: Nanos) -> Self {
+        assert!(per.as_u64() > 0, "Rate period must be non-zero");
+        Self { permits, per }
+    }
+
+    pub const fn per_second(permits: u64) -> Self {
+        Self::new(permits, Nanos::from_secs(1))
+    }
+
+    pub const fn permits(&self) -> u64 {
+        self.permits
+    }
+
+    pub const fn per(&self) -> Nanos {
+        self.per
+    }
+
+    /// The time between two permits when they are spread evenly, rounded
+    /// down to at least one nanosecond. A rate of zero permits spaces them
+    /// a whole period apart.
+    pub const fn spacing(&self) -> Nanos {
+        match self.permits {
+            0 => self.per,
+            permits => {
+                let spacing = self.per.as_u64() / permits;
+                Nanos::new(if spacing == 0 { 1 } else { spacing })
+            }
+        }
+    }
+
+    /// How many whole permits accrue over `duration`, saturating at
+    /// `u64::MAX`.
+    pub fn permits_in(&self, duration: Nanos) -> u64 {
+        let permits = u128::from(self.permits) * u128::from(duration.as_u64())
+            / u128::from(self.per.as_u64());
+        permits.try_into().unwrap_or(u64::MAX)
+    }
+}
+
+/// The deserialized form of a [`Rate`], validated on conversion.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+struct RateConfig {
+    permits: u64,
+    per: Nanos,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<RateConfig> for Rate {
+    type Error = &'static str;
+
+    fn try_from(config: RateConfig) -> Result<Self, Self::Error> {
+        if config.per == Nanos::ZERO {
+            return Err("Rate period must be non-zero");
+        }
+        Ok(Self::new(config.permits, config.per))
+    }
+}
+
+impl PartialEq for Rate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Rate {}
+
+impl PartialOrd for Rate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Compares `permits / per` exactly, by cross-multiplying in 128 bits.
+impl Ord for Rate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let lhs = u128::from(self.permits) * u128::from(other.per.as_u64());
+        let rhs = u128::from(other.permits) * u128::from(self.per.as_u64());
+        lhs.cmp(&rhs)
+    }
+}
+
+/// Scales the rate up by allowing `factor` times the permits per period.
+///
+/// # Panics
+///
+/// Panics if the permits would exceed `u64::MAX`.
+impl Mul<u64> for Rate {
+    type Output = Self;
+
+    fn mul(self, factor: u64) -> Self::Output {
+        let permits = self
+            .permits
+            .checked_mul(factor)
+            .expect("Rate permits overflow u64");
+        Self::new(permits, self.per)
+    }
+}
+
+/// Scales the rate down by stretching the period `divisor` times.
+///
+/// # Panics
+///
+/// Panics if `divisor` is zero or the period would exceed about 584 years.
+impl Div<u64> for Rate {
+    type Output = Self;
+
+    fn div(self, divisor: u64) -> Self::Output {
+        let per = self
+            .per
+            .checked_mul(divisor)
+            .expect("Rate period is longer than 584 years");
+        Self::new(self.permits, per)
+    }
+}
+
+impl Quota {
+    /// The sustained rate of the quota: `allowed` permits per window.
+    pub const fn rate(&self) -> Rate {
+        Rate::new(self.allowed(), self.window())
+    }
+}
+
+/// A quota allowing the rate's permits per its period, with a burst of as
+/// many.
+impl From<Rate> for Quota {
+    fn from(rate: Rate) -> Self {
+        Self::new(rate.permits, rate.per)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_spacing_and_comparison() {
+        let rate = Rate::per_second(4);
+        assert_eq!(rate.spacing(), Nanos::new(250_000_000));
+        assert_eq!(Rate::per_second(0).spacing(), Nanos::new(1_000_000_000));
+        assert_eq!(Rate::new(10, Nanos::new(3)).spacing(), Nanos::new(1));
+
+        // 按值比较：每秒 2 个等于每分钟 120 个
+        assert_eq!(
+            Rate::per_second(2),
+            Rate::new(120, Nanos::new(60_000_000_000))
+        );
+        assert!(Rate::per_second(3) > Rate::new(120, Nanos::new(60_000_000_000)));
+        assert!(Rate::per_second(0) < Rate::per_second(1));
+    }
+
+    #[test]
+    fn test_rate_scaling() {
+        let rate = Rate::per_second(4);
+        assert_eq!(rate * 3, Rate::per_second(12));
+        assert_eq!(rate / 2, Rate::per_second(2));
+        assert_eq!(rate.permits_in(Nanos::new(1_500_000_000)), 6);
+        assert_eq!(
+            Rate::new(u64::MAX, Nanos::new(1)).permits_in(Nanos::MAX),
+            u64::MAX
+        );
+
+        assert_eq!(Quota::per_second(5).rate(), Rate::per_second(5));
+        assert_eq!(Quota::from(rate), Quota::per_second(4));
+    }
+
+    #[test]
+    #[should_panic(expected = "Rate permits overflow u64")]
+    fn test_rate_mul_overflow() {
+        let _ = Rate::per_second(u64::MAX / 2) * 3;
+    }
+}
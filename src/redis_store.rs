@@ -0,0 +1,347 @@
+use std::{fmt, marker::PhantomData, sync::Arc, time::Duration};
+
+use r2d2::Pool;
+use redis::{Client, Commands, RedisError, Script};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::store::{Degraded, DegradedMode, StateStore, Versioned};
+
+/// Stores `ARGV[2]` under `KEYS[1]` if its version is still `ARGV[1]`, or the
+/// key is still absent for an empty `ARGV[1]`, then expires the key after
+/// `ARGV[3]` milliseconds unless that is zero.
+const COMPARE_AND_SWAP: &str = r"
+local version = redis.call('HGET', KEYS[1], 'v')
+if ARGV[1] == '' then
+    if version then return 0 end
+    version = 0
+elseif version == ARGV[1] then
+    version = tonumber(version) + 1
+else
+    return 0
+end
+redis.call('HSET', KEYS[1], 'v', version, 'd', ARGV[2])
+if ARGV[3] ~= '0' then redis.call('PEXPIRE', KEYS[1], ARGV[3]) end
+return 1
+";
+
+/// An error from a [`RedisStore`].
+#[derive(Debug)]
+pub enum RedisStoreError {
+    /// No connection could be taken from the pool.
+    Pool(r2d2::Error),
+    Redis(RedisError),
+    /// A stored value could not be decoded, or a value encoded.
+    Encoding(serde_json::Error),
+}
+
+impl fmt::Display for RedisStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pool(err) => write!(f, "no redis connection: {err}"),
+            Self::Redis(err) => write!(f, "redis: {err}"),
+            Self::Encoding(err) => write!(f, "invalid stored state: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RedisStoreError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Pool(err) => Some(err),
+            Self::Redis(err) => Some(err),
+            Self::Encoding(err) => Some(err),
+        }
+    }
+}
+
+impl RedisStoreError {
+    /// Whether Redis couldn't be reached, rather than misbehaving.
+    fn is_unreachable(&self) -> bool {
+        match self {
+            Self::Pool(_) => true,
+            Self::Redis(err) => {
+                err.is_io_error() || err.is_timeout() || err.is_connection_dropped()
+            }
+            Self::Encoding(_) => false,
+        }
+    }
+}
+
+/// A [`StateStore`] in Redis, so that every process of a service limiting
+/// through it shares a single quota per key.
+///
+/// Each key is a Redis hash holding its version and its value as JSON,
+/// under the [key prefix](Self::with_prefix). Writes run as a Lua script, so
+/// the version check and the update are atomic. Connections are taken from
+/// an [`r2d2`] pool, and while Redis is unreachable the store behaves as
+/// its [`DegradedMode`] says.
+///
+/// ```no_run
+/// use ratelimit::{Algorithm, GcraState, Quota, RedisStore, StoredRateLimiter, SystemClock};
+///
+/// let client = redis::Client::open("redis://127.0.0.1/").unwrap();
+/// let store = RedisStore::new(client).unwrap().with_prefix("api:");
+/// let limiter = StoredRateLimiter::new(store, GcraState::from_quota(Quota::per_second(10), SystemClock));
+/// limiter.acquire_by_key("user-42")?;
+/// # Ok::<(), ratelimit::AcquireError>(())
+/// ```
+pub struct RedisStore<V> {
+    pool: Pool<Client>,
+    script: Script,
+    prefix: String,
+    ttl: Option<Duration>,
+    degraded: Degraded<V>,
+    _value: PhantomData<fn() -> V>,
+}
+
+impl<V> RedisStore<V> {
+    /// Connects through a pool with r2d2's default settings, failing if no
+    /// connection can be established.
+    pub fn new(client: Client) -> Result<Self, RedisStoreError> {
+        let pool = Pool::new(client).map_err(RedisStoreError::Pool)?;
+        Ok(Self::from_pool(pool))
+    }
+
+    /// Connects through `pool`, e.g. one built with a larger size or a
+    /// shorter connection timeout.
+    pub fn from_pool(pool: Pool<Client>) -> Self {
+        Self {
+            pool,
+            script: Script::new(COMPARE_AND_SWAP),
+            prefix: String::new(),
+            ttl: None,
+            degraded: Degraded::new(DegradedMode::default()),
+            _value: PhantomData,
+        }
+    }
+
+    /// Prefixes every Redis key with `prefix`, e.g. to share a database.
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Expires keys not updated for `ttl`, which should be at least as long
+    /// as a state takes to fully recover.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    pub fn with_degraded_mode(mut self, mode: DegradedMode) -> Self {
+        self.degraded.set_mode(mode);
+        self
+    }
+
+    /// Calls `listener` with `true` when Redis becomes unreachable and the
+    /// store starts answering as its [`DegradedMode`] says, and with `false`
+    /// once it answers again, e.g. to alert on a degraded limiter.
+    pub fn with_degraded_listener<F>(mut self, listener: F) -> Self
+    where
+        F: Fn(bool) + Send + Sync + 'static,
+    {
+        self.degraded.set_listener(Arc::new(listener));
+        self
+    }
+
+    pub fn pool(&self) -> &Pool<Client> {
+        &self.pool
+    }
+
+    fn redis_key(&self, key: &impl fmt::Display) -> String {
+        format!("{}{key}", self.prefix)
+    }
+
+    fn connection(&self) -> Result<r2d2::PooledConnection<Client>, RedisStoreError> {
+        self.pool.get().map_err(RedisStoreError::Pool)
+    }
+}
+
+impl<V: Serialize + DeserializeOwned> RedisStore<V> {
+    fn encode(value: &V) -> Result<String, RedisStoreError> {
+        serde_json::to_string(value).map_err(RedisStoreError::Encoding)
+    }
+
+    fn decode(data: &str) -> Result<V, RedisStoreError> {
+        serde_json::from_str(data).map_err(RedisStoreError::Encoding)
+    }
+
+    fn try_load(&self, key: &str) -> Result<Option<Versioned<V>>, RedisStoreError> {
+        let (version, data): (Option<u64>, Option<String>) = self
+            .connection()?
+            .hmget(key, &["v", "d"])
+            .map_err(RedisStoreError::Redis)?;
+        let (Some(version), Some(data)) = (version, data) else {
+            return Ok(None);
+        };
+        Ok(Some(Versioned {
+            value: Self::decode(&data)?,
+            version,
+        }))
+    }
+
+    fn try_compare_and_swap(
+        &self,
+        key: &str,
+        expected: Option<u64>,
+        value: &V,
+    ) -> Result<bool, RedisStoreError> {
+        let data = Self::encode(value)?;
+        let expected = expected.map_or_else(String::new, |version| version.to_string());
+        let ttl = self.ttl.map_or(0, |ttl| ttl.as_millis().max(1));
+        let swapped: i64 = self
+            .script
+            .key(key)
+            .arg(expected)
+            .arg(data)
+            .arg(ttl.to_string())
+            .invoke(&mut *self.connection()?)
+            .map_err(RedisStoreError::Redis)?;
+        Ok(swapped == 1)
+    }
+}
+
+impl<K, V> StateStore<K, V> for RedisStore<V>
+where
+    K: fmt::Display,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    type Error = RedisStoreError;
+
+    fn load(&self, key: &K) -> Result<Option<Versioned<V>>, RedisStoreError> {
+        let key = self.redis_key(key);
+        match self.try_load(&key) {
+            Err(err) if err.is_unreachable() => self.degraded.load(&key, err),
+            loaded => {
+                self.degraded.reached();
+                loaded
+            }
+        }
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &K,
+        expected: Option<u64>,
+        value: V,
+    ) -> Result<bool, RedisStoreError> {
+        let key = self.redis_key(key);
+        match self.try_compare_and_swap(&key, expected, &value) {
+            Err(err) if err.is_unreachable() => {
+                self.degraded.compare_and_swap(&key, expected, value, err)
+            }
+            swapped => {
+                self.degraded.reached();
+                swapped
+            }
+        }
+    }
+
+    fn remove(&self, key: &K) -> Result<bool, RedisStoreError> {
+        let key = self.redis_key(key);
+        let local = self.degraded.remove(&key);
+        let removed: Result<u64, _> = self
+            .connection()
+            .and_then(|mut conn| conn.del(&key).map_err(RedisStoreError::Redis));
+        match removed {
+            Ok(removed) => {
+                self.degraded.reached();
+                Ok(removed > 0 || local)
+            }
+            Err(err) if err.is_unreachable() => self.degraded.unreachable(local, err),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl<V> fmt::Debug for RedisStore<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("prefix", &self.prefix)
+            .field("ttl", &self.ttl)
+            .field("degraded", &self.degraded.mode())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nanos::Nanos, state::WindowSnapshot};
+
+    type Store = RedisStore<WindowSnapshot<Nanos>>;
+
+    /// A store on the Redis server at `REDIS_URL`, or a local one, with keys
+    /// of its own.
+    fn live() -> RedisStore<u64> {
+        let url = std::env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1/".to_owned());
+        let prefix = format!("ratelimit-test-{}:", std::process::id());
+        RedisStore::new(Client::open(url).unwrap())
+            .unwrap()
+            .with_prefix(prefix)
+    }
+
+    #[test]
+    fn test_redis_key_and_encoding() {
+        let client = Client::open("redis://127.0.0.1:1/").unwrap();
+        let store: Store =
+            RedisStore::from_pool(Pool::builder().build_unchecked(client)).with_prefix("api:");
+        assert_eq!(store.redis_key(&"user-42"), "api:user-42");
+
+        let snapshot = WindowSnapshot {
+            start: Nanos::new(1_500),
+            acquired: 2,
+        };
+        let data = Store::encode(&snapshot).unwrap();
+        assert_eq!(data, r#"{"start":1500,"acquired":2}"#);
+        assert_eq!(Store::decode(&data).unwrap(), snapshot);
+        assert!(matches!(
+            Store::decode("{}"),
+            Err(RedisStoreError::Encoding(_))
+        ));
+    }
+
+    #[test]
+    #[ignore = "needs a Redis server, at REDIS_URL or 127.0.0.1:6379"]
+    fn test_redis_compare_and_swap() {
+        let store = live();
+        let key = "k".to_owned();
+        store.remove(&key).unwrap();
+        assert_eq!(store.load(&key).unwrap(), None);
+
+        // 只有键不存在时才能按空版本创建
+        assert!(store.compare_and_swap(&key, None, 1).unwrap());
+        assert!(!store.compare_and_swap(&key, None, 2).unwrap());
+        let loaded = store.load(&key).unwrap().unwrap();
+        assert_eq!(
+            loaded,
+            Versioned {
+                value: 1,
+                version: 0
+            }
+        );
+
+        // 版本匹配时写入并递增版本，否则失败
+        assert!(store.compare_and_swap(&key, Some(0), 3).unwrap());
+        assert!(!store.compare_and_swap(&key, Some(0), 4).unwrap());
+        let loaded = store.load(&key).unwrap().unwrap();
+        assert_eq!(
+            loaded,
+            Versioned {
+                value: 3,
+                version: 1
+            }
+        );
+
+        // 没有 TTL 时不过期，设置后每次写入都续期
+        let redis_key = store.redis_key(&key);
+        let pttl = |store: &RedisStore<u64>| -> i64 {
+            store.connection().unwrap().pttl(&redis_key).unwrap()
+        };
+        assert_eq!(pttl(&store), -1);
+        let store = store.with_ttl(Duration::from_secs(60));
+        assert!(store.compare_and_swap(&key, Some(1), 5).unwrap());
+        assert!((1..=60_000).contains(&pttl(&store)));
+        assert!(store.remove(&key).unwrap());
+    }
+}
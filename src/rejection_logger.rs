@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use crate::{
+    algorithm::Algorithm, clock::Clock, error::AcquireError, limiter::RateLimiter, nanos::Nanos,
+    quota::Quota, state::State,
+};
+
+/// Wraps a [`RateLimiter`] and reports keyed rejections through a callback,
+/// invoking it at most once per `interval` for each key.
+///
+/// The callback receives the key and how many rejections were suppressed
+/// since it was last invoked for that key. Unknown and disabled keys are not
+/// reported, and keys not rejected for an `interval` are forgotten, after a
+/// final report of any rejections still suppressed.
+pub struct RejectionLogger<C: Clock, F: FnMut(&str, u64), S: Algorithm<C> = State<C>> {
+    limiter: RateLimiter<C, S>,
+    interval: Nanos,
+    log_states: HashMap<String, (State<C>, u64)>,
+    on_reject: F,
+}
+
+impl<C: Clock, F: FnMut(&str, u64), S: Algorithm<C>> RejectionLogger<C, F, S> {
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn new(limiter: RateLimiter<C, S>, interval: Nanos, on_reject: F) -> Self {
+        assert!(interval > Nanos::ZERO, "interval must be non-zero");
+        Self {
+            limiter,
+            interval,
+            log_states: HashMap::new(),
+            on_reject,
+        }
+    }
+
+    pub fn acquire_by_key(&mut self, key: &str) -> Result<(), AcquireError> {
+        let error = match self.limiter.acquire_by_key(key) {
+            Ok(()) => return Ok(()),
+            Err(error @ (AcquireError::UnknownKey | AcquireError::Disabled)) => return Err(error),
+            Err(error) => error,
+        };
+
+        if !self.log_states.contains_key(key) {
+            self.prune();
+        }
+        let (state, suppressed) = self.log_states.entry(key.to_string()).or_insert_with(|| {
+            let clock = self.limiter.clock().clone();
+            (State::new(Quota::new(1, self.interval), clock), 0)
+        });
+        if state.acquire().is_ok() {
+            (self.on_reject)(key, *suppressed);
+            *suppressed = 0;
+        } else {
+            *suppressed += 1;
+        }
+        Err(error)
+    }
+
+    /// Forgets the keys whose log interval has elapsed, so that the log
+    /// states do not grow with every key ever rejected.
+    fn prune(&mut self) {
+        let now = self.limiter.clock().now();
+        let on_reject = &mut self.on_reject;
+        self.log_states.retain(|key, (state, suppressed)| {
+            if !state.is_idle_at(now) {
+                return true;
+            }
+            if *suppressed > 0 {
+                on_reject(key, *suppressed);
+            }
+            false
+        });
+    }
+
+    pub fn limiter(&mut self) -> &mut RateLimiter<C, S> {
+        &mut self.limiter
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::clock::FakeRelativeClock;
+
+    #[test]
+    fn test_rejection_logger_throttles_callback() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone());
+        limiter.insert_key("attacker", (1, Duration::from_secs(60)));
+        limiter.insert_key("user", (0, Duration::from_secs(60)));
+
+        let mut logged = Vec::new();
+        let mut logger = RejectionLogger::new(
+            limiter,
+            Nanos::new(1_000_000_000),
+            |key: &str, suppressed| {
+                logged.push((key.to_string(), suppressed));
+            },
+        );
+
+        assert!(logger.acquire_by_key("attacker").is_ok());
+        // 10 秒内每 10ms 一次被拒绝的请求，日志每秒最多一次
+        for _ in 0..1_000 {
+            assert!(logger.acquire_by_key("attacker").is_err());
+            clock.advance(Duration::from_millis(10));
+        }
+        assert!(logger.acquire_by_key("user").is_err());
+        drop(logger);
+
+        let attacker: Vec<_> = logged.iter().filter(|(key, _)| key == "attacker").collect();
+        // 最后一次是 "user" 出现时清理 "attacker" 的补报
+        assert_eq!(attacker.len(), 11);
+        assert_eq!(attacker[0].1, 0);
+        assert!(
+            attacker[1..]
+                .iter()
+                .all(|(_, suppressed)| *suppressed == 99)
+        );
+        assert_eq!(logged.last(), Some(&("user".to_string(), 0)));
+    }
+
+    #[test]
+    fn test_rejection_logger_forgets_idle_keys() {
+        let clock = FakeRelativeClock::default();
+        let limiter = RateLimiter::new(Quota::per_second(1), clock.clone());
+        limiter.insert_key("a", (0, Duration::from_secs(60)));
+        limiter.insert_key("b", (0, Duration::from_secs(60)));
+        limiter.set_key_enabled("off", false);
+
+        let mut logged = Vec::new();
+        let mut logger = RejectionLogger::new(
+            limiter,
+            Nanos::new(1_000_000_000),
+            |key: &str, suppressed| {
+                logged.push((key.to_string(), suppressed));
+            },
+        );
+
+        // 禁用的键不记录
+        assert_eq!(logger.acquire_by_key("off"), Err(AcquireError::Disabled));
+        assert!(logger.log_states.is_empty());
+
+        assert!(logger.acquire_by_key("a").is_err());
+        assert!(logger.acquire_by_key("a").is_err());
+        assert_eq!(logger.log_states.len(), 1);
+
+        // 间隔过后新键出现时，旧键被清理并补报被抑制的次数
+        clock.advance(Duration::from_secs(1));
+        assert!(logger.acquire_by_key("b").is_err());
+        assert_eq!(logger.log_states.len(), 1);
+        assert!(logger.log_states.contains_key("b"));
+        drop(logger);
+
+        assert_eq!(
+            logged,
+            [
+                ("a".to_string(), 0),
+                ("a".to_string(), 1),
+                ("b".to_string(), 0)
+            ]
+        );
+    }
+}
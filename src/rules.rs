@@ -0,0 +1,141 @@
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::quota::Quota;
+
+/// How keys matching a rule are limited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum KeyLimit {
+    Limited(Quota),
+    /// Every request is granted and no state is kept.
+    Unlimited,
+}
+
+impl From<Quota> for KeyLimit {
+    fn from(quota: Quota) -> Self {
+        Self::Limited(quota)
+    }
+}
+
+/// Limits for unknown keys, chosen by the most specific matching pattern.
+#[derive(Debug)]
+pub(crate) struct KeyRules<K> {
+    rules: Vec<(Pattern, KeyLimit)>,
+    key_text: fn(&K) -> &str,
+}
+
+impl<K> KeyRules<K> {
+    pub(crate) fn new(key_text: fn(&K) -> &str) -> Self {
+        Self {
+            rules: Vec::new(),
+            key_text,
+        }
+    }
+
+    pub(crate) fn add(&mut self, pattern: &str, limit: KeyLimit) {
+        self.rules.push((Pattern::new(pattern), limit));
+    }
+
+    /// The limit of the most specific rule matching `key`. Of equally
+    /// specific rules the first added wins.
+    pub(crate) fn limit_for(&self, key: &K) -> Option<KeyLimit> {
+        let text = (self.key_text)(key);
+        self.rules
+            .iter()
+            .rev()
+            .filter(|(pattern, _)| pattern.matches(text))
+            .max_by_key(|(pattern, _)| pattern.specificity())
+            .map(|(_, limit)| *limit)
+    }
+}
+
+/// A glob where `*` matches any run of characters, including none.
+#[derive(Debug)]
+struct Pattern {
+    /// The literal text between wildcards.
+    parts: Vec<String>,
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Self {
+        Self {
+            parts: pattern.split('*').map(str::to_owned).collect(),
+        }
+    }
+
+    /// More literal text is more specific, and an exact pattern beats a glob
+    /// with the same literal text.
+    fn specificity(&self) -> (usize, bool) {
+        let literal = self.parts.iter().map(String::len).sum();
+        (literal, self.parts.len() == 1)
+    }
+
+    fn matches(&self, text: &str) -> bool {
+        let (first, rest) = self.parts.split_first().expect("split yields a part");
+        let Some(mut remaining) = text.strip_prefix(first.as_str()) else {
+            return false;
+        };
+        let Some((last, middle)) = rest.split_last() else {
+            return remaining.is_empty();
+        };
+        for part in middle {
+            match remaining.find(part.as_str()) {
+                Some(index) => remaining = &remaining[index + part.len()..],
+                None => return false,
+            }
+        }
+        remaining.ends_with(last.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_key_limit_serde() {
+        let limits: Vec<KeyLimit> =
+            serde_json::from_str(r#"[{"limited": {"allowed": 10, "window": "1m"}}, "unlimited"]"#)
+                .unwrap();
+        assert_eq!(
+            limits,
+            [
+                KeyLimit::Limited(Quota::per_minute(10)),
+                KeyLimit::Unlimited
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pattern_matches() {
+        let glob = Pattern::new("/api/*/users/*");
+        assert!(glob.matches("/api/v1/users/42"));
+        assert!(glob.matches("/api//users/"));
+        assert!(!glob.matches("/api/v1/orders/42"));
+
+        assert!(Pattern::new("admin:*").matches("admin:root"));
+        assert!(Pattern::new("*").matches(""));
+        assert!(Pattern::new("exact").matches("exact"));
+        assert!(!Pattern::new("exact").matches("exactly"));
+        // 前后缀不能重叠使用同一段字符
+        assert!(!Pattern::new("ab*ba").matches("aba"));
+    }
+
+    #[test]
+    fn test_most_specific_rule_wins() {
+        let mut rules = KeyRules::new(String::as_str);
+        rules.add("/api/*", Quota::per_second(10).into());
+        rules.add("/api/v1/*", Quota::per_second(100).into());
+        rules.add("/api/v1/health", KeyLimit::Unlimited);
+        rules.add("/api/v1/*", Quota::per_second(1).into());
+
+        let limit = |key: &str| rules.limit_for(&key.to_string());
+        assert_eq!(limit("/api/v2/x"), Some(Quota::per_second(10).into()));
+        assert_eq!(limit("/api/v1/x"), Some(Quota::per_second(100).into()));
+        assert_eq!(limit("/api/v1/health"), Some(KeyLimit::Unlimited));
+        assert_eq!(limit("/other"), None);
+    }
+}
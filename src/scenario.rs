@@ -0,0 +1,78 @@
+//! 随机场景测试的公共工具，供各个算法的 proptest 使用
+
+use std::time::Duration;
+
+use proptest::{prelude::*, test_runner::RngSeed};
+
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, FakeRelativeClock},
+    nanos::Nanos,
+};
+
+#[derive(Debug, Clone)]
+pub enum Op {
+    Advance(u64),
+    Acquire,
+}
+
+pub fn op_strategy() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        1 => (0..300u64).prop_map(Op::Advance),
+        3 => Just(Op::Acquire),
+    ]
+}
+
+/// 固定种子，保证每次运行生成相同的场景
+pub fn config() -> ProptestConfig {
+    ProptestConfig {
+        rng_seed: RngSeed::Fixed(0x5eed),
+        failure_persistence: None,
+        ..ProptestConfig::default()
+    }
+}
+
+/// 在假时钟上执行操作序列（时间单位为毫秒），返回所有被接受请求的时间戳
+pub fn run_scenario(
+    ops: &[Op],
+    clock: &FakeRelativeClock,
+    mut acquire: impl FnMut() -> bool,
+) -> Vec<Nanos> {
+    let mut accepted = Vec::new();
+    for op in ops {
+        match op {
+            Op::Advance(ms) => clock.advance(Duration::from_millis(*ms)),
+            Op::Acquire => {
+                if acquire() {
+                    accepted.push(clock.now());
+                }
+            }
+        }
+    }
+    accepted
+}
+
+/// 先 check 再 acquire，断言 check 的结论与实际获取一致
+pub fn checked_acquire<S: Algorithm<FakeRelativeClock>>(state: &mut S) -> bool {
+    let now = state.clock().now();
+    let checked = state.check_at(now);
+    if let Ok(remaining) = checked {
+        assert_eq!(remaining, state.remaining_at(now));
+    }
+    let acquired = state.try_acquire_at(now);
+    assert_eq!(checked.map(|_| ()), acquired);
+    acquired.is_ok()
+}
+
+/// 任意长度为 window 的半开区间内被接受的最大请求数
+pub fn max_accepted_in_any_window(accepted: &[Nanos], window: Nanos) -> u64 {
+    let mut max = 0;
+    let mut start = 0;
+    for end in 0..accepted.len() {
+        while accepted[end].saturating_sub(accepted[start]) >= window {
+            start += 1;
+        }
+        max = max.max(end - start + 1);
+    }
+    max as u64
+}
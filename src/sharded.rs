@@ -0,0 +1,233 @@
+use std::{borrow::Borrow, hash::Hash, marker::PhantomData};
+
+use dashmap::DashMap;
+
+use crate::{algorithm::Algorithm, clock::Clock, error::AcquireError, quota::Quota, state::State};
+
+/// Independently limited keys spread over internally locked shards, so that
+/// concurrent requests for different keys rarely contend.
+///
+/// Where [`RateLimiter`](crate::RateLimiter) locks all of its keys at once,
+/// here a key is hashed to one of the shards and only that shard is locked
+/// while its state is updated. Unknown keys are created on first use as fresh copies of the
+/// template state; [`insert_key`](Self::insert_key) configures individual
+/// quotas.
+///
+/// It is a leaner limiter, not a drop-in replacement: there is no base state
+/// limiting all keys together, no hierarchy, no default quota or rejection of
+/// unknown keys, no disabled keys, key rules or maximum number of keys, no
+/// backoff and no hooks, and idle keys are only dropped by
+/// [`maintain`](Self::maintain).
+#[derive(Debug)]
+pub struct ShardedRateLimiter<C: Clock, S: Algorithm<C> = State<C>, K: Hash + Eq + Clone = String> {
+    keys: DashMap<K, Slot<S>>,
+    template: S,
+    _clock: PhantomData<C>,
+}
+
+#[derive(Debug)]
+struct Slot<S> {
+    state: S,
+    /// Configured through `insert_key` rather than created on first use.
+    pinned: bool,
+}
+
+impl<C: Clock> ShardedRateLimiter<C> {
+    /// Creates a fixed-window limiter whose keys default to `quota`.
+    pub fn new(quota: impl Into<Quota>, clock: C) -> Self {
+        Self::from_state(State::new(quota, clock))
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>> ShardedRateLimiter<C, S> {
+    /// Creates a limiter whose keys default to fresh copies of `template`.
+    pub fn from_state(template: S) -> Self {
+        Self::keyed(template)
+    }
+}
+
+impl<C: Clock, S: Algorithm<C>, K: Hash + Eq + Clone> ShardedRateLimiter<C, S, K> {
+    /// Creates a limiter keyed on `K` rather than `String`.
+    pub fn keyed(template: S) -> Self {
+        Self {
+            keys: DashMap::new(),
+            template,
+            _clock: PhantomData,
+        }
+    }
+
+    /// Like [`keyed`](Self::keyed), with `shards` shards instead of a number
+    /// derived from the available parallelism.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shards` is not a power of two greater than one.
+    pub fn with_shards(template: S, shards: usize) -> Self {
+        Self {
+            keys: DashMap::with_shard_amount(shards),
+            template,
+            _clock: PhantomData,
+        }
+    }
+
+    /// Configures `key` with its own `quota`, replacing any existing state.
+    pub fn insert_key<Q>(&self, key: &Q, quota: impl Into<Quota>)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let state = S::from_quota(quota.into(), self.template.clock().clone());
+        self.keys.insert(
+            key.to_owned(),
+            Slot {
+                state,
+                pinned: true,
+            },
+        );
+    }
+
+    /// Consumes a permit for `key`, creating the key if it is unknown.
+    pub fn acquire_by_key<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.with_state(key, |state| {
+            let now = state.clock().now();
+            state
+                .try_acquire_at(now)
+                .map_err(|not_until| AcquireError::not_allowed(not_until, now))
+        })
+    }
+
+    /// Consumes `n` permits for `key`, or none of them.
+    pub fn acquire_n_by_key<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.with_state(key, |state| {
+            let now = state.clock().now();
+            state
+                .try_acquire_n_at(n, now)?
+                .map_err(|not_until| AcquireError::not_allowed(not_until, now))
+        })
+    }
+
+    /// Reports whether `key` would be granted a permit, and how many remain,
+    /// without consuming one or creating the key.
+    pub fn check_key<Q>(&self, key: &Q) -> Result<u64, AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let check = |state: &S| {
+            let now = state.clock().now();
+            state
+                .check_at(now)
+                .map_err(|not_until| AcquireError::not_allowed(not_until, now))
+        };
+        match self.keys.get(key) {
+            Some(slot) => check(&slot.state),
+            None => check(&self.template.fresh()),
+        }
+    }
+
+    pub fn remove_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.keys.remove(key).is_some()
+    }
+
+    /// The number of tracked keys. Locks every shard in turn.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Removes keys created on first use that have fully recovered, returning
+    /// how many were removed. Locks one shard at a time.
+    pub fn maintain(&self) -> usize {
+        let now = self.template.clock().now();
+        let before = self.keys.len();
+        self.keys
+            .retain(|_, slot| slot.pinned || !slot.state.is_idle_at(now));
+        before.saturating_sub(self.keys.len())
+    }
+
+    /// Runs `f` on the state of `key` while holding its shard's lock.
+    fn with_state<Q, R>(&self, key: &Q, f: impl FnOnce(&mut S) -> R) -> R
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if let Some(mut slot) = self.keys.get_mut(key) {
+            return f(&mut slot.state);
+        }
+        let mut slot = self.keys.entry(key.to_owned()).or_insert_with(|| Slot {
+            state: self.template.fresh(),
+            pinned: false,
+        });
+        f(&mut slot.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::clock::{FakeRelativeClock, MonotonicClock};
+
+    #[test]
+    fn test_sharded_keys_are_independent() {
+        let clock = FakeRelativeClock::default();
+        let limiter = ShardedRateLimiter::new(Quota::per_second(2), clock.clone());
+        limiter.insert_key("vip", Quota::per_second(3));
+
+        // 未知 key 按模板自动创建
+        assert_eq!(limiter.check_key("user"), Ok(2));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_err());
+        assert_eq!(limiter.acquire_n_by_key("vip", 3), Ok(()));
+        assert_eq!(
+            limiter.acquire_n_by_key("other", 3),
+            Err(AcquireError::InsufficientCapacity { capacity: 2 })
+        );
+        assert_eq!(limiter.len(), 3);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(limiter.maintain(), 2);
+        assert!(limiter.remove_key("vip"));
+        assert!(limiter.is_empty());
+    }
+
+    #[test]
+    fn test_sharded_concurrent_acquire() {
+        let limiter: ShardedRateLimiter<_, _, u64> =
+            ShardedRateLimiter::with_shards(State::new(Quota::per_hour(100), MonotonicClock), 8);
+
+        // 每个线程使用自己的 key，互不影响
+        let granted: Vec<usize> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|id| {
+                    let limiter = &limiter;
+                    scope.spawn(move || {
+                        (0..150)
+                            .filter(|_| limiter.acquire_by_key(&id).is_ok())
+                            .count()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+        assert_eq!(granted, vec![100; 8]);
+        assert_eq!(limiter.len(), 8);
+    }
+}
@@ -0,0 +1,370 @@
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    fs::OpenOptions,
+    hash::{Hash, Hasher},
+    io,
+    path::Path,
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use memmap2::{MmapMut, MmapOptions};
+
+use crate::{
+    clock::ReasonablyRealtime, error::AcquireError, gossip::wall_time, nanos::Nanos,
+    not_until::NotUntil, quota::Quota,
+};
+
+/// Marks a file laid out as a [`SharedMemoryRateLimiter`] table.
+const MAGIC: u64 = u64::from_le_bytes(*b"RLSHM\0\0\x01");
+
+/// The words before the first slot: the magic and the slot count.
+const HEADER_WORDS: usize = 2;
+
+/// A keyed GCRA limiter whose states live in a memory-mapped file, so that
+/// the processes of one host, such as the workers of a pre-forked server,
+/// share their quotas without a network store.
+///
+/// The file holds a fixed table of slots, each an atomic key hash and an
+/// atomic theoretical arrival time, updated with compare-and-swap loops
+/// like an [`AtomicGcraState`](crate::AtomicGcraState)'s. Keys claim a slot
+/// on first use and keep it for the file's lifetime, so size the table for
+/// every key the processes will see; once it is full, new keys fail with
+/// [`StoreUnavailable`](AcquireError::StoreUnavailable). Keys are told apart
+/// by a 64-bit hash of their [`Hash`] impl, so all processes must run the
+/// same build, and two keys colliding on the full hash share a state.
+///
+/// Times are stored as wall-clock time, which all processes agree on. Every
+/// process must open the file with the same slot count and configure the
+/// same quotas.
+///
+/// ```no_run
+/// use ratelimit::{Quota, SharedMemoryRateLimiter, SystemClock};
+///
+/// // In every worker, e.g. after forking.
+/// let limiter = SharedMemoryRateLimiter::open(
+///     "/dev/shm/ratelimit",
+///     65_536,
+///     Quota::per_second(100),
+///     SystemClock,
+/// )?;
+/// limiter.acquire_by_key("tenant-a")?;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+#[derive(Debug)]
+pub struct SharedMemoryRateLimiter<C: ReasonablyRealtime, K: Hash + Eq = String> {
+    map: MmapMut,
+    slots: usize,
+    quota: Quota,
+    key_quotas: HashMap<K, Quota>,
+    clock: C,
+}
+
+impl<C: ReasonablyRealtime> SharedMemoryRateLimiter<C> {
+    /// Maps the table of `slots` keys at `path`, creating it if needed,
+    /// and limits every key to `quota`.
+    ///
+    /// Fails if `slots` is zero, or if the file holds a table of another
+    /// size or isn't one at all.
+    pub fn open(
+        path: impl AsRef<Path>,
+        slots: usize,
+        quota: impl Into<Quota>,
+        clock: C,
+    ) -> io::Result<Self> {
+        Self::open_keyed(path, slots, quota, clock)
+    }
+}
+
+impl<C: ReasonablyRealtime, K: Hash + Eq> SharedMemoryRateLimiter<C, K> {
+    /// Like [`open`](SharedMemoryRateLimiter::open), keyed on `K` rather
+    /// than `String`.
+    pub fn open_keyed(
+        path: impl AsRef<Path>,
+        slots: usize,
+        quota: impl Into<Quota>,
+        clock: C,
+    ) -> io::Result<Self> {
+        let len = slots
+            .checked_mul(2)
+            .and_then(|words| words.checked_add(HEADER_WORDS))
+            .and_then(|words| words.checked_mul(8))
+            .filter(|_| slots > 0)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid slot count"))?;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        if file.metadata()?.len() < len as u64 {
+            file.set_len(len as u64)?;
+        }
+        // SAFETY: the file is at least `len` bytes long, and only ever
+        // accessed through atomics, by this process and others alike.
+        let map = unsafe { MmapOptions::new().len(len).map_mut(&file)? };
+        let limiter = Self {
+            map,
+            slots,
+            quota: quota.into(),
+            key_quotas: HashMap::new(),
+            clock,
+        };
+
+        let words = limiter.words();
+        let _ = words[0].compare_exchange(0, MAGIC, Ordering::AcqRel, Ordering::Acquire);
+        let _ = words[1].compare_exchange(0, slots as u64, Ordering::AcqRel, Ordering::Acquire);
+        if words[0].load(Ordering::Acquire) != MAGIC
+            || words[1].load(Ordering::Acquire) != slots as u64
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a rate limiter table of this size",
+            ));
+        }
+        Ok(limiter)
+    }
+
+    /// Limits `key` to `quota` instead of the default quota.
+    pub fn with_key_quota<Q>(mut self, key: &Q, quota: impl Into<Quota>) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.key_quotas.insert(key.to_owned(), quota.into());
+        self
+    }
+
+    /// How many keys the table holds.
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    /// Consumes a permit for `key`.
+    pub fn acquire_by_key<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.acquire_n_by_key(key, 1)
+    }
+
+    /// Consumes `n` permits for `key`, or none of them.
+    pub fn acquire_n_by_key<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let quota = self.quota_of(key);
+        if n > quota.burst() {
+            return Err(AcquireError::InsufficientCapacity {
+                capacity: quota.burst(),
+            });
+        }
+        if n == 0 {
+            return Ok(());
+        }
+        let tat = self.slot(key, true).ok_or_else(|| {
+            AcquireError::StoreUnavailable("shared memory table is full".to_owned())
+        })?;
+        let now = self.clock.now();
+        let wall = u128::from(wall_time(&self.clock, now).as_u64());
+        let gcra = Gcra::new(quota);
+        let increment = gcra.interval * u128::from(n - 1);
+        let mut current = tat.load(Ordering::Acquire);
+        loop {
+            let earliest = (u128::from(current) + increment).saturating_sub(gcra.tolerance);
+            if wall < earliest {
+                let not_until = NotUntil::after_wide(
+                    now,
+                    earliest - wall,
+                    quota,
+                    gcra.remaining(current, wall),
+                );
+                return Err(AcquireError::not_allowed(not_until, now));
+            }
+            let next = u128::from(current).max(wall) + increment + gcra.interval;
+            let next = Nanos::saturating_from_wide(next).as_u64();
+            match tat.compare_exchange_weak(current, next, Ordering::AcqRel, Ordering::Acquire) {
+                Ok(_) => return Ok(()),
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// The permits `key` could take now, without taking any or claiming a
+    /// slot for it.
+    pub fn check_key<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let quota = self.quota_of(key);
+        let Some(tat) = self.slot(key, false) else {
+            return quota.burst();
+        };
+        let wall = u128::from(wall_time(&self.clock, self.clock.now()).as_u64());
+        Gcra::new(quota).remaining(tat.load(Ordering::Acquire), wall)
+    }
+
+    fn quota_of<Q>(&self, key: &Q) -> Quota
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.key_quotas.get(key).copied().unwrap_or(self.quota)
+    }
+
+    fn words(&self) -> &[AtomicU64] {
+        // SAFETY: the map is page-aligned, a whole number of words long and
+        // lives as long as `self`; `AtomicU64` has the layout of `u64`.
+        unsafe { slice::from_raw_parts(self.map.as_ptr().cast(), self.map.len() / 8) }
+    }
+
+    /// The TAT word of the slot `key` hashes to, probing linearly from its
+    /// home slot, and claiming a free one if `claim` is set.
+    fn slot<Q: Hash + ?Sized>(&self, key: &Q, claim: bool) -> Option<&AtomicU64> {
+        let mut hasher = Fnv::default();
+        key.hash(&mut hasher);
+        // Zero marks a free slot.
+        let hash = hasher.finish().max(1);
+        let home = (hash % self.slots as u64) as usize;
+        let words = self.words();
+        for probe in 0..self.slots {
+            let slot = HEADER_WORDS + 2 * ((home + probe) % self.slots);
+            let owner = match words[slot].load(Ordering::Acquire) {
+                0 if claim => words[slot]
+                    .compare_exchange(0, hash, Ordering::AcqRel, Ordering::Acquire)
+                    .map_or_else(|owner| owner, |_| hash),
+                0 => return None,
+                owner => owner,
+            };
+            if owner == hash {
+                return Some(&words[slot + 1]);
+            }
+        }
+        None
+    }
+}
+
+/// A quota's GCRA parameters, in nanoseconds.
+struct Gcra {
+    interval: u128,
+    tolerance: u128,
+}
+
+impl Gcra {
+    fn new(quota: Quota) -> Self {
+        let interval = quota.replenish_interval();
+        Self {
+            interval: u128::from(interval.as_u64()),
+            tolerance: interval.widening_mul(quota.burst().saturating_sub(1)),
+        }
+    }
+
+    fn remaining(&self, tat: u64, now: u128) -> u64 {
+        let backlog = u128::from(tat).saturating_sub(now);
+        let remaining = (self.tolerance + self.interval).saturating_sub(backlog) / self.interval;
+        remaining.try_into().unwrap_or(u64::MAX)
+    }
+}
+
+/// FNV-1a, which unlike the standard library's hasher is the same in every
+/// process.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for Fnv {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        process,
+        time::{Duration, SystemTime},
+    };
+
+    use super::*;
+    use crate::clock::FakeAbsoluteClock;
+
+    fn table(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("ratelimit-{}-{name}", process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_shared_memory_shares_quota() {
+        let path = table("shares");
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_000));
+        let open = || SharedMemoryRateLimiter::open(&path, 8, Quota::per_second(2), clock.clone());
+        let (a, b) = (open().unwrap(), open().unwrap());
+        let key = "k".to_owned();
+
+        // 两个映射共用同一份状态
+        assert_eq!(b.check_key(&key), 2);
+        a.acquire_by_key(&key).unwrap();
+        b.acquire_by_key(&key).unwrap();
+        let Err(AcquireError::NotAllowed { retry_after, .. }) = a.acquire_by_key(&key) else {
+            panic!("expected a denial");
+        };
+        assert_eq!(retry_after, Duration::from_millis(500));
+        assert!(b.acquire_by_key("other").is_ok());
+
+        clock.advance(Duration::from_millis(500));
+        assert_eq!(b.check_key(&key), 1);
+        // 借用的键与拥有的键落在同一个槽位
+        assert_eq!(b.check_key("k"), 1);
+        assert!(matches!(
+            a.acquire_n_by_key(&key, 3),
+            Err(AcquireError::InsufficientCapacity { capacity: 2 })
+        ));
+
+        // 槽位数不同的表不能打开
+        assert_eq!(
+            SharedMemoryRateLimiter::<_>::open(&path, 16, Quota::per_second(2), clock)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_shared_memory_table_full() {
+        let path = table("full");
+        let limiter = SharedMemoryRateLimiter::open(
+            &path,
+            2,
+            Quota::per_second(1),
+            FakeAbsoluteClock::default(),
+        )
+        .unwrap();
+        for key in ["a", "b"] {
+            limiter.acquire_by_key(key).unwrap();
+        }
+        assert!(matches!(
+            limiter.acquire_by_key("c"),
+            Err(AcquireError::StoreUnavailable(_))
+        ));
+        assert_eq!(limiter.check_key("c"), 1);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures_sink::Sink;
+use pin_project_lite::pin_project;
+
+use crate::{
+    algorithm::Algorithm,
+    clock::Clock,
+    error::AcquireError,
+    jitter::Jitter,
+    limiter::RateLimiter,
+    sleeper::{AsyncSleeper, DefaultSleeper},
+};
+
+pin_project! {
+    /// A sink accepting items no faster than the base state of a
+    /// [`RateLimiter`] allows.
+    ///
+    /// `poll_ready` first waits for the inner sink and then for a permit, so
+    /// no permit is spent while the inner sink applies backpressure. A permit
+    /// granted by `poll_ready` is kept until the next `start_send`. While the
+    /// limiter is disabled the sink stalls.
+    #[must_use = "sinks do nothing unless polled"]
+    pub struct RateLimitedSink<'a, Si, C, S, K = String, Sl = DefaultSleeper>
+    where
+        C: Clock,
+        S: Algorithm<C>,
+        K: Hash,
+        K: Eq,
+        K: Clone,
+        Sl: AsyncSleeper,
+    {
+        #[pin]
+        inner: Si,
+        limiter: &'a mut RateLimiter<C, S, K>,
+        permitted: bool,
+        delay: Option<Pin<Box<Sl::Sleep>>>,
+        jitter: Jitter,
+    }
+}
+
+impl<Si, C, S, K, Sl> RateLimitedSink<'_, Si, C, S, K, Sl>
+where
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    Sl: AsyncSleeper,
+{
+    /// Extends every wait for a permit by `jitter`.
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl<Si, Item, C, S, K, Sl> Sink<Item> for RateLimitedSink<'_, Si, C, S, K, Sl>
+where
+    Si: Sink<Item>,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    Sl: AsyncSleeper,
+{
+    type Error = Si::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let mut this = self.project();
+        ready!(this.inner.as_mut().poll_ready(cx))?;
+        while !*this.permitted {
+            if let Some(delay) = this.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                *this.delay = None;
+            }
+            match this.limiter.acquire() {
+                Ok(()) => *this.permitted = true,
+                Err(AcquireError::NotAllowed { retry_after, .. }) => {
+                    *this.delay = Some(Box::pin(Sl::sleep(this.jitter.apply(retry_after))));
+                }
+                Err(_) => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        let this = self.project();
+        *this.permitted = false;
+        this.inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Adds [`ratelimit_sink`](Self::ratelimit_sink) to every [`Sink`].
+pub trait SinkRateLimitExt<Item>: Sink<Item> + Sized {
+    /// Throttles the sink to the base state of `limiter`.
+    fn ratelimit_sink<C, S, K>(
+        self,
+        limiter: &mut RateLimiter<C, S, K>,
+    ) -> RateLimitedSink<'_, Self, C, S, K>
+    where
+        C: Clock,
+        S: Algorithm<C>,
+        K: Hash + Eq + Clone,
+    {
+        RateLimitedSink {
+            inner: self,
+            limiter,
+            permitted: false,
+            delay: None,
+            jitter: Jitter::NONE,
+        }
+    }
+}
+
+impl<Si: Sink<Item>, Item> SinkRateLimitExt<Item> for Si {}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt, channel::mpsc};
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::{clock::TokioClock, quota::Quota};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ratelimited_sink_throttles_sends() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(Quota::per_second(2), TokioClock);
+        let (tx, rx) = mpsc::unbounded();
+
+        let mut sink = tx.ratelimit_sink(&mut limiter);
+        let mut sent_at = Vec::new();
+        for i in 0..5 {
+            sink.send(i).await.unwrap();
+            sent_at.push(start.elapsed().as_millis());
+        }
+        drop(sink);
+
+        // 每个窗口最多发送 2 个
+        assert_eq!(sent_at, vec![0, 0, 1_000, 1_000, 2_000]);
+        assert_eq!(rx.collect::<Vec<_>>().await, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ratelimited_sink_keeps_unused_permit() {
+        let mut limiter = RateLimiter::new(Quota::per_second(1), TokioClock);
+        let (tx, mut rx) = mpsc::unbounded();
+        let mut sink = tx.ratelimit_sink(&mut limiter);
+
+        // 多次 poll_ready 只消耗一个许可
+        futures::future::poll_fn(|cx| Pin::new(&mut sink).poll_ready(cx))
+            .await
+            .unwrap();
+        futures::future::poll_fn(|cx| Pin::new(&mut sink).poll_ready(cx))
+            .await
+            .unwrap();
+        Pin::new(&mut sink).start_send("a").unwrap();
+        assert_eq!(rx.next().await, Some("a"));
+
+        let blocked = tokio::time::timeout(Duration::from_millis(10), sink.send("b")).await;
+        assert!(blocked.is_err());
+    }
+}
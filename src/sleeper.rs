@@ -0,0 +1,82 @@
+use std::{future::Future, time::Duration};
+
+/// The timer the async waits sleep on, so they aren't tied to one runtime.
+///
+/// Implementations are provided for tokio (feature `tokio`) and for the
+/// async-io reactor shared by smol and async-std (features `smol` and
+/// `async-std`). [`DefaultSleeper`] picks one of them, preferring tokio.
+pub trait AsyncSleeper {
+    type Sleep: Future;
+
+    /// Returns a future that completes after `duration`.
+    fn sleep(duration: Duration) -> Self::Sleep;
+}
+
+/// Sleeps on the tokio timer; must be polled inside a tokio runtime.
+#[cfg(feature = "tokio")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioSleeper;
+
+#[cfg(feature = "tokio")]
+impl AsyncSleeper for TokioSleeper {
+    type Sleep = tokio::time::Sleep;
+
+    fn sleep(duration: Duration) -> Self::Sleep {
+        tokio::time::sleep(duration)
+    }
+}
+
+/// Sleeps on an async-io timer, as used by smol and async-std. Works under
+/// any executor.
+#[cfg(feature = "smol")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AsyncIoSleeper;
+
+#[cfg(feature = "smol")]
+impl AsyncSleeper for AsyncIoSleeper {
+    type Sleep = async_io::Timer;
+
+    fn sleep(duration: Duration) -> Self::Sleep {
+        async_io::Timer::after(duration)
+    }
+}
+
+/// The sleeper used by the async methods and adapters.
+#[cfg(feature = "tokio")]
+pub type DefaultSleeper = TokioSleeper;
+
+/// The sleeper used by the async methods and adapters.
+#[cfg(all(feature = "smol", not(feature = "tokio")))]
+pub type DefaultSleeper = AsyncIoSleeper;
+
+#[cfg(all(test, feature = "smol"))]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    #[test]
+    fn test_async_io_sleeper() {
+        let start = Instant::now();
+        async_io::block_on(AsyncIoSleeper::sleep(Duration::from_millis(20)));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    #[cfg(not(feature = "tokio"))]
+    #[test]
+    fn test_until_ready_without_tokio() {
+        use crate::{clock::MonotonicClock, limiter::RateLimiter, quota::Quota};
+
+        // 只启用 smol 时，异步等待走 async-io 的定时器
+        let start = Instant::now();
+        let limiter = RateLimiter::new(
+            Quota::new(1, Duration::from_millis(20).into()),
+            MonotonicClock,
+        );
+        async_io::block_on(async {
+            limiter.until_ready().await.unwrap();
+            limiter.until_ready().await.unwrap();
+        });
+        assert!(start.elapsed() >= Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::VecDeque;
+
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, Reference},
+    error::InsufficientCapacity,
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+};
+
+/// Records the timestamp of every granted permit and admits a request only
+/// if fewer than `allowed` permits were granted in the trailing `window`.
+///
+/// This guarantees that no rolling window ever sees more than `allowed`
+/// permits, at the cost of storing up to `allowed` timestamps.
+#[derive(Debug)]
+pub struct SlidingWindowLog<C: Clock> {
+    quota: Quota,
+    start: C::Instant,
+    log: VecDeque<Nanos>,
+    clock: C,
+}
+
+impl<C: Clock> SlidingWindowLog<C> {
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(window: Nanos, allowed: u64, clock: C) -> Self {
+        Self::from_quota(Quota::new(allowed, window), clock)
+    }
+
+    /// Grants a permit, or on denial reports when the oldest permit in the
+    /// window expires.
+    pub fn acquire(&mut self) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.clock.now();
+        self.try_acquire_at(now)
+    }
+
+    /// Grants `n` permits at once, or none of them. On denial reports when
+    /// enough logged permits expire to make room for `n`.
+    pub fn acquire_n(
+        &mut self,
+        n: u64,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        let now = self.clock.now();
+        self.try_acquire_n_at(n, now)
+    }
+
+    fn offset(&self, now: C::Instant) -> Nanos {
+        now.duration_since(self.start)
+    }
+
+    /// The number of logged permits still inside the window ending at `offset`.
+    fn in_window(&self, offset: Nanos) -> usize {
+        let expired = self
+            .log
+            .partition_point(|&granted| offset.saturating_sub(granted) >= self.quota.window());
+        self.log.len() - expired
+    }
+
+    fn take_at(&mut self, n: u64, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        // An older event time is logged as the newest one, keeping the log sorted.
+        let offset = self
+            .offset(now)
+            .max(self.log.back().copied().unwrap_or(Nanos::ZERO));
+        let expired = self.log.len() - self.in_window(offset);
+        self.log.drain(..expired);
+
+        let logged = self.log.len() as u64;
+        if logged + n <= self.quota.allowed() {
+            self.log.extend((0..n).map(|_| offset));
+            return Ok(());
+        }
+        let must_expire = (logged + n - self.quota.allowed()) as usize;
+        let remaining = self.quota.allowed() - logged;
+        Err(match self.log.get(must_expire - 1) {
+            Some(&granted) => NotUntil::after(
+                self.start,
+                granted.saturating_add(self.quota.window()),
+                self.quota,
+                remaining,
+            ),
+            None => NotUntil::after(now, self.quota.window(), self.quota, remaining),
+        })
+    }
+}
+
+impl<C: Clock> Algorithm<C> for SlidingWindowLog<C> {
+    fn from_quota(quota: Quota, clock: C) -> Self {
+        Self {
+            quota,
+            start: clock.now(),
+            log: VecDeque::new(),
+            clock,
+        }
+    }
+
+    fn fresh(&self) -> Self {
+        Self::from_quota(self.quota, self.clock.clone())
+    }
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    fn capacity(&self) -> u64 {
+        self.quota.allowed()
+    }
+
+    fn try_acquire_at(&mut self, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.take_at(1, now)
+    }
+
+    fn try_acquire_n_at(
+        &mut self,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        if n > self.quota.allowed() {
+            return Err(InsufficientCapacity(self.quota.allowed()));
+        }
+        Ok(self.take_at(n, now))
+    }
+
+    fn check_at(&self, now: C::Instant) -> Result<u64, NotUntil<C::Instant>> {
+        let offset = self.offset(now);
+        let in_window = self.in_window(offset);
+        if (in_window as u64) < self.quota.allowed() {
+            return Ok(self.quota.allowed() - in_window as u64);
+        }
+        Err(match self.log.get(self.log.len() - in_window) {
+            Some(&oldest) => NotUntil::after(
+                self.start,
+                oldest.saturating_add(self.quota.window()),
+                self.quota,
+                0,
+            ),
+            None => NotUntil::after(now, self.quota.window(), self.quota, 0),
+        })
+    }
+
+    fn remaining_at(&self, now: C::Instant) -> u64 {
+        let in_window = self.in_window(self.offset(now)) as u64;
+        self.quota.allowed().saturating_sub(in_window)
+    }
+
+    fn reset_after_at(&self, now: C::Instant) -> Nanos {
+        match self.log.back() {
+            Some(&newest) => newest
+                .saturating_add(self.quota.window())
+                .saturating_sub(self.offset(now)),
+            None => Nanos::ZERO,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        clock::FakeRelativeClock,
+        limiter::RateLimiter,
+        scenario::{self, checked_acquire, max_accepted_in_any_window, op_strategy, run_scenario},
+    };
+
+    #[test]
+    fn test_sliding_log_rolling_window() {
+        let clock = FakeRelativeClock::default();
+        // 任意 1 秒内最多 2 次
+        let mut log = SlidingWindowLog::new(Nanos::new(1_000_000_000), 2, clock.clone());
+
+        assert!(log.acquire().is_ok());
+        clock.advance(Duration::from_millis(900));
+        assert!(log.acquire().is_ok());
+        assert!(log.acquire().is_err());
+
+        // 固定窗口在这里会重置，滑动窗口仍然包含 900ms 处的许可
+        clock.advance(Duration::from_millis(100));
+        assert!(log.acquire().is_ok());
+        assert!(log.acquire().is_err());
+
+        clock.advance(Duration::from_millis(800));
+        assert!(log.acquire().is_err());
+        clock.advance(Duration::from_millis(100));
+        assert!(log.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_sliding_log_not_until_oldest_expires() {
+        let clock = FakeRelativeClock::default();
+        let mut log = SlidingWindowLog::new(Nanos::new(1_000_000_000), 2, clock.clone());
+        assert!(log.acquire().is_ok());
+        clock.advance(Duration::from_millis(400));
+        assert!(log.acquire().is_ok());
+
+        let not_until = log.acquire().unwrap_err();
+        assert_eq!(
+            not_until.wait_time_from(clock.now()),
+            Duration::from_millis(600)
+        );
+        assert_eq!(log.remaining_at(clock.now()), 0);
+        assert_eq!(log.reset_after_at(clock.now()), Nanos::new(1_000_000_000));
+
+        clock.advance(Duration::from_millis(600));
+        assert_eq!(log.remaining_at(clock.now()), 1);
+        assert!(log.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_sliding_log_acquire_n() {
+        let clock = FakeRelativeClock::default();
+        let mut log = SlidingWindowLog::new(Nanos::new(1_000_000_000), 4, clock.clone());
+        assert!(log.acquire().is_ok());
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(log.acquire_n(2), Ok(Ok(())));
+
+        // 请求 3 个需要最早的两条记录过期，即第 2 条在 1.2s 过期
+        clock.advance(Duration::from_millis(300));
+        let not_until = log.acquire_n(3).unwrap().unwrap_err();
+        assert_eq!(not_until.earliest_possible(), Nanos::new(1_200_000_000));
+        assert_eq!(log.remaining_at(clock.now()), 1);
+
+        clock.advance(Duration::from_millis(700));
+        assert_eq!(log.acquire_n(3), Ok(Ok(())));
+        assert_eq!(log.remaining_at(clock.now()), 1);
+        assert_eq!(log.acquire_n(5), Err(InsufficientCapacity(4)));
+    }
+
+    #[test]
+    fn test_sliding_log_older_event_time() {
+        let clock = FakeRelativeClock::default();
+        let mut log = SlidingWindowLog::new(Nanos::new(1_000_000_000), 2, clock.clone());
+        assert!(log.try_acquire_at(Nanos::new(500_000_000)).is_ok());
+
+        // 更早的事件时间按最新记录处理，两条记录在 1.5s 一起过期
+        assert!(log.try_acquire_at(Nanos::new(100_000_000)).is_ok());
+        assert!(log.try_acquire_at(Nanos::new(1_400_000_000)).is_err());
+        assert_eq!(log.remaining_at(Nanos::new(1_500_000_000)), 2);
+    }
+
+    #[test]
+    fn test_sliding_log_zero_allowed() {
+        let clock = FakeRelativeClock::default();
+        let mut log = SlidingWindowLog::new(Nanos::new(1_000_000_000), 0, clock.clone());
+        assert!(log.acquire().is_err());
+        clock.advance(Duration::from_secs(10));
+        assert!(log.acquire().is_err());
+        assert!(log.is_idle_at(clock.now()));
+    }
+
+    #[test]
+    fn test_sliding_log_in_rate_limiter() {
+        let clock = FakeRelativeClock::default();
+        let base = SlidingWindowLog::new(Nanos::new(1_000_000_000), 1, clock.clone());
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", (2, Duration::from_secs(60)));
+
+        assert!(limiter.acquire_by_key("user").is_ok());
+        clock.advance(Duration::from_secs(59));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        clock.advance(Duration::from_secs(1));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_err());
+    }
+
+    proptest! {
+        #![proptest_config(scenario::config())]
+
+        #[test]
+        fn prop_sliding_log_never_exceeds_allowed(
+            allowed in 0..10u64,
+            window_ms in 1..1_000u64,
+            ops in prop::collection::vec(op_strategy(), 0..500),
+        ) {
+            let clock = FakeRelativeClock::default();
+            let window = Nanos::new(window_ms * 1_000_000);
+            let mut log = SlidingWindowLog::new(window, allowed, clock.clone());
+            let accepted = run_scenario(&ops, &clock, || checked_acquire(&mut log));
+
+            // 滑动窗口日志严格保证任意窗口内不超过 allowed
+            prop_assert!(max_accepted_in_any_window(&accepted, window) <= allowed);
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, Reference},
+    error::InsufficientCapacity,
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+};
+
+/// Approximates a sliding window with the counts of the current and the
+/// previous fixed window.
+///
+/// The previous window's count is weighted by how much of it still overlaps
+/// the trailing `window`, assuming its permits were spread evenly, and a
+/// request is admitted if that estimate including the request stays within
+/// `allowed`. This keeps two counters per state while removing most of the
+/// 2x boundary burst of [`State`](crate::State): a rolling window can still
+/// see more than `allowed` permits when they cluster around a boundary, but
+/// never `2 * allowed`.
+#[derive(Debug)]
+pub struct SlidingWindowState<C: Clock> {
+    quota: Quota,
+    start: C::Instant,
+    last_update: C::Instant,
+    window_index: u64,
+    previous: u64,
+    current: u64,
+    clock: C,
+}
+
+impl<C: Clock> SlidingWindowState<C> {
+    /// # Panics
+    ///
+    /// Panics if `window` is zero.
+    pub fn new(window: Nanos, allowed: u64, clock: C) -> Self {
+        Self::from_quota(Quota::new(allowed, window), clock)
+    }
+
+    /// Admits a permit, or on denial reports when the weighted count drops
+    /// far enough to admit a request.
+    pub fn acquire(&mut self) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.clock.now();
+        self.try_acquire_at(now)
+    }
+
+    /// Admits `n` permits at once, or none of them. On denial reports when
+    /// the weighted count drops far enough to admit all `n`.
+    pub fn acquire_n(
+        &mut self,
+        n: u64,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        let now = self.clock.now();
+        self.try_acquire_n_at(n, now)
+    }
+
+    /// The window index, the `(previous, current)` counts and the time elapsed
+    /// in that window at `now`.
+    fn counts_at(&self, now: C::Instant) -> (u64, u64, u64, Nanos) {
+        let offset = now.duration_since(self.start);
+        let index = offset / self.quota.window();
+        let elapsed = offset % self.quota.window();
+        let (previous, current) = match index.saturating_sub(self.window_index) {
+            0 => (self.previous, self.current),
+            1 => (self.current, 0),
+            _ => (0, 0),
+        };
+        (index, previous, current, elapsed)
+    }
+
+    /// The weighted permit count scaled by the window length, so that a
+    /// request is admitted if the estimate including it is at most
+    /// `allowed * window`.
+    fn scaled_estimate(&self, previous: u64, current: u64, elapsed: Nanos) -> u128 {
+        let window = self.quota.window().as_u64() as u128;
+        let overlap = window - elapsed.as_u64() as u128;
+        previous as u128 * overlap + current as u128 * window
+    }
+
+    /// The earliest time into a window starting with `(previous, current)`
+    /// at which `n` more permits are admitted, or `None` if they are not
+    /// admitted anywhere in that window.
+    fn admitted_from(&self, previous: u64, current: u64, n: u64) -> Option<Nanos> {
+        if current.saturating_add(n) > self.quota.allowed() {
+            return None;
+        }
+        if previous == 0 {
+            return Some(Nanos::ZERO);
+        }
+        let window = self.quota.window().as_u64() as u128;
+        let headroom = (self.quota.allowed() - current - n) as u128 * window;
+        let max_overlap = (headroom / previous as u128).min(window);
+        Some(Nanos::new((window - max_overlap) as u64))
+    }
+
+    fn window_start(&self, index: u64) -> C::Instant {
+        self.start + self.window_offset(index)
+    }
+
+    fn window_offset(&self, index: u64) -> Nanos {
+        self.quota.window().saturating_mul(index)
+    }
+
+    fn take_at(&mut self, n: u64, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        // A clock stepped back, or an older event time, sees no time pass.
+        let now = now.max(self.last_update);
+        self.last_update = now;
+        let (index, previous, current, elapsed) = self.counts_at(now);
+        self.window_index = index;
+        self.previous = previous;
+        self.current = current;
+
+        let limit = self.quota.allowed() as u128 * self.quota.window().as_u64() as u128;
+        if self.scaled_estimate(previous, current + n, elapsed) <= limit {
+            self.current += n;
+            return Ok(());
+        }
+        let earliest = self.admitted_after(now, index, previous, current, n);
+        Err(NotUntil::after(
+            self.start,
+            earliest,
+            self.quota,
+            self.remaining_at(now),
+        ))
+    }
+
+    /// How long after the state's start `n` more permits are admitted,
+    /// given the counts of window `index` at `now`.
+    fn admitted_after(
+        &self,
+        now: C::Instant,
+        index: u64,
+        previous: u64,
+        current: u64,
+        n: u64,
+    ) -> Nanos {
+        if self.quota.allowed() == 0 {
+            return now
+                .duration_since(self.start)
+                .saturating_add(self.quota.window());
+        }
+        match self.admitted_from(previous, current, n) {
+            Some(at) => self.window_offset(index).saturating_add(at),
+            None => {
+                let at = self
+                    .admitted_from(current, 0, n)
+                    .expect("an empty window admits up to `allowed`");
+                self.window_offset(index + 1).saturating_add(at)
+            }
+        }
+    }
+}
+
+impl<C: Clock> Algorithm<C> for SlidingWindowState<C> {
+    fn from_quota(quota: Quota, clock: C) -> Self {
+        Self {
+            quota,
+            start: clock.now(),
+            last_update: clock.now(),
+            window_index: 0,
+            previous: 0,
+            current: 0,
+            clock,
+        }
+    }
+
+    fn fresh(&self) -> Self {
+        Self::from_quota(self.quota, self.clock.clone())
+    }
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    fn capacity(&self) -> u64 {
+        self.quota.allowed()
+    }
+
+    fn try_acquire_at(&mut self, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.take_at(1, now)
+    }
+
+    fn try_acquire_n_at(
+        &mut self,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        if n > self.quota.allowed() {
+            return Err(InsufficientCapacity(self.quota.allowed()));
+        }
+        Ok(self.take_at(n, now))
+    }
+
+    fn check_at(&self, now: C::Instant) -> Result<u64, NotUntil<C::Instant>> {
+        match self.remaining_at(now) {
+            0 => {
+                let (index, previous, current, _) = self.counts_at(now);
+                let earliest = self.admitted_after(now, index, previous, current, 1);
+                Err(NotUntil::after(self.start, earliest, self.quota, 0))
+            }
+            remaining => Ok(remaining),
+        }
+    }
+
+    fn remaining_at(&self, now: C::Instant) -> u64 {
+        let (_, previous, current, elapsed) = self.counts_at(now);
+        let window = self.quota.window().as_u64() as u128;
+        let limit = self.quota.allowed() as u128 * window;
+        let headroom = limit.saturating_sub(self.scaled_estimate(previous, 0, elapsed));
+        let admitted = (headroom / window) as u64;
+        admitted.saturating_sub(current)
+    }
+
+    fn reset_after_at(&self, now: C::Instant) -> Nanos {
+        let (index, previous, current, _) = self.counts_at(now);
+        let recovered_at = if current > 0 {
+            self.window_start(index + 2)
+        } else if previous > 0 {
+            self.window_start(index + 1)
+        } else {
+            return Nanos::ZERO;
+        };
+        recovered_at.duration_since(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        clock::{FakeAbsoluteClock, FakeRelativeClock},
+        limiter::RateLimiter,
+        scenario::{self, checked_acquire, max_accepted_in_any_window, op_strategy, run_scenario},
+    };
+
+    #[test]
+    fn test_sliding_window_weights_previous_window() {
+        let clock = FakeRelativeClock::default();
+        let mut state = SlidingWindowState::new(Nanos::new(1_000_000_000), 10, clock.clone());
+
+        // 第一个窗口末尾用满配额
+        clock.advance(Duration::from_millis(900));
+        for _ in 0..10 {
+            assert!(state.acquire().is_ok());
+        }
+        assert!(state.acquire().is_err());
+
+        // 新窗口开始 250ms，上一个窗口仍有 75% 的权重：7.5 + 2 <= 10
+        clock.advance(Duration::from_millis(350));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+        assert_eq!(state.remaining_at(clock.now()), 0);
+
+        // 再过 200ms，权重降到 55%：5.5 + 4 <= 10，还能放行 2 个
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(state.remaining_at(clock.now()), 2);
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_sliding_window_clock_stepped_back() {
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(100));
+        let mut state = SlidingWindowState::new(Nanos::new(1_000_000_000), 2, clock.clone());
+        clock.advance(Duration::from_millis(5_500));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+
+        // 时钟回拨后窗口不会回退，追上之后仍在同一个窗口里
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_millis(100_500));
+        assert!(state.acquire().is_err());
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_millis(105_600));
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_sliding_window_not_until() {
+        let clock = FakeRelativeClock::default();
+        let mut state = SlidingWindowState::new(Nanos::new(1_000_000_000), 4, clock.clone());
+        for _ in 0..4 {
+            assert!(state.acquire().is_ok());
+        }
+
+        // 当前窗口已满，下一个窗口中上个窗口的权重需降到 75%
+        let not_until = state.acquire().unwrap_err();
+        assert_eq!(not_until.earliest_possible(), Nanos::new(1_250_000_000));
+        clock.advance(Duration::from_nanos(1_249_999_999));
+        assert!(state.acquire().is_err());
+        clock.advance(Duration::from_nanos(1));
+        assert!(state.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_sliding_window_reset_after_and_idle() {
+        let clock = FakeRelativeClock::default();
+        let mut state = SlidingWindowState::new(Nanos::new(1_000_000_000), 4, clock.clone());
+        assert!(state.is_idle_at(clock.now()));
+
+        clock.advance(Duration::from_millis(300));
+        assert!(state.acquire().is_ok());
+        assert_eq!(state.reset_after_at(clock.now()), Nanos::new(1_700_000_000));
+
+        clock.advance(Duration::from_millis(1_000));
+        assert_eq!(state.reset_after_at(clock.now()), Nanos::new(700_000_000));
+        assert_eq!(state.remaining_at(clock.now()), 3);
+
+        clock.advance(Duration::from_millis(700));
+        assert!(state.is_idle_at(clock.now()));
+        assert_eq!(state.remaining_at(clock.now()), 4);
+    }
+
+    #[test]
+    fn test_sliding_window_acquire_n() {
+        let clock = FakeRelativeClock::default();
+        let mut state = SlidingWindowState::new(Nanos::new(1_000_000_000), 4, clock.clone());
+        assert_eq!(state.acquire_n(3), Ok(Ok(())));
+
+        // 当前窗口只剩 1 个；下一个窗口中上个窗口权重需降到 1/3 才能放行 3 个
+        let not_until = state.acquire_n(3).unwrap().unwrap_err();
+        assert_eq!(not_until.earliest_possible(), Nanos::new(1_666_666_667));
+        assert!(state.acquire().is_ok());
+
+        assert_eq!(state.acquire_n(5), Err(InsufficientCapacity(4)));
+    }
+
+    #[test]
+    fn test_sliding_window_zero_allowed() {
+        let clock = FakeRelativeClock::default();
+        let mut state = SlidingWindowState::new(Nanos::new(1_000_000_000), 0, clock.clone());
+        assert!(state.acquire().is_err());
+        clock.advance(Duration::from_secs(10));
+        assert!(state.acquire().is_err());
+        assert_eq!(state.remaining_at(clock.now()), 0);
+    }
+
+    #[test]
+    fn test_sliding_window_in_rate_limiter() {
+        let clock = FakeRelativeClock::default();
+        let base = SlidingWindowState::new(Nanos::new(1_000_000_000), 1, clock.clone());
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", (2, Duration::from_secs(1)));
+
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_err());
+
+        // 窗口边界处不会立即放行 2 倍的请求
+        clock.advance(Duration::from_secs(1));
+        assert!(limiter.acquire_by_key("user").is_err());
+    }
+
+    proptest! {
+        #![proptest_config(scenario::config())]
+
+        #[test]
+        fn prop_sliding_window_admits_less_than_twice_allowed(
+            allowed in 0..10u64,
+            window_ms in 1..1_000u64,
+            ops in prop::collection::vec(op_strategy(), 0..500),
+        ) {
+            let clock = FakeRelativeClock::default();
+            let window = Nanos::new(window_ms * 1_000_000);
+            let mut state = SlidingWindowState::new(window, allowed, clock.clone());
+            let accepted = run_scenario(&ops, &clock, || checked_acquire(&mut state));
+
+            // 近似算法不保证严格不超过 allowed，但每个固定窗口内不会超过 allowed，
+            // 且上一个窗口的权重不会提前归零，因此不会达到固定窗口的 2 倍突发
+            let max = max_accepted_in_any_window(&accepted, window);
+            prop_assert!(max <= (2 * allowed).saturating_sub(1));
+        }
+    }
+}
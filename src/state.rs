@@ -0,0 +1,633 @@
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, MonotonicClock, Reference},
+    error::InsufficientCapacity,
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+    store::StoredState,
+};
+
+#[derive(Debug)]
+pub struct State<C: Clock> {
+    last_update: C::Instant,
+    acquired: u64,
+    quota: Quota,
+    clock: C,
+    #[cfg(feature = "tokio")]
+    capacity: CapacitySignal,
+}
+
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+struct CapacitySignal {
+    sender: tokio::sync::watch::Sender<bool>,
+    refresh: Option<tokio::task::AbortHandle>,
+}
+
+impl<C: Clock> State<C> {
+    /// Creates a fixed window admitting `quota.allowed()` permits per
+    /// `quota.window()`; the quota's burst is ignored.
+    pub fn new(quota: impl Into<Quota>, clock: C) -> Self {
+        let quota = quota.into();
+        Self {
+            last_update: clock.now(),
+            acquired: 0,
+            quota,
+            clock,
+            #[cfg(feature = "tokio")]
+            capacity: CapacitySignal {
+                sender: tokio::sync::watch::Sender::new(quota.allowed() > 0),
+                refresh: None,
+            },
+        }
+    }
+
+    /// Consumes a permit, or on denial reports when the current window ends.
+    /// For a state that allows no permits this is only the next window
+    /// boundary, at which the request will be denied again.
+    pub fn acquire(&mut self) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.clock.now();
+        self.try_acquire_at(now)
+    }
+
+    /// Consumes `n` permits from the current window, or none of them. On
+    /// denial reports when the current window ends, since a fresh window can
+    /// always grant `n` permits.
+    pub fn acquire_n(
+        &mut self,
+        n: u64,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        let now = self.clock.now();
+        self.try_acquire_n_at(n, now)
+    }
+
+    /// The quota the state was configured with.
+    pub fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    /// The number of permits left in the current window.
+    pub fn remaining(&self) -> u64 {
+        self.remaining_at(self.clock.now())
+    }
+
+    /// When the current window ends and its permits are restored.
+    pub fn resets_at(&self) -> C::Instant {
+        let now = self.clock.now();
+        now + self.reset_after_at(now)
+    }
+
+    /// Returns a receiver reflecting whether a permit is currently available.
+    ///
+    /// The value is updated on every `acquire`. When the state becomes
+    /// exhausted inside a tokio runtime, a background task flips it back to
+    /// `true` once the window resets; the refresh is scheduled on the tokio
+    /// timer (millisecond granularity) using the remaining window length as
+    /// seen by this state's clock. Outside a runtime the signal only changes
+    /// on `acquire`.
+    #[cfg(feature = "tokio")]
+    pub fn capacity_signal(&self) -> tokio::sync::watch::Receiver<bool> {
+        self.capacity.sender.subscribe()
+    }
+
+    #[cfg(feature = "tokio")]
+    fn publish_capacity(&mut self, now: C::Instant) {
+        let available = self.remaining_at(now) > 0;
+        let changed = self.capacity.sender.send_if_modified(|current| {
+            let changed = *current != available;
+            *current = available;
+            changed
+        });
+        if !changed {
+            return;
+        }
+
+        if let Some(refresh) = self.capacity.refresh.take() {
+            refresh.abort();
+        }
+        if available || self.quota.allowed() == 0 {
+            return;
+        }
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            let sender = self.capacity.sender.clone();
+            let delay: std::time::Duration = self.reset_after_at(now).into();
+            let task = handle.spawn(async move {
+                tokio::time::sleep(delay).await;
+                sender.send_replace(true);
+            });
+            self.capacity.refresh = Some(task.abort_handle());
+        }
+    }
+
+    /// Verifies the internal bookkeeping in debug builds; compiles out in release.
+    fn check_invariants(&self, now: C::Instant) {
+        debug_assert!(
+            self.acquired <= self.quota.allowed(),
+            "acquired {} exceeds allowed {}",
+            self.acquired,
+            self.quota.allowed()
+        );
+        debug_assert!(
+            self.last_update <= now,
+            "last update {:?} is in the future of {:?}",
+            self.last_update,
+            now
+        );
+    }
+
+    /// Projects when the `k`-th additional permit becomes grantable, assuming
+    /// no other acquisitions happen in the meantime.
+    ///
+    /// Fixed windows release permits as a step function: permits
+    /// `1..=remaining` are available now, the next `allowed` permits at the
+    /// end of the current window, the batch after that one window later, and
+    /// so on. Returns `None` if the state allows no permits at all.
+    pub fn available_at(&self, k: u64) -> Option<C::Instant> {
+        let now = self.clock.now();
+        let remaining = self.remaining_at(now);
+        if k <= remaining {
+            return Some(now);
+        }
+        if self.quota.allowed() == 0 {
+            return None;
+        }
+
+        let window_start = if self.window_expired_at(now) {
+            now
+        } else {
+            self.last_update
+        };
+        let windows_ahead = (k - remaining - 1) / self.quota.allowed() + 1;
+        window_start.checked_add(self.quota.window().checked_mul(windows_ahead)?)
+    }
+
+    fn window_expired_at(&self, now: C::Instant) -> bool {
+        now.duration_since(self.last_update) >= self.quota.window()
+    }
+
+    fn take_at(&mut self, n: u64, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        // A clock stepped back, or an older event time, sees no time pass.
+        let now = now.max(self.last_update);
+        if self.window_expired_at(now) {
+            self.last_update = now;
+            self.acquired = 0;
+        }
+        let result = if self.quota.allowed() - self.acquired >= n {
+            self.acquired += n;
+            Ok(())
+        } else {
+            Err(NotUntil::after(
+                self.last_update,
+                self.quota.window(),
+                self.quota,
+                self.remaining_at(now),
+            ))
+        };
+        self.check_invariants(now);
+        #[cfg(feature = "tokio")]
+        self.publish_capacity(now);
+        result
+    }
+}
+
+/// The [snapshot](StoredState::Snapshot) of a [`State`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WindowSnapshot<P> {
+    /// When the current window started.
+    pub start: P,
+    /// The permits granted in the current window.
+    pub acquired: u64,
+}
+
+impl<C: Clock> StoredState<C> for State<C> {
+    type Snapshot = WindowSnapshot<C::Instant>;
+
+    fn snapshot(&self) -> Self::Snapshot {
+        WindowSnapshot {
+            start: self.last_update,
+            acquired: self.acquired,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Self::Snapshot) {
+        // Another process's clock may run slightly ahead of ours.
+        self.last_update = snapshot.start.min(self.clock.now());
+        self.acquired = snapshot.acquired.min(self.quota.allowed());
+    }
+}
+
+impl<C: Clock> Algorithm<C> for State<C> {
+    fn from_quota(quota: Quota, clock: C) -> Self {
+        Self::new(quota, clock)
+    }
+
+    fn fresh(&self) -> Self {
+        Self::new(self.quota, self.clock.clone())
+    }
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    fn capacity(&self) -> u64 {
+        self.quota.allowed()
+    }
+
+    fn try_acquire_at(&mut self, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.take_at(1, now)
+    }
+
+    fn try_acquire_n_at(
+        &mut self,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        if n > self.quota.allowed() {
+            return Err(InsufficientCapacity(self.quota.allowed()));
+        }
+        Ok(self.take_at(n, now))
+    }
+
+    fn check_at(&self, now: C::Instant) -> Result<u64, NotUntil<C::Instant>> {
+        let remaining = self.remaining_at(now);
+        if remaining > 0 {
+            return Ok(remaining);
+        }
+        let window_start = if self.window_expired_at(now) {
+            now
+        } else {
+            self.last_update
+        };
+        Err(NotUntil::after(
+            window_start,
+            self.quota.window(),
+            self.quota,
+            0,
+        ))
+    }
+
+    fn remaining_at(&self, now: C::Instant) -> u64 {
+        if self.window_expired_at(now) {
+            self.quota.allowed()
+        } else {
+            self.quota.allowed().saturating_sub(self.acquired)
+        }
+    }
+
+    fn reset_after_at(&self, now: C::Instant) -> Nanos {
+        self.quota
+            .window()
+            .saturating_sub(now.duration_since(self.last_update))
+    }
+}
+
+impl State<MonotonicClock> {
+    pub fn per_second(max_burst: u64) -> Self {
+        Self::new(Quota::per_second(max_burst), MonotonicClock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        clock::{FakeAbsoluteClock, FakeRelativeClock},
+        scenario::{self, checked_acquire, max_accepted_in_any_window, op_strategy, run_scenario},
+    };
+
+    #[test]
+    fn test_state() {
+        let mut state = State::per_second(1);
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_state_reset_after_duration() {
+        let mut state = State::new(Quota::new(2, Nanos::new(100_000_000)), MonotonicClock); // 100ms 内允许2次
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err()); // 第3次应该失败
+
+        std::thread::sleep(std::time::Duration::from_millis(101));
+        assert!(state.acquire().is_ok()); // 窗口重置后应该成功
+    }
+
+    #[test]
+    fn test_state_zero_allowed() {
+        let mut state = State::per_second(0);
+        assert!(state.acquire().is_err()); // 应该一直失败
+    }
+
+    // 使用 FakeRelativeClock 的测试
+
+    #[test]
+    fn test_state_with_fake_clock_basic() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(2, Nanos::new(1_000_000_000)), clock.clone()); // 1秒内允许2次
+
+        // 第一次和第二次应该成功
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+
+        // 第三次应该失败（配额用完）
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_state_clock_stepped_back() {
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(100));
+        let mut state = State::new(Quota::per_second(2), clock.clone());
+        clock.advance(Duration::from_millis(500));
+        assert!(state.acquire().is_ok());
+
+        // 时钟回拨后不算时间流逝，窗口也不会提前重置
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_secs(50));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_secs(101));
+        assert!(state.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_state_introspection() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::per_second(2), clock.clone());
+        assert_eq!(state.quota(), Quota::per_second(2));
+        assert_eq!(
+            (state.remaining(), state.resets_at()),
+            (2, Nanos::from_secs(1))
+        );
+
+        clock.advance(Duration::from_millis(300));
+        state.acquire().unwrap();
+        assert_eq!(state.remaining(), 1);
+        assert_eq!(state.resets_at(), Nanos::from_secs(1));
+
+        // 窗口结束后配额回满
+        clock.advance(Duration::from_millis(700));
+        assert_eq!(
+            (state.remaining(), state.resets_at()),
+            (2, Nanos::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn test_state_with_fake_clock_time_window_reset() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(3, Nanos::new(1_000_000_000)), clock.clone()); // 1秒内允许3次
+
+        // 用完配额
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+
+        // 推进时间 0.5 秒，还不够重置
+        clock.advance(std::time::Duration::from_millis(500));
+        assert!(state.acquire().is_err());
+
+        // 再推进 0.5 秒，总共1秒，窗口应该重置
+        clock.advance(std::time::Duration::from_millis(500));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_state_with_fake_clock_multiple_resets() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(1, Nanos::new(100_000_000)), clock.clone()); // 100ms 内允许1次
+
+        // 第一个窗口
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+
+        // 推进 100ms，重置窗口
+        clock.advance(std::time::Duration::from_millis(100));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+
+        // 再推进 100ms，再次重置窗口
+        clock.advance(std::time::Duration::from_millis(100));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_state_with_fake_clock_precise_timing() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(5, Nanos::new(1_000_000_000)), clock.clone()); // 1秒内允许5次
+
+        // 快速使用3次配额
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+
+        // 推进 0.9 秒（还不到1秒）
+        clock.advance(std::time::Duration::from_millis(900));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err()); // 配额用完
+
+        // 推进 0.2 秒（总共超过1秒），窗口重置
+        clock.advance(std::time::Duration::from_millis(200));
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_err()); // 新窗口的配额也用完
+    }
+
+    #[test]
+    fn test_state_with_fake_clock_burst_control() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(10, Nanos::new(500_000_000)), clock.clone()); // 500ms 内允许10次
+
+        // 用完所有配额
+        for _ in 0..10 {
+            assert!(state.acquire().is_ok());
+        }
+        assert!(state.acquire().is_err());
+
+        // 推进时间到窗口边界
+        clock.advance(std::time::Duration::from_millis(500));
+
+        // 新窗口，配额恢复
+        for _ in 0..10 {
+            assert!(state.acquire().is_ok());
+        }
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_state_with_fake_clock_zero_allowed() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(0, Nanos::new(1_000_000_000)), clock.clone()); // 不允许任何请求
+
+        assert!(state.acquire().is_err());
+
+        // 即使推进时间也不应该允许
+        clock.advance(std::time::Duration::from_secs(10));
+        assert!(state.acquire().is_err());
+    }
+
+    #[test]
+    fn test_state_invariants_hold_over_many_operations() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(7, Nanos::new(100_000_000)), clock.clone());
+
+        let mut granted = 0;
+        for i in 0..10_000u64 {
+            if state.acquire().is_ok() {
+                granted += 1;
+            }
+            state.check_invariants(clock.now());
+            clock.advance(Duration::from_millis(i % 13));
+        }
+        assert!(granted > 0);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(start_paused = true)]
+    async fn test_capacity_signal_flips_on_exhaustion_and_reset() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(2, Nanos::new(1_000_000_000)), clock.clone());
+        let mut signal = state.capacity_signal();
+        assert!(*signal.borrow_and_update());
+
+        assert!(state.acquire().is_ok());
+        assert!(!signal.has_changed().unwrap());
+
+        // 配额用完，信号变为 false
+        assert!(state.acquire().is_ok());
+        assert!(signal.has_changed().unwrap());
+        assert!(!*signal.borrow_and_update());
+
+        // 窗口重置后，后台任务把信号恢复为 true
+        clock.advance(Duration::from_secs(1));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        signal.changed().await.unwrap();
+        assert!(*signal.borrow_and_update());
+        assert!(state.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_available_at_within_current_window() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(3, Nanos::new(1_000_000_000)), clock.clone());
+        assert!(state.acquire().is_ok());
+        clock.advance(Duration::from_millis(400));
+
+        // 剩余 2 个许可，立即可用
+        let now = clock.now();
+        assert_eq!(state.available_at(0), Some(now));
+        assert_eq!(state.available_at(1), Some(now));
+        assert_eq!(state.available_at(2), Some(now));
+    }
+
+    #[test]
+    fn test_available_at_spills_into_next_windows() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(3, Nanos::new(1_000_000_000)), clock.clone());
+        assert!(state.acquire().is_ok());
+        assert!(state.acquire().is_ok());
+        clock.advance(Duration::from_millis(400));
+
+        // 第 2 到 4 个许可在窗口重置时可用，第 5 个在再下一个窗口
+        assert_eq!(state.available_at(2), Some(Nanos::new(1_000_000_000)));
+        assert_eq!(state.available_at(4), Some(Nanos::new(1_000_000_000)));
+        assert_eq!(state.available_at(5), Some(Nanos::new(2_000_000_000)));
+
+        // 窗口过期后，新窗口从现在开始
+        clock.advance(Duration::from_millis(1_100));
+        let now = clock.now();
+        assert_eq!(state.available_at(3), Some(now));
+        assert_eq!(state.available_at(4), Some(now + Nanos::new(1_000_000_000)));
+    }
+
+    #[test]
+    fn test_available_at_zero_allowed() {
+        let clock = FakeRelativeClock::default();
+        let state = State::new(Quota::new(0, Nanos::new(1_000_000_000)), clock);
+        assert_eq!(state.available_at(1), None);
+    }
+
+    #[test]
+    fn test_acquire_not_until() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::new(2, Nanos::new(1_000_000_000)), clock.clone());
+        assert!(state.acquire().is_ok());
+        clock.advance(Duration::from_millis(300));
+        assert!(state.acquire().is_ok());
+
+        let not_until = state.acquire().unwrap_err();
+        let now = clock.now();
+        assert_eq!(not_until.quota(), Quota::per_second(2));
+        assert_eq!(not_until.remaining(), 0);
+        assert_eq!(not_until.earliest_possible(), Nanos::new(1_000_000_000));
+        assert_eq!(
+            not_until.wait_time_from(now),
+            Duration::from(state.reset_after_at(now))
+        );
+        assert_eq!(not_until.wait_time_from(now), Duration::from_millis(700));
+
+        clock.advance(not_until.wait_time_from(now));
+        assert!(state.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_acquire_n_all_or_nothing() {
+        let clock = FakeRelativeClock::default();
+        let mut state = State::new(Quota::per_second(5), clock.clone());
+        assert_eq!(state.acquire_n(3), Ok(Ok(())));
+
+        // 剩余 2 个，请求 3 个时一个都不消耗
+        clock.advance(Duration::from_millis(400));
+        let not_until = state.acquire_n(3).unwrap().unwrap_err();
+        assert_eq!(
+            not_until.wait_time_from(clock.now()),
+            Duration::from_millis(600)
+        );
+        assert_eq!(state.acquire_n(2), Ok(Ok(())));
+        assert_eq!(state.acquire_n(0), Ok(Ok(())));
+        assert!(state.acquire().is_err());
+
+        assert_eq!(state.acquire_n(6), Err(InsufficientCapacity(5)));
+        clock.advance(Duration::from_millis(600));
+        assert_eq!(state.acquire_n(5), Ok(Ok(())));
+    }
+
+    // 基于 proptest 的随机场景测试
+
+    proptest! {
+        #![proptest_config(scenario::config())]
+
+        #[test]
+        fn prop_fixed_window_admits_at_most_twice_allowed(
+            allowed in 0..10u64,
+            window_ms in 1..1_000u64,
+            ops in prop::collection::vec(op_strategy(), 0..500),
+        ) {
+            let clock = FakeRelativeClock::default();
+            let window = Nanos::new(window_ms * 1_000_000);
+            let mut state = State::new(Quota::new(allowed, window), clock.clone());
+            let accepted = run_scenario(&ops, &clock, || checked_acquire(&mut state));
+
+            // 固定窗口在窗口边界两侧各用满配额时，最多出现 2 倍突发
+            prop_assert!(max_accepted_in_any_window(&accepted, window) <= 2 * allowed);
+        }
+    }
+}
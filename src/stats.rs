@@ -0,0 +1,417 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{error::AcquireError, hooks::Hooks, quota::Quota};
+
+/// [`Hooks`] that count a limiter's decisions, to tell how often requests
+/// are actually limited without external tooling.
+///
+/// Totals accumulate from creation or the last [`reset`](Self::reset), for
+/// the base state and every key together and for each key on its own.
+/// Clones share their totals, so keep one to read them after handing
+/// another to [`with_hooks`](crate::RateLimiter::with_hooks).
+///
+/// ```
+/// use ratelimit::{DecisionStats, MonotonicClock, Quota, RateLimiter};
+///
+/// let stats = DecisionStats::new();
+/// let limiter = RateLimiter::new(Quota::per_second(100), MonotonicClock)
+///     .with_default_key_quota(Quota::per_second(1))
+///     .with_hooks(stats.clone());
+///
+/// limiter.acquire_by_key("user")?;
+/// assert!(limiter.acquire_by_key("user").is_err());
+///
+/// let totals = stats.take();
+/// assert_eq!(totals.acceptance_ratio(), 0.5);
+/// assert_eq!(totals.keys["user"].denied, 1);
+/// # Ok::<(), ratelimit::AcquireError>(())
+/// ```
+pub struct DecisionStats<K: Hash + Eq = String> {
+    totals: Arc<Mutex<DecisionTotals<K>>>,
+}
+
+impl<K: Hash + Eq + Clone> DecisionStats<K> {
+    pub fn new() -> Self {
+        Self {
+            totals: Arc::new(Mutex::new(DecisionTotals::default())),
+        }
+    }
+
+    /// The totals so far.
+    pub fn totals(&self) -> DecisionTotals<K> {
+        self.lock().clone()
+    }
+
+    /// The totals for `key` so far, if it was ever acquired for.
+    pub fn key_totals(&self, key: &K) -> Option<KeyTotals> {
+        self.lock().keys.get(key).copied()
+    }
+
+    /// Starts the totals over from zero.
+    pub fn reset(&self) {
+        self.take();
+    }
+
+    /// The totals so far, starting them over from zero, so that no decision
+    /// made in between is missed or counted twice.
+    pub fn take(&self) -> DecisionTotals<K> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, DecisionTotals<K>> {
+        self.totals.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<K: Hash + Eq + Clone> Default for DecisionStats<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq> Clone for DecisionStats<K> {
+    fn clone(&self) -> Self {
+        Self {
+            totals: Arc::clone(&self.totals),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone + Send> Hooks<K> for DecisionStats<K> {
+    fn on_allowed(&self, key: Option<&K>, _quota: Option<Quota>, permits: u64) {
+        let mut totals = self.lock();
+        totals.allowed += 1;
+        totals.permits += permits;
+        if let Some(key) = key {
+            let key = totals.key(key);
+            key.allowed += 1;
+            key.permits += permits;
+        }
+    }
+
+    fn on_denied(&self, key: Option<&K>, _quota: Option<Quota>, _err: &AcquireError) {
+        let mut totals = self.lock();
+        totals.denied += 1;
+        if let Some(key) = key {
+            totals.key(key).denied += 1;
+        }
+    }
+}
+
+impl<K: Hash + Eq> fmt::Debug for DecisionStats<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecisionStats").finish_non_exhaustive()
+    }
+}
+
+/// Decision counts gathered by [`DecisionStats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct DecisionTotals<K: Hash + Eq = String> {
+    /// Requests granted, by the base state or any key.
+    pub allowed: u64,
+    /// Requests denied or failed.
+    pub denied: u64,
+    /// Permits granted, which exceeds `allowed` for requests of several.
+    pub permits: u64,
+    /// The totals of every key acquired for.
+    pub keys: HashMap<K, KeyTotals>,
+}
+
+impl<K: Hash + Eq> DecisionTotals<K> {
+    /// Requests decided, granted or not.
+    pub fn acquires(&self) -> u64 {
+        self.allowed + self.denied
+    }
+
+    /// The share of requests granted, or 1 if none were decided.
+    pub fn acceptance_ratio(&self) -> f64 {
+        ratio(self.allowed, self.denied)
+    }
+
+    /// How many distinct keys were acquired for.
+    pub fn distinct_keys(&self) -> usize {
+        self.keys.len()
+    }
+}
+
+impl<K: Hash + Eq + Clone> DecisionTotals<K> {
+    fn key(&mut self, key: &K) -> &mut KeyTotals {
+        if !self.keys.contains_key(key) {
+            self.keys.insert(key.clone(), KeyTotals::default());
+        }
+        self.keys.get_mut(key).expect("key was just inserted")
+    }
+}
+
+impl<K: Hash + Eq> Default for DecisionTotals<K> {
+    fn default() -> Self {
+        Self {
+            allowed: 0,
+            denied: 0,
+            permits: 0,
+            keys: HashMap::new(),
+        }
+    }
+}
+
+/// Decision counts of one key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct KeyTotals {
+    pub allowed: u64,
+    pub denied: u64,
+    pub permits: u64,
+}
+
+impl KeyTotals {
+    /// The share of the key's requests granted, or 1 if none were decided.
+    pub fn acceptance_ratio(&self) -> f64 {
+        ratio(self.allowed, self.denied)
+    }
+}
+
+fn ratio(allowed: u64, denied: u64) -> f64 {
+    match allowed + denied {
+        0 => 1.0,
+        total => allowed as f64 / total as f64,
+    }
+}
+
+/// [`Hooks`] that record how long waits took, in buckets, to tell whether
+/// quotas are too tight for the callers waiting on them.
+///
+/// A wait is an async `until_ready` or one of its variants, or a blocking
+/// [`acquire_wait`](crate::RateLimiter::acquire_wait), timed until it is
+/// granted or gives up. Each bucket counts the waits longer than the bound
+/// before it and at most its own; waits longer than every bound are counted
+/// apart. Clones share their counts.
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use ratelimit::{MonotonicClock, Quota, RateLimiter, WaitHistogram};
+///
+/// let waits = WaitHistogram::new([Duration::from_millis(10), Duration::from_millis(100)]);
+/// let limiter = RateLimiter::new(Quota::per_second(100), MonotonicClock)
+///     .with_hooks(waits.clone());
+///
+/// limiter.acquire_wait(None)?;
+/// let snapshot = waits.snapshot();
+/// assert_eq!(snapshot.count, 1);
+/// assert_eq!(snapshot.quantile(0.99), Some(Duration::from_millis(10)));
+/// # Ok::<(), ratelimit::AcquireError>(())
+/// ```
+#[derive(Clone)]
+pub struct WaitHistogram {
+    inner: Arc<Histogram>,
+}
+
+struct Histogram {
+    bounds: Vec<Duration>,
+    /// One count per bound, then the count of longer waits.
+    counts: Vec<AtomicU64>,
+    sum: AtomicU64,
+}
+
+impl WaitHistogram {
+    /// Buckets waits by the upper `bounds`, in any order.
+    pub fn new(bounds: impl IntoIterator<Item = Duration>) -> Self {
+        let mut bounds: Vec<_> = bounds.into_iter().collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+        let counts = (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect();
+        Self {
+            inner: Arc::new(Histogram {
+                bounds,
+                counts,
+                sum: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Records a wait that took `waited`.
+    pub fn record(&self, waited: Duration) {
+        let inner = &*self.inner;
+        let bucket = inner.bounds.partition_point(|bound| *bound < waited);
+        inner.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        let nanos = u64::try_from(waited.as_nanos()).unwrap_or(u64::MAX);
+        inner.sum.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    /// The counts so far. Waits recorded meanwhile may be missing from some
+    /// buckets or the sum.
+    pub fn snapshot(&self) -> WaitHistogramSnapshot {
+        let inner = &*self.inner;
+        let counts: Vec<u64> = inner
+            .counts
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect();
+        WaitHistogramSnapshot {
+            buckets: inner
+                .bounds
+                .iter()
+                .copied()
+                .zip(counts.iter().copied())
+                .collect(),
+            overflow: counts[inner.bounds.len()],
+            count: counts.iter().sum(),
+            sum: Duration::from_nanos(inner.sum.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Starts the counts over from zero.
+    pub fn reset(&self) {
+        for count in &self.inner.counts {
+            count.store(0, Ordering::Relaxed);
+        }
+        self.inner.sum.store(0, Ordering::Relaxed);
+    }
+}
+
+impl Default for WaitHistogram {
+    /// Buckets from 5ms to 10s, as Prometheus's default buckets do.
+    fn default() -> Self {
+        Self::new(
+            [5, 10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000].map(Duration::from_millis),
+        )
+    }
+}
+
+impl<K> Hooks<K> for WaitHistogram {
+    fn on_waited(&self, _key: Option<&K>, waited: Duration) {
+        self.record(waited);
+    }
+}
+
+impl fmt::Debug for WaitHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WaitHistogram")
+            .field("bounds", &self.inner.bounds)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Wait counts recorded by a [`WaitHistogram`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct WaitHistogramSnapshot {
+    /// Each upper bound with the waits counted in its bucket.
+    pub buckets: Vec<(Duration, u64)>,
+    /// Waits longer than every bound.
+    pub overflow: u64,
+    /// All waits recorded.
+    pub count: u64,
+    /// How long all waits took together.
+    pub sum: Duration,
+}
+
+impl WaitHistogramSnapshot {
+    /// The bound of the bucket holding the `q`-quantile wait, e.g. `0.99` for
+    /// one that 99% of waits took at most, or `None` if there are no waits or
+    /// it is longer than every bound.
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for &(bound, count) in &self.buckets {
+            seen += count;
+            if seen >= rank {
+                return Some(bound);
+            }
+        }
+        None
+    }
+
+    /// The average wait, or `None` if there are none.
+    pub fn mean(&self) -> Option<Duration> {
+        let count = u32::try_from(self.count).unwrap_or(u32::MAX);
+        (count > 0).then(|| self.sum / count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::FakeRelativeClock, limiter::RateLimiter};
+
+    #[test]
+    fn test_decision_stats() {
+        let stats = DecisionStats::new();
+        let limiter = RateLimiter::new(Quota::per_second(1), FakeRelativeClock::default())
+            .with_default_key_quota(Quota::per_second(3))
+            .with_hooks(stats.clone());
+
+        assert_eq!(stats.totals().acceptance_ratio(), 1.0);
+        limiter.acquire().unwrap();
+        assert!(limiter.acquire().is_err());
+        limiter.acquire_n_by_key("a", 3).unwrap();
+        assert!(limiter.acquire_by_key("a").is_err());
+        limiter.acquire_by_key("b").unwrap();
+
+        let totals = stats.totals();
+        assert_eq!((totals.allowed, totals.denied, totals.permits), (3, 2, 5));
+        assert_eq!(totals.acquires(), 5);
+        assert_eq!(totals.acceptance_ratio(), 0.6);
+        assert_eq!(totals.distinct_keys(), 2);
+        assert_eq!(
+            stats.key_totals(&"a".to_owned()),
+            Some(KeyTotals {
+                allowed: 1,
+                denied: 1,
+                permits: 3
+            })
+        );
+        assert_eq!(totals.keys["b"].acceptance_ratio(), 1.0);
+
+        // 取出后从零开始
+        assert_eq!(stats.take(), totals);
+        assert_eq!(stats.totals(), DecisionTotals::default());
+        assert!(limiter.acquire_by_key("b").is_ok());
+        stats.reset();
+        assert_eq!(stats.key_totals(&"b".to_owned()), None);
+    }
+
+    #[test]
+    fn test_wait_histogram() {
+        let waits = WaitHistogram::new([Duration::from_secs(1), Duration::from_millis(100)]);
+        let clock = FakeRelativeClock::default();
+        let limiter =
+            RateLimiter::new(Quota::per_second(1), clock.clone()).with_hooks(waits.clone());
+
+        assert_eq!(waits.snapshot().quantile(0.5), None);
+        assert_eq!(waits.snapshot().mean(), None);
+        limiter.acquire_wait(None).unwrap();
+        // 假时钟的 sleep 会推进时间，第二次等待满一个窗口
+        limiter.acquire_wait(None).unwrap();
+        waits.record(Duration::from_millis(100));
+        waits.record(Duration::from_secs(5));
+
+        let snapshot = waits.snapshot();
+        assert_eq!(
+            snapshot.buckets,
+            [(Duration::from_millis(100), 2), (Duration::from_secs(1), 1)]
+        );
+        assert_eq!((snapshot.overflow, snapshot.count), (1, 4));
+        assert_eq!(snapshot.sum, Duration::from_millis(6_100));
+        assert_eq!(snapshot.mean(), Some(Duration::from_millis(1_525)));
+        assert_eq!(snapshot.quantile(0.5), Some(Duration::from_millis(100)));
+        assert_eq!(snapshot.quantile(0.75), Some(Duration::from_secs(1)));
+        assert_eq!(snapshot.quantile(0.99), None);
+
+        waits.reset();
+        assert_eq!(waits.snapshot().count, 0);
+    }
+}
@@ -0,0 +1,594 @@
+use std::{
+    borrow::Borrow, collections::HashMap, convert::Infallible, hash::Hash, marker::PhantomData,
+    sync::Arc,
+};
+#[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use dashmap::{DashMap, mapref::entry::Entry};
+
+use crate::{algorithm::Algorithm, clock::Clock, error::AcquireError, quota::Quota, state::State};
+
+/// How many times a [`StoredRateLimiter`], or a store-backed limiter
+/// generally, retries an update that lost a race before giving up.
+pub(crate) const MAX_ATTEMPTS: usize = 32;
+
+/// An [`Algorithm`] whose changing state can be taken apart from its
+/// configuration and clock, so that it can live in a [`StateStore`].
+pub trait StoredState<C: Clock>: Algorithm<C> {
+    /// What the state remembers between calls.
+    type Snapshot: Clone;
+
+    fn snapshot(&self) -> Self::Snapshot;
+
+    /// Replaces the state's history with `snapshot`, keeping its
+    /// configuration.
+    fn restore(&mut self, snapshot: Self::Snapshot);
+}
+
+/// A key's value in a [`StateStore`], with the version it was stored at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Versioned<V> {
+    pub value: V,
+    /// Changes on every update, so a writer can tell whether the value is
+    /// still the one it read.
+    pub version: u64,
+}
+
+/// Where the per-key states of a [`StoredRateLimiter`] live.
+///
+/// A store only holds values; the limiter reads a key's state, runs the
+/// algorithm on it locally and writes it back with
+/// [`compare_and_swap`](Self::compare_and_swap), retrying if another writer
+/// got there first. Any store offering a conditional write, such as a
+/// database or a key-value service, can therefore back a limiter shared by
+/// many processes.
+pub trait StateStore<K, V> {
+    type Error: std::error::Error;
+
+    /// The current value of `key`, if any.
+    fn load(&self, key: &K) -> Result<Option<Versioned<V>>, Self::Error>;
+
+    /// Stores `value` for `key` if the key is still at version `expected`,
+    /// or still absent for `None`, returning whether it was stored.
+    fn compare_and_swap(
+        &self,
+        key: &K,
+        expected: Option<u64>,
+        value: V,
+    ) -> Result<bool, Self::Error>;
+
+    /// Removes `key`, returning whether it was present.
+    fn remove(&self, key: &K) -> Result<bool, Self::Error>;
+}
+
+impl<K, V, St: StateStore<K, V> + ?Sized> StateStore<K, V> for Arc<St> {
+    type Error = St::Error;
+
+    fn load(&self, key: &K) -> Result<Option<Versioned<V>>, Self::Error> {
+        (**self).load(key)
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &K,
+        expected: Option<u64>,
+        value: V,
+    ) -> Result<bool, Self::Error> {
+        (**self).compare_and_swap(key, expected, value)
+    }
+
+    fn remove(&self, key: &K) -> Result<bool, Self::Error> {
+        (**self).remove(key)
+    }
+}
+
+/// What a store backed by a remote service does while the service can't be
+/// reached.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DegradedMode {
+    /// Fails every request with
+    /// [`StoreUnavailable`](AcquireError::StoreUnavailable).
+    #[default]
+    FailClosed,
+    /// Admits every request, as if each key were fresh.
+    FailOpen,
+    /// Limits each key in this process's memory until the service is back,
+    /// so every process enforces the quota on its own.
+    Local,
+}
+
+/// Told whether a remote store's service just became unreachable, `true`,
+/// or reachable again, `false`.
+#[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+type DegradedListener = Arc<dyn Fn(bool) + Send + Sync>;
+
+/// Answers for a remote store while its service is unreachable, as its
+/// [`DegradedMode`] says.
+#[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+pub(crate) struct Degraded<V> {
+    mode: DegradedMode,
+    local: MemoryStore<String, V>,
+    unreachable: AtomicBool,
+    listener: Option<DegradedListener>,
+}
+
+#[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+impl<V> Degraded<V> {
+    pub(crate) fn new(mode: DegradedMode) -> Self {
+        Self {
+            mode,
+            local: MemoryStore::new(),
+            unreachable: AtomicBool::new(false),
+            listener: None,
+        }
+    }
+
+    pub(crate) fn mode(&self) -> DegradedMode {
+        self.mode
+    }
+
+    pub(crate) fn set_mode(&mut self, mode: DegradedMode) {
+        self.mode = mode;
+    }
+
+    pub(crate) fn set_listener(&mut self, listener: DegradedListener) {
+        self.listener = Some(listener);
+    }
+
+    /// Records that the service answered, telling the listener if it was
+    /// unreachable until now.
+    pub(crate) fn reached(&self) {
+        self.set_unreachable(false);
+    }
+
+    fn set_unreachable(&self, unreachable: bool) {
+        if self.unreachable.swap(unreachable, Ordering::AcqRel) != unreachable
+            && let Some(listener) = &self.listener
+        {
+            listener(unreachable);
+        }
+    }
+
+    /// Answers `answer` for an operation whose remote part failed with
+    /// `err`, unless failing closed.
+    pub(crate) fn unreachable<T, E>(&self, answer: T, err: E) -> Result<T, E> {
+        self.set_unreachable(true);
+        match self.mode {
+            DegradedMode::FailClosed => Err(err),
+            DegradedMode::FailOpen | DegradedMode::Local => Ok(answer),
+        }
+    }
+}
+
+#[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+impl<V: Clone> Degraded<V> {
+    /// Loads `key` after the remote load failed with `err`.
+    pub(crate) fn load<E>(&self, key: &str, err: E) -> Result<Option<Versioned<V>>, E> {
+        self.set_unreachable(true);
+        match self.mode {
+            DegradedMode::FailClosed => Err(err),
+            DegradedMode::FailOpen => Ok(None),
+            DegradedMode::Local => {
+                let Ok(loaded) = self.local.load(&key.to_owned());
+                Ok(loaded)
+            }
+        }
+    }
+
+    /// Stores `value` for `key` after the remote write failed with `err`.
+    pub(crate) fn compare_and_swap<E>(
+        &self,
+        key: &str,
+        expected: Option<u64>,
+        value: V,
+        err: E,
+    ) -> Result<bool, E> {
+        self.set_unreachable(true);
+        match self.mode {
+            DegradedMode::FailClosed => Err(err),
+            DegradedMode::FailOpen => Ok(true),
+            DegradedMode::Local => {
+                let Ok(swapped) = self
+                    .local
+                    .compare_and_swap(&key.to_owned(), expected, value);
+                Ok(swapped)
+            }
+        }
+    }
+
+    /// Removes the local copy of `key`, returning whether there was one.
+    pub(crate) fn remove(&self, key: &str) -> bool {
+        let Ok(removed) = self.local.remove(&key.to_owned());
+        removed
+    }
+}
+
+#[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+impl<V> fmt::Debug for Degraded<V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Degraded")
+            .field("mode", &self.mode)
+            .field("unreachable", &self.unreachable)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Keeps values in memory, in a sharded map. Never fails.
+#[derive(Debug)]
+pub struct MemoryStore<K: Hash + Eq, V> {
+    entries: DashMap<K, Versioned<V>>,
+}
+
+impl<K: Hash + Eq, V> MemoryStore<K, V> {
+    pub fn new() -> Self {
+        Self {
+            entries: DashMap::new(),
+        }
+    }
+
+    /// The number of stored keys.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<K: Hash + Eq, V> Default for MemoryStore<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> StateStore<K, V> for MemoryStore<K, V> {
+    type Error = Infallible;
+
+    fn load(&self, key: &K) -> Result<Option<Versioned<V>>, Infallible> {
+        Ok(self.entries.get(key).map(|entry| entry.clone()))
+    }
+
+    fn compare_and_swap(
+        &self,
+        key: &K,
+        expected: Option<u64>,
+        value: V,
+    ) -> Result<bool, Infallible> {
+        Ok(match (self.entries.entry(key.clone()), expected) {
+            (Entry::Occupied(mut entry), Some(expected)) if entry.get().version == expected => {
+                let version = expected.wrapping_add(1);
+                entry.insert(Versioned { value, version });
+                true
+            }
+            (Entry::Vacant(entry), None) => {
+                entry.insert(Versioned { value, version: 0 });
+                true
+            }
+            _ => false,
+        })
+    }
+
+    fn remove(&self, key: &K) -> Result<bool, Infallible> {
+        Ok(self.entries.remove(key).is_some())
+    }
+}
+
+/// Independently limited keys whose states live in a [`StateStore`], so that
+/// limiters in several processes sharing the store enforce one quota per key.
+///
+/// Each acquire loads the key's [snapshot](StoredState::Snapshot), restores
+/// it into a fresh copy of the template, runs the algorithm and writes the
+/// new snapshot back with compare-and-swap, retrying from the load if the
+/// key changed in the meantime. Denials are not written back. Snapshots hold
+/// the clock's instants, so processes sharing a remote store should read a
+/// clock with the same meaning everywhere, such as
+/// [`SystemClock`](crate::SystemClock).
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use ratelimit::{Algorithm, MemoryStore, MonotonicClock, Quota, State, StoredRateLimiter};
+///
+/// let store = Arc::new(MemoryStore::new());
+/// let template = State::new(Quota::per_second(2), MonotonicClock);
+/// let a = StoredRateLimiter::new(store.clone(), template.fresh());
+/// let b = StoredRateLimiter::new(store, template);
+///
+/// assert!(a.acquire_by_key("user").is_ok());
+/// assert!(b.acquire_by_key("user").is_ok());
+/// // Both limiters drew on the same quota.
+/// assert!(a.acquire_by_key("user").is_err());
+/// ```
+#[derive(Debug)]
+pub struct StoredRateLimiter<
+    St,
+    C: Clock,
+    S: Algorithm<C> = State<C>,
+    K: Hash + Eq + Clone = String,
+> {
+    store: St,
+    template: S,
+    key_quotas: HashMap<K, Quota>,
+    _clock: PhantomData<C>,
+}
+
+impl<St, C, S, K> StoredRateLimiter<St, C, S, K>
+where
+    St: StateStore<K, S::Snapshot>,
+    C: Clock,
+    S: StoredState<C>,
+    K: Hash + Eq + Clone,
+{
+    /// Creates a limiter whose unknown keys start as fresh copies of
+    /// `template`.
+    pub fn new(store: St, template: S) -> Self {
+        Self {
+            store,
+            template,
+            key_quotas: HashMap::new(),
+            _clock: PhantomData,
+        }
+    }
+
+    /// Starts `key` with `quota` rather than the template's when it isn't
+    /// stored yet. Every limiter sharing the store should be configured
+    /// alike.
+    pub fn with_key_quota<Q>(mut self, key: &Q, quota: impl Into<Quota>) -> Self
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.key_quotas.insert(key.to_owned(), quota.into());
+        self
+    }
+
+    pub fn store(&self) -> &St {
+        &self.store
+    }
+
+    /// Consumes a permit for `key`.
+    pub fn acquire_by_key<Q>(&self, key: &Q) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.acquire_n_by_key(key, 1)
+    }
+
+    /// Consumes `n` permits for `key`, or none of them.
+    ///
+    /// Fails with [`StoreUnavailable`](AcquireError::StoreUnavailable) if the
+    /// store fails, or if the key keeps changing under contention.
+    pub fn acquire_n_by_key<Q>(&self, key: &Q, n: u64) -> Result<(), AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let key = key.to_owned();
+        for _ in 0..MAX_ATTEMPTS {
+            let stored = self.store.load(&key).map_err(unavailable)?;
+            let version = stored.as_ref().map(|stored| stored.version);
+            let mut state = self.state(&key, stored);
+            let now = state.clock().now();
+            state
+                .try_acquire_n_at(n, now)?
+                .map_err(|not_until| AcquireError::not_allowed(not_until, now))?;
+            if self
+                .store
+                .compare_and_swap(&key, version, state.snapshot())
+                .map_err(unavailable)?
+            {
+                return Ok(());
+            }
+        }
+        Err(AcquireError::StoreUnavailable(
+            "too many conflicting updates".to_owned(),
+        ))
+    }
+
+    /// Reports whether `key` would be granted a permit, and how many remain,
+    /// without consuming one or storing the key.
+    pub fn check_key<Q>(&self, key: &Q) -> Result<u64, AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let key = key.to_owned();
+        let state = self.state(&key, self.store.load(&key).map_err(unavailable)?);
+        let now = state.clock().now();
+        state
+            .check_at(now)
+            .map_err(|not_until| AcquireError::not_allowed(not_until, now))
+    }
+
+    /// Forgets the stored state of `key`, returning whether it was stored.
+    pub fn remove_key<Q>(&self, key: &Q) -> Result<bool, AcquireError>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.store.remove(&key.to_owned()).map_err(unavailable)
+    }
+
+    /// The state of `key`, restored from its `stored` snapshot if any.
+    fn state(&self, key: &K, stored: Option<Versioned<S::Snapshot>>) -> S {
+        let mut state = match self.key_quotas.get(key) {
+            Some(quota) => S::from_quota(*quota, self.template.clock().clone()),
+            None => self.template.fresh(),
+        };
+        if let Some(stored) = stored {
+            state.restore(stored.value);
+        }
+        state
+    }
+}
+
+fn unavailable(err: impl std::error::Error) -> AcquireError {
+    AcquireError::StoreUnavailable(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use super::*;
+    use crate::{
+        clock::{FakeRelativeClock, MonotonicClock},
+        gcra::GcraState,
+        nanos::Nanos,
+    };
+
+    #[test]
+    fn test_memory_store_compare_and_swap() {
+        let store = MemoryStore::new();
+        let key = "k".to_owned();
+        assert_eq!(store.load(&key), Ok(None));
+        assert_eq!(store.compare_and_swap(&key, None, 1), Ok(true));
+        // 已存在时不能按不存在写入，版本不符时也不能写入
+        assert_eq!(store.compare_and_swap(&key, None, 2), Ok(false));
+        assert_eq!(store.compare_and_swap(&key, Some(7), 2), Ok(false));
+        assert_eq!(store.compare_and_swap(&key, Some(0), 2), Ok(true));
+        assert_eq!(
+            store.load(&key),
+            Ok(Some(Versioned {
+                value: 2,
+                version: 1
+            }))
+        );
+        assert_eq!(store.remove(&key), Ok(true));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_stored_limiters_share_quota() {
+        let clock = FakeRelativeClock::default();
+        let store = Arc::new(MemoryStore::new());
+        let template = State::new(Quota::per_second(2), clock.clone());
+        let a = StoredRateLimiter::new(store.clone(), template.fresh())
+            .with_key_quota("vip", Quota::per_second(3));
+        let b = StoredRateLimiter::new(store.clone(), template)
+            .with_key_quota("vip", Quota::per_second(3));
+        let user = "user".to_owned();
+
+        assert_eq!(a.check_key(&user), Ok(2));
+        assert!(a.acquire_by_key(&user).is_ok());
+        assert!(b.acquire_by_key(&user).is_ok());
+        assert!(matches!(
+            a.acquire_by_key(&user),
+            Err(AcquireError::NotAllowed { .. })
+        ));
+        assert_eq!(b.acquire_n_by_key("vip", 3), Ok(()));
+        // 拒绝不会写回存储
+        assert_eq!(store.load(&user).unwrap().unwrap().version, 1);
+
+        clock.advance(Duration::from_secs(1));
+        assert!(b.acquire_by_key(&user).is_ok());
+        assert_eq!(a.remove_key(&user), Ok(true));
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn test_stored_gcra_state() {
+        let clock = FakeRelativeClock::default();
+        let store = Arc::new(MemoryStore::new());
+        let a = StoredRateLimiter::new(
+            store.clone(),
+            GcraState::new(2, Nanos::from(1_000), clock.clone()),
+        );
+        // 另一个进程的状态晚些创建，TAT 按各自的起点换算
+        clock.advance(Duration::from_nanos(500));
+        let b = StoredRateLimiter::new(store, GcraState::new(2, Nanos::from(1_000), clock.clone()));
+        let key = "k".to_owned();
+
+        assert!(a.acquire_by_key(&key).is_ok());
+        assert!(b.acquire_by_key(&key).is_ok());
+        assert!(a.acquire_by_key(&key).is_err());
+        clock.advance(Duration::from_nanos(1_000));
+        assert!(b.acquire_by_key(&key).is_ok());
+        assert!(a.acquire_by_key(&key).is_err());
+    }
+
+    #[test]
+    fn test_stored_concurrent_acquire() {
+        let limiter = StoredRateLimiter::new(
+            MemoryStore::new(),
+            State::new(Quota::per_hour(100), MonotonicClock),
+        );
+        let key = "shared".to_owned();
+
+        // 并发的读-改-写不会多发放许可
+        let granted: usize = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8)
+                .map(|_| {
+                    let (limiter, key) = (&limiter, &key);
+                    scope.spawn(move || {
+                        (0..50)
+                            .filter(|_| {
+                                loop {
+                                    match limiter.acquire_by_key(key) {
+                                        Err(AcquireError::StoreUnavailable(_)) => continue,
+                                        acquired => break acquired.is_ok(),
+                                    }
+                                }
+                            })
+                            .count()
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).sum()
+        });
+        assert_eq!(granted, 100);
+    }
+
+    #[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+    #[test]
+    fn test_degraded_modes() {
+        let closed = Degraded::<u64>::new(DegradedMode::FailClosed);
+        assert_eq!(closed.load("k", "down"), Err("down"));
+        assert_eq!(closed.compare_and_swap("k", None, 1, "down"), Err("down"));
+        assert_eq!(closed.unreachable(false, "down"), Err("down"));
+
+        // 放行模式下每个 key 都当作新的
+        let open = Degraded::new(DegradedMode::FailOpen);
+        assert_eq!(open.compare_and_swap("k", None, 1, "down"), Ok(true));
+        assert_eq!(open.compare_and_swap("k", None, 1, "down"), Ok(true));
+        assert_eq!(open.load("k", "down"), Ok(None));
+
+        // 本地模式退化为进程内存储
+        let local = Degraded::new(DegradedMode::Local);
+        assert_eq!(local.compare_and_swap("k", None, 1, "down"), Ok(true));
+        assert_eq!(local.compare_and_swap("k", None, 2, "down"), Ok(false));
+        let loaded = local.load("k", "down").unwrap().unwrap();
+        assert_eq!(loaded.value, 1);
+        assert_eq!(
+            local.compare_and_swap("k", Some(loaded.version), 3, "down"),
+            Ok(true)
+        );
+        assert!(local.remove("k"));
+        assert!(!local.remove("k"));
+        assert_eq!(local.unreachable(false, "down"), Ok(false));
+    }
+
+    #[cfg(any(feature = "etcd", feature = "memcache", feature = "redis"))]
+    #[test]
+    fn test_degraded_listener() {
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut degraded = Degraded::<u64>::new(DegradedMode::FailOpen);
+        degraded.set_listener({
+            let events = events.clone();
+            Arc::new(move |unreachable| events.lock().unwrap().push(unreachable))
+        });
+
+        // 只在状态变化时通知
+        assert_eq!(degraded.load("k", "down"), Ok(None));
+        assert_eq!(degraded.load("k", "down"), Ok(None));
+        degraded.reached();
+        degraded.reached();
+        assert_eq!(*events.lock().unwrap(), [true, false]);
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    hash::Hash,
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+
+use crate::{
+    algorithm::Algorithm,
+    clock::Clock,
+    error::AcquireError,
+    jitter::Jitter,
+    limiter::RateLimiter,
+    sleeper::{AsyncSleeper, DefaultSleeper},
+};
+
+pin_project! {
+    /// A stream yielding the items of an inner stream no faster than the base
+    /// state of a [`RateLimiter`] allows.
+    ///
+    /// Each item is pulled from the inner stream first and held until a permit
+    /// is granted, so no permit is spent while the inner stream is pending.
+    /// While the limiter is disabled the stream stalls.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct RateLimitedStream<'a, St, C, S, K = String, Sl = DefaultSleeper>
+    where
+        St: Stream,
+        C: Clock,
+        S: Algorithm<C>,
+        // pin_project! only accepts a single bound per predicate.
+        K: Hash,
+        K: Eq,
+        K: Clone,
+        Sl: AsyncSleeper,
+    {
+        #[pin]
+        inner: St,
+        limiter: &'a mut RateLimiter<C, S, K>,
+        buffered: Option<St::Item>,
+        delay: Option<Pin<Box<Sl::Sleep>>>,
+        jitter: Jitter,
+    }
+}
+
+impl<St, C, S, K, Sl> RateLimitedStream<'_, St, C, S, K, Sl>
+where
+    St: Stream,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    Sl: AsyncSleeper,
+{
+    /// Extends every wait for a permit by `jitter`.
+    pub fn with_jitter(mut self, jitter: Jitter) -> Self {
+        self.jitter = jitter;
+        self
+    }
+}
+
+impl<St, C, S, K, Sl> Stream for RateLimitedStream<'_, St, C, S, K, Sl>
+where
+    St: Stream,
+    C: Clock,
+    S: Algorithm<C>,
+    K: Hash + Eq + Clone,
+    Sl: AsyncSleeper,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(delay) = this.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                *this.delay = None;
+            }
+            if this.buffered.is_none() {
+                match ready!(this.inner.as_mut().poll_next(cx)) {
+                    Some(item) => *this.buffered = Some(item),
+                    None => return Poll::Ready(None),
+                }
+            }
+            match this.limiter.acquire() {
+                Ok(()) => return Poll::Ready(this.buffered.take()),
+                Err(AcquireError::NotAllowed { retry_after, .. }) => {
+                    *this.delay = Some(Box::pin(Sl::sleep(this.jitter.apply(retry_after))));
+                }
+                Err(_) => return Poll::Pending,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let buffered = usize::from(self.buffered.is_some());
+        let (lower, upper) = self.inner.size_hint();
+        (
+            lower.saturating_add(buffered),
+            upper.and_then(|upper| upper.checked_add(buffered)),
+        )
+    }
+}
+
+/// Adds [`ratelimit`](Self::ratelimit) to every [`Stream`].
+pub trait StreamRateLimitExt: Stream + Sized {
+    /// Throttles the stream to the base state of `limiter`.
+    fn ratelimit<C, S, K>(
+        self,
+        limiter: &mut RateLimiter<C, S, K>,
+    ) -> RateLimitedStream<'_, Self, C, S, K>
+    where
+        C: Clock,
+        S: Algorithm<C>,
+        K: Hash + Eq + Clone,
+    {
+        RateLimitedStream {
+            inner: self,
+            limiter,
+            buffered: None,
+            delay: None,
+            jitter: Jitter::NONE,
+        }
+    }
+}
+
+impl<St: Stream> StreamRateLimitExt for St {}
+
+#[cfg(all(test, feature = "tokio"))]
+mod tests {
+    use std::time::Duration;
+
+    use futures::{StreamExt, stream};
+    use tokio::time::Instant;
+
+    use super::*;
+    use crate::{clock::TokioClock, quota::Quota};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ratelimited_stream_spaces_items() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(Quota::per_second(2), TokioClock);
+
+        let mut elapsed = Vec::new();
+        let mut items = stream::iter(0..5).ratelimit(&mut limiter);
+        while let Some(item) = items.next().await {
+            elapsed.push((item, start.elapsed().as_millis()));
+        }
+
+        // 每个窗口最多放行 2 个
+        assert_eq!(
+            elapsed,
+            vec![(0, 0), (1, 0), (2, 1_000), (3, 1_000), (4, 2_000)]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_ratelimited_stream_holds_item_until_permitted() {
+        let mut limiter = RateLimiter::new(Quota::per_second(1), TokioClock);
+        let mut items = stream::iter(["a", "b"]).ratelimit(&mut limiter);
+        assert_eq!(items.size_hint(), (2, Some(2)));
+        assert_eq!(items.next().await, Some("a"));
+
+        // 第二个元素已取出但仍在等待许可，取消 next() 不会丢失该元素
+        let pending = tokio::time::timeout(Duration::from_millis(10), items.next()).await;
+        assert!(pending.is_err());
+        assert_eq!(items.size_hint(), (1, Some(1)));
+        assert_eq!(items.next().await, Some("b"));
+        assert_eq!(items.next().await, None);
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// How a [`RateLimiter`](crate::RateLimiter) guards its state.
+///
+/// Either way a lock is held only for a single attempt. For state updated
+/// without any lock at all, share an
+/// [`AtomicGcraState`](crate::AtomicGcraState) directly.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SyncBackend {
+    /// [`std::sync::Mutex`], which adds no dependencies.
+    #[default]
+    Std,
+    /// `parking_lot::Mutex`, which spins briefly before parking and is
+    /// cheaper to lock when contended.
+    #[cfg(feature = "parking_lot")]
+    ParkingLot,
+}
+
+/// A mutex implementation that a [`Lock`] can be backed by.
+trait RawLock<T> {
+    type Guard<'a>: DerefMut<Target = T>
+    where
+        Self: 'a;
+
+    fn lock(&self) -> Self::Guard<'_>;
+
+    fn get_mut(&mut self) -> &mut T;
+
+    fn into_inner(self) -> T;
+}
+
+impl<T> RawLock<T> for Mutex<T> {
+    type Guard<'a>
+        = MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    /// A panic while the lock was held leaves the state consistent, since
+    /// every update is a single assignment, so poisoning is ignored.
+    fn lock(&self) -> MutexGuard<'_, T> {
+        Mutex::lock(self).unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        Mutex::get_mut(self).unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn into_inner(self) -> T {
+        Mutex::into_inner(self).unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(feature = "parking_lot")]
+impl<T> RawLock<T> for parking_lot::Mutex<T> {
+    type Guard<'a>
+        = parking_lot::MutexGuard<'a, T>
+    where
+        T: 'a;
+
+    fn lock(&self) -> parking_lot::MutexGuard<'_, T> {
+        parking_lot::Mutex::lock(self)
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        parking_lot::Mutex::get_mut(self)
+    }
+
+    fn into_inner(self) -> T {
+        parking_lot::Mutex::into_inner(self)
+    }
+}
+
+/// A mutex backed by the [`SyncBackend`] chosen at construction.
+pub(crate) enum Lock<T> {
+    Std(Mutex<T>),
+    #[cfg(feature = "parking_lot")]
+    ParkingLot(parking_lot::Mutex<T>),
+}
+
+impl<T> Lock<T> {
+    pub(crate) fn new(backend: SyncBackend, value: T) -> Self {
+        match backend {
+            SyncBackend::Std => Self::Std(Mutex::new(value)),
+            #[cfg(feature = "parking_lot")]
+            SyncBackend::ParkingLot => Self::ParkingLot(parking_lot::Mutex::new(value)),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> Guard<'_, T> {
+        match self {
+            Self::Std(lock) => Guard::Std(RawLock::lock(lock)),
+            #[cfg(feature = "parking_lot")]
+            Self::ParkingLot(lock) => Guard::ParkingLot(RawLock::lock(lock)),
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        match self {
+            Self::Std(lock) => RawLock::get_mut(lock),
+            #[cfg(feature = "parking_lot")]
+            Self::ParkingLot(lock) => RawLock::get_mut(lock),
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> T {
+        match self {
+            Self::Std(lock) => RawLock::into_inner(lock),
+            #[cfg(feature = "parking_lot")]
+            Self::ParkingLot(lock) => RawLock::into_inner(lock),
+        }
+    }
+
+    /// Moves the value into a lock of another backend.
+    pub(crate) fn with_backend(self, backend: SyncBackend) -> Self {
+        Self::new(backend, self.into_inner())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Lock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Std(lock) => lock.fmt(f),
+            #[cfg(feature = "parking_lot")]
+            Self::ParkingLot(lock) => lock.fmt(f),
+        }
+    }
+}
+
+pub(crate) enum Guard<'a, T> {
+    Std(MutexGuard<'a, T>),
+    #[cfg(feature = "parking_lot")]
+    ParkingLot(parking_lot::MutexGuard<'a, T>),
+}
+
+impl<T> Deref for Guard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self {
+            Self::Std(guard) => guard,
+            #[cfg(feature = "parking_lot")]
+            Self::ParkingLot(guard) => guard,
+        }
+    }
+}
+
+impl<T> DerefMut for Guard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        match self {
+            Self::Std(guard) => guard,
+            #[cfg(feature = "parking_lot")]
+            Self::ParkingLot(guard) => guard,
+        }
+    }
+}
@@ -0,0 +1,360 @@
+use crate::{
+    algorithm::Algorithm,
+    clock::{Clock, Reference},
+    error::InsufficientCapacity,
+    nanos::Nanos,
+    not_until::NotUntil,
+    quota::Quota,
+};
+
+/// A token bucket holding up to `capacity` tokens and refilling one token
+/// every `refill_interval`.
+///
+/// Unlike the fixed window of [`State`](crate::State), tokens are replenished
+/// continuously, so no more than `capacity` permits can be granted in a
+/// burst, even across window boundaries. The bucket starts full.
+#[derive(Debug)]
+pub struct TokenBucketState<C: Clock> {
+    quota: Quota,
+    refill_interval: Nanos,
+    tokens: u64,
+    last_refill: C::Instant,
+    clock: C,
+}
+
+impl<C: Clock> TokenBucketState<C> {
+    /// # Panics
+    ///
+    /// Panics if `refill_interval` is zero.
+    pub fn new(capacity: u64, refill_interval: Nanos, clock: C) -> Self {
+        assert!(
+            refill_interval > Nanos::ZERO,
+            "refill interval must be non-zero"
+        );
+        Self::from_quota(Quota::new(1, refill_interval).allow_burst(capacity), clock)
+    }
+
+    /// Takes a token from the bucket, or on denial reports when the next
+    /// token is added.
+    pub fn acquire(&mut self) -> Result<(), NotUntil<C::Instant>> {
+        let now = self.clock.now();
+        self.try_acquire_at(now)
+    }
+
+    /// Takes `n` tokens from the bucket, or none of them. On denial reports
+    /// when the bucket will hold `n` tokens.
+    pub fn acquire_n(
+        &mut self,
+        n: u64,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        let now = self.clock.now();
+        self.try_acquire_n_at(n, now)
+    }
+
+    /// The number of tokens in the bucket at `now` and the instant from which
+    /// the next token accrues.
+    fn refilled_at(&self, now: C::Instant) -> (u64, C::Instant) {
+        if self.tokens >= self.quota.burst() {
+            return (self.quota.burst(), now);
+        }
+        let added = now.duration_since(self.last_refill) / self.refill_interval;
+        let tokens = self.tokens.saturating_add(added);
+        if tokens >= self.quota.burst() {
+            (self.quota.burst(), now)
+        } else {
+            (
+                tokens,
+                self.last_refill + self.refill_interval.saturating_mul(added),
+            )
+        }
+    }
+
+    /// Verifies the internal bookkeeping in debug builds; compiles out in release.
+    fn check_invariants(&self, now: C::Instant) {
+        debug_assert!(
+            self.tokens <= self.quota.burst(),
+            "tokens {} exceed capacity {}",
+            self.tokens,
+            self.quota.burst()
+        );
+        debug_assert!(
+            self.last_refill <= now,
+            "last refill {:?} is in the future of {:?}",
+            self.last_refill,
+            now
+        );
+    }
+
+    fn take_at(&mut self, n: u64, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        // A clock stepped back, or an older event time, sees no time pass.
+        let now = now.max(self.last_refill);
+        let (tokens, last_refill) = self.refilled_at(now);
+        self.tokens = tokens;
+        self.last_refill = last_refill;
+        let result = if self.tokens >= n {
+            self.tokens -= n;
+            Ok(())
+        } else {
+            let missing = n - self.tokens;
+            let refill = self.refill_interval.widening_mul(missing);
+            Err(NotUntil::after_wide(
+                self.last_refill,
+                refill,
+                self.quota,
+                self.tokens,
+            ))
+        };
+        self.check_invariants(now);
+        result
+    }
+}
+
+impl<C: Clock> Algorithm<C> for TokenBucketState<C> {
+    /// Holds `quota.burst()` tokens and refills `quota.allowed()` of them
+    /// evenly over `quota.window()`.
+    fn from_quota(quota: Quota, clock: C) -> Self {
+        Self {
+            quota,
+            refill_interval: quota.replenish_interval(),
+            tokens: quota.burst(),
+            last_refill: clock.now(),
+            clock,
+        }
+    }
+
+    fn fresh(&self) -> Self {
+        Self::from_quota(self.quota, self.clock.clone())
+    }
+
+    fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    fn quota(&self) -> Quota {
+        self.quota
+    }
+
+    fn capacity(&self) -> u64 {
+        self.quota.burst()
+    }
+
+    fn try_acquire_at(&mut self, now: C::Instant) -> Result<(), NotUntil<C::Instant>> {
+        self.take_at(1, now)
+    }
+
+    fn try_acquire_n_at(
+        &mut self,
+        n: u64,
+        now: C::Instant,
+    ) -> Result<Result<(), NotUntil<C::Instant>>, InsufficientCapacity> {
+        if n > self.quota.burst() {
+            return Err(InsufficientCapacity(self.quota.burst()));
+        }
+        Ok(self.take_at(n, now))
+    }
+
+    fn check_at(&self, now: C::Instant) -> Result<u64, NotUntil<C::Instant>> {
+        match self.refilled_at(now) {
+            (0, last_refill) => Err(NotUntil::after(
+                last_refill,
+                self.refill_interval,
+                self.quota,
+                0,
+            )),
+            (tokens, _) => Ok(tokens),
+        }
+    }
+
+    fn remaining_at(&self, now: C::Instant) -> u64 {
+        self.refilled_at(now).0
+    }
+
+    fn reset_after_at(&self, now: C::Instant) -> Nanos {
+        let (tokens, last_refill) = self.refilled_at(now);
+        let missing = self.quota.burst() - tokens;
+        let refill = self.refill_interval.widening_mul(missing);
+        let elapsed = u128::from(now.duration_since(last_refill).as_u64());
+        Nanos::saturating_from_wide(refill.saturating_sub(elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{
+        clock::{FakeAbsoluteClock, FakeRelativeClock},
+        limiter::RateLimiter,
+        scenario::{self, checked_acquire, max_accepted_in_any_window, op_strategy, run_scenario},
+    };
+
+    #[test]
+    fn test_token_bucket_burst_then_refill() {
+        let clock = FakeRelativeClock::default();
+        // 容量 3，每 100ms 补充 1 个令牌
+        let mut bucket = TokenBucketState::new(3, Nanos::new(100_000_000), clock.clone());
+
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_err());
+
+        clock.advance(Duration::from_millis(150));
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_err());
+
+        // 上次补充后多出的 50ms 不会丢失
+        clock.advance(Duration::from_millis(50));
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_clock_stepped_back() {
+        let clock = FakeAbsoluteClock::new(SystemTime::UNIX_EPOCH + Duration::from_secs(100));
+        let mut bucket = TokenBucketState::new(2, Nanos::new(100_000_000), clock.clone());
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_ok());
+        clock.advance(Duration::from_millis(150));
+        assert!(bucket.acquire().is_ok());
+
+        // 时钟回拨后不补充令牌，追上之后照常补充
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_secs(50));
+        assert!(bucket.acquire().is_err());
+        clock.set(SystemTime::UNIX_EPOCH + Duration::from_millis(100_200));
+        assert!(bucket.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_token_bucket_capped_at_capacity() {
+        let clock = FakeRelativeClock::default();
+        let mut bucket = TokenBucketState::new(2, Nanos::new(100_000_000), clock.clone());
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(bucket.remaining_at(clock.now()), 2);
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_not_until_next_token() {
+        let clock = FakeRelativeClock::default();
+        let mut bucket = TokenBucketState::new(2, Nanos::new(100_000_000), clock.clone());
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_ok());
+
+        clock.advance(Duration::from_millis(30));
+        let not_until = bucket.acquire().unwrap_err();
+        assert_eq!(
+            not_until.wait_time_from(clock.now()),
+            Duration::from_millis(70)
+        );
+        // 补满需要再等 70ms + 100ms
+        assert_eq!(bucket.reset_after_at(clock.now()), Nanos::new(170_000_000));
+
+        clock.advance(Duration::from_millis(70));
+        assert!(bucket.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_token_bucket_zero_capacity() {
+        let clock = FakeRelativeClock::default();
+        let mut bucket = TokenBucketState::new(0, Nanos::new(100_000_000), clock.clone());
+        assert!(bucket.acquire().is_err());
+        clock.advance(Duration::from_secs(10));
+        assert!(bucket.acquire().is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_from_quota() {
+        let clock = FakeRelativeClock::default();
+        let mut bucket =
+            TokenBucketState::from_quota((4, Duration::from_secs(1)).into(), clock.clone());
+        assert_eq!(bucket.capacity(), 4);
+        for _ in 0..4 {
+            assert!(bucket.acquire().is_ok());
+        }
+        assert!(bucket.acquire().is_err());
+
+        // 每 250ms 补充 1 个
+        clock.advance(Duration::from_millis(250));
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_from_quota_with_burst() {
+        let clock = FakeRelativeClock::default();
+        let quota = Quota::per_second(4).allow_burst(1);
+        let mut bucket = TokenBucketState::from_quota(quota, clock.clone());
+        assert_eq!(bucket.capacity(), 1);
+        assert!(bucket.acquire().is_ok());
+        assert!(bucket.acquire().is_err());
+
+        // 突发量为 1，但补充速率仍是每 250ms 1 个
+        clock.advance(Duration::from_millis(250));
+        assert!(bucket.acquire().is_ok());
+    }
+
+    #[test]
+    fn test_token_bucket_acquire_n() {
+        let clock = FakeRelativeClock::default();
+        let mut bucket = TokenBucketState::new(4, Nanos::new(100_000_000), clock.clone());
+        assert_eq!(bucket.acquire_n(3), Ok(Ok(())));
+
+        // 只剩 1 个令牌，还差 2 个，需要等 200ms
+        let not_until = bucket.acquire_n(3).unwrap().unwrap_err();
+        assert_eq!(not_until.earliest_possible(), Nanos::new(200_000_000));
+        assert_eq!(bucket.remaining_at(clock.now()), 1);
+
+        clock.advance(Duration::from_millis(200));
+        assert_eq!(bucket.acquire_n(3), Ok(Ok(())));
+        assert!(bucket.acquire().is_err());
+        assert_eq!(bucket.acquire_n(5), Err(InsufficientCapacity(4)));
+    }
+
+    #[test]
+    fn test_token_bucket_in_rate_limiter() {
+        let clock = FakeRelativeClock::default();
+        let base = TokenBucketState::new(1, Nanos::new(1_000_000_000), clock.clone());
+        let limiter = RateLimiter::from_state(base);
+        limiter.insert_key("user", (2, Duration::from_secs(1)));
+
+        assert!(limiter.acquire().is_ok());
+        assert!(limiter.acquire().is_err());
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire_by_key("user").is_err());
+
+        clock.advance(Duration::from_millis(500));
+        assert!(limiter.acquire_by_key("user").is_ok());
+        assert!(limiter.acquire().is_err());
+    }
+
+    proptest! {
+        #![proptest_config(scenario::config())]
+
+        #[test]
+        fn prop_token_bucket_admits_at_most_capacity_plus_refill(
+            capacity in 0..10u64,
+            interval_ms in 1..500u64,
+            window_ms in 1..2_000u64,
+            ops in prop::collection::vec(op_strategy(), 0..500),
+        ) {
+            let clock = FakeRelativeClock::default();
+            let interval = Nanos::new(interval_ms * 1_000_000);
+            let mut bucket = TokenBucketState::new(capacity, interval, clock.clone());
+            let accepted = run_scenario(&ops, &clock, || checked_acquire(&mut bucket));
+
+            // 任意窗口内最多是初始容量加上窗口内补充的令牌
+            let window = Nanos::new(window_ms * 1_000_000);
+            let refills = window_ms.div_ceil(interval_ms);
+            let bound = if capacity == 0 { 0 } else { capacity + refills };
+            prop_assert!(max_accepted_in_any_window(&accepted, window) <= bound);
+        }
+    }
+}
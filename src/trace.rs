@@ -0,0 +1,153 @@
+use std::{fmt, time::Duration};
+
+use tracing::{debug, field, info, trace};
+
+use crate::{error::AcquireError, hooks::Hooks, quota::Quota};
+
+/// [`Hooks`] that emit a limiter's decisions, waits, key lifecycle and quota
+/// changes as `tracing` events with the `ratelimit` target.
+///
+/// Events are emitted in the span of the code that acquires, so a denied
+/// request shows up in its trace, with fields for the key, the quota, the
+/// permits remaining and how long to retry after. Grants are traced at
+/// `TRACE` level, denials, waits and keys created or evicted at `DEBUG`, and
+/// quota changes at `INFO`, for audit logs.
+///
+/// ```
+/// use ratelimit::{MonotonicClock, Quota, RateLimiter, TracingHooks};
+///
+/// let limiter = RateLimiter::new(Quota::per_second(100), MonotonicClock)
+///     .with_hooks(TracingHooks);
+/// # drop(limiter);
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TracingHooks;
+
+impl<K: fmt::Debug> Hooks<K> for TracingHooks {
+    fn on_allowed(&self, key: Option<&K>, quota: Option<Quota>, permits: u64) {
+        trace!(
+            target: "ratelimit",
+            key = key.map(field::debug),
+            quota = quota.map(field::debug),
+            permits,
+            "permits granted",
+        );
+    }
+
+    fn on_denied(&self, key: Option<&K>, quota: Option<Quota>, err: &AcquireError) {
+        let key = key.map(field::debug);
+        match err {
+            AcquireError::NotAllowed {
+                retry_after,
+                remaining,
+                ..
+            } => debug!(
+                target: "ratelimit",
+                key,
+                quota = quota.map(field::debug),
+                remaining,
+                retry_after = ?retry_after,
+                "rate limited",
+            ),
+            err => debug!(target: "ratelimit", key, error = %err, "acquire failed"),
+        }
+    }
+
+    fn on_key_created(&self, key: &K, quota: Quota) {
+        debug!(target: "ratelimit", key = ?key, quota = ?quota, "key created");
+    }
+
+    fn on_evicted(&self, key: &K) {
+        debug!(target: "ratelimit", key = ?key, "key evicted");
+    }
+
+    fn on_waited(&self, key: Option<&K>, waited: Duration) {
+        debug!(
+            target: "ratelimit",
+            key = key.map(field::debug),
+            waited = ?waited,
+            "waited for permits",
+        );
+    }
+
+    fn on_quota_changed(&self, key: &K, quota: Option<Quota>) {
+        match quota {
+            Some(quota) => info!(target: "ratelimit", key = ?key, quota = ?quota, "key configured"),
+            None => info!(target: "ratelimit", key = ?key, "key removed"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fmt::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+
+    use super::*;
+    use crate::{clock::FakeRelativeClock, limiter::RateLimiter};
+
+    /// Records every event as `LEVEL field=value ...`.
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<String>>>);
+
+    struct Line(String);
+
+    impl Visit for Line {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            write!(self.0, " {}={value:?}", field.name()).unwrap();
+        }
+    }
+
+    impl Subscriber for Events {
+        fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, _span: &Attributes<'_>) -> Id {
+            Id::from_u64(1)
+        }
+
+        fn record(&self, _span: &Id, _values: &Record<'_>) {}
+
+        fn record_follows_from(&self, _span: &Id, _follows: &Id) {}
+
+        fn event(&self, event: &Event<'_>) {
+            let mut line = Line(event.metadata().level().to_string());
+            event.record(&mut line);
+            self.0.lock().unwrap().push(line.0);
+        }
+
+        fn enter(&self, _span: &Id) {}
+
+        fn exit(&self, _span: &Id) {}
+    }
+
+    #[test]
+    fn test_tracing_hooks() {
+        let events = Events::default();
+        tracing::subscriber::with_default(events.clone(), || {
+            let limiter = RateLimiter::new(Quota::per_second(1), FakeRelativeClock::default())
+                .with_hooks(TracingHooks);
+            limiter.insert_key("user", Quota::per_second(1));
+            limiter.acquire_by_key("user").unwrap();
+            assert!(limiter.acquire_by_key("user").is_err());
+            assert!(limiter.acquire_wait(Some(Duration::ZERO)).is_ok());
+        });
+
+        let events = events.0.lock().unwrap();
+        assert_eq!(events.len(), 5);
+        assert!(events[0].starts_with("INFO message=key configured key=\"user\""));
+        assert!(events[1].starts_with("TRACE message=permits granted key=\"user\""));
+        assert!(events[2].ends_with("remaining=0 retry_after=1s"));
+        assert_eq!(events[3], "DEBUG message=waited for permits waited=0ns");
+        assert!(events[4].ends_with("permits=1"));
+    }
+}